@@ -0,0 +1,221 @@
+//! The decoder and the stepper of goto without the standard library or an
+//! allocator, for running programs on microcontrollers or in a kernel. The
+//! program lies in a fixed memory in the von Neumann layout of the goto
+//! crate: three cells per instruction from the code base on, the opcode as
+//! in the `.gbc` format and up to two operands. The console, RAND and the
+//! stack need a host, the stepper hands them back to the caller.
+//!
+//! `execute` holds the semantics of the instructions, the interpreter of the
+//! goto crate runs every instruction that needs no host through it too.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use core::convert::TryFrom;
+use core::fmt;
+
+pub const CELLS_PER_INSTRUCTION: usize = 3;
+
+/// An instruction as it is decoded from its cells.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Op {
+    Stop,
+    Inc(usize),
+    Dec(usize),
+    Goto(usize),
+    GotoZ(usize, usize),
+    Assert(usize, u64),
+    Print(usize),
+    Read(usize),
+    OutC(usize),
+    Rand(usize, u64),
+    Push(usize),
+    Pop(usize),
+    StoreI(usize, u64),
+    GotoNz(usize, usize),
+    /// jumps to the third operand if the cells hold the same value, with its
+    /// three operands it has no encoding in the memory
+    GotoEq(usize, usize, usize),
+    /// jumps to the third operand if the first cell holds a larger value
+    GotoGt(usize, usize, usize),
+    Nop,
+}
+
+/// What `execute` did.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Executed {
+    /// the program counter of the next instruction, `None` after STOP
+    pub next: Option<usize>,
+    /// the cell the instruction wrote with its value before
+    pub write: Option<(usize, u64)>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Fault {
+    OutsideProgram { program_counter: usize },
+    OperandTooLarge { cell: usize, value: u64 },
+    NotAnOpcode { cell: usize, opcode: u64 },
+    OutsideMemory { cell: usize, size: usize },
+    Overflow { cell: usize },
+    Underflow { cell: usize },
+    AssertFailed { cell: usize, expected: u64, actual: u64 },
+    /// an instruction that only the host can run, the program counter still
+    /// points at it
+    Host(Op),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::OutsideProgram { program_counter } => write!(f, "program counter {} is outside of the program", program_counter),
+            Fault::OperandTooLarge { cell, value } => write!(f, "cell {}: {} is too large for an operand", cell, value),
+            Fault::NotAnOpcode { cell, opcode } => write!(f, "cell {}: {} is not an opcode", cell, opcode),
+            Fault::OutsideMemory { cell, size } => write!(f, "cell {} is outside of the memory of size {}", cell, size),
+            Fault::Overflow { cell } => write!(f, "INC {} overflows", cell),
+            Fault::Underflow { cell } => write!(f, "DEC {} on a cell that is 0", cell),
+            Fault::AssertFailed { cell, expected: 0, actual } => write!(f, "ASSERTZ {} failed, cell {} is {}", cell, cell, actual),
+            Fault::AssertFailed { cell, expected, actual } => write!(f, "ASSERT {} {} failed, cell {} is {}", cell, expected, cell, actual),
+            Fault::Host(op) => write!(f, "{:?} needs the host", op),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Fault {}
+
+/// Decodes the instruction for the program counter from the memory, any
+/// value in the cells is either an instruction or a fault.
+pub fn decode(memory: &[u64], code_base: usize, program_counter: usize) -> Result<Op, Fault> {
+    let start = program_counter.checked_mul(CELLS_PER_INSTRUCTION).and_then(|offset| offset.checked_add(code_base))
+        .filter(|start| start.checked_add(CELLS_PER_INSTRUCTION).is_some_and(|end| end <= memory.len()))
+        .ok_or(Fault::OutsideProgram { program_counter })?;
+    let cells = &memory[start..start + CELLS_PER_INSTRUCTION];
+    let operand = |index: usize| usize::try_from(cells[index])
+        .map_err(|_| Fault::OperandTooLarge { cell: start + index, value: cells[index] });
+    Ok(match cells[0] {
+        0 => Op::Stop,
+        1 => Op::Inc(operand(1)?),
+        2 => Op::Dec(operand(1)?),
+        3 => Op::Goto(operand(1)?),
+        4 => Op::GotoZ(operand(1)?, operand(2)?),
+        5 => Op::Assert(operand(1)?, cells[2]),
+        6 => Op::Print(operand(1)?),
+        7 => Op::Read(operand(1)?),
+        8 => Op::OutC(operand(1)?),
+        9 => Op::Rand(operand(1)?, cells[2]),
+        10 => Op::Push(operand(1)?),
+        11 => Op::Pop(operand(1)?),
+        12 => Op::StoreI(operand(1)?, cells[2]),
+        13 => Op::GotoNz(operand(1)?, operand(2)?),
+        16 => Op::Nop,
+        opcode => return Err(Fault::NotAnOpcode { cell: start, opcode }),
+    })
+}
+
+/// Runs the instruction at the program counter on the memory. On a fault
+/// nothing changed.
+pub fn execute(op: Op, memory: &mut [u64], program_counter: usize) -> Result<Executed, Fault> {
+    let size = memory.len();
+    let value = |memory: &[u64], cell: usize| memory.get(cell).cloned().ok_or(Fault::OutsideMemory { cell, size });
+    let next = program_counter + 1;
+    let jump = |condition: bool, target: usize| Executed { next: Some(if condition { target } else { next }), write: None };
+    // only for cells `value` found in the memory
+    let write = |memory: &mut [u64], cell: usize, new: u64| {
+        let old = core::mem::replace(&mut memory[cell], new);
+        Ok(Executed { next: Some(next), write: Some((cell, old)) })
+    };
+    match op {
+        Op::Stop => Ok(Executed { next: None, write: None }),
+        Op::Inc(cell) => write(memory, cell, value(memory, cell)?.checked_add(1).ok_or(Fault::Overflow { cell })?),
+        Op::Dec(cell) => write(memory, cell, value(memory, cell)?.checked_sub(1).ok_or(Fault::Underflow { cell })?),
+        Op::StoreI(cell, new) => {
+            value(memory, cell)?;
+            write(memory, cell, new)
+        }
+        Op::Goto(target) => Ok(jump(true, target)),
+        Op::GotoZ(cell, target) => Ok(jump(value(memory, cell)? == 0, target)),
+        Op::GotoNz(cell, target) => Ok(jump(value(memory, cell)? != 0, target)),
+        Op::GotoEq(left, right, target) => Ok(jump(value(memory, left)? == value(memory, right)?, target)),
+        Op::GotoGt(left, right, target) => Ok(jump(value(memory, left)? > value(memory, right)?, target)),
+        Op::Assert(cell, expected) => {
+            let actual = value(memory, cell)?;
+            if actual != expected {
+                return Err(Fault::AssertFailed { cell, expected, actual });
+            }
+            Ok(jump(false, next))
+        }
+        Op::Nop => Ok(jump(false, next)),
+        Op::Print(_) | Op::Read(_) | Op::OutC(_) | Op::Rand(..) | Op::Push(_) | Op::Pop(_) => Err(Fault::Host(op)),
+    }
+}
+
+/// A run on a memory of `N` cells that holds both the data and the program.
+#[derive(Debug, Clone)]
+pub struct Machine<const N: usize> {
+    pub memory: [u64; N],
+    pub code_base: usize,
+    pub program_counter: usize,
+    pub steps: u64,
+}
+
+impl<const N: usize> Machine<N> {
+    pub fn new(memory: [u64; N], code_base: usize) -> Machine<N> {
+        Machine { memory, code_base, program_counter: 0, steps: 0 }
+    }
+
+    /// Runs one instruction, true after STOP. On a fault nothing changed.
+    pub fn step(&mut self) -> Result<bool, Fault> {
+        let op = decode(&self.memory, self.code_base, self.program_counter)?;
+        let executed = execute(op, &mut self.memory, self.program_counter)?;
+        self.steps += 1;
+        match executed.next {
+            Some(next) => self.program_counter = next,
+            None => return Ok(true),
+        }
+        Ok(false)
+    }
+
+    /// Steps until STOP or a fault, or `None` after `max_steps` steps.
+    pub fn run(&mut self, max_steps: u64) -> Option<Result<(), Fault>> {
+        for _ in 0..max_steps {
+            match self.step() {
+                Ok(true) => return Some(Ok(())),
+                Ok(false) => {}
+                Err(fault) => return Some(Err(fault)),
+            }
+        }
+        None
+    }
+}
+
+#[test]
+fn test_machine() {
+    // counts cell 1 down into cell 2, the program starts at cell 3
+    let mut machine = Machine::new([
+        0, 3, 0,
+        4, 1, 4,
+        2, 1, 0,
+        1, 2, 0,
+        3, 0, 0,
+        0, 0, 0,
+    ], 3);
+    assert_eq!(Some(Ok(())), machine.run(100));
+    assert_eq!([0, 0, 3], machine.memory[..3]);
+    assert_eq!((4, 14), (machine.program_counter, machine.steps));
+
+    let mut machine = Machine::new([0, 6, 0, 0, 2, 0, 0], 1);
+    assert_eq!(Err(Fault::Host(Op::Print(0))), machine.step());
+    assert_eq!(0, machine.program_counter);
+    machine.program_counter = 1;
+    assert_eq!(Err(Fault::Underflow { cell: 0 }), machine.step());
+    assert_eq!(Err(Fault::NotAnOpcode { cell: 1, opcode: 14 }), decode(&[0, 14, 0, 0], 1, 0));
+    assert_eq!(Err(Fault::OutsideProgram { program_counter: 2 }), decode(&machine.memory, 1, 2));
+
+    let mut memory = [3, 2, u64::MAX];
+    assert_eq!(Ok(Executed { next: Some(7), write: None }), execute(Op::GotoGt(0, 1, 7), &mut memory, 4));
+    assert_eq!(Ok(Executed { next: Some(5), write: None }), execute(Op::GotoEq(0, 1, 7), &mut memory, 4));
+    assert_eq!(Ok(Executed { next: Some(1), write: Some((1, 2)) }), execute(Op::StoreI(1, 9), &mut memory, 0));
+    assert_eq!(Err(Fault::Overflow { cell: 2 }), execute(Op::Inc(2), &mut memory, 0));
+    assert_eq!(Err(Fault::OutsideMemory { cell: 3, size: 3 }), execute(Op::GotoEq(0, 3, 7), &mut memory, 0));
+    assert_eq!([3, 9, u64::MAX], memory);
+    assert_eq!(Err(Fault::AssertFailed { cell: 0, expected: 0, actual: 3 }), execute(Op::Assert(0, 0), &mut memory, 0));
+}
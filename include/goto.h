@@ -0,0 +1,62 @@
+#ifndef GOTO_H
+#define GOTO_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdint.h>
+#include <stddef.h>
+
+// `goto_run` executed STOP.
+#define GOTO_STOPPED 0
+
+// `goto_run` reached its step limit, another call continues.
+#define GOTO_RUNNING 1
+
+#define GOTO_ERROR 2
+
+// A parsed program with the memory, program counter and steps of its run.
+typedef struct GotoMachine GotoMachine;
+
+// Parses the NUL-terminated UTF-8 source, returns null if it is invalid.
+//
+// # Safety
+// `source` must be null or a NUL-terminated string.
+GotoMachine *goto_parse(const char *source);
+
+// Starts the program from the beginning on the `len` cells at `input` and
+// runs it for at most `max_steps` steps. With a null `input` it continues
+// where the last call stopped. Returns `GOTO_STOPPED`, `GOTO_RUNNING` or
+// `GOTO_ERROR`, after an error the machine is at the failed instruction.
+//
+// # Safety
+// `machine` must come from `goto_parse`, `input` must be null or point to
+// `len` readable `uint64_t`s.
+int32_t goto_run(GotoMachine *machine, const uint64_t *input, size_t len, uint64_t max_steps);
+
+// The value of a cell, 0 for a cell outside of the memory.
+//
+// # Safety
+// `machine` must be null or come from `goto_parse`.
+uint64_t goto_get_cell(const GotoMachine *machine, size_t cell);
+
+// # Safety
+// `machine` must be null or come from `goto_parse`.
+size_t goto_memory_len(const GotoMachine *machine);
+
+// # Safety
+// `machine` must be null or come from `goto_parse`.
+size_t goto_pc(const GotoMachine *machine);
+
+// # Safety
+// `machine` must be null or come from `goto_parse`.
+uint64_t goto_steps(const GotoMachine *machine);
+
+// The message of the last failed call on this thread, valid until the next
+// call that fails.
+const char *goto_last_error(void);
+
+// # Safety
+// `machine` must be null or come from `goto_parse` and not be used after.
+void goto_free(GotoMachine *machine);
+
+#endif  /* GOTO_H */
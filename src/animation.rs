@@ -0,0 +1,97 @@
+use std::io::{self, BufRead, Write};
+use std::thread::sleep;
+use std::time::Duration;
+
+use goto::GotoProgramState;
+
+/// Parses delays like `300ms`, `2s` or `0.5s`, a plain number is milliseconds.
+pub fn parse_delay(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid delay {}, expected something like 300ms or 1.5s", text);
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(index) => text.split_at(index),
+        None => (text, "ms"),
+    };
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        _ => return Err(invalid()),
+    };
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+const BAR_WIDTH: usize = 50;
+
+/// One labeled horizontal bar per cell, scaled so the largest value fills `BAR_WIDTH`
+/// unless all values fit with one block per unit.
+pub fn memory_chart(memory: &[u64]) -> Vec<String> {
+    let max = memory.iter().cloned().max().unwrap_or(0);
+    let cell_width = memory.len().saturating_sub(1).to_string().len();
+    let value_width = max.to_string().len();
+    memory.iter().enumerate().map(|(cell, &value)| {
+        let length = if max as usize <= BAR_WIDTH {
+            value as usize
+        } else {
+            (value as f64 / max as f64 * BAR_WIDTH as f64).round() as usize
+        };
+        format!("[{:>cell_width$}] {:>value_width$} {}", cell, value, "\u{2588}".repeat(length),
+                cell_width = cell_width, value_width = value_width)
+    }).collect()
+}
+
+/// Shows a run one step at a time, redrawing the same lines in place.
+pub struct Animation {
+    pub delay: Duration,
+    pub paused: bool,
+    pub visualize: bool,
+    pub drawn_lines: usize,
+}
+
+impl Animation {
+    /// Draws the state before the next step, then waits for the delay or for Enter.
+    pub fn frame(&mut self, state: &GotoProgramState) -> io::Result<()> {
+        let instruction = match state.program.instructions.get(state.program_counter) {
+            Some(instruction) => instruction.to_string(),
+            None => "-".to_string(),
+        };
+        let status = format!("step {:>6}  pc {:>3}  {:<12}", state.steps, state.program_counter, instruction);
+        let lines = if self.visualize {
+            let mut lines = vec![status];
+            lines.extend(memory_chart(&state.memory));
+            lines
+        } else {
+            vec![format!("{} mem: {:?}", status, state.memory)]
+        };
+        let mut out = io::stdout();
+        if self.drawn_lines > 1 {
+            write!(out, "\x1b[{}A", self.drawn_lines - 1)?;
+        }
+        write!(out, "\r{}\x1b[K\x1b[J", lines.join("\x1b[K\n"))?;
+        self.drawn_lines = lines.len();
+        out.flush()?;
+        if self.paused {
+            io::stdin().lock().read_line(&mut String::new())?;
+            // Enter moved the cursor down, go back to the last line that was drawn
+            write!(out, "\x1b[A")?;
+        } else {
+            sleep(self.delay);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_parse_delay() {
+    assert_eq!(Ok(Duration::from_millis(300)), parse_delay("300ms"));
+    assert_eq!(Ok(Duration::from_millis(1500)), parse_delay("1.5s"));
+    assert_eq!(Ok(Duration::from_millis(40)), parse_delay("40"));
+    assert!(parse_delay("3min").is_err());
+    assert!(parse_delay("ms").is_err());
+}
+
+#[test]
+fn test_memory_chart() {
+    assert_eq!(vec!["[0] 3 \u{2588}\u{2588}\u{2588}", "[1] 0 ", "[2] 1 \u{2588}"], memory_chart(&[3, 0, 1]));
+    let chart = memory_chart(&[1000, 500]);
+    assert_eq!(("[0] 1000 ".len() + BAR_WIDTH * 3, "[1]  500 ".len() + BAR_WIDTH / 2 * 3), (chart[0].len(), chart[1].len()));
+}
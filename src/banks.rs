@@ -0,0 +1,78 @@
+//! Several independent memories in one, like the tapes of a multi-tape
+//! machine. Cell `c` of bank `b` is cell `c·banks + b` of the memory, so
+//! every bank can grow without running into the next one. Everything after
+//! the parser, including the translations, only sees the one memory.
+
+use crate::{Instruction, RegisterIndex};
+
+/// How many cells a memory can have at most, a `Vec<u64>` holds at most
+/// `isize::MAX` bytes.
+pub const MAX_CELLS: usize = isize::MAX as usize / 8;
+
+/// The cell of the memory that holds cell `cell` of the bank, an error if
+/// no memory can have it. Without banks every cell stays, like any cell
+/// outside of the memory it only fails when it is used.
+pub fn flat_cell(bank: usize, cell: RegisterIndex, banks: usize) -> Result<RegisterIndex, String> {
+    if banks == 1 {
+        return Ok(cell);
+    }
+    cell.checked_mul(banks).and_then(|cell| cell.checked_add(bank)).filter(|cell| *cell < MAX_CELLS)
+        .ok_or_else(|| format!("cell {} of bank {} is too large", cell, bank))
+}
+
+/// The instruction with its cells moved into the bank, jump targets stay.
+pub fn in_bank(instruction: Instruction, bank: usize, banks: usize) -> Result<Instruction, String> {
+    instruction.map_cells(|cell| flat_cell(bank, cell, banks))
+}
+
+/// The memory of every bank with a cell in the memory, taken apart. The
+/// banks after them are empty.
+pub fn split(memory: &[u64], banks: usize) -> Vec<Vec<u64>> {
+    (0..banks.min(memory.len())).map(|bank| memory.iter().skip(bank).step_by(banks).cloned().collect()).collect()
+}
+
+/// One memory of `banks` banks with the memories of some of them in it, the
+/// cells no bank has are 0. An error if the memory is too large.
+pub fn join(memories: &[(usize, Vec<u64>)], banks: usize) -> Result<Vec<u64>, String> {
+    let rows = memories.iter().map(|(_, memory)| memory.len()).max().unwrap_or(0);
+    let too_large = || format!("the memory of {} banks with {} cells each is too large", banks, rows);
+    let length = rows.checked_mul(banks).filter(|length| *length <= MAX_CELLS).ok_or_else(too_large)?;
+    let mut memory = vec![];
+    memory.try_reserve_exact(length).map_err(|_| too_large())?;
+    memory.resize(length, 0);
+    for (bank, bank_memory) in memories {
+        for (cell, &value) in bank_memory.iter().enumerate() {
+            memory[cell * banks + bank] = value;
+        }
+    }
+    Ok(memory)
+}
+
+#[test]
+fn test_banks() {
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nBANK 2 INC 1\nGOTO loop\nend: BANK 1 STOP".to_string()).unwrap();
+    assert_eq!(3, program.banks);
+    assert_eq!(vec![
+        Instruction::GotoZ { condition_cell: 0, goto_cell: 4 },
+        Instruction::Dec { cell: 0 },
+        Instruction::Inc { cell: 5 },
+        Instruction::Goto { cell: 0 },
+        Instruction::Stop,
+    ], program.instructions);
+    let memory = join(&[(0, vec![3]), (1, vec![7, 8])], 3).unwrap();
+    assert_eq!(vec![3, 7, 0, 0, 8, 0], memory);
+    let mut state = crate::GotoProgramState::new(&program, memory);
+    state.run().unwrap();
+    assert_eq!(vec![vec![0, 0], vec![7, 8], vec![0, 3]], split(&state.memory, 3));
+    assert!(crate::parse_program("BANK x INC 0".to_string()).is_err());
+    assert!(crate::parse_program("BANK 1".to_string()).is_err());
+    assert!(crate::parse_program(format!("BANK 1 INC {}", usize::MAX)).is_err());
+    assert!(crate::parse_program(format!("BANK {} INC 0", usize::MAX)).is_err());
+    assert!(crate::parse_program(format!("BANK {} INC 0", MAX_CELLS)).is_err());
+    assert_eq!(vec![Instruction::Inc { cell: usize::MAX }], crate::parse_program(format!("INC {}", usize::MAX)).unwrap().instructions);
+    let far = crate::parse_program("BANK 99999999999 INC 0\nSTOP".to_string()).unwrap();
+    assert_eq!(100_000_000_000, far.banks);
+    assert_eq!(vec![vec![5], vec![0]], split(&[5, 0], far.banks));
+    assert_eq!(Ok(vec![5]), join(&[(0, vec![5])], 1));
+    assert!(join(&[(0, vec![5, 6])], far.banks).is_err());
+}
@@ -0,0 +1,131 @@
+//! Runs one program over every input file in a directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::bytecode::Bytecode;
+use crate::footprint::Footprint;
+use crate::snapshot::{memory_summary, show_output_cells, Snapshot, Status};
+use crate::{read_input, GotoProgram, GotoProgramState, RegisterIndex};
+
+/// The files in the directory except for hidden ones, in order of their name.
+pub fn input_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))? {
+        let path = entry.map_err(|e| format!("{}: {}", dir.display(), e))?.path();
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if path.is_file() && !hidden {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Runs the program on the memory in the input file. An input that cannot
+/// be read or is too short for the cells of the program in `footprint` ends
+/// as an error before the first step.
+pub fn run_file(program: &GotoProgram, footprint: &Footprint, bytecode: &Bytecode, input: &Path, max_steps: u64) -> Snapshot {
+    let memory = fs::read_to_string(input).map_err(|e| e.to_string()).and_then(read_input)
+        .map(|memory| program.initial_memory(&memory))
+        .and_then(|memory| footprint.check_memory(memory.len()).map(|_| memory));
+    let memory = match memory {
+        Ok(memory) => memory,
+        Err(e) => return Snapshot { status: Status::Error(format!("invalid input: {}", e)), program_counter: 0, steps: 0, memory: vec![], stack: vec![] },
+    };
+    let mut state = GotoProgramState::new(program, memory);
+    let status = match bytecode.run(&mut state, max_steps) {
+        Ok(true) => Status::Stopped,
+        Ok(false) => Status::StepLimit,
+        Err(e) => Status::Error(e),
+    };
+    Snapshot::of(&state, status)
+}
+
+/// Applies `f` to every item on `jobs` threads, the results are in the order
+/// of the items however the threads are scheduled.
+pub fn map_parallel<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let jobs = jobs.clamp(1, items.len().max(1));
+    let f = &f;
+    let mut results: Vec<Option<R>> = thread::scope(|scope| {
+        let threads: Vec<_> = (0..jobs).map(|job| scope.spawn(move || {
+            items.iter().enumerate().skip(job).step_by(jobs).map(|(i, item)| (i, f(item))).collect::<Vec<_>>()
+        })).collect();
+        let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+        for thread in threads {
+            for (i, result) in thread.join().unwrap() {
+                results[i] = Some(result);
+            }
+        }
+        results
+    });
+    results.iter_mut().map(|result| result.take().unwrap()).collect()
+}
+
+/// Runs the program on every input file with `jobs` threads, each run on
+/// its own state.
+pub fn run_files(program: &GotoProgram, bytecode: &Bytecode, inputs: &[PathBuf], max_steps: u64, jobs: usize) -> Vec<Snapshot> {
+    let footprint = Footprint::of(program);
+    map_parallel(inputs, jobs, |input| run_file(program, &footprint, bytecode, input, max_steps))
+}
+
+/// A table with a row per run, with the output cells instead of the memory
+/// unless there are none.
+pub fn summary(runs: &[(String, Snapshot)], cells: &[(String, RegisterIndex)]) -> String {
+    let result = if cells.is_empty() { "memory" } else { "result" };
+    let mut rows = vec![("input".to_string(), "status".to_string(), "steps".to_string(), result.to_string())];
+    for (name, snapshot) in runs {
+        let status = match &snapshot.status {
+            Status::Error(_) => "error".to_string(),
+            status => status.to_string(),
+        };
+        let result = match &snapshot.status {
+            Status::Error(e) => e.clone(),
+            _ if cells.is_empty() => memory_summary(&snapshot.memory),
+            _ => show_output_cells(&snapshot.memory, cells),
+        };
+        rows.push((name.clone(), status, snapshot.steps.to_string(), result));
+    }
+    let name_width = rows.iter().map(|r| r.0.len()).max().unwrap();
+    let status_width = rows.iter().map(|r| r.1.len()).max().unwrap();
+    let steps_width = rows.iter().map(|r| r.2.len()).max().unwrap();
+    let mut table = String::new();
+    for (name, status, steps, result) in &rows {
+        table += &format!("{:name_width$}  {:status_width$}  {:>steps_width$}  {}\n", name, status, steps, result,
+                          name_width = name_width, status_width = status_width, steps_width = steps_width);
+    }
+    let stopped = runs.iter().filter(|(_, s)| s.status == Status::Stopped).count();
+    table + &format!("{} of {} runs stopped\n", stopped, runs.len())
+}
+
+#[test]
+fn test_batch() {
+    let dir = std::env::temp_dir().join(format!("goto-batch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "3 4").unwrap();
+    fs::write(dir.join("b.txt"), "0 x").unwrap();
+    fs::write(dir.join("c.txt"), "3").unwrap();
+    fs::write(dir.join(".hidden"), "1").unwrap();
+    let files = input_files(&dir).unwrap();
+    assert_eq!(vec![dir.join("a.txt"), dir.join("b.txt"), dir.join("c.txt")], files);
+
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let bytecode = Bytecode::compile(&program).unwrap();
+    let snapshots = run_files(&program, &bytecode, &files, 1000, 4);
+    assert_eq!(snapshots, run_files(&program, &bytecode, &files, 1000, 1));
+    let runs: Vec<_> = files.iter().map(|f| f.file_name().unwrap().to_string_lossy().to_string()).zip(snapshots).collect();
+    assert_eq!(vec![0, 7], runs[0].1.memory);
+    assert_eq!(Status::StepLimit, run_file(&program, &Footprint::of(&program), &bytecode, &files[0], 5).status);
+    assert_eq!("input  status   steps  memory
+a.txt  stopped     14  2 cells, 1 nonzero, max 7 in [1]: [0, 7]
+b.txt  error        0  invalid input: Number parsing error: invalid digit found in string
+c.txt  error        0  invalid input: the program uses cell 1 and needs 2 cells, the memory has 1
+1 of 3 runs stopped
+", summary(&runs, &[]));
+    assert!(summary(&runs, &[("1".to_string(), 1)]).starts_with("input  status   steps  result\na.txt  stopped     14  1 = 7\n"));
+    fs::remove_dir_all(&dir).unwrap();
+
+    let squares = map_parallel(&(0..100u64).collect::<Vec<_>>(), 3, |n| n * n);
+    assert_eq!((0..100u64).map(|n| n * n).collect::<Vec<_>>(), squares);
+}
@@ -0,0 +1,75 @@
+//! Repeated timed runs for measuring the interpreter, bytecode VM and JIT.
+
+use std::time::{Duration, Instant};
+
+use goto::{GotoProgram, GotoProgramState};
+
+use crate::SliceRunner;
+
+/// How long to keep benchmarking.
+pub enum Budget {
+    Runs(usize),
+    Duration(Duration),
+}
+
+pub struct Report {
+    pub steps: u64,
+    pub times: Vec<Duration>,
+}
+
+fn median(times: &[Duration]) -> Duration {
+    let mut sorted = times.to_vec();
+    sorted.sort();
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2
+    } else {
+        sorted[middle]
+    }
+}
+
+impl Report {
+    pub fn summary(&self) -> String {
+        let median = median(&self.times);
+        let (min, max) = (self.times.iter().min().unwrap(), self.times.iter().max().unwrap());
+        let rate = self.steps as f64 / median.as_secs_f64().max(1e-9);
+        format!("{} runs of {} steps\nmedian {:.3?} (min {:.3?}, max {:.3?})\n{:.0} steps/s",
+                self.times.len(), self.steps, median, min, max, rate)
+    }
+}
+
+/// Runs the program on `memory` until it stops, as often as the budget allows
+/// and at least once. `engine` is "interpreter", "bytecode" or "jit".
+pub fn run(program: &GotoProgram, memory: &[u64], engine: &str, budget: Budget) -> Result<Report, String> {
+    let mut first = GotoProgramState::new(program, memory.to_vec());
+    let mut run_slice: SliceRunner = match engine {
+        "interpreter" => Box::new(|state, max_steps| state.run_with_limit(max_steps).map_err(String::from)),
+        "jit" => crate::run_slice_compiled(&mut first),
+        _ => crate::run_slice_bytecode(&mut first),
+    };
+    let started = Instant::now();
+    let mut report = Report { steps: 0, times: vec![] };
+    loop {
+        let mut state = GotoProgramState::new(program, memory.to_vec());
+        let run_started = Instant::now();
+        if !run_slice(&mut state, u64::MAX)? {
+            return Err("the program did not stop".to_string());
+        }
+        report.times.push(run_started.elapsed());
+        report.steps = state.steps;
+        let done = match budget {
+            Budget::Runs(runs) => report.times.len() >= runs,
+            Budget::Duration(duration) => started.elapsed() >= duration,
+        };
+        if done {
+            return Ok(report);
+        }
+    }
+}
+
+#[test]
+fn test_median() {
+    let ms = Duration::from_millis;
+    assert_eq!(ms(2), median(&[ms(3), ms(1), ms(2)]));
+    assert_eq!(ms(3), median(&[ms(4), ms(1), ms(2), ms(9)]));
+}
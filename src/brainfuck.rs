@@ -0,0 +1,147 @@
+//! Translation between Brainfuck and goto programs.
+//!
+//! Goto instructions name their cells directly, so only Brainfuck programs
+//! whose pointer position is known at every command can be imported: every
+//! loop has to end on the cell it started on and the pointer must not move
+//! left of cell 0. `.` and `,` have no counterpart. In the other direction
+//! only structured programs map, made of INC, DEC, `l: GOTOZ c e ... GOTO l`
+//! loops where `e` is the instruction after the GOTO, and a final STOP.
+//!
+//! Cells keep goto semantics in both directions: DEC on 0 is an error instead
+//! of wrapping around.
+
+use std::fmt::Write;
+
+use crate::{GotoProgram, Instruction, RegisterIndex};
+
+/// Translates Brainfuck into goto source, ignoring characters that are not commands.
+pub fn to_goto(brainfuck: &str) -> Result<String, String> {
+    let mut lines = vec![];
+    let mut pointer: i64 = 0;
+    let mut highest = 0;
+    let mut loops = vec![];
+    let mut loop_count = 0;
+    for (position, command) in brainfuck.chars().enumerate() {
+        let cell = || -> Result<i64, String> {
+            if pointer < 0 {
+                Err(format!("the pointer moves left of cell 0 at position {}", position))
+            } else {
+                Ok(pointer)
+            }
+        };
+        match command {
+            '>' => pointer += 1,
+            '<' => pointer -= 1,
+            '+' => lines.push(format!("    INC {}", cell()?)),
+            '-' => lines.push(format!("    DEC {}", cell()?)),
+            '[' => {
+                lines.push(format!("loop{}: GOTOZ {} end{}", loop_count, cell()?, loop_count));
+                loops.push((loop_count, pointer, position));
+                loop_count += 1;
+            }
+            ']' => {
+                let (id, start, start_position) = loops.pop()
+                    .ok_or_else(|| format!("unmatched ] at position {}", position))?;
+                if start != pointer {
+                    return Err(format!("the loop at position {} does not end on the cell it started on", start_position));
+                }
+                lines.push(format!("    GOTO loop{}", id));
+                lines.push(format!("end{}:", id));
+            }
+            '.' | ',' => return Err(format!("{} at position {} has no goto counterpart", command, position)),
+            _ => continue,
+        }
+        highest = highest.max(pointer);
+    }
+    if let Some((_, _, position)) = loops.pop() {
+        return Err(format!("unmatched [ at position {}", position));
+    }
+    lines.push("    STOP".to_string());
+    let mut goto = format!("; translated from Brainfuck, needs a memory of {} cells\n", highest + 1);
+    for line in lines {
+        writeln!(goto, "{}", line).unwrap();
+    }
+    Ok(goto)
+}
+
+struct Exporter<'a> {
+    instructions: &'a [Instruction],
+    output: String,
+    pointer: RegisterIndex,
+}
+
+impl<'a> Exporter<'a> {
+    fn move_to(&mut self, cell: RegisterIndex) {
+        let (symbol, distance) = if cell > self.pointer { ('>', cell - self.pointer) } else { ('<', self.pointer - cell) };
+        self.output.extend(std::iter::repeat_n(symbol, distance));
+        self.pointer = cell;
+    }
+
+    /// Exports the instructions in `start..end`, which must not jump outside of it.
+    fn block(&mut self, start: usize, end: usize) -> Result<(), String> {
+        let mut pc = start;
+        while pc < end {
+            match self.instructions[pc] {
+                Instruction::Inc { cell } => {
+                    self.move_to(cell);
+                    self.output.push('+');
+                }
+                Instruction::Dec { cell } => {
+                    self.move_to(cell);
+                    self.output.push('-');
+                }
+                Instruction::GotoZ { condition_cell, goto_cell }
+                    if goto_cell > pc + 1 && goto_cell <= end
+                        && self.instructions[goto_cell - 1] == Instruction::Goto { cell: pc } =>
+                {
+                    self.move_to(condition_cell);
+                    self.output.push('[');
+                    self.block(pc + 1, goto_cell - 1)?;
+                    self.move_to(condition_cell);
+                    self.output.push(']');
+                    pc = goto_cell;
+                    continue;
+                }
+                Instruction::Stop if pc + 1 == self.instructions.len() => {}
+                ref instruction => {
+                    return Err(format!("instruction {} ({}) does not map to a Brainfuck loop", pc, instruction));
+                }
+            }
+            pc += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Translates a structured goto program into Brainfuck.
+pub fn from_goto(program: &GotoProgram) -> Result<String, String> {
+    let mut exporter = Exporter { instructions: &program.instructions, output: String::new(), pointer: 0 };
+    exporter.block(0, program.instructions.len())?;
+    Ok(exporter.output)
+}
+
+#[test]
+fn test_round_trip() {
+    // move cell 0 to cell 2
+    let brainfuck = "[->>+<<] this is a comment";
+    let goto = to_goto(brainfuck).unwrap();
+    assert_eq!("; translated from Brainfuck, needs a memory of 3 cells
+loop0: GOTOZ 0 end0
+    DEC 0
+    INC 2
+    GOTO loop0
+end0:
+    STOP
+", goto);
+    let program = crate::parse_program(goto).unwrap();
+    let mut state = crate::GotoProgramState::new(&program, vec![4, 0, 1]);
+    state.run_with_limit(100).unwrap();
+    assert_eq!(vec![0, 0, 5], state.memory);
+    assert_eq!(Ok("[->>+<<]".to_string()), from_goto(&program));
+
+    assert!(to_goto("[>]").is_err());
+    assert!(to_goto("<+").is_err());
+    assert!(to_goto("+.").is_err());
+    assert!(to_goto("[[]").is_err());
+    assert!(from_goto(&crate::parse_program("a: INC 0\nGOTO a".to_string()).unwrap()).is_err());
+}
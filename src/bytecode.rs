@@ -0,0 +1,357 @@
+use std::convert::TryFrom;
+
+use crate::{GotoProgram, GotoProgramState, Instruction};
+
+/// A compact form of an instruction with operands that fit in 32 bits.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Op {
+    Stop,
+    Inc(u32),
+    Dec(u32),
+    Goto(u32),
+    GotoZ(u32, u32),
+    GotoNz(u32, u32),
+    GotoEq(u32, u32, u32),
+    GotoGt(u32, u32, u32),
+    Assert(u32, u64),
+    Print(u32),
+    Read(u32),
+    OutC(u32),
+    Rand(u32, u64),
+    Push(u32),
+    Pop(u32),
+    StoreI(u32, u64),
+    Nop,
+    /// a GOTOZ that tests a counting loop, with the index of the loop
+    CountingLoop(u32),
+}
+
+/// A cycle of INC and DEC instructions with a single GOTOZ that exits it,
+/// where every round decrements the tested cell by one. Each round starts at
+/// the GOTOZ, so the loop runs exactly as many rounds as the cell holds.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct CountingLoop {
+    condition: u32,
+    exit: u32,
+    steps_per_round: u64,
+    cells: Vec<CellChange>,
+}
+
+/// How a round of a counting loop changes a cell, the lowest and highest
+/// offsets from its value at the start of the round that it passes through.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct CellChange {
+    cell: u32,
+    delta: i64,
+    lowest: i64,
+    highest: i64,
+}
+
+impl CountingLoop {
+    /// The counting loop tested by the GOTOZ at `test`, if it is one.
+    fn detect(ops: &[Op], test: usize) -> Option<CountingLoop> {
+        let (condition, exit) = match ops[test] {
+            Op::GotoZ(condition, exit) => (condition, exit),
+            _ => return None,
+        };
+        let is_arithmetic = |op: &Op| matches!(op, Op::Inc(_) | Op::Dec(_));
+        let back = test + 1 + ops[test + 1..].iter().position(|op| !is_arithmetic(op))?;
+        let top = match ops[back] {
+            Op::Goto(top) if top as usize <= test => top as usize,
+            _ => return None,
+        };
+        if !ops[top..test].iter().all(is_arithmetic) {
+            return None;
+        }
+        let mut cells: Vec<CellChange> = vec![];
+        for op in ops[test + 1..back].iter().chain(&ops[top..test]) {
+            let (cell, change) = match *op {
+                Op::Inc(cell) => (cell, 1),
+                Op::Dec(cell) => (cell, -1),
+                _ => unreachable!(),
+            };
+            let index = match cells.iter().position(|c| c.cell == cell) {
+                Some(index) => index,
+                None => {
+                    cells.push(CellChange { cell, delta: 0, lowest: 0, highest: 0 });
+                    cells.len() - 1
+                }
+            };
+            let c = &mut cells[index];
+            c.delta += change;
+            c.lowest = c.lowest.min(c.delta);
+            c.highest = c.highest.max(c.delta);
+        }
+        if cells.iter().find(|c| c.cell == condition).map(|c| c.delta) != Some(-1) {
+            return None;
+        }
+        Some(CountingLoop { condition, exit, steps_per_round: (back + 1 - top) as u64, cells })
+    }
+
+    /// Runs `rounds` rounds at once if none of them would leave the memory,
+    /// overflow or decrement a cell that is 0.
+    fn run_rounds(&self, memory: &mut [u64], rounds: u64) -> bool {
+        let rounds = i128::from(rounds);
+        let fits = |c: &CellChange| match memory.get(c.cell as usize) {
+            Some(&value) => {
+                let value = i128::from(value);
+                // the lowest and highest values are reached in the first or the last round
+                let last = value + (rounds - 1) * i128::from(c.delta);
+                value.min(last) + i128::from(c.lowest) >= 0
+                    && value.max(last) + i128::from(c.highest) <= i128::from(u64::MAX)
+            }
+            None => false,
+        };
+        if !self.cells.iter().all(fits) {
+            return false;
+        }
+        for c in &self.cells {
+            let value = &mut memory[c.cell as usize];
+            *value = (i128::from(*value) + rounds * i128::from(c.delta)) as u64;
+        }
+        true
+    }
+}
+
+/// A program compiled for running without tracing, hooks or per-step allocation.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Bytecode {
+    ops: Vec<Op>,
+    loops: Vec<CountingLoop>,
+}
+
+fn operand(value: usize) -> Result<u32, String> {
+    u32::try_from(value).map_err(|_| format!("operand {} is too large for bytecode", value))
+}
+
+impl Bytecode {
+    pub fn compile(program: &GotoProgram) -> Result<Bytecode, String> {
+        let ops = program.instructions.iter().map(|instruction| Ok(match *instruction {
+            Instruction::Stop => Op::Stop,
+            Instruction::Inc { cell } => Op::Inc(operand(cell)?),
+            Instruction::Dec { cell } => Op::Dec(operand(cell)?),
+            Instruction::Goto { cell } => Op::Goto(operand(cell)?),
+            Instruction::GotoZ { condition_cell, goto_cell } => Op::GotoZ(operand(condition_cell)?, operand(goto_cell)?),
+            Instruction::GotoNz { condition_cell, goto_cell } => Op::GotoNz(operand(condition_cell)?, operand(goto_cell)?),
+            Instruction::GotoEq { left, right, goto_cell } => Op::GotoEq(operand(left)?, operand(right)?, operand(goto_cell)?),
+            Instruction::GotoGt { left, right, goto_cell } => Op::GotoGt(operand(left)?, operand(right)?, operand(goto_cell)?),
+            Instruction::Assert { cell, value } => Op::Assert(operand(cell)?, value),
+            Instruction::Print { cell } => Op::Print(operand(cell)?),
+            Instruction::Read { cell } => Op::Read(operand(cell)?),
+            Instruction::OutC { cell } => Op::OutC(operand(cell)?),
+            Instruction::Rand { cell, max } => Op::Rand(operand(cell)?, max),
+            Instruction::Push { cell } => Op::Push(operand(cell)?),
+            Instruction::Pop { cell } => Op::Pop(operand(cell)?),
+            Instruction::StoreI { cell, value } => Op::StoreI(operand(cell)?, value),
+            Instruction::Nop => Op::Nop,
+        })).collect::<Result<Vec<_>, String>>()?;
+        let mut loops = vec![];
+        let mut accelerated = ops.clone();
+        for (test, op) in accelerated.iter_mut().enumerate() {
+            if let Some(counting_loop) = CountingLoop::detect(&ops, test) {
+                *op = Op::CountingLoop(operand(loops.len())?);
+                loops.push(counting_loop);
+            }
+        }
+        Ok(Bytecode { ops: accelerated, loops })
+    }
+
+    /// Behaves like `GotoProgramState::run_with_limit` but much faster, the state
+    /// must belong to the program this was compiled from. Counting loops run
+    /// all their rounds in one go with the same steps and memory as one at a time.
+    pub fn run(&self, state: &mut GotoProgramState, max_steps: u64) -> Result<bool, String> {
+        if state.code_base.is_some() {
+            return Err("the bytecode VM does not run programs in von Neumann mode".to_string());
+        }
+        let mut pc = state.program_counter;
+        let mut steps = state.steps;
+        let memory = &mut state.memory;
+        let size = memory.len();
+        let outside = |cell: u32| format!("cell {} is outside of the memory of size {}", cell, size);
+        let result = loop {
+            if steps >= max_steps {
+                break Ok(false);
+            }
+            let op = match self.ops.get(pc) {
+                Some(op) => *op,
+                None => break Err(format!("program counter {} is outside of the program", pc)),
+            };
+            match op {
+                Op::Stop => {
+                    steps += 1;
+                    break Ok(true);
+                }
+                Op::Inc(cell) => match memory.get_mut(cell as usize) {
+                    Some(value) if *value < u64::MAX => {
+                        *value += 1;
+                        pc += 1;
+                    }
+                    Some(_) => break Err(format!("INC {} overflows", cell)),
+                    None => break Err(outside(cell)),
+                },
+                Op::Dec(cell) => match memory.get_mut(cell as usize) {
+                    Some(value) if *value > 0 => {
+                        *value -= 1;
+                        pc += 1;
+                    }
+                    Some(_) => break Err(format!("DEC {} on a cell that is 0", cell)),
+                    None => break Err(outside(cell)),
+                },
+                Op::Goto(target) => pc = target as usize,
+                Op::GotoZ(cell, target) => match memory.get(cell as usize) {
+                    Some(0) => pc = target as usize,
+                    Some(_) => pc += 1,
+                    None => break Err(outside(cell)),
+                },
+                Op::GotoNz(cell, target) => match memory.get(cell as usize) {
+                    Some(0) => pc += 1,
+                    Some(_) => pc = target as usize,
+                    None => break Err(outside(cell)),
+                },
+                Op::GotoEq(left, right, target) | Op::GotoGt(left, right, target) => match (memory.get(left as usize), memory.get(right as usize)) {
+                    (Some(a), Some(b)) => {
+                        let jumps = if let Op::GotoEq(..) = op { a == b } else { a > b };
+                        pc = if jumps { target as usize } else { pc + 1 };
+                    }
+                    (None, _) => break Err(outside(left)),
+                    (_, None) => break Err(outside(right)),
+                },
+                Op::Assert(cell, value) => match memory.get(cell as usize) {
+                    Some(&actual) if actual == value => pc += 1,
+                    Some(&actual) => {
+                        let instruction = Instruction::Assert { cell: cell as usize, value };
+                        break Err(format!("{} failed, cell {} is {}", instruction, cell, actual));
+                    }
+                    None => break Err(outside(cell)),
+                },
+                Op::Print(cell) => match memory.get(cell as usize) {
+                    Some(&value) => match state.console.print(value) {
+                        Ok(()) => pc += 1,
+                        Err(e) => break Err(e),
+                    },
+                    None => break Err(outside(cell)),
+                },
+                Op::OutC(cell) => match memory.get(cell as usize) {
+                    Some(&value) => match state.console.print_char(cell as usize, value) {
+                        Ok(()) => pc += 1,
+                        Err(e) => break Err(e),
+                    },
+                    None => break Err(outside(cell)),
+                },
+                Op::Rand(cell, _) if cell as usize >= size => break Err(outside(cell)),
+                Op::Rand(cell, 0) => break Err(format!("{} has no number below 0", Instruction::Rand { cell: cell as usize, max: 0 })),
+                Op::Rand(cell, max) => {
+                    memory[cell as usize] = state.random.below(max);
+                    pc += 1;
+                }
+                Op::Push(cell) => match memory.get(cell as usize) {
+                    Some(&value) => {
+                        state.stack.push(value);
+                        pc += 1;
+                    }
+                    None => break Err(outside(cell)),
+                },
+                Op::Pop(cell) if cell as usize >= size => break Err(outside(cell)),
+                Op::Pop(cell) => match state.stack.pop() {
+                    Some(value) => {
+                        memory[cell as usize] = value;
+                        pc += 1;
+                    }
+                    None => break Err(format!("POP {} on an empty stack", cell)),
+                },
+                Op::StoreI(cell, value) => match memory.get_mut(cell as usize) {
+                    Some(old) => {
+                        *old = value;
+                        pc += 1;
+                    }
+                    None => break Err(outside(cell)),
+                },
+                Op::Nop => pc += 1,
+                Op::Read(cell) if cell as usize >= size => break Err(outside(cell)),
+                Op::Read(cell) => match state.console.read(cell as usize) {
+                    Ok(value) => {
+                        memory[cell as usize] = value;
+                        pc += 1;
+                    }
+                    Err(e) => break Err(e),
+                },
+                Op::CountingLoop(index) => {
+                    let counting_loop = &self.loops[index as usize];
+                    match memory.get(counting_loop.condition as usize) {
+                        Some(0) => pc = counting_loop.exit as usize,
+                        Some(&count) => {
+                            let rounds = count.min((max_steps - steps) / counting_loop.steps_per_round);
+                            if rounds > 0 && counting_loop.run_rounds(memory, rounds) {
+                                steps += rounds * counting_loop.steps_per_round;
+                                continue;
+                            }
+                            pc += 1;
+                        }
+                        None => break Err(outside(counting_loop.condition)),
+                    }
+                }
+            }
+            steps += 1;
+        };
+        state.program_counter = pc;
+        state.steps = steps;
+        result
+    }
+}
+
+#[test]
+fn test_bytecode_matches_interpreter() {
+    let program = crate::parse_program("loop: GOTOZ 0 end
+    DEC 0
+    INC 1
+    GOTO loop
+    end: DEC 2
+    ASSERT 1 7
+    STOP".to_string()).unwrap();
+    let bytecode = Bytecode::compile(&program).unwrap();
+    for (memory, max_steps) in [(vec![5, 2, 1], 1000), (vec![5, 2, 1], 7), (vec![5, 2, 0], 1000), (vec![5], 1000), (vec![5, 1, 1], 1000)] {
+        let mut interpreted = GotoProgramState::new(&program, memory.clone());
+        let mut compiled = GotoProgramState::new(&program, memory);
+        assert_eq!(interpreted.run_with_limit(max_steps).map_err(String::from), bytecode.run(&mut compiled, max_steps));
+        assert_eq!((interpreted.program_counter, interpreted.steps, interpreted.memory),
+                   (compiled.program_counter, compiled.steps, compiled.memory));
+    }
+
+    let echo = crate::parse_program("READ 0\nPRINT 0\nREAD 1\nSTOP".to_string()).unwrap();
+    let bytecode = Bytecode::compile(&echo).unwrap();
+    let console = crate::console::Buffered { input: vec![4].into(), ..Default::default() };
+    let mut interpreted = GotoProgramState::new(&echo, vec![0, 0]);
+    let mut compiled = GotoProgramState::new(&echo, vec![0, 0]);
+    interpreted.console = Box::new(console.clone());
+    compiled.console = Box::new(console);
+    assert_eq!(interpreted.run_with_limit(10).map_err(String::from), bytecode.run(&mut compiled, 10));
+    assert_eq!((2, format!("{:?}", interpreted.console)), (compiled.program_counter, format!("{:?}", compiled.console)));
+}
+
+#[test]
+fn test_counting_loops_match_interpreter() {
+    // a loop tested at the top and one tested in the middle that also moves cell 1 to cell 2
+    let program = crate::parse_program("loop: GOTOZ 0 rotated
+    DEC 0
+    INC 1
+    INC 1
+    GOTO loop
+    rotated: DEC 1
+    INC 2
+    GOTOZ 3 end
+    DEC 3
+    GOTO rotated
+    end: STOP".to_string()).unwrap();
+    let bytecode = Bytecode::compile(&program).unwrap();
+    assert_eq!(2, bytecode.loops.len());
+    let memories = [vec![1000, 0, 0, 2001], vec![1000, 0, 0, 5000], vec![3, u64::MAX - 5, 0, 5], vec![3, 0, u64::MAX - 8, 7]];
+    for memory in memories.iter() {
+        for &max_steps in [5, 4003, 10_000, 100_000].iter() {
+            let mut interpreted = GotoProgramState::new(&program, memory.clone());
+            let mut compiled = GotoProgramState::new(&program, memory.clone());
+            assert_eq!(interpreted.run_with_limit(max_steps).map_err(String::from), bytecode.run(&mut compiled, max_steps));
+            assert_eq!((interpreted.program_counter, interpreted.steps, &interpreted.memory),
+                       (compiled.program_counter, compiled.steps, &compiled.memory));
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs::{create_dir_all, read_dir, remove_file, rename, write};
+use std::path::{Path, PathBuf};
+
+use crate::snapshot::{Snapshot, Status};
+use crate::GotoProgramState;
+
+const PREFIX: &str = "checkpoint-";
+const EXTENSION: &str = ".json";
+
+fn checkpoint_step(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_prefix(PREFIX)?.strip_suffix(EXTENSION)?.parse().ok()
+}
+
+/// The checkpoints in `dir` ordered from oldest to newest.
+pub fn checkpoints(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = read_dir(dir).map_err(|e| format!("cannot read {}: {}", dir.display(), e))?;
+    let mut paths: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| checkpoint_step(&entry.path()).map(|step| (step, entry.path())))
+        .collect();
+    paths.sort();
+    Ok(paths.into_iter().map(|(_, path)| path).collect())
+}
+
+pub fn latest(dir: &Path) -> Result<PathBuf, String> {
+    checkpoints(dir)?.pop().ok_or_else(|| format!("no checkpoints in {}", dir.display()))
+}
+
+/// Writes a snapshot every `every` steps into `dir`, keeping the newest `keep` of them.
+pub struct Checkpointer {
+    dir: PathBuf,
+    every: u64,
+    keep: usize,
+}
+
+impl Checkpointer {
+    pub fn new(dir: PathBuf, every: u64, keep: usize) -> Result<Checkpointer, String> {
+        if every == 0 {
+            return Err("checkpoints need an interval of at least one step".to_string());
+        }
+        create_dir_all(&dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+        Ok(Checkpointer { dir, every, keep: keep.max(1) })
+    }
+
+    /// Call before every step, returns the path of a checkpoint written for this step.
+    pub fn observe(&mut self, state: &GotoProgramState) -> Result<Option<PathBuf>, String> {
+        if state.steps == 0 || !state.steps.is_multiple_of(self.every) {
+            return Ok(None);
+        }
+        let path = self.dir.join(format!("{}{:020}{}", PREFIX, state.steps, EXTENSION));
+        // write next to the target and rename so a crash never leaves half a checkpoint
+        let partial = path.with_extension("partial");
+        let json = Snapshot::of(state, Status::Running).to_json().to_string() + "\n";
+        write(&partial, json).map_err(|e| format!("cannot write {}: {}", partial.display(), e))?;
+        rename(&partial, &path).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+        let existing = checkpoints(&self.dir)?;
+        for old in &existing[..existing.len().saturating_sub(self.keep)] {
+            remove_file(old).map_err(|e| format!("cannot remove {}: {}", old.display(), e))?;
+        }
+        Ok(Some(path))
+    }
+}
+
+#[test]
+fn test_checkpointer() {
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let dir = std::env::temp_dir().join(format!("goto-checkpoints-{}", std::process::id()));
+    let mut checkpointer = Checkpointer::new(dir.clone(), 5, 2).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![4, 0]);
+    let mut written = 0;
+    loop {
+        written += checkpointer.observe(&state).unwrap().iter().count();
+        if state.step().unwrap() {
+            break;
+        }
+    }
+    assert_eq!(3, written);
+    let names: Vec<_> = checkpoints(&dir).unwrap().iter().map(|p| checkpoint_step(p).unwrap()).collect();
+    assert_eq!(vec![10, 15], names);
+    let snapshot = Snapshot::parse(&std::fs::read_to_string(latest(&dir).unwrap()).unwrap()).unwrap();
+    assert_eq!((15, vec![0, 4]), (snapshot.steps, snapshot.memory));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,66 @@
+use std::env;
+
+/// Wraps parts of the output in ANSI colors, or leaves them alone when colors are off.
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    pub enabled: bool,
+}
+
+impl Palette {
+    /// `choice` is one of `auto`, `always` and `never`, `auto` colors only a terminal
+    /// and honors `NO_COLOR`.
+    pub fn new(choice: &str, stream: atty::Stream) -> Palette {
+        let enabled = match choice {
+            "always" => true,
+            "never" => false,
+            _ => env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && atty::is(stream),
+        };
+        Palette { enabled }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn instruction(&self, text: &str) -> String {
+        self.paint("1;36", text)
+    }
+
+    pub fn changed(&self, text: &str) -> String {
+        self.paint("1;33", text)
+    }
+
+    pub fn error(&self, text: &str) -> String {
+        self.paint("1;31", text)
+    }
+
+    pub fn warning(&self, text: &str) -> String {
+        self.paint("35", text)
+    }
+
+    /// Formats a memory like `{:?}` does, with `changed` highlighted.
+    pub fn memory(&self, memory: &[u64], changed: Option<usize>) -> String {
+        let cells: Vec<_> = memory.iter().enumerate()
+            .map(|(cell, value)| if Some(cell) == changed {
+                self.changed(&value.to_string())
+            } else {
+                value.to_string()
+            })
+            .collect();
+        format!("[{}]", cells.join(", "))
+    }
+}
+
+#[test]
+fn test_palette() {
+    let plain = Palette { enabled: false };
+    assert_eq!(format!("{:?}", [1, 2, 3]), plain.memory(&[1, 2, 3], Some(1)));
+    let colored = Palette { enabled: true };
+    assert_eq!("[1, \x1b[1;33m2\x1b[0m]", colored.memory(&[1, 2], Some(1)));
+    assert!(!Palette::new("never", atty::Stream::Stdout).enabled);
+    assert!(Palette::new("always", atty::Stream::Stdout).enabled);
+}
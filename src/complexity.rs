@@ -0,0 +1,179 @@
+//! Measures how the number of steps grows with the value of an input cell
+//! and fits simple growth models to the measurements by least squares.
+
+use std::fmt;
+
+use crate::bytecode::Bytecode;
+use crate::{ranges, GotoProgram, GotoProgramState};
+
+/// The steps of a run with the cell set to `n`, or why there are none.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Measurement {
+    pub n: u64,
+    pub steps: Result<u64, String>,
+}
+
+/// Runs the program with `cell` of `input` set to every value in `values`,
+/// the memory is extended to the cells the program uses.
+pub fn measure(program: &GotoProgram, input: &[u64], cell: usize, values: impl Iterator<Item = u64>, within: u64) -> Result<Vec<Measurement>, String> {
+    let bytecode = Bytecode::compile(program)?;
+    let used = ranges::used_cells(program).last().map(|cell| cell + 1).unwrap_or(0);
+    let mut memory = input.to_vec();
+    memory.resize(memory.len().max(used).max(cell + 1), 0);
+    Ok(values.map(|n| {
+        memory[cell] = n;
+        let mut state = GotoProgramState::new(program, memory.clone());
+        let steps = match bytecode.run(&mut state, within) {
+            Ok(true) => Ok(state.steps),
+            Ok(false) => Err(format!("still running after {} steps", within)),
+            Err(e) => Err(e),
+        };
+        Measurement { n, steps }
+    }).collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    /// a·n + b
+    Linear { a: f64, b: f64 },
+    /// a·n² + b·n + c
+    Quadratic { a: f64, b: f64, c: f64 },
+    /// a·bⁿ
+    Exponential { a: f64, b: f64 },
+}
+
+impl Model {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Model::Linear { .. } => "linear",
+            Model::Quadratic { .. } => "quadratic",
+            Model::Exponential { .. } => "exponential",
+        }
+    }
+
+    pub fn predict(&self, n: f64) -> f64 {
+        match *self {
+            Model::Linear { a, b } => a * n + b,
+            Model::Quadratic { a, b, c } => a * n * n + b * n + c,
+            Model::Exponential { a, b } => a * b.powf(n),
+        }
+    }
+}
+
+/// A term after the first one, with its sign as the operator.
+fn term(value: f64) -> String {
+    if value < 0.0 { format!(" - {:.3}", -value) } else { format!(" + {:.3}", value) }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Model::Linear { a, b } => write!(f, "{:.3}·n{}", a, term(b)),
+            Model::Quadratic { a, b, c } => write!(f, "{:.3}·n²{}·n{}", a, term(b), term(c)),
+            Model::Exponential { a, b } => write!(f, "{:.3}·{:.3}ⁿ", a, b),
+        }
+    }
+}
+
+/// Solves the least squares problem for `y ≈ Σ coefficients[i] · features(x)[i]`
+/// through the normal equations.
+fn least_squares(points: &[(f64, f64)], features: impl Fn(f64) -> Vec<f64>) -> Option<Vec<f64>> {
+    let size = features(0.0).len();
+    let mut matrix = vec![vec![0.0; size + 1]; size];
+    for &(x, y) in points {
+        let row = features(x);
+        for i in 0..size {
+            for j in 0..size {
+                matrix[i][j] += row[i] * row[j];
+            }
+            matrix[i][size] += row[i] * y;
+        }
+    }
+    for column in 0..size {
+        let pivot = (column..size).max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        let pivot_row = matrix[column].clone();
+        for (_, row) in matrix.iter_mut().enumerate().filter(|(i, _)| *i != column) {
+            let factor = row[column] / pivot_row[column];
+            for (value, pivot_value) in row.iter_mut().zip(&pivot_row).skip(column) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+    Some((0..size).map(|i| matrix[i][size] / matrix[i][i]).collect())
+}
+
+/// How much of the variance of the steps the model explains, 1 for a perfect fit.
+pub fn r_squared(model: &Model, points: &[(f64, f64)]) -> f64 {
+    let mean = points.iter().map(|p| p.1).sum::<f64>() / points.len() as f64;
+    let total: f64 = points.iter().map(|p| (p.1 - mean).powi(2)).sum();
+    let residual: f64 = points.iter().map(|p| (p.1 - model.predict(p.0)).powi(2)).sum();
+    if total == 0.0 {
+        if residual < 1e-9 { 1.0 } else { 0.0 }
+    } else {
+        1.0 - residual / total
+    }
+}
+
+/// The models that can be fitted to the finished runs, with their R².
+pub fn fit(measurements: &[Measurement]) -> Vec<(Model, f64)> {
+    let points: Vec<_> = measurements.iter()
+        .filter_map(|m| m.steps.as_ref().ok().map(|&steps| (m.n as f64, steps as f64)))
+        .collect();
+    let mut models = vec![];
+    if let Some(c) = least_squares(&points, |n| vec![n, 1.0]) {
+        models.push(Model::Linear { a: c[0], b: c[1] });
+    }
+    if let Some(c) = least_squares(&points, |n| vec![n * n, n, 1.0]) {
+        models.push(Model::Quadratic { a: c[0], b: c[1], c: c[2] });
+    }
+    let logarithms: Vec<_> = points.iter().filter(|p| p.1 > 0.0).map(|&(n, steps)| (n, steps.ln())).collect();
+    if logarithms.len() == points.len() {
+        if let Some(c) = least_squares(&logarithms, |n| vec![n, 1.0]) {
+            models.push(Model::Exponential { a: c[1].exp(), b: c[0].exp() });
+        }
+    }
+    models.into_iter().map(|model| (model, r_squared(&model, &points))).collect()
+}
+
+/// The simplest model that fits almost as well as the best one.
+pub fn best_fit(fits: &[(Model, f64)]) -> Option<Model> {
+    let best = fits.iter().map(|f| f.1).fold(f64::NEG_INFINITY, f64::max);
+    fits.iter().find(|f| f.1 >= best - 1e-3).map(|f| f.0)
+}
+
+#[test]
+fn test_complexity() {
+    // sets cell 1 to 1 + 2 + ... + n, which takes about 4.5·n² steps
+    let program = crate::parse_program("outer: GOTOZ 0 end
+        DEC 0
+        INC 2
+        copy: GOTOZ 2 outer_next
+        DEC 2
+        INC 1
+        INC 3
+        GOTO copy
+        outer_next: GOTOZ 3 outer
+        DEC 3
+        INC 2
+        GOTO outer_next
+        end: STOP".to_string()).unwrap();
+    let measurements = measure(&program, &[], 0, 1..=3, 10000).unwrap();
+    assert_eq!(vec![Ok(16), Ok(39), Ok(71)], measurements.iter().map(|m| m.steps.clone()).collect::<Vec<_>>());
+    let measurements = measure(&program, &[], 0, (0..=40).step_by(4), 100000).unwrap();
+    let fits = fit(&measurements);
+    assert_eq!(vec!["linear", "quadratic", "exponential"], fits.iter().map(|f| f.0.name()).collect::<Vec<_>>());
+    let best = best_fit(&fits).unwrap();
+    assert_eq!("quadratic", best.name());
+    assert!((best.predict(10.0) - 10.0 * 10.0 * 4.5).abs() < 200.0);
+
+    let linear = [(0, 5), (1, 9), (2, 13), (3, 17)].iter().map(|&(n, steps)| Measurement { n, steps: Ok(steps) }).collect::<Vec<_>>();
+    let best = best_fit(&fit(&linear)).unwrap();
+    assert_eq!("linear", best.name());
+    assert!((best.predict(10.0) - 45.0).abs() < 1e-6);
+    assert_eq!("2.000·n² - 1.500·n + 0.000", Model::Quadratic { a: 2.0, b: -1.5, c: 0.0 }.to_string());
+    assert_eq!(Err("still running after 10 steps".to_string()), measure(&program, &[], 0, 5..=5, 10).unwrap()[0].steps);
+}
@@ -0,0 +1,287 @@
+//! Predicates over the machine state such as `2 == 0`, `[0] > [1] + 3` or
+//! `steps >= 1000 && 1 != 0`.
+//!
+//! `[n]` or `mem[n]` is the value of cell `n`, `steps` and `pc` are the step
+//! counter and the program counter. As a shorthand a plain number on the left
+//! side of a comparison also names a cell, so `2 == 0` reads "cell 2 is zero".
+//! In contracts `old(e)` is the value of `e` on the input memory.
+
+use std::fmt;
+
+use crate::{GotoProgramState, RegisterIndex};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Expr {
+    Number(i128),
+    Cell(RegisterIndex),
+    Steps,
+    ProgramCounter,
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Old(Box<Expr>),
+    Compare(Box<Expr>, &'static str, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Condition {
+    text: String,
+    expr: Expr,
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+const OPERATORS: [&str; 13] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "(", ")"];
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if let Some(op) = OPERATORS.iter().chain(["[", "]", "!"].iter()).find(|op| rest.starts_with(*op)) {
+            tokens.push(op.to_string());
+            rest = &rest[op.len()..];
+        } else {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("unexpected character: {}", &rest[..1]));
+            }
+            tokens.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("unexpected end of condition")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            ref token if token == expected => Ok(()),
+            token => Err(format!("expected {}, found {}", expected, token)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.peek() == Some("||") {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.comparison()?;
+        while self.peek() == Some("&&") {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some("!") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.comparison()?)));
+        }
+        let left = self.sum()?;
+        let op = match self.peek() {
+            Some(op) => ["==", "!=", "<=", ">=", "<", ">"].iter().find(|o| **o == op).cloned(),
+            None => None,
+        };
+        match op {
+            Some(op) => {
+                self.pos += 1;
+                // a plain number on the left names a cell
+                let left = match left {
+                    Expr::Number(n) => Expr::Cell(n as RegisterIndex),
+                    left => left,
+                };
+                Ok(Expr::Compare(Box::new(left), op, Box::new(self.sum()?)))
+            }
+            None => Ok(left),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.product()?;
+        loop {
+            match self.peek() {
+                Some("+") => {
+                    self.pos += 1;
+                    left = Expr::Add(Box::new(left), Box::new(self.product()?));
+                }
+                Some("-") => {
+                    self.pos += 1;
+                    left = Expr::Sub(Box::new(left), Box::new(self.product()?));
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.atom()?;
+        while self.peek() == Some("*") {
+            self.pos += 1;
+            left = Expr::Mul(Box::new(left), Box::new(self.atom()?));
+        }
+        Ok(left)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.next()?;
+        match token.as_str() {
+            "steps" => Ok(Expr::Steps),
+            "pc" => Ok(Expr::ProgramCounter),
+            "(" => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            "old" => {
+                self.expect("(")?;
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(Expr::Old(Box::new(expr)))
+            }
+            "mem" => {
+                self.expect("[")?;
+                self.cell()
+            }
+            "[" => self.cell(),
+            number => number.parse().map(Expr::Number).map_err(|_| format!("unexpected {}", number)),
+        }
+    }
+
+    /// The rest of `[n]` after the bracket.
+    fn cell(&mut self) -> Result<Expr, String> {
+        let cell = self.next()?;
+        let cell = cell.parse().map_err(|_| format!("{} is not a cell", cell))?;
+        self.expect("]")?;
+        Ok(Expr::Cell(cell))
+    }
+}
+
+/// What an expression is evaluated on, `old` is the input memory if known.
+#[derive(Clone, Copy)]
+struct Machine<'a> {
+    memory: &'a [u64],
+    old: Option<&'a [u64]>,
+    steps: u64,
+    program_counter: usize,
+}
+
+fn eval(expr: &Expr, state: Machine) -> Result<i128, String> {
+    let truth = |b: bool| if b { 1 } else { 0 };
+    let overflow = || "the value is too large".to_string();
+    Ok(match expr {
+        Expr::Number(n) => *n,
+        Expr::Cell(cell) => *state.memory.get(*cell)
+            .ok_or_else(|| format!("cell {} is outside of the memory of size {}", cell, state.memory.len()))? as i128,
+        Expr::Steps => state.steps as i128,
+        Expr::ProgramCounter => state.program_counter as i128,
+        Expr::Add(a, b) => eval(a, state)?.checked_add(eval(b, state)?).ok_or_else(overflow)?,
+        Expr::Sub(a, b) => eval(a, state)?.checked_sub(eval(b, state)?).ok_or_else(overflow)?,
+        Expr::Mul(a, b) => eval(a, state)?.checked_mul(eval(b, state)?).ok_or_else(overflow)?,
+        Expr::Old(a) => {
+            let old = state.old.ok_or("old() can only be used in contracts")?;
+            eval(a, Machine { memory: old, old: None, ..state })?
+        }
+        Expr::Compare(a, op, b) => {
+            let (a, b) = (eval(a, state)?, eval(b, state)?);
+            truth(match *op {
+                "==" => a == b,
+                "!=" => a != b,
+                "<=" => a <= b,
+                ">=" => a >= b,
+                "<" => a < b,
+                _ => a > b,
+            })
+        }
+        Expr::And(a, b) => truth(eval(a, state)? != 0 && eval(b, state)? != 0),
+        Expr::Or(a, b) => truth(eval(a, state)? != 0 || eval(b, state)? != 0),
+        Expr::Not(a) => truth(eval(a, state)? == 0),
+    })
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, pos: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {}", token));
+        }
+        Ok(Condition { text: text.trim().to_string(), expr })
+    }
+
+    pub fn eval(&self, state: &GotoProgramState) -> Result<bool, String> {
+        self.eval_with_old(state, None)
+    }
+
+    /// Evaluates the condition with `old` as the memory that `old()` reads.
+    pub fn eval_with_old(&self, state: &GotoProgramState, old: Option<&[u64]>) -> Result<bool, String> {
+        let machine = Machine { memory: &state.memory, old, steps: state.steps, program_counter: state.program_counter };
+        eval(&self.expr, machine).map(|v| v != 0)
+    }
+
+    /// The highest cell the condition reads, `None` if it reads none.
+    pub fn highest_cell(&self) -> Option<RegisterIndex> {
+        fn highest(expr: &Expr) -> Option<RegisterIndex> {
+            match expr {
+                Expr::Cell(cell) => Some(*cell),
+                Expr::Number(_) | Expr::Steps | Expr::ProgramCounter => None,
+                Expr::Old(a) | Expr::Not(a) => highest(a),
+                Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Compare(a, _, b) | Expr::And(a, b) | Expr::Or(a, b) =>
+                    highest(a).max(highest(b)),
+            }
+        }
+        highest(&self.expr)
+    }
+}
+
+#[test]
+fn test_condition() {
+    let program = crate::parse_program("STOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![0, 150, 3]);
+    state.steps = 42;
+    let check = |text: &str| Condition::parse(text).unwrap().eval(&state);
+    assert_eq!(Ok(true), check("0 == 0"));
+    assert_eq!(Ok(true), check("1 > 100"));
+    assert_eq!(Ok(false), check("2 == 0"));
+    assert_eq!(Ok(true), check("[1] - [2] == 147 && steps >= 40"));
+    assert_eq!(Ok(true), check("!(pc != 0) || 5 == 5"));
+    assert!(check("7 == 0").is_err());
+    assert!(Condition::parse("1 ==").is_err());
+    assert!(Condition::parse("1 == 2 3").is_err());
+    assert!(Condition::parse("[x] == 2").is_err());
+
+    let doubled = Condition::parse("mem[1] == old(mem[0]) * 2 + 2").unwrap();
+    assert_eq!(Ok(true), doubled.eval_with_old(&state, Some(&[74, 0, 0])));
+    assert_eq!(Ok(false), doubled.eval_with_old(&state, Some(&[75, 0, 0])));
+    assert_eq!(Ok(true), Condition::parse("1 == 2 * 3 * 5 * 5").unwrap().eval(&state));
+    assert!(doubled.eval(&state).is_err());
+    assert_eq!(Some(2), Condition::parse("old([2]) > [1]").unwrap().highest_cell());
+}
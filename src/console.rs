@@ -0,0 +1,212 @@
+//! Where `READ` gets its numbers from and where `PRINT` and `OUTC` write to.
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use crate::RegisterIndex;
+
+pub trait Console: fmt::Debug + Send {
+    /// The number for `READ cell`.
+    fn read(&mut self, cell: RegisterIndex) -> Result<u64, String>;
+    fn print(&mut self, value: u64) -> Result<(), String>;
+    /// Writes the value of the cell as a character, for `OUTC cell`.
+    fn print_char(&mut self, cell: RegisterIndex, value: u64) -> Result<(), String>;
+
+    /// A value for a cell outside of the memory, without one the access fails.
+    fn missing_cell(&mut self, _cell: RegisterIndex) -> Result<Option<u64>, String> {
+        Ok(None)
+    }
+
+    /// The values `missing_cell` gave, in the order they were asked for.
+    fn answers(&self) -> &[(RegisterIndex, u64)] {
+        &[]
+    }
+}
+
+/// How `OUTC` turns a value into the bytes it writes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Encoding {
+    /// any Unicode scalar value
+    Utf8,
+    Ascii,
+    /// values up to 255 as single bytes
+    Latin1,
+}
+
+impl Encoding {
+    pub const NAMES: [&'static str; 3] = ["utf-8", "ascii", "latin-1"];
+
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name {
+            "utf-8" => Some(Encoding::Utf8),
+            "ascii" => Some(Encoding::Ascii),
+            "latin-1" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+
+    /// The character with the value as its code point, if the encoding has it.
+    pub fn char(self, cell: RegisterIndex, value: u64) -> Result<char, String> {
+        let limit = match self {
+            Encoding::Utf8 => u64::from(char::MAX),
+            Encoding::Ascii => 0x7F,
+            Encoding::Latin1 => 0xFF,
+        };
+        u32::try_from(value).ok().filter(|&v| u64::from(v) <= limit).and_then(char::from_u32)
+            .ok_or_else(|| format!("OUTC {}: {} is not a character in {}", cell, value, Encoding::NAMES[self as usize]))
+    }
+
+    pub fn encode(self, c: char, bytes: &mut Vec<u8>) {
+        match self {
+            Encoding::Utf8 | Encoding::Ascii => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            Encoding::Latin1 => bytes.push(c as u8),
+        }
+    }
+}
+
+fn parse_number(token: &str, cell: RegisterIndex) -> Result<u64, String> {
+    token.parse().map_err(|_| format!("READ {}: {} is not a number", cell, token))
+}
+
+/// Reads from a list of numbers and collects the output, the console of
+/// every run that is not connected to a terminal.
+/// The output holds the characters of `OUTC`, not their encoding.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Buffered {
+    pub input: VecDeque<u64>,
+    pub output: String,
+    pub encoding: Encoding,
+}
+
+impl Default for Buffered {
+    fn default() -> Buffered {
+        Buffered { input: VecDeque::new(), output: String::new(), encoding: Encoding::Utf8 }
+    }
+}
+
+impl Console for Buffered {
+    fn read(&mut self, cell: RegisterIndex) -> Result<u64, String> {
+        self.input.pop_front().ok_or_else(|| format!("READ {}: there is no more input", cell))
+    }
+
+    fn print(&mut self, value: u64) -> Result<(), String> {
+        self.output += &format!("{}\n", value);
+        Ok(())
+    }
+
+    fn print_char(&mut self, cell: RegisterIndex, value: u64) -> Result<(), String> {
+        self.output.push(self.encoding.char(cell, value)?);
+        Ok(())
+    }
+}
+
+/// Reads whitespace separated numbers from stdin and prints to stdout,
+/// prompting on stderr when stdin is a terminal. An interactive terminal
+/// also asks for the values of cells outside of the memory.
+#[derive(Debug)]
+pub struct Terminal {
+    pending: VecDeque<String>,
+    encoding: Encoding,
+    interactive: bool,
+    answers: Vec<(RegisterIndex, u64)>,
+}
+
+impl Terminal {
+    pub fn new(encoding: Encoding, interactive: bool) -> Terminal {
+        Terminal { pending: VecDeque::new(), encoding, interactive, answers: vec![] }
+    }
+}
+
+/// A line from stdin, `None` at the end of it.
+fn read_line(cell: RegisterIndex) -> Result<Option<String>, String> {
+    let mut line = String::new();
+    match std::io::stdin().lock().read_line(&mut line) {
+        Ok(0) => Ok(None),
+        Ok(_) => Ok(Some(line)),
+        Err(e) => Err(format!("cell {}: {}", cell, e)),
+    }
+}
+
+impl Console for Terminal {
+    fn read(&mut self, cell: RegisterIndex) -> Result<u64, String> {
+        while self.pending.is_empty() {
+            if atty::is(atty::Stream::Stdin) {
+                eprint!("READ {}: ", cell);
+            }
+            let line = read_line(cell)?.ok_or_else(|| format!("READ {}: there is no more input", cell))?;
+            self.pending.extend(line.split_whitespace().map(String::from));
+        }
+        parse_number(&self.pending.pop_front().unwrap(), cell)
+    }
+
+    fn print(&mut self, value: u64) -> Result<(), String> {
+        let mut stdout = std::io::stdout();
+        writeln!(stdout, "{}", value).and_then(|_| stdout.flush()).map_err(|e| e.to_string())
+    }
+
+    fn print_char(&mut self, cell: RegisterIndex, value: u64) -> Result<(), String> {
+        let mut bytes = vec![];
+        self.encoding.encode(self.encoding.char(cell, value)?, &mut bytes);
+        let mut stdout = std::io::stdout();
+        stdout.write_all(&bytes).and_then(|_| stdout.flush()).map_err(|e| e.to_string())
+    }
+
+    fn missing_cell(&mut self, cell: RegisterIndex) -> Result<Option<u64>, String> {
+        if !self.interactive {
+            return Ok(None);
+        }
+        loop {
+            eprint!("value for cell {}: ", cell);
+            let line = read_line(cell)?.ok_or_else(|| format!("no value for cell {}", cell))?;
+            match line.trim().parse() {
+                Ok(value) => {
+                    self.answers.push((cell, value));
+                    return Ok(Some(value));
+                }
+                Err(_) if atty::is(atty::Stream::Stdin) => eprintln!("{} is not a number", line.trim()),
+                Err(_) => return Err(format!("value for cell {}: {} is not a number", cell, line.trim())),
+            }
+        }
+    }
+
+    fn answers(&self) -> &[(RegisterIndex, u64)] {
+        &self.answers
+    }
+}
+
+/// The input with the answers of an interactive run filled in, cells
+/// nobody was asked for are 0.
+pub fn completed_input(input: &[u64], answers: &[(RegisterIndex, u64)]) -> Vec<u64> {
+    let mut completed = input.to_vec();
+    for &(cell, value) in answers {
+        if cell >= completed.len() {
+            completed.resize(cell + 1, 0);
+        }
+        completed[cell] = value;
+    }
+    completed
+}
+
+#[test]
+fn test_buffered() {
+    let mut console = Buffered { input: vec![4, 2].into(), ..Buffered::default() };
+    assert_eq!(Ok(4), console.read(0));
+    assert_eq!(Ok(2), console.read(0));
+    assert_eq!(Err("READ 3: there is no more input".to_string()), console.read(3));
+    console.print(7).unwrap();
+    console.print(0).unwrap();
+    assert_eq!("7\n0\n", console.output);
+    assert_eq!(Err("READ 1: x is not a number".to_string()), parse_number("x", 1));
+    console.print_char(0, 0xE9).unwrap();
+    console.print_char(0, 0x1F600).unwrap();
+    assert_eq!("7\n0\né😀", console.output);
+    assert_eq!(Err("OUTC 2: 55296 is not a character in utf-8".to_string()), console.print_char(2, 0xD800));
+    assert_eq!(Err("OUTC 2: 233 is not a character in ascii".to_string()), Encoding::Ascii.char(2, 0xE9));
+    let mut bytes = vec![];
+    Encoding::Latin1.encode(Encoding::Latin1.char(0, 0xE9).unwrap(), &mut bytes);
+    Encoding::Utf8.encode('é', &mut bytes);
+    assert_eq!(vec![0xE9, 0xC3, 0xA9], bytes);
+    assert_eq!(vec![1, 2, 0, 7, 0, 5], completed_input(&[1, 2], &[(5, 5), (3, 7)]));
+}
@@ -0,0 +1,132 @@
+//! Contracts written as annotations in comments,
+//!
+//! ```text
+//! ;@ requires mem[0] > 0
+//! ;@ ensures mem[1] == old(mem[0]) * 2
+//! ```
+//!
+//! checked by running the program on every input of an input space that
+//! satisfies the `requires` conditions, for a bounded number of steps. Runs
+//! that stop have to satisfy the `ensures` conditions, with `old()` reading
+//! the input, and must not fail on the way.
+
+use crate::condition::Condition;
+use crate::inputs::InputSpace;
+use crate::{source, GotoProgram, GotoProgramState};
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Contract {
+    /// the conditions with their source line
+    pub requires: Vec<(usize, Condition)>,
+    pub ensures: Vec<(usize, Condition)>,
+}
+
+impl Contract {
+    /// Collects the annotations of a program source.
+    pub fn parse(source: &str) -> Result<Contract, String> {
+        let mut contract = Contract::default();
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let comment = match source::parse_line(line) {
+                Ok(source::SourceLine { comment: Some(comment), .. }) => comment,
+                _ => continue,
+            };
+            let annotation = match comment.strip_prefix(";@") {
+                Some(annotation) => annotation.trim(),
+                None => continue,
+            };
+            let (kind, text) = annotation.split_at(annotation.find(char::is_whitespace).unwrap_or(annotation.len()));
+            let condition = Condition::parse(text).map_err(|e| format!("line {}: {}", number, e))?;
+            match kind {
+                "requires" => contract.requires.push((number, condition)),
+                "ensures" => contract.ensures.push((number, condition)),
+                _ => return Err(format!("line {}: unknown annotation {}, expected requires or ensures", number, kind)),
+            }
+        }
+        Ok(contract)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requires.is_empty() && self.ensures.is_empty()
+    }
+
+    fn highest_cell(&self) -> Option<usize> {
+        self.requires.iter().chain(&self.ensures).filter_map(|(_, condition)| condition.highest_cell()).max()
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Counterexample {
+    pub input: Vec<u64>,
+    /// what went wrong, like "ensures on line 3 does not hold: ..."
+    pub problem: String,
+}
+
+/// How many inputs were checked, and why the others were not.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Checked {
+    pub stopped: u64,
+    /// inputs the `requires` conditions rule out
+    pub excluded: u64,
+    /// inputs that were still running after the step bound
+    pub unfinished: u64,
+}
+
+/// Checks the contract on every input, returns the first input that breaks it.
+pub fn check(program: &GotoProgram, contract: &Contract, inputs: &InputSpace, within: u64) -> Result<Result<Checked, Counterexample>, String> {
+    let size = inputs.size.max(contract.highest_cell().map(|cell| cell + 1).unwrap_or(0));
+    let mut checked = Checked::default();
+    for mut input in inputs.iter() {
+        input.resize(size, 0);
+        let mut state = GotoProgramState::new(program, input.clone());
+        let mut allowed = true;
+        for (line, condition) in &contract.requires {
+            allowed &= condition.eval_with_old(&state, Some(&input)).map_err(|e| format!("line {}: {}", line, e))?;
+        }
+        if !allowed {
+            checked.excluded += 1;
+            continue;
+        }
+        match state.run_with_limit(within) {
+            Ok(true) => {}
+            Ok(false) => {
+                checked.unfinished += 1;
+                continue;
+            }
+            Err(e) => return Ok(Err(Counterexample { input, problem: format!("fails after {} steps: {}", state.steps, e) })),
+        }
+        for (line, condition) in &contract.ensures {
+            if !condition.eval_with_old(&state, Some(&input)).map_err(|e| format!("line {}: {}", line, e))? {
+                let problem = format!("ensures on line {} does not hold: {}, the memory is {:?}", line, condition, state.memory);
+                return Ok(Err(Counterexample { input, problem }));
+            }
+        }
+        checked.stopped += 1;
+    }
+    Ok(Ok(checked))
+}
+
+#[test]
+fn test_contract() {
+    let source = ";@ requires mem[0] > 0
+        ;@ ensures mem[1] == old(mem[0]) * 2
+        loop: GOTOZ 0 end ; doubles cell 0 into cell 1
+        DEC 0
+        INC 1
+        INC 1
+        GOTO loop
+        end: STOP";
+    let program = crate::parse_program(source.to_string()).unwrap();
+    let contract = Contract::parse(source).unwrap();
+    assert_eq!((vec![1], vec![2]), (contract.requires.iter().map(|r| r.0).collect(), contract.ensures.iter().map(|e| e.0).collect()));
+    let inputs = InputSpace { cells: 0..1, values: 0..20, size: 2 };
+    assert_eq!(Ok(Ok(Checked { stopped: 19, excluded: 1, unfinished: 0 })), check(&program, &contract, &inputs, 1000));
+    assert_eq!(Ok(Ok(Checked { stopped: 3, excluded: 1, unfinished: 16 })), check(&program, &contract, &inputs, 20));
+
+    let wrong = Contract::parse(&source.replace("* 2", "* 2 + mem[0]")).unwrap();
+    let inputs = InputSpace { cells: 0..2, values: 0..3, size: 2 };
+    assert_eq!(Ok(Err(Counterexample { input: vec![1, 1], problem: "ensures on line 2 does not hold: mem[1] == old(mem[0]) * 2 + mem[0], the memory is [0, 3]".to_string() })),
+               check(&program, &wrong, &inputs, 1000));
+    assert!(Contract::parse(";@ invariant mem[0] > 0").is_err());
+    assert!(Contract::parse(";@ requires mem[0] >").is_err());
+}
@@ -0,0 +1,84 @@
+//! Which instructions of a program ran at least once.
+
+use std::fmt::Write;
+
+use crate::observer::ExecutionObserver;
+use crate::{GotoProgram, GotoProgramState, Instruction};
+
+const UNCOVERED_MARK: &str = "#####";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Coverage {
+    pub executed: Vec<bool>,
+}
+
+impl Coverage {
+    pub fn new(program: &GotoProgram) -> Coverage {
+        Coverage { executed: vec![false; program.instructions.len()] }
+    }
+
+    pub fn record(&mut self, program_counter: usize) {
+        if let Some(executed) = self.executed.get_mut(program_counter) {
+            *executed = true;
+        }
+    }
+
+    /// The source lines of instructions that never ran.
+    pub fn uncovered_lines(&self, program: &GotoProgram) -> Vec<usize> {
+        program.source_lines.iter().zip(&self.executed).filter(|(_, executed)| !**executed).map(|(line, _)| *line).collect()
+    }
+
+    pub fn summary(&self, program: &GotoProgram) -> String {
+        let covered = self.executed.iter().filter(|e| **e).count();
+        let total = self.executed.len();
+        let percent = if total == 0 { 100.0 } else { 100.0 * covered as f64 / total as f64 };
+        let uncovered: Vec<_> = self.uncovered_lines(program).iter().map(|l| l.to_string()).collect();
+        let mut summary = format!("coverage: {} of {} instructions ({:.1}%)", covered, total, percent);
+        if !uncovered.is_empty() {
+            summary += &format!(", not executed: lines {}", uncovered.join(", "));
+        }
+        summary
+    }
+
+    /// The source with a `#####` in front of every line whose instruction never ran.
+    pub fn annotated_source(&self, program: &GotoProgram, source: &str) -> String {
+        let mut listing = String::new();
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let mark = match program.source_lines.iter().position(|l| *l == line) {
+                Some(pc) if self.executed[pc] => "",
+                Some(_) => UNCOVERED_MARK,
+                None => "",
+            };
+            writeln!(listing, "{:>5} {:>4} | {}", mark, line, text).unwrap();
+        }
+        listing
+    }
+}
+
+impl ExecutionObserver for Coverage {
+    fn before_step(&mut self, state: &GotoProgramState, _instruction: &Instruction) {
+        self.record(state.program_counter);
+    }
+}
+
+#[test]
+fn test_coverage() {
+    let source = "GOTOZ 0 done\nINC 1\n; comment\ndone: STOP";
+    let program = crate::parse_program(source.to_string()).unwrap();
+    let mut coverage = Coverage::new(&program);
+    let mut state = crate::GotoProgramState::new(&program, vec![0, 0]);
+    loop {
+        coverage.record(state.program_counter);
+        if state.step().unwrap() {
+            break;
+        }
+    }
+    assert_eq!(vec![2], coverage.uncovered_lines(&program));
+    assert_eq!("coverage: 2 of 3 instructions (66.7%), not executed: lines 2", coverage.summary(&program));
+    assert_eq!("         1 | GOTOZ 0 done
+#####    2 | INC 1
+         3 | ; comment
+         4 | done: STOP
+", coverage.annotated_source(&program, source));
+}
@@ -0,0 +1,87 @@
+//! Finds runs that will never stop because the machine returned to a state it
+//! was in before, with Brent's algorithm: the state is saved at steps that are
+//! powers of two apart and every later state is compared to the saved one.
+
+use std::fmt;
+
+use crate::{GotoProgramState, RegisterIndex};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Cycle {
+    pub first_step: u64,
+    pub second_step: u64,
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "non-termination detected: state repeated at steps {} and {}", self.first_step, self.second_step)
+    }
+}
+
+pub struct CycleDetector {
+    saved_step: u64,
+    saved_program_counter: RegisterIndex,
+    saved_memory: Vec<u64>,
+    power: u64,
+}
+
+impl Default for CycleDetector {
+    fn default() -> CycleDetector {
+        CycleDetector::new()
+    }
+}
+
+impl CycleDetector {
+    pub fn new() -> CycleDetector {
+        CycleDetector { saved_step: 0, saved_program_counter: 0, saved_memory: vec![], power: 0 }
+    }
+
+    /// Call before every step. A cycle is found within about twice as many
+    /// steps as it took to enter it, plus its length.
+    pub fn observe(&mut self, state: &GotoProgramState) -> Option<Cycle> {
+        if self.power > 0 && state.program_counter == self.saved_program_counter && state.memory == self.saved_memory {
+            return Some(Cycle { first_step: self.saved_step, second_step: state.steps });
+        }
+        if self.power == 0 || state.steps - self.saved_step == self.power {
+            self.power = if self.power == 0 { 1 } else { self.power * 2 };
+            self.saved_step = state.steps;
+            self.saved_program_counter = state.program_counter;
+            self.saved_memory.clone_from(&state.memory);
+        }
+        None
+    }
+}
+
+#[test]
+fn test_cycle_detector() {
+    let program = crate::parse_program("loop: GOTOZ 0 end
+    DEC 0
+    INC 1
+    GOTO loop
+    end: GOTOZ 1 stuck
+    DEC 1
+    GOTO end
+    stuck: INC 0
+    DEC 0
+    GOTO stuck".to_string()).unwrap();
+    let mut state = crate::GotoProgramState::new(&program, vec![5, 0]);
+    let mut detector = CycleDetector::new();
+    let cycle = loop {
+        if let Some(cycle) = detector.observe(&state) {
+            break cycle;
+        }
+        state.step().unwrap();
+    };
+    // the program reaches stuck after 37 steps and repeats it every 3 steps
+    assert!(cycle.first_step >= 37);
+    assert_eq!(3, cycle.second_step - cycle.first_step);
+    assert!(cycle.second_step < 2 * (37 + 3));
+
+    let program = crate::parse_program("loop: INC 0\nGOTO loop".to_string()).unwrap();
+    let mut state = crate::GotoProgramState::new(&program, vec![0]);
+    let mut detector = CycleDetector::new();
+    for _ in 0..1000 {
+        assert_eq!(None, detector.observe(&state));
+        state.step().unwrap();
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::BTreeSet;
+use std::fs::read_to_string;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use goto::json::Value;
+use goto::{parse_program, read_input, GotoProgram, GotoProgramState};
+
+const STEPS_PER_SLICE: u64 = 10_000;
+const MEMORY_REFERENCE: u64 = 1;
+const MACHINE_REFERENCE: u64 = 2;
+
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let text = String::from_utf8_lossy(&body);
+    Value::parse(&text)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn spawn_reader() -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut reader = stdin.lock();
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn arguments<'v>(request: &'v Value, key: &str) -> Option<&'v Value> {
+    request.get("arguments").and_then(|a| a.get(key))
+}
+
+/// A debug session speaking the Debug Adapter Protocol. Breakpoints are set on
+/// source lines, memory cells are exposed as variables.
+struct Session<W: Write> {
+    out: W,
+    seq: u64,
+    source_path: String,
+    breakpoint_lines: BTreeSet<usize>,
+    initial_memory: Vec<u64>,
+    state: Option<GotoProgramState<'static>>,
+    stop_on_entry: bool,
+    running: bool,
+}
+
+impl<W: Write> Session<W> {
+    fn new(out: W) -> Session<W> {
+        Session {
+            out,
+            seq: 0,
+            source_path: String::new(),
+            breakpoint_lines: BTreeSet::new(),
+            initial_memory: vec![],
+            state: None,
+            stop_on_entry: false,
+            running: false,
+        }
+    }
+
+    fn send(&mut self, mut fields: Vec<(&str, Value)>) {
+        self.seq += 1;
+        fields.insert(0, ("seq", self.seq.into()));
+        let body = Value::object(fields).to_string();
+        let _ = write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = self.out.flush();
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) {
+        let request_seq = request.get("seq").cloned().unwrap_or(Value::Null);
+        let command = request.get("command").cloned().unwrap_or(Value::Null);
+        let mut fields = vec![
+            ("type", "response".into()),
+            ("request_seq", request_seq),
+            ("command", command),
+        ];
+        match result {
+            Ok(body) => {
+                fields.push(("success", true.into()));
+                fields.push(("body", body));
+            }
+            Err(message) => {
+                fields.push(("success", false.into()));
+                fields.push(("message", message.into()));
+            }
+        }
+        self.send(fields);
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(vec![("type", "event".into()), ("event", event.into()), ("body", body)]);
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<String>) {
+        self.running = false;
+        let mut body = vec![("reason", reason.into()), ("threadId", 1u64.into())];
+        if let Some(description) = description {
+            body.push(("description", description.clone().into()));
+            body.push(("text", description.into()));
+        }
+        self.event("stopped", Value::object(body));
+    }
+
+    fn output(&mut self, category: &str, text: String) {
+        self.event("output", Value::object(vec![("category", category.into()), ("output", text.into())]));
+    }
+
+    fn program(&self) -> Option<&'static GotoProgram> {
+        self.state.as_ref().map(|state| state.program)
+    }
+
+    fn launch(&mut self, request: &Value) -> Result<Value, String> {
+        let path = arguments(request, "program").and_then(|p| p.as_str())
+            .ok_or("launch needs a program")?;
+        let code = read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let program = parse_program(code)?;
+        let memory = match (arguments(request, "input").and_then(|i| i.as_str()), arguments(request, "memory")) {
+            (Some(input), _) => {
+                let text = read_to_string(input).map_err(|e| format!("cannot read {}: {}", input, e))?;
+                read_input(text)?
+            }
+            (None, Some(memory)) => memory.as_array().into_iter().flatten()
+                .map(|v| v.as_u64().ok_or("memory must be an array of numbers"))
+                .collect::<Result<_, _>>()?,
+            (None, None) => vec![],
+        };
+        self.source_path = path.to_string();
+        self.stop_on_entry = arguments(request, "stopOnEntry").and_then(|s| s.as_bool()).unwrap_or(false);
+        self.initial_memory = memory.clone();
+        // the session lives until the process exits, so the program is never freed
+        let program: &'static GotoProgram = Box::leak(Box::new(program));
+        self.state = Some(GotoProgramState::new(program, memory));
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, request: &Value) -> Result<Value, String> {
+        let lines: Vec<usize> = arguments(request, "breakpoints")
+            .and_then(|b| b.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|b| b.get("line").and_then(|l| l.as_u64()))
+            .map(|l| l as usize)
+            .collect();
+        self.breakpoint_lines = lines.iter().cloned().collect();
+        let program = self.program();
+        let breakpoints: Vec<Value> = lines.iter()
+            .map(|&line| {
+                let verified = program.map(|p| p.source_lines.contains(&line)).unwrap_or(true);
+                Value::object(vec![("verified", verified.into()), ("line", line.into())])
+            })
+            .collect();
+        Ok(Value::object(vec![("breakpoints", breakpoints.into())]))
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let state = self.state.as_ref().ok_or("no program launched")?;
+        let pc = state.program_counter;
+        let (name, line) = match state.program.instructions.get(pc) {
+            Some(instruction) => (instruction.to_string(), state.program.source_lines[pc]),
+            None => ("<end of program>".to_string(), state.program.source_lines.last().cloned().unwrap_or(1)),
+        };
+        let frame = Value::object(vec![
+            ("id", 1u64.into()),
+            ("name", name.into()),
+            ("source", Value::object(vec![("path", self.source_path.as_str().into())])),
+            ("line", line.into()),
+            ("column", 1u64.into()),
+        ]);
+        Ok(Value::object(vec![("stackFrames", vec![frame].into()), ("totalFrames", 1u64.into())]))
+    }
+
+    fn variables(&self, request: &Value) -> Result<Value, String> {
+        let state = self.state.as_ref().ok_or("no program launched")?;
+        let variable = |name: String, value: String| Value::object(vec![
+            ("name", name.into()),
+            ("value", value.into()),
+            ("variablesReference", 0u64.into()),
+        ]);
+        let variables: Vec<Value> = match arguments(request, "variablesReference").and_then(|r| r.as_u64()) {
+            Some(MEMORY_REFERENCE) => state.memory.iter().enumerate()
+                .map(|(cell, value)| variable(format!("[{}]", cell), value.to_string()))
+                .collect(),
+            Some(MACHINE_REFERENCE) => vec![
+                variable("pc".to_string(), state.program_counter.to_string()),
+                variable("steps".to_string(), state.steps.to_string()),
+            ],
+            _ => vec![],
+        };
+        Ok(Value::object(vec![("variables", variables.into())]))
+    }
+
+    fn set_variable(&mut self, request: &Value) -> Result<Value, String> {
+        let state = self.state.as_mut().ok_or("no program launched")?;
+        let name = arguments(request, "name").and_then(|n| n.as_str()).unwrap_or("");
+        let value = arguments(request, "value").and_then(|v| v.as_str()).unwrap_or("");
+        let value: u64 = value.trim().parse().map_err(|_| format!("{} is not a number", value))?;
+        let target = match arguments(request, "variablesReference").and_then(|r| r.as_u64()) {
+            Some(MEMORY_REFERENCE) => name.trim_start_matches('[').trim_end_matches(']').parse::<usize>().ok()
+                .and_then(|cell| state.memory.get_mut(cell)),
+            _ => None,
+        };
+        let target = target.ok_or_else(|| format!("{} cannot be changed", name))?;
+        *target = value;
+        Ok(Value::object(vec![("value", value.to_string().into())]))
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        match &self.state {
+            Some(state) => state.program.source_lines.get(state.program_counter)
+                .map(|line| self.breakpoint_lines.contains(line))
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Executes a single instruction and reports a halt, returns whether execution may go on.
+    fn step(&mut self) -> bool {
+        let result = match self.state.as_mut() {
+            Some(state) => state.step(),
+            None => return false,
+        };
+        match result {
+            Ok(false) => true,
+            Ok(true) => {
+                let memory = format!("result: {:?}\n", self.state.as_ref().unwrap().memory);
+                self.running = false;
+                self.output("stdout", memory);
+                self.event("exited", Value::object(vec![("exitCode", 0u64.into())]));
+                self.event("terminated", Value::object(vec![]));
+                false
+            }
+            Err(e) => {
+                self.output("stderr", format!("error: {}\n", e));
+                self.stopped("exception", Some(e.to_string()));
+                false
+            }
+        }
+    }
+
+    fn run_slice(&mut self) {
+        for _ in 0..STEPS_PER_SLICE {
+            if !self.step() {
+                return;
+            }
+            if self.at_breakpoint() {
+                self.stopped("breakpoint", None);
+                return;
+            }
+        }
+    }
+
+    /// Handles one request, returns `false` when the session is over.
+    fn handle(&mut self, request: &Value) -> bool {
+        let command = request.get("command").and_then(|c| c.as_str()).unwrap_or("").to_string();
+        match command.as_str() {
+            "initialize" => {
+                let capabilities = Value::object(vec![
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsSetVariable", true.into()),
+                    ("supportsRestartRequest", true.into()),
+                ]);
+                self.respond(request, Ok(capabilities));
+                self.event("initialized", Value::object(vec![]));
+            }
+            "launch" => {
+                let result = self.launch(request);
+                self.respond(request, result);
+            }
+            "setBreakpoints" => {
+                let result = self.set_breakpoints(request);
+                self.respond(request, result);
+            }
+            "configurationDone" => {
+                self.respond(request, Ok(Value::Null));
+                if self.stop_on_entry {
+                    self.stopped("entry", None);
+                } else if self.at_breakpoint() {
+                    self.stopped("breakpoint", None);
+                } else {
+                    self.running = true;
+                }
+            }
+            "restart" => {
+                let memory = self.initial_memory.clone();
+                if let Some(state) = self.state.as_mut() {
+                    *state = GotoProgramState::new(state.program, memory);
+                }
+                self.respond(request, Ok(Value::Null));
+                self.stopped("entry", None);
+            }
+            "threads" => {
+                let thread = Value::object(vec![("id", 1u64.into()), ("name", "main".into())]);
+                self.respond(request, Ok(Value::object(vec![("threads", vec![thread].into())])));
+            }
+            "stackTrace" => {
+                let result = self.stack_trace();
+                self.respond(request, result);
+            }
+            "scopes" => {
+                let scope = |name: &str, reference: u64| Value::object(vec![
+                    ("name", name.into()),
+                    ("variablesReference", reference.into()),
+                    ("expensive", false.into()),
+                ]);
+                let scopes = vec![scope("Memory", MEMORY_REFERENCE), scope("Machine", MACHINE_REFERENCE)];
+                self.respond(request, Ok(Value::object(vec![("scopes", scopes.into())])));
+            }
+            "variables" => {
+                let result = self.variables(request);
+                self.respond(request, result);
+            }
+            "setVariable" => {
+                let result = self.set_variable(request);
+                self.respond(request, result);
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.respond(request, Ok(Value::Null));
+                if self.step() {
+                    self.stopped("step", None);
+                }
+            }
+            "continue" => {
+                self.respond(request, Ok(Value::object(vec![("allThreadsContinued", true.into())])));
+                // leave the current breakpoint before checking for the next one
+                if self.step() {
+                    if self.at_breakpoint() {
+                        self.stopped("breakpoint", None);
+                    } else {
+                        self.running = true;
+                    }
+                }
+            }
+            "pause" => {
+                self.respond(request, Ok(Value::Null));
+                self.stopped("pause", None);
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(Value::Null));
+                return false;
+            }
+            _ => self.respond(request, Err(format!("unsupported request: {}", command))),
+        }
+        true
+    }
+
+    fn serve(&mut self, requests: Receiver<Value>) {
+        loop {
+            let request = if self.running {
+                match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            } else {
+                match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return,
+                }
+            };
+            if let Some(request) = request {
+                if !self.handle(&request) {
+                    return;
+                }
+            }
+            if self.running {
+                self.run_slice();
+            }
+        }
+    }
+}
+
+pub fn run() {
+    let stdout = io::stdout();
+    Session::new(stdout.lock()).serve(spawn_reader());
+}
+
+#[test]
+fn test_dap_session() {
+    let dir = std::env::temp_dir().join(format!("goto-dap-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("add.goto");
+    std::fs::write(&source, "loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP\n").unwrap();
+
+    let requests = vec![
+        r#"{"seq":1,"type":"request","command":"initialize","arguments":{}}"#.to_string(),
+        format!(r#"{{"seq":2,"type":"request","command":"launch","arguments":{{"program":{},"memory":[2,5]}}}}"#,
+                Value::from(source.to_str().unwrap())),
+        r#"{"seq":3,"type":"request","command":"setBreakpoints","arguments":{"breakpoints":[{"line":3},{"line":6}]}}"#.to_string(),
+        r#"{"seq":4,"type":"request","command":"configurationDone"}"#.to_string(),
+        r#"{"seq":5,"type":"request","command":"continue"}"#.to_string(),
+        r#"{"seq":6,"type":"request","command":"variables","arguments":{"variablesReference":1}}"#.to_string(),
+        r#"{"seq":7,"type":"request","command":"setBreakpoints","arguments":{"breakpoints":[]}}"#.to_string(),
+        r#"{"seq":8,"type":"request","command":"continue"}"#.to_string(),
+    ];
+    let (sender, receiver) = mpsc::channel();
+    for request in requests {
+        sender.send(Value::parse(&request).unwrap()).unwrap();
+    }
+    drop(sender);
+    let mut out = vec![];
+    Session::new(&mut out).serve(receiver);
+
+    let mut reader = io::BufReader::new(out.as_slice());
+    let mut messages = vec![];
+    while let Some(message) = read_message(&mut reader).unwrap() {
+        messages.push(message);
+    }
+    let summary: Vec<String> = messages.iter()
+        .map(|m| match m.get("type").and_then(|t| t.as_str()) {
+            Some("event") => m.get("event").unwrap().as_str().unwrap().to_string(),
+            _ => format!("{}:{}", m.get("command").unwrap().as_str().unwrap(), m.get("success").unwrap()),
+        })
+        .collect();
+    assert_eq!(vec![
+        "initialize:true", "initialized", "launch:true", "setBreakpoints:true", "configurationDone:true",
+        "stopped", "continue:true", "stopped", "variables:true", "setBreakpoints:true", "continue:true",
+        "output", "exited", "terminated",
+    ], summary);
+    let breakpoints = messages[3].get("body").unwrap().get("breakpoints").unwrap().to_string();
+    assert_eq!(r#"[{"verified":true,"line":3},{"verified":false,"line":6}]"#, breakpoints);
+    let variables = messages[8].get("body").unwrap().get("variables").unwrap().as_array().unwrap();
+    assert_eq!(Some("0"), variables[0].get("value").unwrap().as_str());
+    assert_eq!(Some("6"), variables[1].get("value").unwrap().as_str());
+    assert_eq!(Some("result: [0, 7]\n"), messages[11].get("body").unwrap().get("output").unwrap().as_str());
+    std::fs::remove_dir_all(dir).unwrap();
+}
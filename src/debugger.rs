@@ -0,0 +1,317 @@
+use std::collections::BTreeSet;
+
+use goto::condition::Condition;
+use goto::history::History;
+use goto::{GotoProgram, GotoProgramState, RegisterIndex, StepEffect};
+
+use crate::line_editor::LineEditor;
+
+const HELP: &str = "break <line>      stop before the instruction on a source line
+break when <cond> stop when a condition like `2 == 0` or `[0] > 100` becomes true
+delete <line>     remove a breakpoint
+delete when <n>   remove the n-th conditional breakpoint
+watch <cell>      stop whenever the value of a cell, given by number or alias, changes
+unwatch <cell>    remove a watchpoint
+step [<n>]        execute one or n instructions
+step-back [<n>]   undo one or n instructions
+continue          run until a breakpoint or the end of the program
+print [<cell>]    show a cell or the whole memory
+set <cell> <n>    change the value of a cell
+list              show the source around the current instruction
+run               restart the program with the initial memory
+help              show this help
+quit              leave the debugger";
+
+pub struct Debugger<'a> {
+    source: Vec<String>,
+    input: Vec<u64>,
+    state: GotoProgramState<'a>,
+    breakpoint_lines: BTreeSet<usize>,
+    watched_cells: BTreeSet<RegisterIndex>,
+    /// conditional breakpoints with their value after the last step
+    conditions: Vec<(Condition, bool)>,
+    history: History,
+    halted: Option<String>,
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("{} is not a number", text))
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(source: &str, program: &'a GotoProgram, input: Vec<u64>, history_size: usize) -> Debugger<'a> {
+        Debugger {
+            source: source.lines().map(|l| l.to_string()).collect(),
+            input: input.clone(),
+            state: GotoProgramState::new(program, input),
+            breakpoint_lines: BTreeSet::new(),
+            watched_cells: BTreeSet::new(),
+            conditions: vec![],
+            history: History::new(history_size),
+            halted: None,
+        }
+    }
+
+    fn current_line(&self) -> Option<usize> {
+        self.state.program.source_lines.get(self.state.program_counter).cloned()
+    }
+
+    fn show_position(&self) {
+        match (&self.halted, self.current_line()) {
+            (Some(reason), _) => println!("program {} after {} steps", reason, self.state.steps),
+            (None, Some(line)) => println!("{:>4} {}", line, self.source[line - 1]),
+            (None, None) => println!("program counter {} is outside of the program", self.state.program_counter),
+        }
+    }
+
+    /// Re-evaluates the conditional breakpoints, returns whether one became true.
+    fn check_conditions(&mut self) -> bool {
+        let mut hit = false;
+        for (nr, (condition, last)) in self.conditions.iter_mut().enumerate() {
+            match condition.eval(&self.state) {
+                Ok(value) => {
+                    if value && !*last {
+                        println!("condition {} is true: {}", nr + 1, condition);
+                        hit = true;
+                    }
+                    *last = value;
+                }
+                Err(e) => {
+                    println!("condition {} cannot be evaluated: {}", nr + 1, e);
+                    hit = true;
+                }
+            }
+        }
+        hit
+    }
+
+    /// Executes one instruction, returns `false` once the program halted, a
+    /// watched cell changed or a condition became true.
+    fn step(&mut self) -> bool {
+        if self.halted.is_some() {
+            return false;
+        }
+        let program = self.state.program;
+        let source = &self.source;
+        let watched_cells = &self.watched_cells;
+        let history = &mut self.history;
+        let mut watch_hit = false;
+        let result = self.state.step_with_hook(&mut |effect: &StepEffect| {
+            history.record(effect);
+            if let Some(write) = effect.write.filter(|w| watched_cells.contains(&w.cell)) {
+                let line = program.source_lines[effect.program_counter];
+                println!("watchpoint [{}]: {} -> {} by line {}: {}",
+                         program.cell_name(write.cell), write.old, write.new, line, source[line - 1].trim());
+                watch_hit = true;
+            }
+        });
+        let condition_hit = self.check_conditions();
+        match result {
+            Ok(false) => return !watch_hit && !condition_hit,
+            Ok(true) => self.halted = Some("stopped".to_string()),
+            Err(e) => self.halted = Some(format!("failed with: {}", e)),
+        }
+        false
+    }
+
+    /// Undoes up to `n` steps, returns `false` if the history ran out first.
+    fn step_back(&mut self, n: u64) -> bool {
+        for _ in 0..n {
+            if !self.history.step_back(&mut self.state) {
+                return false;
+            }
+            self.halted = None;
+            for (condition, last) in &mut self.conditions {
+                *last = condition.eval(&self.state).unwrap_or(false);
+            }
+        }
+        true
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.current_line().map(|line| self.breakpoint_lines.contains(&line)).unwrap_or(false)
+    }
+
+    fn continue_execution(&mut self) {
+        while self.step() {
+            if self.at_breakpoint() {
+                println!("breakpoint at line {}", self.current_line().unwrap());
+                return;
+            }
+        }
+    }
+
+    fn list(&self) {
+        let current = self.current_line();
+        let first = current.unwrap_or(1).saturating_sub(5);
+        for (index, text) in self.source.iter().enumerate().skip(first).take(10) {
+            let line = index + 1;
+            let marker = if Some(line) == current { "=>" } else { "  " };
+            let breakpoint = if self.breakpoint_lines.contains(&line) { '*' } else { ' ' };
+            println!("{}{}{:>4} {}", marker, breakpoint, line, text);
+        }
+    }
+
+    /// Handles one command, returns `false` when the session should end.
+    pub fn eval(&mut self, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["quit"] | ["q"] => return Ok(false),
+            ["help"] | ["h"] => println!("{}", HELP),
+            ["break", "when", ..] | ["b", "when", ..] => {
+                let text = line.split_once("when").unwrap().1;
+                let condition = Condition::parse(text)?;
+                let value = condition.eval(&self.state).unwrap_or(false);
+                println!("condition {}: {}", self.conditions.len() + 1, condition);
+                self.conditions.push((condition, value));
+            }
+            ["delete", "when", nr] | ["d", "when", nr] => {
+                let nr: usize = parse_number(nr)?;
+                if nr == 0 || nr > self.conditions.len() {
+                    return Err(format!("there is no condition {}", nr));
+                }
+                self.conditions.remove(nr - 1);
+            }
+            ["break", line] | ["b", line] => {
+                let line = parse_number(line)?;
+                if !self.state.program.source_lines.contains(&line) {
+                    return Err(format!("there is no instruction on line {}", line));
+                }
+                self.breakpoint_lines.insert(line);
+                println!("breakpoint at line {}", line);
+            }
+            ["delete", line] | ["d", line] => {
+                if !self.breakpoint_lines.remove(&parse_number(line)?) {
+                    return Err(format!("there is no breakpoint on line {}", line));
+                }
+            }
+            ["watch", cell] | ["w", cell] => {
+                let cell = self.state.program.parse_cell(cell)?;
+                self.watched_cells.insert(cell);
+                println!("watchpoint on cell {}", self.state.program.cell_name(cell));
+            }
+            ["unwatch", cell] => {
+                if !self.watched_cells.remove(&self.state.program.parse_cell(cell)?) {
+                    return Err(format!("there is no watchpoint on cell {}", cell));
+                }
+            }
+            ["step"] | ["s"] => {
+                self.step();
+                self.show_position();
+            }
+            ["step", n] | ["s", n] => {
+                for _ in 0..parse_number::<u64>(n)? {
+                    if !self.step() {
+                        break;
+                    }
+                }
+                self.show_position();
+            }
+            ["step-back"] | ["sb"] => {
+                if !self.step_back(1) {
+                    return Err("no more recorded steps".to_string());
+                }
+                self.show_position();
+            }
+            ["step-back", n] | ["sb", n] => {
+                if !self.step_back(parse_number(n)?) {
+                    println!("reached the oldest recorded step");
+                }
+                self.show_position();
+            }
+            ["continue"] | ["c"] => {
+                self.continue_execution();
+                self.show_position();
+            }
+            ["print"] | ["p"] => println!("mem: {:?}", self.state.memory),
+            ["print", cell] | ["p", cell] => {
+                let cell = self.state.program.parse_cell(cell)?;
+                match self.state.memory.get(cell) {
+                    Some(value) => println!("[{}] = {}", self.state.program.cell_name(cell), value),
+                    None => return Err(format!("cell {} is outside of the memory of size {}", cell, self.state.memory.len())),
+                }
+            }
+            ["set", cell, value] => {
+                self.state.set_cell(self.state.program.parse_cell(cell)?, parse_number(value)?)?;
+                // the recorded steps do not know about the new value
+                self.history.clear();
+            }
+            ["list"] | ["l"] => self.list(),
+            ["run"] | ["r"] => {
+                self.state = GotoProgramState::new(self.state.program, self.input.clone());
+                self.halted = None;
+                self.history.clear();
+                for (condition, last) in &mut self.conditions {
+                    *last = condition.eval(&self.state).unwrap_or(false);
+                }
+                self.show_position();
+            }
+            _ => return Err(format!("unknown command: {}, type help for help", line.trim())),
+        }
+        Ok(true)
+    }
+}
+
+pub fn run(source: &str, program: &GotoProgram, input: Vec<u64>, history_size: usize) {
+    let history_file = std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join(".goto_debug_history"));
+    let mut editor = LineEditor::new(history_file);
+    let mut debugger = Debugger::new(source, program, input, history_size);
+    debugger.show_position();
+    while let Ok(Some(line)) = editor.read_line("(goto) ") {
+        editor.add_history(&line);
+        match debugger.eval(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
+
+#[test]
+fn test_debugger() {
+    let source = "loop: GOTOZ 0 end\nDEC 0\n\nINC 1\nGOTO loop\nend: STOP";
+    let program = goto::parse_program(source.to_string()).unwrap();
+    let mut debugger = Debugger::new(source, &program, vec![3, 0], 100);
+    assert!(debugger.eval("break 3").is_err());
+    assert_eq!(Ok(true), debugger.eval("break 4"));
+    assert_eq!(Ok(true), debugger.eval("continue"));
+    assert_eq!((2, vec![2, 0]), (debugger.state.program_counter, debugger.state.memory.clone()));
+    assert_eq!(Ok(true), debugger.eval("set 0 10"));
+    assert_eq!(Ok(true), debugger.eval("step 2"));
+    assert_eq!((0, vec![10, 1]), (debugger.state.program_counter, debugger.state.memory.clone()));
+    assert_eq!(Ok(true), debugger.eval("delete 4"));
+    assert_eq!(Ok(true), debugger.eval("c"));
+    assert_eq!(Some("stopped".to_string()), debugger.halted);
+    assert_eq!(vec![0, 11], debugger.state.memory);
+    assert!(debugger.eval("set 2 1").is_err());
+    assert_eq!(Ok(true), debugger.eval("run"));
+    assert_eq!(vec![3, 0], debugger.state.memory);
+    assert!(debugger.eval("watch counter").is_err());
+    assert_eq!(Ok(true), debugger.eval("watch 1"));
+    assert_eq!(Ok(true), debugger.eval("continue"));
+    assert_eq!((3, vec![2, 1]), (debugger.state.program_counter, debugger.state.memory.clone()));
+    assert_eq!(Ok(true), debugger.eval("step 10"));
+    assert_eq!((3, vec![1, 2]), (debugger.state.program_counter, debugger.state.memory.clone()));
+    assert_eq!(Ok(true), debugger.eval("unwatch 1"));
+    assert_eq!(Ok(true), debugger.eval("continue"));
+    assert_eq!(vec![0, 3], debugger.state.memory);
+
+    assert_eq!(Ok(true), debugger.eval("run"));
+    assert_eq!(Ok(true), debugger.eval("break when 1 == 2"));
+    assert!(debugger.eval("break when 1 = 2").is_err());
+    assert_eq!(Ok(true), debugger.eval("continue"));
+    assert_eq!((3, vec![1, 2]), (debugger.state.program_counter, debugger.state.memory.clone()));
+    assert_eq!(Ok(true), debugger.eval("continue"));
+    assert_eq!(Some("stopped".to_string()), debugger.halted);
+    assert_eq!(Ok(true), debugger.eval("delete when 1"));
+    assert!(debugger.eval("delete when 1").is_err());
+
+    assert_eq!(Ok(true), debugger.eval("step-back 4"));
+    assert_eq!((2, vec![0, 2], None), (debugger.state.program_counter, debugger.state.memory.clone(), debugger.halted.clone()));
+    assert_eq!(Ok(true), debugger.eval("step-back 100"));
+    assert_eq!((0, vec![3, 0], 0), (debugger.state.program_counter, debugger.state.memory.clone(), debugger.state.steps));
+    assert!(debugger.eval("step-back").is_err());
+    assert_eq!(Ok(false), debugger.eval("quit"));
+}
@@ -0,0 +1,163 @@
+//! Differential testing of the ways to run a program: the interpreter is
+//! the reference, and the bytecode VM, the JIT and the program translated to
+//! C have to end with the same memory on every input.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::bytecode::Bytecode;
+use crate::equiv::{self, Compared, Outcome};
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
+use crate::transpile::{self, Arithmetic};
+use crate::{GotoProgram, GotoProgramState};
+
+pub enum Backend {
+    Bytecode(Bytecode),
+    #[cfg(feature = "jit")]
+    Jit(Jit),
+    /// an executable compiled from the C translation
+    C(PathBuf),
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backend::Bytecode(_) => write!(f, "bytecode"),
+            #[cfg(feature = "jit")]
+            Backend::Jit(_) => write!(f, "jit"),
+            Backend::C(_) => write!(f, "c"),
+        }
+    }
+}
+
+/// Translates the program to C in `dir` and compiles it with `compiler`.
+pub fn compile_c(program: &GotoProgram, compiler: &str, dir: &Path) -> Result<Backend, String> {
+    let source = dir.join("program.c");
+    let executable = dir.join("program");
+    fs::write(&source, transpile::to_c(program, None, Arithmetic::Checked)).map_err(|e| format!("{}: {}", source.display(), e))?;
+    let output = Command::new(compiler).arg("-O1").arg("-o").arg(&executable).arg(&source).output()
+        .map_err(|e| format!("could not run {}: {}", compiler, e))?;
+    if !output.status.success() {
+        return Err(format!("{} failed: {}", compiler, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(Backend::C(executable))
+}
+
+#[cfg(feature = "jit")]
+fn jit_backend(program: &GotoProgram, memory_size: usize) -> Option<Backend> {
+    Jit::compile(program, memory_size).map(Backend::Jit)
+}
+
+#[cfg(not(feature = "jit"))]
+fn jit_backend(_: &GotoProgram, _: usize) -> Option<Backend> {
+    None
+}
+
+/// The backends there are for the memory size, the JIT only when built with
+/// the jit feature and the C one when a compiler is given.
+pub fn backends(program: &GotoProgram, memory_size: usize, compiler: Option<&str>, dir: &Path) -> Result<Vec<Backend>, String> {
+    let mut backends = vec![Backend::Bytecode(Bytecode::compile(program)?)];
+    backends.extend(jit_backend(program, memory_size));
+    if let Some(compiler) = compiler {
+        backends.push(compile_c(program, compiler, dir)?);
+    }
+    Ok(backends)
+}
+
+impl Backend {
+    /// Runs the program on the input, which the interpreter finished within
+    /// the step limit. The C executable has no step limit.
+    pub fn run(&self, program: &GotoProgram, input: &[u64], within: u64) -> Result<Outcome, String> {
+        let in_process = |result: Result<bool, String>, state: GotoProgramState| match result {
+            Ok(true) => Outcome::Stopped(state.memory),
+            Ok(false) => Outcome::Running(within),
+            Err(e) => Outcome::Failed(e),
+        };
+        match self {
+            Backend::Bytecode(bytecode) => {
+                let mut state = GotoProgramState::new(program, input.to_vec());
+                Ok(in_process(bytecode.run(&mut state, within), state))
+            }
+            #[cfg(feature = "jit")]
+            Backend::Jit(jit) => {
+                let mut state = GotoProgramState::new(program, input.to_vec());
+                Ok(in_process(jit.run(&mut state, within), state))
+            }
+            Backend::C(executable) => {
+                let output = Command::new(executable).args(input.iter().map(|v| v.to_string())).output()
+                    .map_err(|e| format!("could not run {}: {}", executable.display(), e))?;
+                if !output.status.success() {
+                    return Ok(Outcome::Failed(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+                }
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                // what the program printed comes before the memory
+                let memory = stdout.rfind("result: [").map(|start| stdout[start..].trim()).and_then(|last| last.strip_prefix("result: [")).and_then(|rest| rest.strip_suffix(']'))
+                    .ok_or_else(|| format!("unexpected output of the C program: {}", stdout.trim()))?;
+                let memory = memory.split(", ").filter(|v| !v.is_empty())
+                    .map(|v| v.parse().map_err(|_| format!("unexpected output of the C program: {}", stdout.trim())))
+                    .collect::<Result<_, _>>()?;
+                Ok(Outcome::Stopped(memory))
+            }
+        }
+    }
+}
+
+/// An input on which a backend ends differently than the interpreter.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Mismatch {
+    pub input: Vec<u64>,
+    pub backend: String,
+    pub expected: Outcome,
+    pub actual: Outcome,
+}
+
+/// Runs every backend on every input the interpreter finishes within the
+/// step limit, the inputs it does not finish are counted as unfinished.
+/// Errors are problems with running a backend at all.
+pub fn check(program: &GotoProgram, backends: &[Backend], inputs: impl Iterator<Item = Vec<u64>>,
+             within: u64) -> Result<Result<Compared, Mismatch>, String> {
+    let mut compared = Compared::default();
+    for input in inputs {
+        compared.inputs += 1;
+        let expected = equiv::outcome(program, input.clone(), None, within);
+        if let Outcome::Running(_) = expected {
+            compared.unfinished += 1;
+            continue;
+        }
+        for backend in backends {
+            let actual = backend.run(program, &input, within)?;
+            if !expected.matches(&actual) {
+                return Ok(Err(Mismatch { input, backend: backend.to_string(), expected, actual }));
+            }
+        }
+    }
+    Ok(Ok(compared))
+}
+
+#[test]
+fn test_difftest() {
+    use crate::inputs::InputSpace;
+
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: DEC 2\nSTOP".to_string()).unwrap();
+    let inputs = InputSpace::new(&program, 0..3, 0..4);
+    let dir = std::env::temp_dir().join(format!("goto-difftest-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let backends = backends(&program, inputs.size, None, &dir).unwrap();
+    assert_eq!(Ok(Compared { inputs: 64, unfinished: 16 }), check(&program, &backends, inputs.iter(), 12).unwrap());
+
+    // a C program that forgets to take the value from cell 0
+    let wrong = dir.join("wrong.sh");
+    fs::write(&wrong, "#!/bin/sh\nif [ \"$3\" = 0 ]; then echo 'DEC 2 on a cell that is 0' >&2; exit 1; fi\necho \"result: [$1, $2, $(($3 - 1))]\"\n").unwrap();
+    fs::set_permissions(&wrong, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    let mismatch = check(&program, &[Backend::C(wrong)], inputs.iter(), 1000).unwrap().unwrap_err();
+    assert_eq!(Mismatch {
+        input: vec![1, 0, 1],
+        backend: "c".to_string(),
+        expected: Outcome::Stopped(vec![0, 1, 0]),
+        actual: Outcome::Stopped(vec![1, 0, 0]),
+    }, mismatch);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,80 @@
+//! Turns a parsed program back into source text.
+
+use std::collections::BTreeMap;
+
+use crate::{formatter, GotoProgram, Instruction};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DisasmOptions {
+    /// Jump to labels instead of instruction indices, named `L<index>` where
+    /// `names` has no name for the target.
+    pub labels: bool,
+    /// Add the instruction index as a comment to every instruction.
+    pub indices: bool,
+}
+
+/// Canonically formatted source for `program`, `names` are known labels with
+/// the index of the instruction they name.
+pub fn disassemble(program: &GotoProgram, names: &[(String, usize)], options: DisasmOptions) -> String {
+    let count = program.instructions.len();
+    let mut labels: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    if options.labels {
+        for (name, index) in names {
+            labels.entry(*index).or_default().push(name.clone());
+        }
+        for instruction in &program.instructions {
+            let target = match instruction.jump_target() {
+                Some(cell) if cell <= count => cell,
+                _ => continue,
+            };
+            labels.entry(target).or_insert_with(|| {
+                let mut name = format!("L{}", target);
+                while names.iter().any(|(n, _)| *n == name) {
+                    name.push('_');
+                }
+                vec![name]
+            });
+        }
+    }
+    let target = |cell: usize| labels.get(&cell).map(|names| names[0].clone()).unwrap_or_else(|| cell.to_string());
+    let mut text = String::new();
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        for name in labels.get(&index).into_iter().flatten() {
+            text += &format!("{}:\n", name);
+        }
+        text += &match *instruction {
+            Instruction::Goto { cell } => format!("GOTO {}", target(cell)),
+            Instruction::GotoZ { condition_cell, goto_cell } => format!("GOTOZ {} {}", condition_cell, target(goto_cell)),
+            Instruction::GotoNz { condition_cell, goto_cell } => format!("GOTONZ {} {}", condition_cell, target(goto_cell)),
+            Instruction::GotoEq { left, right, goto_cell } => format!("GOTOEQ {} {} {}", left, right, target(goto_cell)),
+            Instruction::GotoGt { left, right, goto_cell } => format!("GOTOGT {} {} {}", left, right, target(goto_cell)),
+            ref other => other.to_string(),
+        };
+        if options.indices {
+            text += &format!(" ; {}", index);
+        }
+        text.push('\n');
+    }
+    for name in labels.get(&count).into_iter().flatten() {
+        text += &format!("{}:\n", name);
+    }
+    formatter::format_source(&text).expect("disassembled programs parse")
+}
+
+#[test]
+fn test_disassemble() {
+    let program = crate::parse_program("start: GOTOZ 0 5\nDEC 0\nGOTO start\nINC 1\nGOTO 9\nSTOP".to_string()).unwrap();
+    let plain = disassemble(&program, &[], DisasmOptions::default());
+    assert_eq!("    GOTOZ 0 5\n    DEC   0\n    GOTO  0\n    INC   1\n    GOTO  9\n    STOP\n", plain);
+    let labeled = disassemble(&program, &[("start".to_string(), 0)], DisasmOptions { labels: true, indices: true });
+    assert_eq!("start:
+    GOTOZ 0 L5  ; 0
+    DEC   0     ; 1
+    GOTO  start ; 2
+    INC   1     ; 3
+    GOTO  9     ; 4
+L5:
+    STOP        ; 5
+", labeled);
+    assert_eq!(program.instructions, crate::parse_program(labeled).unwrap().instructions);
+}
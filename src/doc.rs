@@ -0,0 +1,225 @@
+//! Documentation of programs and libraries from their doc comments. A
+//! comment of `;;` lines right before a label or a `.macro` documents that
+//! routine, `@param cell text` describes a cell it reads and `@returns cell
+//! text` a cell it leaves its result in. A doc comment before the code that
+//! documents no routine documents the whole file.
+//!
+//! Every macro is part of the summary, labels only with a doc comment, the
+//! other labels are jump targets within a routine.
+
+use crate::source;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Routine {
+    pub name: String,
+    /// the name with the parameters of a macro
+    pub signature: String,
+    pub line: usize,
+    pub description: String,
+    pub params: Vec<(String, String)>,
+    pub returns: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Documentation {
+    pub description: String,
+    pub routines: Vec<Routine>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 2] = ["markdown", "html"];
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "markdown" => Some(Format::Markdown),
+            "html" => Some(Format::Html),
+            _ => None,
+        }
+    }
+}
+
+/// The text of a doc comment line, `None` for other lines.
+fn doc_line(line: &str) -> Option<&str> {
+    let text = line.trim_start().strip_prefix(";;")?;
+    Some(text.strip_prefix(' ').unwrap_or(text).trim_end())
+}
+
+/// Fills the description and tags of a routine from its doc comment.
+fn parse_comment(routine: &mut Routine, comment: &[(usize, &str)], parameters: Option<&[String]>) -> Result<(), String> {
+    let mut description = vec![];
+    // whether the last tag was a @returns, the lines after a tag continue it
+    let mut returns = None;
+    for &(line_nr, text) in comment {
+        let error = |e: String| format!("error in line {}: {}", line_nr + 1, e);
+        if !text.starts_with('@') {
+            let tag = match returns {
+                Some(true) => routine.returns.last_mut(),
+                Some(false) => routine.params.last_mut(),
+                None => None,
+            };
+            match tag {
+                Some((_, tag)) if !text.is_empty() => *tag = format!("{} {}", tag, text.trim()).trim().to_string(),
+                Some(_) => {}
+                None => description.push(text),
+            }
+            continue;
+        }
+        let mut words = text.splitn(3, char::is_whitespace);
+        let (tag, cell, text) = (words.next().unwrap(), words.next().unwrap_or_default(), words.next().unwrap_or_default().trim());
+        let tags = match tag {
+            "@param" => &mut routine.params,
+            "@returns" => &mut routine.returns,
+            _ => return Err(error(format!("Unknown tag: {}, expected @param or @returns", tag))),
+        };
+        if cell.is_empty() {
+            return Err(error(format!("expected {} <cell> <description>", tag)));
+        }
+        if parameters.is_some_and(|parameters| !parameters.iter().any(|parameter| parameter == cell)) {
+            return Err(error(format!("{} is no parameter of {}", cell, routine.name)));
+        }
+        tags.push((cell.to_string(), text.to_string()));
+        returns = Some(tag == "@returns");
+    }
+    routine.description = description.join("\n").trim().to_string();
+    Ok(())
+}
+
+pub fn extract(text: &str) -> Result<Documentation, String> {
+    let mut documentation = Documentation::default();
+    let mut comment: Vec<(usize, &str)> = vec![];
+    let mut code = false;
+    for (line_nr, line) in text.lines().enumerate() {
+        if let Some(text) = doc_line(line) {
+            comment.push((line_nr, text));
+            continue;
+        }
+        let segments = source::parse_segments(line).map_err(|e| format!("error in line {}: {}", line_nr + 1, e))?;
+        let routine = match segments.first() {
+            Some(segment) if segment.tokens.first().is_some_and(|token| token == ".macro") && segment.tokens.len() > 1 => {
+                Some((segment.tokens[1].clone(), segment.tokens[1..].join(" "), Some(&segment.tokens[2..])))
+            }
+            Some(segment) => segment.label.clone().filter(|_| !comment.is_empty()).map(|label| (label.clone(), label, None)),
+            None => None,
+        };
+        match routine {
+            Some((name, signature, parameters)) => {
+                let mut routine = Routine { name, signature, line: line_nr + 1, ..Routine::default() };
+                parse_comment(&mut routine, &comment, parameters)?;
+                documentation.routines.push(routine);
+            }
+            None if !comment.is_empty() && !code && documentation.description.is_empty() => {
+                documentation.description = comment.iter().map(|&(_, text)| text).collect::<Vec<_>>().join("\n").trim().to_string();
+            }
+            None if !comment.is_empty() && !line.trim().is_empty() => {
+                return Err(format!("error in line {}: a doc comment has to be followed by a label or .macro", line_nr + 1));
+            }
+            None => {}
+        }
+        comment.clear();
+        code |= segments.iter().any(|segment| segment.label.is_some() || !segment.tokens.is_empty());
+    }
+    if !code && documentation.description.is_empty() {
+        documentation.description = comment.iter().map(|&(_, text)| text).collect::<Vec<_>>().join("\n").trim().to_string();
+    }
+    Ok(documentation)
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn markdown(name: &str, documentation: &Documentation) -> String {
+    let mut text = format!("# {}\n", name);
+    if !documentation.description.is_empty() {
+        text += &format!("\n{}\n", documentation.description);
+    }
+    for routine in &documentation.routines {
+        text += &format!("\n## `{}`\n", routine.signature);
+        if !routine.description.is_empty() {
+            text += &format!("\n{}\n", routine.description);
+        }
+        for (heading, tags) in [("Parameters", &routine.params), ("Returns", &routine.returns)] {
+            if !tags.is_empty() {
+                text += &format!("\n{}:\n\n", heading);
+                for (cell, description) in tags {
+                    text += &format!("- `{}`: {}\n", cell, description);
+                }
+            }
+        }
+    }
+    text
+}
+
+/// The paragraphs of a description, separated by empty lines.
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n").map(|paragraph| format!("<p>{}</p>\n", escape_html(paragraph.trim()))).collect()
+}
+
+fn html(name: &str, documentation: &Documentation) -> String {
+    let mut text = format!("<section>\n<h1>{}</h1>\n", escape_html(name));
+    if !documentation.description.is_empty() {
+        text += &paragraphs(&documentation.description);
+    }
+    for routine in &documentation.routines {
+        text += &format!("<h2 id=\"{}\"><code>{}</code></h2>\n", escape_html(&routine.name), escape_html(&routine.signature));
+        if !routine.description.is_empty() {
+            text += &paragraphs(&routine.description);
+        }
+        for (heading, tags) in [("Parameters", &routine.params), ("Returns", &routine.returns)] {
+            if !tags.is_empty() {
+                text += &format!("<h3>{}</h3>\n<ul>\n", heading);
+                for (cell, description) in tags {
+                    text += &format!("<li><code>{}</code>: {}</li>\n", escape_html(cell), escape_html(description));
+                }
+                text += "</ul>\n";
+            }
+        }
+    }
+    text + "</section>\n"
+}
+
+/// The documentation of the files with their names, one HTML page for all.
+pub fn render(format: Format, files: &[(String, Documentation)]) -> String {
+    match format {
+        Format::Markdown => files.iter().map(|(name, documentation)| markdown(name, documentation)).collect::<Vec<_>>().join("\n"),
+        Format::Html => {
+            let title = files.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", ");
+            let body: String = files.iter().map(|(name, documentation)| html(name, documentation)).collect();
+            format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n", escape_html(&title), body)
+        }
+    }
+}
+
+#[test]
+fn test_doc() {
+    let text = ";; Doubles numbers.\n\n.alias x 0\n;; doubles x into cell 1\n;;\n;; x is 0 afterwards\n;; @param x the number\n;; @returns 1 twice the\n;;   number\ndouble: GOTOZ x end\nloop: DEC x\n;; the end\nend: STOP";
+    let documentation = extract(text).unwrap();
+    assert_eq!("Doubles numbers.", documentation.description);
+    assert_eq!(vec!["double", "end"], documentation.routines.iter().map(|routine| routine.name.as_str()).collect::<Vec<_>>());
+    assert_eq!(Routine {
+        name: "double".to_string(),
+        signature: "double".to_string(),
+        line: 10,
+        description: "doubles x into cell 1\n\nx is 0 afterwards".to_string(),
+        params: vec![("x".to_string(), "the number".to_string())],
+        returns: vec![("1".to_string(), "twice the number".to_string())],
+    }, documentation.routines[0]);
+    assert_eq!("# a.goto\n\nDoubles numbers.\n\n## `double`\n\ndoubles x into cell 1\n\nx is 0 afterwards\n\nParameters:\n\n- `x`: the number\n\nReturns:\n\n- `1`: twice the number\n\n## `end`\n\nthe end\n",
+               render(Format::Markdown, &[("a.goto".to_string(), documentation.clone())]));
+    let html = render(Format::Html, &[("a<b>.goto".to_string(), documentation)]);
+    assert!(html.contains("<h1>a&lt;b&gt;.goto</h1>\n<p>Doubles numbers.</p>\n<h2 id=\"double\"><code>double</code></h2>\n<p>doubles x into cell 1</p>\n<p>x is 0 afterwards</p>\n<h3>Parameters</h3>\n<ul>\n<li><code>x</code>: the number</li>\n</ul>\n"));
+
+    let arith = extract(crate::macros::library("std/arith").unwrap()).unwrap();
+    assert!(arith.description.starts_with("Arithmetic on cells"));
+    assert_eq!(11, arith.routines.len());
+    assert_eq!(("DIV a b q r t u", 2), (arith.routines[7].signature.as_str(), arith.routines[7].returns.len()));
+    assert_eq!(Err("error in line 1: c is no parameter of A".to_string()), extract(";; @param c x\n.macro A a\n.endmacro"));
+    assert_eq!(Err("error in line 1: Unknown tag: @return, expected @param or @returns".to_string()), extract(";; @return 0\na: STOP"));
+    assert!(extract("STOP\n;; what?\nINC 0").is_err());
+}
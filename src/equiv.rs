@@ -0,0 +1,119 @@
+//! Checks that two programs compute the same outputs, by running both on
+//! every input of an input space or on random samples of it.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::batch::map_parallel;
+use crate::{GotoProgram, GotoProgramState};
+
+/// How a bounded run ended, the memory of a stopped run limited to the
+/// output cells.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Outcome {
+    Stopped(Vec<u64>),
+    Failed(String),
+    Running(u64),
+}
+
+impl Outcome {
+    /// Runs that both fail count as the same, the error messages name
+    /// instructions that differ between the programs.
+    pub(crate) fn matches(&self, other: &Outcome) -> bool {
+        match (self, other) {
+            (Outcome::Stopped(a), Outcome::Stopped(b)) => a == b,
+            (Outcome::Failed(_), Outcome::Failed(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Stopped(output) => write!(f, "stops with {:?}", output),
+            Outcome::Failed(error) => write!(f, "fails: {}", error),
+            Outcome::Running(steps) => write!(f, "is still running after {} steps", steps),
+        }
+    }
+}
+
+/// Runs `program` on `input`, `outputs` are the cells to compare, all of the
+/// memory if `None`.
+pub fn outcome(program: &GotoProgram, input: Vec<u64>, outputs: Option<&Range<usize>>, within: u64) -> Outcome {
+    let mut state = GotoProgramState::new(program, input);
+    match state.run_with_limit(within) {
+        Ok(true) => Outcome::Stopped(match outputs {
+            Some(cells) => cells.clone().map(|cell| state.memory.get(cell).cloned().unwrap_or(0)).collect(),
+            None => state.memory,
+        }),
+        Ok(false) => Outcome::Running(within),
+        Err(e) => Outcome::Failed(e.to_string()),
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Divergence {
+    pub input: Vec<u64>,
+    pub first: Outcome,
+    pub second: Outcome,
+}
+
+/// How many inputs were checked, and on how many of them the outcomes were
+/// not compared because a program was still running after the step bound.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Compared {
+    pub inputs: u64,
+    pub unfinished: u64,
+}
+
+/// Compares the programs on every input, returns the first input on which
+/// they differ. A slower program is not different, so inputs on which one of
+/// them runs out of steps are skipped. The inputs are run in blocks on
+/// `jobs` threads and compared in order, so the divergence found does not
+/// depend on the number of threads.
+pub fn check(first: &GotoProgram, second: &GotoProgram, inputs: impl Iterator<Item = Vec<u64>>,
+             outputs: Option<&Range<usize>>, within: u64, jobs: usize) -> Result<Compared, Divergence> {
+    let mut compared = Compared::default();
+    let mut inputs = inputs.peekable();
+    while inputs.peek().is_some() {
+        let block: Vec<_> = inputs.by_ref().take(jobs.max(1) * 256).collect();
+        let outcomes = map_parallel(&block, jobs, |input| {
+            (outcome(first, input.clone(), outputs, within), outcome(second, input.clone(), outputs, within))
+        });
+        for (input, (a, b)) in block.into_iter().zip(outcomes) {
+            compared.inputs += 1;
+            if matches!(a, Outcome::Running(_)) || matches!(b, Outcome::Running(_)) {
+                compared.unfinished += 1;
+            } else if !a.matches(&b) {
+                return Err(Divergence { input, first: a, second: b });
+            }
+        }
+    }
+    Ok(compared)
+}
+
+#[test]
+fn test_equiv() {
+    use crate::inputs::InputSpace;
+
+    // both add cell 0 to cell 1, the second one through cell 2
+    let direct = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let detour = crate::parse_program("a: GOTOZ 0 b
+        DEC 0
+        INC 2
+        GOTO a
+        b: GOTOZ 2 end
+        DEC 2
+        INC 1
+        GOTO b
+        end: STOP".to_string()).unwrap();
+    let inputs = InputSpace { cells: 0..2, values: 0..10, size: 3 };
+    assert_eq!(Ok(Compared { inputs: 100, unfinished: 0 }), check(&direct, &detour, inputs.iter(), None, 1000, 1));
+    assert_eq!(Ok(Compared { inputs: 100, unfinished: 70 }), check(&direct, &detour, inputs.iter(), None, 20, 3));
+
+    let off_by_one = crate::parse_program("INC 1\nloop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    assert_eq!(Err(Divergence { input: vec![0, 0, 0], first: Outcome::Stopped(vec![0]), second: Outcome::Stopped(vec![1]) }),
+               check(&direct, &off_by_one, inputs.iter(), Some(&(1..2)), 1000, 4));
+    assert_eq!("stops with [1]", Outcome::Stopped(vec![1]).to_string());
+}
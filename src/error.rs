@@ -0,0 +1,165 @@
+//! The errors of the library by class: the program does not parse, running
+//! it fails, a file cannot be read or written, or a flag or an input is
+//! invalid. The command line exits with a different code for each class.
+
+use std::{fmt, io};
+
+use crate::{Instruction, ParseError, RegisterIndex};
+
+/// At most that many parse errors are shown, then only how many more there are.
+pub const MAX_PARSE_ERRORS: usize = 20;
+
+/// Why executing an instruction failed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RuntimeError {
+    /// a cell beyond the memory, which has `size` cells
+    OutsideMemory { cell: RegisterIndex, size: usize },
+    OutsideProgram { program_counter: usize },
+    /// INC on the largest value
+    Overflow { cell: RegisterIndex },
+    /// DEC on 0
+    Underflow { cell: RegisterIndex },
+    AssertFailed { instruction: Instruction, cell: RegisterIndex, actual: u64 },
+    /// RAND with a maximum of 0
+    EmptyRange { instruction: Instruction },
+    EmptyStack { cell: RegisterIndex },
+    /// READ, PRINT or OUTC failed on the console
+    Console(String),
+    /// a von Neumann program has a cell that is no instruction
+    Decode(String),
+    /// the compiled, bytecode or JIT run failed
+    Backend(String),
+    /// the run left the golden trace
+    Diverged(String),
+    /// a detector ended a run that makes no progress, like `--detect-cycles`
+    Stalled(String),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::OutsideMemory { cell, size } => write!(f, "cell {} is outside of the memory of size {}", cell, size),
+            RuntimeError::OutsideProgram { program_counter } => write!(f, "program counter {} is outside of the program", program_counter),
+            RuntimeError::Overflow { cell } => write!(f, "INC {} overflows", cell),
+            RuntimeError::Underflow { cell } => write!(f, "DEC {} on a cell that is 0", cell),
+            RuntimeError::AssertFailed { instruction, cell, actual } => write!(f, "{} failed, cell {} is {}", instruction, cell, actual),
+            RuntimeError::EmptyRange { instruction } => write!(f, "{} has no number below 0", instruction),
+            RuntimeError::EmptyStack { cell } => write!(f, "POP {} on an empty stack", cell),
+            RuntimeError::Console(message) | RuntimeError::Decode(message)
+            | RuntimeError::Backend(message) | RuntimeError::Diverged(message) | RuntimeError::Stalled(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// For the callers that still report errors as text.
+impl From<RuntimeError> for String {
+    fn from(error: RuntimeError) -> String {
+        error.to_string()
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum Error {
+    /// the errors of a program, with its text to show them in
+    Parse { errors: Vec<ParseError>, source: String },
+    /// a run that failed at `program_counter` after `steps` steps
+    Runtime { program_counter: usize, steps: u64, error: RuntimeError },
+    /// what the command was doing when the file access failed
+    Io { context: String, error: io::Error },
+    /// a flag, an input or a file that is not what it should be
+    Invalid { context: String, reason: String },
+}
+
+impl Error {
+    /// 1 is left for commands whose check fails, like `lint` or `verify`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Parse { .. } => 2,
+            Error::Runtime { .. } => 3,
+            Error::Io { .. } => 4,
+            Error::Invalid { .. } => 5,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse { errors, source } => {
+                let shown: Vec<_> = errors.iter().take(MAX_PARSE_ERRORS).map(|error| error.render(source)).collect();
+                write!(f, "{}", shown.join("\n\n"))?;
+                if errors.len() > MAX_PARSE_ERRORS {
+                    write!(f, "\n\nand {} more errors", errors.len() - MAX_PARSE_ERRORS)?;
+                }
+                Ok(())
+            }
+            Error::Runtime { program_counter, steps, error } => write!(f, "error at pc {} after {} steps: {}", program_counter, steps, error),
+            Error::Io { context, error } => write!(f, "Error while {}: {}", context, error),
+            Error::Invalid { context, reason } => write!(f, "Error while {}: {}", context, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            Error::Runtime { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// An error that can be the reason of an `Error`, which decides its class.
+pub trait Reason: fmt::Display {
+    fn with_context(self, context: &str) -> Error where Self: Sized {
+        Error::Invalid { context: context.to_string(), reason: self.to_string() }
+    }
+}
+
+impl Reason for io::Error {
+    fn with_context(self, context: &str) -> Error {
+        Error::Io { context: context.to_string(), error: self }
+    }
+}
+
+impl Reason for String {}
+impl Reason for std::num::ParseIntError {}
+impl Reason for std::num::ParseFloatError {}
+
+/// Says what was being done when a `Result` failed.
+pub trait Context<T> {
+    fn context(self, context: &str) -> Result<T, Error>;
+}
+
+impl<T, E: Reason> Context<T> for Result<T, E> {
+    fn context(self, context: &str) -> Result<T, Error> {
+        self.map_err(|error| error.with_context(context))
+    }
+}
+
+#[test]
+fn test_error() {
+    let program = crate::parse_program("INC 0\nDEC 0\nDEC 0".to_string()).unwrap();
+    let mut state = crate::GotoProgramState::new(&program, vec![0]);
+    assert_eq!(Err(RuntimeError::Underflow { cell: 0 }), state.run_with_limit(10));
+    let error = Error::Runtime { program_counter: state.program_counter, steps: state.steps, error: RuntimeError::Underflow { cell: 0 } };
+    assert_eq!("error at pc 2 after 2 steps: DEC 0 on a cell that is 0", error.to_string());
+    assert_eq!(3, error.exit_code());
+
+    let source = "INC\nDEC".to_string();
+    let errors = crate::parse_program_errors(source.clone(), &crate::ParseOptions::default()).unwrap_err();
+    let error = Error::Parse { errors, source };
+    assert_eq!(2, error.exit_code());
+    assert!(error.to_string().starts_with("error in line 1: Not 2 tokens in: INC\n"));
+
+    let error = std::fs::read("/nonexistent/goto").map(|_| ()).context("reading code").unwrap_err();
+    assert_eq!(4, error.exit_code());
+    assert!(std::error::Error::source(&error).is_some());
+    let error = "x".parse::<u64>().context("parsing --seed").unwrap_err();
+    assert_eq!((5, "Error while parsing --seed: invalid digit found in string".to_string()), (error.exit_code(), error.to_string()));
+}
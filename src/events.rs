@@ -0,0 +1,83 @@
+//! An execution as an iterator of what every step did, for callers that
+//! drive a program one instruction at a time and look at each step, like a
+//! debugger, a GUI or a test. The iterator ends with the STOP or the error.
+
+use crate::{GotoProgramState, Instruction, RuntimeError, StepEffect};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StepEvent {
+    /// an instruction other than STOP ran
+    Executed { instruction: Instruction, effect: StepEffect },
+    /// STOP ran, the last event
+    Stopped { effect: StepEffect },
+    /// the instruction at the program counter failed, the last event, the
+    /// state is as it was before
+    Failed { program_counter: usize, error: RuntimeError },
+}
+
+pub struct Events<'s, 'a> {
+    state: &'s mut GotoProgramState<'a>,
+    done: bool,
+}
+
+impl<'s, 'a> Events<'s, 'a> {
+    pub fn new(state: &'s mut GotoProgramState<'a>) -> Events<'s, 'a> {
+        Events { state, done: false }
+    }
+
+    /// The state between steps, for looking at the memory.
+    pub fn state(&self) -> &GotoProgramState<'a> {
+        self.state
+    }
+}
+
+impl Iterator for Events<'_, '_> {
+    type Item = StepEvent;
+
+    fn next(&mut self) -> Option<StepEvent> {
+        if self.done {
+            return None;
+        }
+        let program_counter = self.state.program_counter;
+        // in von Neumann mode the instruction is only known before it runs
+        let instruction = self.state.instruction();
+        let mut effect = None;
+        let event = match self.state.step_with_hook(&mut |executed| effect = Some(*executed)) {
+            Ok(true) => StepEvent::Stopped { effect: effect.unwrap() },
+            Ok(false) => StepEvent::Executed { instruction: instruction.unwrap(), effect: effect.unwrap() },
+            Err(error) => StepEvent::Failed { program_counter, error },
+        };
+        self.done = !matches!(event, StepEvent::Executed { .. });
+        Some(event)
+    }
+}
+
+#[test]
+fn test_events() {
+    use crate::MemoryWrite;
+
+    let program = crate::parse_program("INC 0\nGOTOZ 1 3\nDEC 1\nSTOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![0, 0]);
+    let events: Vec<_> = state.events().collect();
+    assert_eq!(vec![
+        StepEvent::Executed {
+            instruction: Instruction::Inc { cell: 0 },
+            effect: StepEffect { program_counter: 0, write: Some(MemoryWrite { cell: 0, old: 0, new: 1 }), stack: None },
+        },
+        StepEvent::Executed { instruction: Instruction::GotoZ { condition_cell: 1, goto_cell: 3 }, effect: StepEffect { program_counter: 1, write: None, stack: None } },
+        StepEvent::Stopped { effect: StepEffect { program_counter: 3, write: None, stack: None } },
+    ], events);
+    assert_eq!(3, state.steps);
+
+    let mut state = GotoProgramState::new(&program, vec![0, 1]);
+    let mut events = state.events();
+    assert_eq!(2, events.by_ref().take(2).count());
+    assert_eq!((vec![1, 1], 2), (events.state().memory.clone(), events.state().program_counter));
+    assert_eq!(2, events.count());
+
+    let program = crate::parse_program("DEC 0".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![0]);
+    let mut events = state.events();
+    assert_eq!(Some(StepEvent::Failed { program_counter: 0, error: RuntimeError::Underflow { cell: 0 } }), events.next());
+    assert_eq!(None, events.next());
+}
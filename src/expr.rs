@@ -0,0 +1,103 @@
+//! Operands that are computed while parsing, like `loop+2`, `(5*8)` or
+//! `BASE+1` with `.const BASE 10`. An expression is a single token, it has
+//! numbers, names, `+ - * / %` with the usual precedence and parentheses.
+//! Every intermediate value has to be a `u64`.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+struct Parser<'a, F> {
+    text: &'a str,
+    chars: Peekable<Chars<'a>>,
+    lookup: F,
+}
+
+impl<F: Fn(&str) -> Result<u64, String>> Parser<'_, F> {
+    fn error(&self, what: &str) -> String {
+        format!("{} in expression: {}", what, self.text)
+    }
+
+    fn sum(&mut self) -> Result<u64, String> {
+        let mut value = self.product()?;
+        while let Some(&op) = self.chars.peek().filter(|&&c| c == '+' || c == '-') {
+            self.chars.next();
+            let right = self.product()?;
+            value = if op == '+' { value.checked_add(right) } else { value.checked_sub(right) }
+                .ok_or_else(|| self.error(if op == '+' { "overflow" } else { "negative value" }))?;
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<u64, String> {
+        let mut value = self.atom()?;
+        while let Some(&op) = self.chars.peek().filter(|&&c| c == '*' || c == '/' || c == '%') {
+            self.chars.next();
+            let right = self.atom()?;
+            value = match op {
+                '*' => value.checked_mul(right).ok_or_else(|| self.error("overflow"))?,
+                '/' => value.checked_div(right).ok_or_else(|| self.error("division by 0"))?,
+                _ => value.checked_rem(right).ok_or_else(|| self.error("division by 0"))?,
+            };
+        }
+        Ok(value)
+    }
+
+    fn atom(&mut self) -> Result<u64, String> {
+        match self.chars.peek().copied() {
+            Some('(') => {
+                self.chars.next();
+                let value = self.sum()?;
+                match self.chars.next() {
+                    Some(')') => Ok(value),
+                    _ => Err(self.error("missing )")),
+                }
+            }
+            Some(c) if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                    word.push(c);
+                    self.chars.next();
+                }
+                if word.starts_with(|c: char| c.is_ascii_digit()) {
+                    word.parse().map_err(|e| format!("{} is not a number (reason: {:?})", word, e))
+                } else {
+                    (self.lookup)(&word)
+                }
+            }
+            Some(c) => Err(self.error(&format!("unexpected {}", c))),
+            None => Err(self.error("missing operand")),
+        }
+    }
+}
+
+/// The value of the expression, with `lookup` giving the values of names.
+pub fn eval(text: &str, lookup: impl Fn(&str) -> Result<u64, String>) -> Result<u64, String> {
+    let mut parser = Parser { text, chars: text.chars().peekable(), lookup };
+    let value = parser.sum()?;
+    match parser.chars.next() {
+        None => Ok(value),
+        Some(c) => Err(parser.error(&format!("unexpected {}", c))),
+    }
+}
+
+#[test]
+fn test_eval() {
+    let lookup = |name: &str| match name {
+        "BASE" => Ok(10),
+        _ => Err(format!("Unknown constant: {}", name)),
+    };
+    assert_eq!(Ok(42), eval("42", lookup));
+    assert_eq!(Ok(11), eval("BASE+1", lookup));
+    assert_eq!(Ok(40), eval("(5*8)", lookup));
+    assert_eq!(Ok(23), eval("3+BASE*2", lookup));
+    assert_eq!(Ok(26), eval("(3+BASE)*2", lookup));
+    assert_eq!(Ok(4), eval("BASE-6%4*3", lookup));
+    assert_eq!(Err("Unknown constant: TOP".to_string()), eval("TOP-1", lookup));
+    assert_eq!(Err("negative value in expression: 1-2".to_string()), eval("1-2", lookup));
+    assert_eq!(Err("division by 0 in expression: BASE/0".to_string()), eval("BASE/0", lookup));
+    assert_eq!(Err("missing ) in expression: (1+2".to_string()), eval("(1+2", lookup));
+    assert_eq!(Err("unexpected ) in expression: 1)".to_string()), eval("1)", lookup));
+    assert!(eval("1+", lookup).is_err());
+    assert!(eval("18446744073709551615+1", lookup).is_err());
+    assert!(eval("12x", lookup).is_err());
+}
@@ -0,0 +1,168 @@
+//! An `extern "C"` interface for embedding the interpreter in programs
+//! written in other languages.
+//!
+//! `cargo build --release --features ffi` builds the library as
+//! `target/release/libgoto.so`, the header `include/goto.h` is generated with
+//! `cbindgen --config cbindgen.toml --output include/goto.h src/ffi.rs`.
+//! A machine from `goto_parse` is run with `goto_run`, read with
+//! `goto_get_cell` and released with `goto_free`. Functions that fail leave
+//! their message for `goto_last_error`.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::{ptr, slice};
+
+use crate::{parse_program, GotoProgram, GotoProgramState};
+
+/// `goto_run` executed STOP.
+pub const GOTO_STOPPED: i32 = 0;
+/// `goto_run` reached its step limit, another call continues.
+pub const GOTO_RUNNING: i32 = 1;
+pub const GOTO_ERROR: i32 = 2;
+
+/// A parsed program with the memory, program counter and steps of its run.
+pub struct GotoMachine {
+    program: GotoProgram,
+    memory: Vec<u64>,
+    program_counter: usize,
+    steps: u64,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_error(error: String) {
+    let error = CString::new(error.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+}
+
+/// Parses the NUL-terminated UTF-8 source, returns null if it is invalid.
+///
+/// # Safety
+/// `source` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn goto_parse(source: *const c_char) -> *mut GotoMachine {
+    if source.is_null() {
+        set_error("the source is null".to_string());
+        return ptr::null_mut();
+    }
+    let program = CStr::from_ptr(source).to_str().map_err(|e| e.to_string())
+        .and_then(|text| parse_program(text.to_string()));
+    match program {
+        Ok(program) => Box::into_raw(Box::new(GotoMachine { program, memory: vec![], program_counter: 0, steps: 0 })),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Starts the program from the beginning on the `len` cells at `input` and
+/// runs it for at most `max_steps` steps. With a null `input` it continues
+/// where the last call stopped. Returns `GOTO_STOPPED`, `GOTO_RUNNING` or
+/// `GOTO_ERROR`, after an error the machine is at the failed instruction.
+///
+/// # Safety
+/// `machine` must come from `goto_parse`, `input` must be null or point to
+/// `len` readable `uint64_t`s.
+#[no_mangle]
+pub unsafe extern "C" fn goto_run(machine: *mut GotoMachine, input: *const u64, len: usize, max_steps: u64) -> i32 {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => {
+            set_error("the machine is null".to_string());
+            return GOTO_ERROR;
+        }
+    };
+    if !input.is_null() {
+        machine.memory = slice::from_raw_parts(input, len).to_vec();
+        machine.program_counter = 0;
+        machine.steps = 0;
+    }
+    let mut state = GotoProgramState::new(&machine.program, std::mem::take(&mut machine.memory));
+    state.program_counter = machine.program_counter;
+    state.steps = machine.steps;
+    let result = state.run_with_limit(machine.steps.saturating_add(max_steps));
+    machine.memory = std::mem::take(&mut state.memory);
+    machine.program_counter = state.program_counter;
+    machine.steps = state.steps;
+    match result {
+        Ok(true) => GOTO_STOPPED,
+        Ok(false) => GOTO_RUNNING,
+        Err(e) => {
+            set_error(e.to_string());
+            GOTO_ERROR
+        }
+    }
+}
+
+/// The value of a cell, 0 for a cell outside of the memory.
+///
+/// # Safety
+/// `machine` must be null or come from `goto_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn goto_get_cell(machine: *const GotoMachine, cell: usize) -> u64 {
+    machine.as_ref().and_then(|machine| machine.memory.get(cell).cloned()).unwrap_or(0)
+}
+
+/// # Safety
+/// `machine` must be null or come from `goto_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn goto_memory_len(machine: *const GotoMachine) -> usize {
+    machine.as_ref().map_or(0, |machine| machine.memory.len())
+}
+
+/// # Safety
+/// `machine` must be null or come from `goto_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn goto_pc(machine: *const GotoMachine) -> usize {
+    machine.as_ref().map_or(0, |machine| machine.program_counter)
+}
+
+/// # Safety
+/// `machine` must be null or come from `goto_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn goto_steps(machine: *const GotoMachine) -> u64 {
+    machine.as_ref().map_or(0, |machine| machine.steps)
+}
+
+/// The message of the last failed call on this thread, valid until the next
+/// call that fails.
+#[no_mangle]
+pub extern "C" fn goto_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// # Safety
+/// `machine` must be null or come from `goto_parse` and not be used after.
+#[no_mangle]
+pub unsafe extern "C" fn goto_free(machine: *mut GotoMachine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+#[test]
+fn test_ffi() {
+    let text = |text: &str| CString::new(text).unwrap();
+    let error = || unsafe { CStr::from_ptr(goto_last_error()) }.to_str().unwrap().to_string();
+    unsafe {
+        assert!(goto_parse(text("GOTO nowhere").as_ptr()).is_null());
+        assert_eq!("error in line 1: Unknown label: nowhere", error());
+
+        let machine = goto_parse(text("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP").as_ptr());
+        assert_eq!(GOTO_RUNNING, goto_run(machine, [2, 5].as_ptr(), 2, 3));
+        assert_eq!((1, 6, 3), (goto_get_cell(machine, 0), goto_get_cell(machine, 1), goto_steps(machine)));
+        assert_eq!(GOTO_STOPPED, goto_run(machine, ptr::null(), 0, 100));
+        assert_eq!((0, 7, 0, 2), (goto_get_cell(machine, 0), goto_get_cell(machine, 1), goto_get_cell(machine, 2), goto_memory_len(machine)));
+        assert_eq!((4, 10), (goto_pc(machine), goto_steps(machine)));
+        goto_free(machine);
+
+        let machine = goto_parse(text("DEC 0").as_ptr());
+        assert_eq!(GOTO_ERROR, goto_run(machine, [0].as_ptr(), 1, 100));
+        assert_eq!("DEC 0 on a cell that is 0", error());
+        goto_free(machine);
+    }
+}
@@ -0,0 +1,76 @@
+//! A hash of an execution, equal for two runs exactly when they executed
+//! the same instructions and made the same writes, on any machine and with
+//! any version of `goto`.
+//!
+//! Every step adds a 0 byte and its program counter, every write a 1 byte
+//! and its cell, old and new value, all numbers as 8 little endian bytes, to
+//! a 64 bit FNV-1a hash.
+
+use std::fmt;
+
+use crate::observer::ExecutionObserver;
+use crate::{GotoProgramState, Instruction, MemoryWrite};
+
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Fingerprint {
+    pub hash: u64,
+    pub steps: u64,
+}
+
+impl Default for Fingerprint {
+    fn default() -> Fingerprint {
+        Fingerprint { hash: OFFSET_BASIS, steps: 0 }
+    }
+}
+
+impl Fingerprint {
+    fn add(&mut self, tag: u8, numbers: &[u64]) {
+        let bytes = numbers.iter().flat_map(|number| number.to_le_bytes());
+        for byte in std::iter::once(tag).chain(bytes) {
+            self.hash = (self.hash ^ byte as u64).wrapping_mul(PRIME);
+        }
+    }
+}
+
+impl ExecutionObserver for Fingerprint {
+    fn before_step(&mut self, state: &GotoProgramState, _instruction: &Instruction) {
+        self.steps += 1;
+        self.add(0, &[state.program_counter as u64]);
+    }
+
+    fn on_memory_write(&mut self, write: &MemoryWrite) {
+        self.add(1, &[write.cell as u64, write.old, write.new]);
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.hash)
+    }
+}
+
+#[test]
+fn test_fingerprint() {
+    use std::sync::{Arc, Mutex};
+
+    let fingerprint = |text: &str, input: Vec<u64>| {
+        let program = crate::parse_program(text.to_string()).unwrap();
+        let mut state = GotoProgramState::new(&program, input);
+        let fingerprint = Arc::new(Mutex::new(Fingerprint::default()));
+        state.observers.push(Box::new(fingerprint.clone()));
+        assert_eq!(Ok(true), state.run_with_limit(100));
+        let fingerprint = *fingerprint.lock().unwrap();
+        fingerprint
+    };
+    let double = "loop: GOTOZ 0 end\nDEC 0\nINC 1\nINC 1\nGOTO loop\nend: STOP";
+    assert_eq!("cbf29ce484222325", Fingerprint::default().to_string());
+    assert_eq!(fingerprint(double, vec![2, 0]), fingerprint(&format!("; the same\n{}", double), vec![2, 0]));
+    assert_eq!(12, fingerprint(double, vec![2, 0]).steps);
+    assert_ne!(fingerprint(double, vec![2, 0]), fingerprint(double, vec![2, 1]));
+    // the same steps writing the same cells in another order
+    assert_ne!(fingerprint("INC 0\nINC 1\nSTOP", vec![0, 0]).hash, fingerprint("INC 1\nINC 0\nSTOP", vec![0, 0]).hash);
+    assert_eq!("e604823a249029bf", fingerprint("STOP", vec![]).to_string());
+}
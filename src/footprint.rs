@@ -0,0 +1,128 @@
+//! What a program needs of its memory, found without running it.
+//!
+//! Instructions only address cells directly, so the highest cell is exact.
+//! A cell is an input when some path reads it before writing it, a cell the
+//! program writes is scratch when the range analysis shows it to be 0 at
+//! every `STOP` and an output otherwise.
+
+use std::collections::BTreeSet;
+
+use crate::json::Value;
+use crate::layout::Layout;
+use crate::ranges::{self, Interval};
+use crate::{GotoProgram, Instruction, RegisterIndex};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Footprint {
+    /// how long the memory has to be for every cell the program uses
+    pub required_cells: usize,
+    pub inputs: Vec<RegisterIndex>,
+    pub outputs: Vec<RegisterIndex>,
+    pub scratch: Vec<RegisterIndex>,
+    /// cells with a value from `.data` or `.init`
+    pub data: Vec<RegisterIndex>,
+}
+
+/// The cells the instruction reads and the ones it sets without reading them.
+fn reads_and_sets(instruction: Instruction) -> (Vec<RegisterIndex>, Option<RegisterIndex>) {
+    match instruction {
+        Instruction::Inc { cell } | Instruction::Dec { cell } | Instruction::GotoZ { condition_cell: cell, .. }
+        | Instruction::GotoNz { condition_cell: cell, .. } | Instruction::Assert { cell, .. } | Instruction::Print { cell }
+        | Instruction::OutC { cell } | Instruction::Push { cell } => (vec![cell], None),
+        Instruction::GotoEq { left, right, .. } | Instruction::GotoGt { left, right, .. } => (vec![left, right], None),
+        Instruction::Read { cell } | Instruction::Rand { cell, .. } | Instruction::Pop { cell } | Instruction::StoreI { cell, .. } => (vec![], Some(cell)),
+        Instruction::Stop | Instruction::Goto { .. } | Instruction::Nop => (vec![], None),
+    }
+}
+
+fn writes(instruction: Instruction) -> Option<RegisterIndex> {
+    match instruction {
+        Instruction::Inc { cell } | Instruction::Dec { cell } => Some(cell),
+        _ => reads_and_sets(instruction).1,
+    }
+}
+
+fn successors(instruction: Instruction, pc: usize) -> Vec<usize> {
+    match instruction {
+        Instruction::Stop => vec![],
+        Instruction::Goto { cell } => vec![cell],
+        _ => instruction.jump_target().into_iter().chain(Some(pc + 1)).collect(),
+    }
+}
+
+/// The cells some path from the start reads before it writes them.
+fn live_at_start(program: &GotoProgram) -> BTreeSet<RegisterIndex> {
+    let count = program.instructions.len();
+    let mut live: Vec<BTreeSet<RegisterIndex>> = vec![BTreeSet::new(); count];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for pc in (0..count).rev() {
+            let instruction = program.instructions[pc];
+            let mut after: BTreeSet<RegisterIndex> = successors(instruction, pc).into_iter()
+                .filter(|&next| next < count)
+                .flat_map(|next| live[next].clone())
+                .collect();
+            let (reads, sets) = reads_and_sets(instruction);
+            if let Some(cell) = sets {
+                after.remove(&cell);
+            }
+            after.extend(reads);
+            if after != live[pc] {
+                live[pc] = after;
+                changed = true;
+            }
+        }
+    }
+    live.into_iter().next().unwrap_or_default()
+}
+
+impl Footprint {
+    pub fn of(program: &GotoProgram) -> Footprint {
+        let required_cells = Layout::of(program).required_cells();
+        let data: BTreeSet<RegisterIndex> = program.data.iter().map(|&(cell, _)| cell).collect();
+        let inputs = live_at_start(program).into_iter().filter(|cell| !data.contains(cell)).collect();
+        let written: BTreeSet<RegisterIndex> = program.instructions.iter().filter_map(|&instruction| writes(instruction)).collect();
+
+        // the ranges of the cells at every STOP that runs
+        let analysis = ranges::analyze(program, None);
+        let stops: Vec<&Vec<Interval>> = program.instructions.iter().zip(&analysis.at)
+            .filter(|(instruction, _)| **instruction == Instruction::Stop)
+            .filter_map(|(_, at)| at.as_ref())
+            .collect();
+        let always_zero = |cell: &RegisterIndex| {
+            let slot = analysis.cells.binary_search(cell).unwrap();
+            !stops.is_empty() && stops.iter().all(|at| at[slot] == Interval { low: 0, high: 0 })
+        };
+        let (scratch, outputs) = written.into_iter().partition(always_zero);
+        Footprint { required_cells, inputs, outputs, scratch, data: data.into_iter().collect() }
+    }
+
+    /// Why the memory of a run is too short for the program, if it is.
+    pub fn check_memory(&self, cells: usize) -> Result<(), String> {
+        if cells < self.required_cells {
+            return Err(format!("the program uses cell {} and needs {} cells, the memory has {}", self.required_cells - 1, self.required_cells, cells));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let cells = |cells: &[RegisterIndex]| Value::Array(cells.iter().map(|&cell| cell.into()).collect());
+        Value::object(vec![
+            ("required_cells", self.required_cells.into()),
+            ("inputs", cells(&self.inputs)),
+            ("outputs", cells(&self.outputs)),
+            ("scratch", cells(&self.scratch)),
+            ("data", cells(&self.data)),
+        ])
+    }
+}
+
+#[test]
+fn test_footprint() {
+    let program = crate::parse_program(".init 6 = 1\nloop: GOTOZ 0 end\nDEC 0\nINC 4\nINC 2\nGOTO loop\nend: GOTOZ 4 done\nDEC 4\nINC 3\nGOTO end\ndone: STOREI 5 7\nASSERT 5 7\nSTOP".to_string()).unwrap();
+    let footprint = Footprint::of(&program);
+    assert_eq!(r#"{"required_cells":7,"inputs":[0,2,3,4],"outputs":[2,3,5],"scratch":[0,4],"data":[6]}"#, footprint.to_json().to_string());
+    assert_eq!(Err("the program uses cell 6 and needs 7 cells, the memory has 5".to_string()), footprint.check_memory(5));
+    assert_eq!(Ok(()), footprint.check_memory(7));
+}
@@ -0,0 +1,154 @@
+use crate::source::{parse_segments, SourceLine, SEPARATOR};
+
+const INDENT: &str = "    ";
+
+enum Item {
+    Blank,
+    Comment(String),
+    Label { name: String, comment: Option<String> },
+    Code { tokens: Vec<String>, comment: Option<String> },
+}
+
+fn items(lines: Vec<SourceLine>) -> Vec<Item> {
+    let mut result = vec![];
+    for line in lines {
+        if line.is_blank() {
+            // collapse runs of blank lines and drop leading ones
+            match result.last() {
+                None | Some(Item::Blank) => {}
+                _ => result.push(Item::Blank),
+            }
+            continue;
+        }
+        let SourceLine { label, tokens, comment } = line;
+        match (label, tokens.is_empty()) {
+            (Some(name), true) => result.push(Item::Label { name, comment }),
+            (Some(name), false) => {
+                result.push(Item::Label { name, comment: None });
+                result.push(Item::Code { tokens, comment });
+            }
+            (None, false) => result.push(Item::Code { tokens, comment }),
+            (None, true) => result.push(Item::Comment(comment.unwrap_or_default())),
+        }
+    }
+    if let Some(Item::Blank) = result.last() {
+        result.pop();
+    }
+    result
+}
+
+/// The segments of a line as one, with the separator and the labels of the
+/// later segments as tokens. Empty segments are dropped.
+fn joined(mut segments: Vec<SourceLine>) -> SourceLine {
+    let comment = segments.last_mut().and_then(|segment| segment.comment.take());
+    let mut segments = segments.into_iter().filter(|segment| segment.label.is_some() || !segment.tokens.is_empty());
+    let mut line = segments.next().unwrap_or_default();
+    for segment in segments {
+        line.tokens.push(SEPARATOR.to_string());
+        line.tokens.extend(segment.label.map(|label| format!("{}:", label)));
+        line.tokens.extend(segment.tokens);
+    }
+    line.comment = comment;
+    line
+}
+
+fn mnemonic_width(items: &[Item]) -> usize {
+    items.iter()
+        .filter_map(|item| match item {
+            Item::Code { tokens, .. } => Some(tokens[0].len()),
+            _ => None
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn format_code(tokens: &[String], width: usize) -> String {
+    let line = format!("{}{:width$} {}", INDENT, tokens[0], tokens[1..].join(" "), width = width);
+    line.trim_end().to_string()
+}
+
+/// Rewrites goto source into its canonical form. Labels are put on their own
+/// line, instructions are indented with their operands starting in a common
+/// column and trailing comments are aligned as well.
+pub fn format_source(text: &str) -> Result<String, String> {
+    // modules with `.import` only parse as modules
+    crate::link::parse_module("the program", text, &Default::default())?;
+    let mut lines = vec![];
+    for line in text.lines() {
+        lines.push(joined(parse_segments(line)?));
+    }
+    let items = items(lines);
+    let width = mnemonic_width(&items);
+    let comment_column = items.iter()
+        .filter_map(|item| match item {
+            Item::Code { tokens, comment: Some(_) } => Some(format_code(tokens, width).len()),
+            _ => None
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut result = String::new();
+    for (i, item) in items.iter().enumerate() {
+        match item {
+            Item::Blank => {}
+            Item::Comment(comment) => {
+                // comments belong to the next instruction or label and share its indentation
+                let next = items[i..].iter().find(|item| !matches!(item, Item::Comment(_)));
+                if let Some(Item::Code { .. }) = next {
+                    result.push_str(INDENT);
+                }
+                result.push_str(comment);
+            }
+            Item::Label { name, comment } => {
+                result.push_str(name);
+                result.push(':');
+                if let Some(comment) = comment {
+                    result.push(' ');
+                    result.push_str(comment);
+                }
+            }
+            Item::Code { tokens, comment } => {
+                let code = format_code(tokens, width);
+                result.push_str(&code);
+                if let Some(comment) = comment {
+                    result.push_str(&" ".repeat(comment_column - code.len() + 1));
+                    result.push_str(comment);
+                }
+            }
+        }
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+#[test]
+fn test_format_source() {
+    let input = "
+; add cell 0 to cell 1
+
+
+loop:   GOTOZ 0   end;finished?
+  DEC 0
+INC   1 ; count
+  GOTO loop
+  ; stop here
+end: STOP
+
+";
+    let expected = "; add cell 0 to cell 1
+
+loop:
+    GOTOZ 0 end ;finished?
+    DEC   0
+    INC   1     ; count
+    GOTO  loop
+; stop here
+end:
+    STOP
+";
+    assert_eq!(Result::Ok(expected.to_string()), format_source(input));
+    assert_eq!(Result::Ok(expected.to_string()), format_source(expected));
+    assert!(format_source("INC").is_err());
+    assert_eq!(Ok("loop:\n    INC 1 | DEC 2 | b: GOTOZ 2 loop ; dense\n".to_string()),
+               format_source("loop: INC 1|DEC 2 |  | b: GOTOZ 2 loop | ; dense"));
+}
@@ -0,0 +1,145 @@
+//! Runs a program on random inputs and reports the runs that fail or do not
+//! stop within a step limit, together with statistics of the outputs of the
+//! runs that stop.
+
+use std::fmt;
+
+use crate::batch::map_parallel;
+use crate::bytecode::Bytecode;
+use crate::inputs::InputSpace;
+use crate::minimize::error_kind;
+use crate::random::Random;
+use crate::{GotoProgram, GotoProgramState};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Fuzz {
+    pub runs: u64,
+    pub max_steps: u64,
+    pub seed: u64,
+    pub jobs: usize,
+}
+
+/// Runs that failed with the same kind of error, see `error_kind`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Failures {
+    pub message: String,
+    pub first_input: Vec<u64>,
+    pub runs: u64,
+}
+
+impl Failures {
+    pub fn is_underflow(&self) -> bool {
+        self.message.contains("on a cell that is 0")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CellStats {
+    pub min: u64,
+    pub max: u64,
+    pub sum: u128,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Report {
+    pub fuzz: Fuzz,
+    pub stopped: u64,
+    /// in order of their first input
+    pub failures: Vec<Failures>,
+    pub unfinished: u64,
+    pub first_unfinished: Option<Vec<u64>>,
+    /// the final memory of the runs that stopped, cell by cell
+    pub outputs: Vec<CellStats>,
+}
+
+impl Report {
+    pub fn failed(&self) -> u64 {
+        self.failures.iter().map(|f| f.runs).sum()
+    }
+}
+
+enum Ending {
+    Stopped(Vec<u64>),
+    Failed(String),
+    Running,
+}
+
+pub fn fuzz(program: &GotoProgram, inputs: &InputSpace, fuzz: Fuzz) -> Result<Report, String> {
+    let bytecode = Bytecode::compile(program)?;
+    let mut random = Random::new(fuzz.seed);
+    let memories: Vec<_> = (0..fuzz.runs).map(|_| inputs.sample(&mut random)).collect();
+    let endings = map_parallel(&memories, fuzz.jobs, |memory| {
+        let mut state = GotoProgramState::new(program, memory.clone());
+        match bytecode.run(&mut state, fuzz.max_steps) {
+            Ok(true) => Ending::Stopped(state.memory),
+            Ok(false) => Ending::Running,
+            Err(e) => Ending::Failed(e),
+        }
+    });
+    let mut report = Report { fuzz, stopped: 0, failures: vec![], unfinished: 0, first_unfinished: None, outputs: vec![] };
+    for (input, ending) in memories.into_iter().zip(endings) {
+        match ending {
+            Ending::Stopped(memory) => {
+                report.stopped += 1;
+                for (cell, &value) in memory.iter().enumerate() {
+                    if cell == report.outputs.len() {
+                        report.outputs.push(CellStats { min: value, max: value, sum: 0 });
+                    }
+                    let stats = &mut report.outputs[cell];
+                    stats.min = stats.min.min(value);
+                    stats.max = stats.max.max(value);
+                    stats.sum += value as u128;
+                }
+            }
+            Ending::Failed(message) => {
+                let kind = error_kind(&message);
+                match report.failures.iter_mut().find(|f| error_kind(&f.message) == kind) {
+                    Some(failures) => failures.runs += 1,
+                    None => report.failures.push(Failures { message, first_input: input, runs: 1 }),
+                }
+            }
+            Ending::Running => {
+                report.unfinished += 1;
+                report.first_unfinished.get_or_insert(input);
+            }
+        }
+    }
+    Ok(report)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} runs with seed {}: {} stopped, {} failed, {} still running after {} steps",
+                 self.fuzz.runs, self.fuzz.seed, self.stopped, self.failed(), self.unfinished, self.fuzz.max_steps)?;
+        for failures in &self.failures {
+            let kind = if failures.is_underflow() { "underflow" } else { "error" };
+            writeln!(f, "{} in {} runs: {}, first on {:?}", kind, failures.runs, failures.message, failures.first_input)?;
+        }
+        if let Some(input) = &self.first_unfinished {
+            writeln!(f, "possibly not stopping in {} runs, first on {:?}", self.unfinished, input)?;
+        }
+        if self.stopped > 0 {
+            writeln!(f, "memory after the runs that stopped:")?;
+            for (cell, stats) in self.outputs.iter().enumerate() {
+                writeln!(f, "  cell {}: min {}, max {}, mean {:.2}", cell, stats.min, stats.max, stats.sum as f64 / self.stopped as f64)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_fuzz() {
+    // moves cell 0 to cell 1 and then takes 3 from it
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: DEC 1\nDEC 1\nDEC 1\nSTOP".to_string()).unwrap();
+    let inputs = InputSpace::new(&program, 0..1, 0..10);
+    let settings = Fuzz { runs: 200, max_steps: 30, seed: 7, jobs: 3 };
+    let report = fuzz(&program, &inputs, settings).unwrap();
+    let single_threaded = fuzz(&program, &inputs, Fuzz { jobs: 1, ..settings }).unwrap();
+    assert_eq!(report, Report { fuzz: settings, ..single_threaded });
+    assert_eq!(200, report.stopped + report.failed() + report.unfinished);
+    assert!(report.failures.iter().all(|f| f.is_underflow() && f.first_input[0] < 3));
+    assert!(report.first_unfinished.as_ref().is_some_and(|input| input[0] >= 7));
+    assert_eq!(0, report.outputs[0].max);
+    assert!(report.outputs[1].min <= report.outputs[1].max && report.outputs[1].max <= 6);
+}
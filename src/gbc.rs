@@ -0,0 +1,276 @@
+//! The binary `.gbc` file format for parsed programs.
+//!
+//! All numbers are little endian, strings are a `u32` length and UTF-8 bytes.
+//!
+//! ```text
+//! "GOTOBC" magic, u16 version
+//! string source path
+//! u32 label count, per label: string name, u64 instruction index
+//! u32 instruction count, per instruction: u8 opcode, u64 operands, u64 source line
+//! ```
+//!
+//! Opcodes are 0 STOP, 1 INC, 2 DEC, 3 GOTO, 4 GOTOZ, 5 ASSERT, 6 PRINT, 7 READ,
+//! 8 OUTC, 9 RAND, 10 PUSH, 11 POP, 12 STOREI, 13 GOTONZ, 14 GOTOEQ,
+//! 15 GOTOGT and 16 NOP. GOTOEQ and GOTOGT have three operands, GOTOZ,
+//! GOTONZ, ASSERT, RAND and STOREI two, STOP and NOP none and the others one.
+
+use std::convert::TryFrom;
+
+use crate::{preprocess, source, GotoProgram, Instruction};
+
+const MAGIC: &[u8] = b"GOTOBC";
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct BytecodeFile {
+    pub source_path: String,
+    pub labels: Vec<(String, usize)>,
+    pub program: GotoProgram,
+}
+
+/// The labels of the source of the program, with the index of the first
+/// instruction from their segment or a later one. `.ifdef` blocks count as
+/// without defines, for others pass the preprocessed source.
+pub fn label_table(text: &str, program: &GotoProgram) -> Vec<(String, usize)> {
+    let text = preprocess::preprocess(text, &[]).unwrap_or_else(|_| text.to_string());
+    let mut labels = vec![];
+    for (line_nr, line) in text.lines().enumerate() {
+        let mut index = program.source_lines.iter().position(|&line| line > line_nr).unwrap_or(program.instructions.len());
+        for segment in source::parse_segments(line).unwrap_or_default() {
+            if let Some(label) = &segment.label {
+                labels.push((label.clone(), index));
+            }
+            if !segment.tokens.is_empty() && !segment.is_directive() {
+                index += 1;
+            }
+        }
+    }
+    labels
+}
+
+pub fn is_bytecode_file(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(count).filter(|end| *end <= self.bytes.len())
+            .ok_or("the file ends too early")?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(<[u8; 2]>::try_from(self.take(2)?).unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(<[u8; 4]>::try_from(self.take(4)?).unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(<[u8; 8]>::try_from(self.take(8)?).unwrap()))
+    }
+
+    fn usize(&mut self) -> Result<usize, String> {
+        let value = self.u64()?;
+        usize::try_from(value).map_err(|_| format!("{} is too large for this machine", value))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+fn push_usize(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend_from_slice(&(value as u64).to_le_bytes());
+}
+
+fn push_string(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+impl BytecodeFile {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_mapped().0
+    }
+
+    /// The bytes with the offset of every instruction in them.
+    pub fn to_bytes_mapped(&self) -> (Vec<u8>, Vec<usize>) {
+        let mut bytes = MAGIC.to_vec();
+        let mut offsets = vec![];
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        push_string(&mut bytes, &self.source_path);
+        bytes.extend_from_slice(&(self.labels.len() as u32).to_le_bytes());
+        for (name, index) in &self.labels {
+            push_string(&mut bytes, name);
+            push_usize(&mut bytes, *index);
+        }
+        bytes.extend_from_slice(&(self.program.instructions.len() as u32).to_le_bytes());
+        for (instruction, line) in self.program.instructions.iter().zip(&self.program.source_lines) {
+            offsets.push(bytes.len());
+            match *instruction {
+                Instruction::Stop => bytes.push(0),
+                Instruction::Inc { cell } => {
+                    bytes.push(1);
+                    push_usize(&mut bytes, cell);
+                }
+                Instruction::Dec { cell } => {
+                    bytes.push(2);
+                    push_usize(&mut bytes, cell);
+                }
+                Instruction::Goto { cell } => {
+                    bytes.push(3);
+                    push_usize(&mut bytes, cell);
+                }
+                Instruction::GotoZ { condition_cell, goto_cell } => {
+                    bytes.push(4);
+                    push_usize(&mut bytes, condition_cell);
+                    push_usize(&mut bytes, goto_cell);
+                }
+                Instruction::Assert { cell, value } => {
+                    bytes.push(5);
+                    push_usize(&mut bytes, cell);
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                Instruction::Print { cell } => {
+                    bytes.push(6);
+                    push_usize(&mut bytes, cell);
+                }
+                Instruction::Read { cell } => {
+                    bytes.push(7);
+                    push_usize(&mut bytes, cell);
+                }
+                Instruction::OutC { cell } => {
+                    bytes.push(8);
+                    push_usize(&mut bytes, cell);
+                }
+                Instruction::Rand { cell, max } => {
+                    bytes.push(9);
+                    push_usize(&mut bytes, cell);
+                    bytes.extend_from_slice(&max.to_le_bytes());
+                }
+                Instruction::Push { cell } => {
+                    bytes.push(10);
+                    push_usize(&mut bytes, cell);
+                }
+                Instruction::Pop { cell } => {
+                    bytes.push(11);
+                    push_usize(&mut bytes, cell);
+                }
+                Instruction::StoreI { cell, value } => {
+                    bytes.push(12);
+                    push_usize(&mut bytes, cell);
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                Instruction::GotoNz { condition_cell, goto_cell } => {
+                    bytes.push(13);
+                    push_usize(&mut bytes, condition_cell);
+                    push_usize(&mut bytes, goto_cell);
+                }
+                Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => {
+                    bytes.push(if let Instruction::GotoEq { .. } = instruction { 14 } else { 15 });
+                    push_usize(&mut bytes, left);
+                    push_usize(&mut bytes, right);
+                    push_usize(&mut bytes, goto_cell);
+                }
+                Instruction::Nop => bytes.push(16),
+            }
+            push_usize(&mut bytes, *line);
+        }
+        (bytes, offsets)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<BytecodeFile, String> {
+        if !is_bytecode_file(bytes) {
+            return Err("not a goto bytecode file".to_string());
+        }
+        let mut reader = Reader { bytes, pos: MAGIC.len() };
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(format!("unsupported bytecode version {}", version));
+        }
+        let source_path = reader.string()?;
+        let labels = (0..reader.u32()?)
+            .map(|_| Ok((reader.string()?, reader.usize()?)))
+            .collect::<Result<_, String>>()?;
+        let mut program = GotoProgram { instructions: vec![], source_lines: vec![], banks: 1, aliases: vec![], data: vec![], header: Default::default() };
+        for _ in 0..reader.u32()? {
+            let instruction = match reader.u8()? {
+                0 => Instruction::Stop,
+                1 => Instruction::Inc { cell: reader.usize()? },
+                2 => Instruction::Dec { cell: reader.usize()? },
+                3 => Instruction::Goto { cell: reader.usize()? },
+                4 => Instruction::GotoZ { condition_cell: reader.usize()?, goto_cell: reader.usize()? },
+                5 => Instruction::Assert { cell: reader.usize()?, value: reader.u64()? },
+                6 => Instruction::Print { cell: reader.usize()? },
+                7 => Instruction::Read { cell: reader.usize()? },
+                8 => Instruction::OutC { cell: reader.usize()? },
+                9 => Instruction::Rand { cell: reader.usize()?, max: reader.u64()? },
+                10 => Instruction::Push { cell: reader.usize()? },
+                11 => Instruction::Pop { cell: reader.usize()? },
+                12 => Instruction::StoreI { cell: reader.usize()?, value: reader.u64()? },
+                13 => Instruction::GotoNz { condition_cell: reader.usize()?, goto_cell: reader.usize()? },
+                14 => Instruction::GotoEq { left: reader.usize()?, right: reader.usize()?, goto_cell: reader.usize()? },
+                15 => Instruction::GotoGt { left: reader.usize()?, right: reader.usize()?, goto_cell: reader.usize()? },
+                16 => Instruction::Nop,
+                opcode => return Err(format!("unknown opcode {}", opcode)),
+            };
+            program.instructions.push(instruction);
+            program.source_lines.push(reader.usize()?);
+        }
+        if reader.pos != bytes.len() {
+            return Err("unexpected bytes after the last instruction".to_string());
+        }
+        Ok(BytecodeFile { source_path, labels, program })
+    }
+
+    /// Source text with every instruction on its original line, for when the
+    /// source file is gone. Labels are restored, comments and jump targets by
+    /// name are not.
+    pub fn reconstructed_source(&self) -> String {
+        let line_count = self.program.source_lines.iter().max().cloned().unwrap_or(0);
+        let mut lines = vec![String::new(); line_count];
+        for (index, (instruction, line)) in self.program.instructions.iter().zip(&self.program.source_lines).enumerate() {
+            let labels: Vec<_> = self.labels.iter().filter(|(_, i)| *i == index).map(|(name, _)| format!("{}: ", name)).collect();
+            let code = format!("{}{}", labels.concat(), instruction);
+            if lines[line - 1].is_empty() {
+                lines[line - 1] = code;
+            } else {
+                lines[line - 1] = format!("{} {} {}", lines[line - 1], source::SEPARATOR, code);
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let source = "; move cell 0 to cell 1\nloop: GOTOZ 0 end\n    DEC 0\n    INC 1\n    GOTO loop\nend:\n    ASSERTZ 0\n    PRINT 1\n    READ 2\n    OUTC 2\n    RAND 0 6\n    PUSH 1\n    POP 2\n    STOREI 0 5\n    GOTONZ 1 loop\n    GOTOEQ 0 1 end\n    GOTOGT 1 0 end\n    NOP | c: INC 0\n    GOTO c\n    STOP\n";
+    let program = crate::parse_program(source.to_string()).unwrap();
+    let file = BytecodeFile {
+        source_path: "move.goto".to_string(),
+        labels: label_table(source, &program),
+        program,
+    };
+    assert_eq!(vec![("loop".to_string(), 0), ("end".to_string(), 4), ("c".to_string(), 16)], file.labels);
+    let bytes = file.to_bytes();
+    assert_eq!(Ok(&file), BytecodeFile::from_bytes(&bytes).as_ref());
+    let reconstructed = crate::parse_program(file.reconstructed_source()).unwrap();
+    assert_eq!(file.program, reconstructed);
+
+    assert!(BytecodeFile::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(BytecodeFile::from_bytes(b"INC 0").is_err());
+}
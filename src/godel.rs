@@ -0,0 +1,306 @@
+//! Gödel numbers of goto programs.
+//!
+//! An instruction has the code
+//!
+//! ```text
+//! INC c       5c
+//! DEC c       5c + 1
+//! GOTO t      5t + 2
+//! GOTOZ c t   5π(c, t) + 3
+//! STOP        4
+//! ASSERT c v  5(π(0, π(c, v)) + 1) + 4
+//! PRINT c     5(π(1, c) + 1) + 4
+//! READ c      5(π(2, c) + 1) + 4
+//! OUTC c      5(π(3, c) + 1) + 4
+//! RAND c m    5(π(4, π(c, m)) + 1) + 4
+//! PUSH c      5(π(5, c) + 1) + 4
+//! POP c       5(π(6, c) + 1) + 4
+//! STOREI c v  5(π(7, π(c, v)) + 1) + 4
+//! GOTONZ c t  5(π(8, π(c, t)) + 1) + 4
+//! GOTOEQ a b t  5(π(9, π(a, π(b, t))) + 1) + 4
+//! GOTOGT a b t  5(π(10, π(a, π(b, t))) + 1) + 4
+//! NOP         5(π(11, 0) + 1) + 4
+//! ```
+//!
+//! with the Cantor pairing function `π(a, b) = (a + b)(a + b + 1) / 2 + b`,
+//! targets being instruction numbers from 0. A program with the codes
+//! `k0, ..., kn` has the number `2^(k0 + 1) · 3^(k1 + 1) · ... · pn^(kn + 1)`,
+//! where `pn` is the n-th prime, so the empty program is 1.
+
+use std::convert::TryFrom;
+
+use crate::{GotoProgram, Instruction, RegisterIndex};
+
+/// Numbers with more bits are not computed, they would take too long to print.
+pub const MAX_BITS: f64 = 65_536.0;
+
+/// `w(w + 1) / 2`, `None` if it does not fit into 128 bits.
+fn triangle(w: u128) -> Option<u128> {
+    let (even, odd) = if w.is_multiple_of(2) { (w / 2, w.checked_add(1)?) } else { (w.div_ceil(2), w) };
+    even.checked_mul(odd)
+}
+
+/// `None` if the pair does not fit into 128 bits.
+pub fn pair(a: u128, b: u128) -> Option<u128> {
+    triangle(a.checked_add(b)?)?.checked_add(b)
+}
+
+pub fn unpair(z: u128) -> (u128, u128) {
+    // the largest w with w(w + 1) / 2 <= z
+    let mut w = ((8.0 * z as f64 + 1.0).sqrt() as u128).saturating_sub(1) / 2;
+    while triangle(w + 1).is_some_and(|t| t <= z) {
+        w += 1;
+    }
+    while triangle(w).is_none_or(|t| t > z) {
+        w -= 1;
+    }
+    let b = z - triangle(w).unwrap();
+    (w - b, b)
+}
+
+/// `None` if the code does not fit into 128 bits, the number of a program
+/// with it would have far more than `MAX_BITS` bits anyway.
+pub fn instruction_code(instruction: &Instruction) -> Option<u128> {
+    let code = |kind: u128, operand: Option<u128>| operand?.checked_mul(5)?.checked_add(kind);
+    // the codes 5(π(k, o) + 1) + 4 of the instructions added to the language
+    let extended = |kind: u128, operand: Option<u128>| code(4, pair(kind, operand?)?.checked_add(1));
+    let c = |cell: usize| cell as u128;
+    match *instruction {
+        Instruction::Inc { cell } => code(0, Some(c(cell))),
+        Instruction::Dec { cell } => code(1, Some(c(cell))),
+        Instruction::Goto { cell } => code(2, Some(c(cell))),
+        Instruction::GotoZ { condition_cell, goto_cell } => code(3, pair(c(condition_cell), c(goto_cell))),
+        Instruction::Stop => Some(4),
+        Instruction::Assert { cell, value } => extended(0, pair(c(cell), value as u128)),
+        Instruction::Print { cell } => extended(1, Some(c(cell))),
+        Instruction::Read { cell } => extended(2, Some(c(cell))),
+        Instruction::OutC { cell } => extended(3, Some(c(cell))),
+        Instruction::Rand { cell, max } => extended(4, pair(c(cell), max as u128)),
+        Instruction::Push { cell } => extended(5, Some(c(cell))),
+        Instruction::Pop { cell } => extended(6, Some(c(cell))),
+        Instruction::StoreI { cell, value } => extended(7, pair(c(cell), value as u128)),
+        Instruction::GotoNz { condition_cell, goto_cell } => extended(8, pair(c(condition_cell), c(goto_cell))),
+        Instruction::GotoEq { left, right, goto_cell } => extended(9, pair(c(left), pair(c(right), c(goto_cell))?)),
+        Instruction::GotoGt { left, right, goto_cell } => extended(10, pair(c(left), pair(c(right), c(goto_cell))?)),
+        Instruction::Nop => extended(11, Some(0)),
+    }
+}
+
+pub fn decode_instruction(code: u128) -> Result<Instruction, String> {
+    let index = |n: u128| RegisterIndex::try_from(n).map_err(|_| format!("{} is too large for a cell or target", n));
+    let operand = code / 5;
+    Ok(match code % 5 {
+        0 => Instruction::Inc { cell: index(operand)? },
+        1 => Instruction::Dec { cell: index(operand)? },
+        2 => Instruction::Goto { cell: index(operand)? },
+        3 => {
+            let (condition_cell, goto_cell) = unpair(operand);
+            Instruction::GotoZ { condition_cell: index(condition_cell)?, goto_cell: index(goto_cell)? }
+        }
+        _ if code == 4 => Instruction::Stop,
+        _ => match unpair(operand - 1) {
+            (kind @ 0, operand) | (kind @ 4, operand) | (kind @ 7, operand) => {
+                let (cell, value) = unpair(operand);
+                let value = u64::try_from(value).map_err(|_| format!("{} is too large for a cell value", value))?;
+                match kind {
+                    0 => Instruction::Assert { cell: index(cell)?, value },
+                    4 => Instruction::Rand { cell: index(cell)?, max: value },
+                    _ => Instruction::StoreI { cell: index(cell)?, value },
+                }
+            }
+            (1, cell) => Instruction::Print { cell: index(cell)? },
+            (2, cell) => Instruction::Read { cell: index(cell)? },
+            (3, cell) => Instruction::OutC { cell: index(cell)? },
+            (5, cell) => Instruction::Push { cell: index(cell)? },
+            (6, cell) => Instruction::Pop { cell: index(cell)? },
+            (8, operand) => {
+                let (condition_cell, goto_cell) = unpair(operand);
+                Instruction::GotoNz { condition_cell: index(condition_cell)?, goto_cell: index(goto_cell)? }
+            }
+            (kind @ 9, operand) | (kind @ 10, operand) => {
+                let (left, rest) = unpair(operand);
+                let (right, goto_cell) = unpair(rest);
+                let (left, right, goto_cell) = (index(left)?, index(right)?, index(goto_cell)?);
+                if kind == 9 {
+                    Instruction::GotoEq { left, right, goto_cell }
+                } else {
+                    Instruction::GotoGt { left, right, goto_cell }
+                }
+            }
+            (11, 0) => Instruction::Nop,
+            _ => return Err(format!("{} is not the code of an instruction", code)),
+        },
+    })
+}
+
+/// An arbitrarily large natural number, as base 2^32 digits with the least significant first.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Natural(Vec<u32>);
+
+impl Natural {
+    pub fn one() -> Natural {
+        Natural(vec![1])
+    }
+
+    fn is_one(&self) -> bool {
+        self.0 == [1]
+    }
+
+    fn multiply(&mut self, factor: u32) {
+        let mut carry = 0u64;
+        for digit in self.0.iter_mut() {
+            let product = *digit as u64 * factor as u64 + carry;
+            *digit = product as u32;
+            carry = product >> 32;
+        }
+        if carry > 0 {
+            self.0.push(carry as u32);
+        }
+    }
+
+    fn add(&mut self, summand: u32) {
+        let mut carry = summand as u64;
+        for digit in self.0.iter_mut() {
+            let sum = *digit as u64 + carry;
+            *digit = sum as u32;
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            self.0.push(carry as u32);
+        }
+    }
+
+    /// Divides in place and returns the remainder.
+    fn divide(&mut self, divisor: u32) -> u32 {
+        let mut remainder = 0u64;
+        for digit in self.0.iter_mut().rev() {
+            let current = (remainder << 32) | *digit as u64;
+            *digit = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        while self.0.len() > 1 && self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+        remainder as u32
+    }
+
+    fn multiply_power(&mut self, base: u32, mut exponent: u128) {
+        // multiply by the largest powers of the base that fit into a digit
+        let mut chunk = (1u32, 0u128);
+        while let Some(next) = chunk.0.checked_mul(base) {
+            chunk = (next, chunk.1 + 1);
+        }
+        while exponent >= chunk.1 {
+            self.multiply(chunk.0);
+            exponent -= chunk.1;
+        }
+        self.multiply(base.pow(exponent as u32));
+    }
+
+    pub fn parse(text: &str) -> Result<Natural, String> {
+        if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("{} is not a natural number", text));
+        }
+        let mut number = Natural(vec![0]);
+        for byte in text.bytes() {
+            number.multiply(10);
+            number.add((byte - b'0') as u32);
+        }
+        Ok(number)
+    }
+}
+
+impl std::fmt::Display for Natural {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut rest = self.clone();
+        let mut chunks = vec![];
+        loop {
+            chunks.push(rest.divide(1_000_000_000));
+            if rest.0 == [0] {
+                break;
+            }
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn primes() -> impl Iterator<Item = u32> {
+    (2u32..).filter(|n| (2..).take_while(|d| d * d <= *n).all(|d| n % d != 0))
+}
+
+pub fn encode(program: &GotoProgram) -> Result<Natural, String> {
+    let too_large = |instruction: &Instruction| format!("the code of {} is too large, the number would have far more than {} bits", instruction, MAX_BITS);
+    let codes = program.instructions.iter().map(|instruction| instruction_code(instruction).ok_or_else(|| too_large(instruction)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let bits: f64 = codes.iter().zip(primes()).map(|(code, prime)| (*code as f64 + 1.0) * (prime as f64).log2()).sum();
+    if bits > MAX_BITS {
+        return Err(format!("the number would have about {:.0} bits, more than the {} supported", bits, MAX_BITS));
+    }
+    let mut number = Natural::one();
+    for (code, prime) in codes.iter().zip(primes()) {
+        number.multiply_power(prime, code + 1);
+    }
+    Ok(number)
+}
+
+pub fn decode(number: &Natural) -> Result<Vec<Instruction>, String> {
+    if number.0 == [0] {
+        return Err("0 is not the number of a program".to_string());
+    }
+    let mut rest = number.clone();
+    let mut instructions = vec![];
+    for prime in primes() {
+        if rest.is_one() {
+            break;
+        }
+        let mut exponent = 0u128;
+        loop {
+            let mut quotient = rest.clone();
+            if quotient.divide(prime) != 0 {
+                break;
+            }
+            rest = quotient;
+            exponent += 1;
+        }
+        if exponent == 0 {
+            return Err(format!("the number is not divisible by {}, but by a larger prime", prime));
+        }
+        instructions.push(decode_instruction(exponent - 1)?);
+    }
+    Ok(instructions)
+}
+
+#[test]
+fn test_encode_decode() {
+    let program = crate::parse_program("INC 0\nSTOP".to_string()).unwrap();
+    // 2^1 · 3^5
+    assert_eq!("486", encode(&program).unwrap().to_string());
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: ASSERTZ 0\nASSERT 1 3\nSTOP".to_string()).unwrap();
+    let number = encode(&program).unwrap();
+    assert_eq!(Ok(program.instructions), decode(&Natural::parse(&number.to_string()).unwrap()));
+    for instruction in crate::parse_program("PRINT 1\nREAD 2\nOUTC 0\nRAND 3 10\nPUSH 4\nPOP 5\nSTOREI 6 7\nGOTONZ 2 0\nGOTOEQ 1 2 3\nGOTOGT 4 0 1\nNOP".to_string()).unwrap().instructions {
+        assert_eq!(Ok(instruction), decode_instruction(instruction_code(&instruction).unwrap()));
+    }
+    assert_eq!((7, 12), unpair(pair(7, 12).unwrap()));
+    assert_eq!((u64::MAX as u128, 0), unpair(pair(u64::MAX as u128, 0).unwrap()));
+    assert_eq!(None, pair(u64::MAX as u128 * 2, 0));
+    let (a, b) = unpair(u128::MAX);
+    assert_eq!(Some(u128::MAX), pair(a, b));
+
+    for text in ["GOTOZ 18446744073709551615 0", "GOTOEQ 100000 100000 0", "STOREI 0 18446744073709551615"] {
+        let program = crate::parse_program(format!(".dialect extended\n{}", text)).unwrap();
+        assert_eq!(None, instruction_code(&program.instructions[0]));
+        assert!(encode(&program).unwrap_err().starts_with("the code of "));
+    }
+    for instruction in [Instruction::GotoZ { condition_cell: 1 << 40, goto_cell: 3 }, Instruction::StoreI { cell: 5, value: 1 << 20 }] {
+        assert_eq!(Ok(instruction), decode_instruction(instruction_code(&instruction).unwrap()));
+    }
+
+    assert!(decode(&Natural::parse("10").unwrap()).is_err());
+    assert!(decode(&Natural::parse("0").unwrap()).is_err());
+    assert_eq!(Ok(vec![]), decode(&Natural::one()));
+}
@@ -0,0 +1,177 @@
+//! Structured execution traces as JSON lines with one event per step, and
+//! their comparison against a stored golden trace to catch changes in what
+//! a run does.
+
+use std::fmt;
+
+use crate::json::Value;
+use crate::{MemoryWrite, RegisterIndex, StepEffect};
+
+/// A step of a run: the instruction at `pc` and the cell it wrote, after
+/// which the run continues at `next_pc`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceEvent {
+    pub step: u64,
+    pub program_counter: RegisterIndex,
+    pub instruction: String,
+    pub write: Option<MemoryWrite>,
+    pub next_program_counter: RegisterIndex,
+}
+
+impl TraceEvent {
+    pub fn new(step: u64, effect: &StepEffect, instruction: String, next_program_counter: RegisterIndex) -> TraceEvent {
+        TraceEvent { step, program_counter: effect.program_counter, instruction, write: effect.write, next_program_counter }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let write = match self.write {
+            Some(write) => Value::object(vec![("cell", write.cell.into()), ("old", write.old.into()), ("new", write.new.into())]),
+            None => Value::Null,
+        };
+        Value::object(vec![
+            ("step", self.step.into()),
+            ("pc", self.program_counter.into()),
+            ("instruction", self.instruction.as_str().into()),
+            ("write", write),
+            ("next_pc", self.next_program_counter.into()),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Result<TraceEvent, String> {
+        let number = |value: &Value, key: &str| value.get(key).and_then(Value::as_u64).ok_or_else(|| format!("missing or invalid {}", key));
+        let write = match value.get("write") {
+            Some(Value::Null) | None => None,
+            Some(write) => Some(MemoryWrite { cell: number(write, "cell")? as RegisterIndex, old: number(write, "old")?, new: number(write, "new")? }),
+        };
+        Ok(TraceEvent {
+            step: number(value, "step")?,
+            program_counter: number(value, "pc")? as RegisterIndex,
+            instruction: value.get("instruction").and_then(Value::as_str).ok_or("missing or invalid instruction")?.to_string(),
+            write,
+            next_program_counter: number(value, "next_pc")? as RegisterIndex,
+        })
+    }
+}
+
+/// The events of a trace written with `--record-trace`.
+pub fn parse_trace(text: &str) -> Result<Vec<TraceEvent>, String> {
+    text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| Value::parse(line).and_then(|value| TraceEvent::from_json(&value)).map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+/// The first difference between a run and its golden trace.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Divergence {
+    pub step: u64,
+    /// the field that differs, like `write.new`, empty if one trace ends first
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "trace diverges at step {}: expected {}, found {}", self.step, self.expected, self.actual)
+        } else {
+            write!(f, "trace diverges at step {} in {}: expected {}, found {}", self.step, self.field, self.expected, self.actual)
+        }
+    }
+}
+
+/// The first field in which the values differ, with nested objects named
+/// by their path.
+fn difference(path: &str, expected: &Value, actual: &Value) -> Option<(String, String, String)> {
+    match (expected, actual) {
+        (Value::Object(expected_fields), Value::Object(actual_fields)) => {
+            let keys = expected_fields.iter().map(|(k, _)| k)
+                .chain(actual_fields.iter().map(|(k, _)| k).filter(|k| expected.get(k).is_none()));
+            keys.filter_map(|key| {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                let missing = Value::String("nothing".to_string());
+                difference(&path, expected.get(key).unwrap_or(&missing), actual.get(key).unwrap_or(&missing))
+            }).next()
+        }
+        _ if expected == actual => None,
+        _ => Some((path.to_string(), expected.to_string(), actual.to_string())),
+    }
+}
+
+/// Checks the events of a run one by one against a golden trace.
+#[derive(Debug, Clone)]
+pub struct GoldenTrace {
+    events: Vec<Value>,
+    position: usize,
+}
+
+impl GoldenTrace {
+    pub fn parse(text: &str) -> Result<GoldenTrace, String> {
+        let events = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| Value::parse(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(GoldenTrace { events, position: 0 })
+    }
+
+    pub fn observe(&mut self, event: &TraceEvent) -> Result<(), Divergence> {
+        let expected = self.events.get(self.position).ok_or_else(|| Divergence {
+            step: event.step,
+            field: String::new(),
+            expected: format!("the run to end after {} steps", self.events.len()),
+            actual: format!("another step, {} at pc {}", event.instruction, event.program_counter),
+        })?;
+        self.position += 1;
+        match difference("", expected, &event.to_json()) {
+            Some((field, expected, actual)) => Err(Divergence { step: event.step, field, expected, actual }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the golden trace has no events left once the run ended.
+    pub fn finish(&self) -> Result<(), Divergence> {
+        match self.events.get(self.position) {
+            Some(expected) => Err(Divergence {
+                step: self.position as u64 + 1,
+                field: String::new(),
+                expected: expected.to_string(),
+                actual: format!("the run to end after {} steps", self.position),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_golden() {
+    let program = crate::parse_program("INC 0\nGOTOZ 1 3\nINC 1\nSTOP".to_string()).unwrap();
+    let trace = |memory: Vec<u64>| {
+        let mut state = crate::GotoProgramState::new(&program, memory);
+        let mut events = vec![];
+        loop {
+            let (step, instruction) = (state.steps + 1, program.instructions[state.program_counter].to_string());
+            let mut effect = None;
+            let stopped = state.step_with_hook(&mut |e| effect = Some(*e)).unwrap();
+            events.push(TraceEvent::new(step, &effect.unwrap(), instruction, state.program_counter));
+            if stopped {
+                return events;
+            }
+        }
+    };
+    let golden: String = trace(vec![0, 0]).iter().map(|e| e.to_json().to_string() + "\n").collect();
+    assert_eq!(r#"{"step":1,"pc":0,"instruction":"INC 0","write":{"cell":0,"old":0,"new":1},"next_pc":1}"#, golden.lines().next().unwrap());
+
+    assert_eq!(Ok(trace(vec![0, 0])), parse_trace(&golden));
+
+    let mut same = GoldenTrace::parse(&golden).unwrap();
+    assert!(trace(vec![0, 0]).iter().all(|e| same.observe(e).is_ok()));
+    assert_eq!(Ok(()), same.finish());
+
+    let mut other = GoldenTrace::parse(&golden).unwrap();
+    let events = trace(vec![4, 1]);
+    assert_eq!("trace diverges at step 1 in write.old: expected 0, found 4", other.observe(&events[0]).unwrap_err().to_string());
+    assert_eq!("trace diverges at step 2 in next_pc: expected 3, found 2", other.observe(&events[1]).unwrap_err().to_string());
+    let mut shorter = GoldenTrace::parse(golden.lines().next().unwrap()).unwrap();
+    assert!(shorter.observe(&events[0]).is_err());
+    assert_eq!("trace diverges at step 2: expected the run to end after 1 steps, found another step, GOTOZ 1 3 at pc 1",
+               shorter.observe(&events[1]).unwrap_err().to_string());
+}
@@ -0,0 +1,284 @@
+//! Grading of goto programs against test specifications.
+//!
+//! A test specification is a text file with one directive per line:
+//!
+//! ```text
+//! # comments start with '#'
+//! max_steps 100000
+//! case add small numbers
+//! input 3 4
+//! expect 1=7
+//! case add zero
+//! input 0 5
+//! expect 0=0 1=5
+//! ```
+//!
+//! The program is run once per case and the results are scored by a
+//! [`GradingPolicy`].
+
+use std::fmt;
+
+use crate::{GotoProgram, GotoProgramState, RegisterIndex};
+
+pub const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TestCase {
+    pub name: String,
+    pub input: Vec<u64>,
+    pub expected: Vec<(RegisterIndex, u64)>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Limits {
+    pub max_steps: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits { max_steps: DEFAULT_MAX_STEPS }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct TestSpec {
+    pub limits: Limits,
+    pub cases: Vec<TestCase>,
+}
+
+fn parse_expectation(text: &str) -> Result<(RegisterIndex, u64), String> {
+    let mut parts = text.splitn(2, '=');
+    let cell = parts.next().unwrap().parse::<RegisterIndex>();
+    let value = parts.next().map(|v| v.parse::<u64>());
+    match (cell, value) {
+        (Ok(cell), Some(Ok(value))) => Ok((cell, value)),
+        _ => Err(format!("expected cell=value, found: {}", text)),
+    }
+}
+
+impl TestSpec {
+    pub fn parse(text: &str) -> Result<TestSpec, String> {
+        let mut spec = TestSpec::default();
+        for (line_nr, line) in text.lines().enumerate() {
+            let error = |e: String| format!("error in line {}: {}", line_nr + 1, e);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (directive, rest) = match line.find(char::is_whitespace) {
+                Some(pos) => (&line[..pos], line[pos..].trim()),
+                None => (line, ""),
+            };
+            if directive == "max_steps" {
+                spec.limits.max_steps = rest.parse()
+                    .map_err(|e| error(format!("{} is not a number (reason: {:?})", rest, e)))?;
+                continue;
+            }
+            if directive == "case" {
+                let name = if rest.is_empty() { format!("case {}", spec.cases.len() + 1) } else { rest.to_string() };
+                spec.cases.push(TestCase { name, input: vec![], expected: vec![] });
+                continue;
+            }
+            let case = spec.cases.last_mut()
+                .ok_or_else(|| error(format!("{} before the first case", directive)))?;
+            match directive {
+                "input" => case.input = crate::read_input(rest.to_string()).map_err(error)?,
+                "expect" => {
+                    for expectation in rest.split_whitespace() {
+                        case.expected.push(parse_expectation(expectation).map_err(error)?);
+                    }
+                }
+                _ => return Err(error(format!("unknown directive: {}", directive))),
+            }
+        }
+        Ok(spec)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Outcome {
+    Passed,
+    WrongOutput { cell: RegisterIndex, expected: u64, actual: Option<u64> },
+    StepLimitExceeded,
+    RuntimeError(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Passed => write!(f, "passed"),
+            Outcome::WrongOutput { cell, expected, actual: Some(actual) } =>
+                write!(f, "cell {} is {}, expected {}", cell, actual, expected),
+            Outcome::WrongOutput { cell, expected, actual: None } =>
+                write!(f, "cell {} does not exist, expected {}", cell, expected),
+            Outcome::StepLimitExceeded => write!(f, "step limit exceeded"),
+            Outcome::RuntimeError(e) => write!(f, "runtime error: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CaseResult {
+    pub name: String,
+    pub outcome: Outcome,
+    pub steps: u64,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+}
+
+pub fn run_case(program: &GotoProgram, case: &TestCase, limits: &Limits) -> CaseResult {
+    let mut state = GotoProgramState::new(program, case.input.clone());
+    let outcome = match state.run_with_limit(limits.max_steps) {
+        Err(e) => Outcome::RuntimeError(e.to_string()),
+        Ok(false) => Outcome::StepLimitExceeded,
+        Ok(true) => case.expected.iter()
+            .find(|&&(cell, expected)| state.memory.get(cell) != Some(&expected))
+            .map(|&(cell, expected)| Outcome::WrongOutput { cell, expected, actual: state.memory.get(cell).cloned() })
+            .unwrap_or(Outcome::Passed),
+    };
+    CaseResult { name: case.name.clone(), outcome, steps: state.steps }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score {
+    pub points: f64,
+    pub max_points: f64,
+}
+
+/// Turns the results of all cases into a score. Implement this to plug
+/// custom scoring schemes into [`grade`].
+pub trait GradingPolicy {
+    fn name(&self) -> String;
+    fn score(&self, results: &[CaseResult]) -> Score;
+}
+
+/// Full points only if every case passes.
+pub struct AllOrNothing {
+    pub points: f64,
+}
+
+impl GradingPolicy for AllOrNothing {
+    fn name(&self) -> String {
+        "all or nothing".to_string()
+    }
+
+    fn score(&self, results: &[CaseResult]) -> Score {
+        let points = if results.iter().all(|r| r.passed()) { self.points } else { 0.0 };
+        Score { points, max_points: self.points }
+    }
+}
+
+/// A fixed number of points per passing case.
+pub struct PartialCredit {
+    pub points_per_case: f64,
+}
+
+impl GradingPolicy for PartialCredit {
+    fn name(&self) -> String {
+        "partial credit".to_string()
+    }
+
+    fn score(&self, results: &[CaseResult]) -> Score {
+        let passed = results.iter().filter(|r| r.passed()).count();
+        Score {
+            points: passed as f64 * self.points_per_case,
+            max_points: results.len() as f64 * self.points_per_case,
+        }
+    }
+}
+
+/// One point per passing case, of which `weight` is only awarded in full if
+/// the case needs at most `reference_steps` steps and shrinks proportionally
+/// for slower solutions.
+pub struct StepEfficiency {
+    pub reference_steps: u64,
+    pub weight: f64,
+}
+
+impl GradingPolicy for StepEfficiency {
+    fn name(&self) -> String {
+        format!("step efficiency (reference {} steps)", self.reference_steps)
+    }
+
+    fn score(&self, results: &[CaseResult]) -> Score {
+        let points = results.iter()
+            .filter(|r| r.passed())
+            .map(|r| {
+                let efficiency = (self.reference_steps as f64 / r.steps.max(1) as f64).min(1.0);
+                (1.0 - self.weight) + self.weight * efficiency
+            })
+            .sum();
+        Score { points, max_points: results.len() as f64 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub policy: String,
+    pub results: Vec<CaseResult>,
+    pub score: Score,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for result in &self.results {
+            writeln!(f, "{}: {} ({} steps)", result.name, result.outcome, result.steps)?;
+        }
+        let passed = self.results.iter().filter(|r| r.passed()).count();
+        writeln!(f, "{} of {} cases passed", passed, self.results.len())?;
+        write!(f, "score: {:.2} / {:.2} ({})", self.score.points, self.score.max_points, self.policy)
+    }
+}
+
+pub fn grade(program: &GotoProgram, spec: &TestSpec, policy: &dyn GradingPolicy) -> Report {
+    let results: Vec<_> = spec.cases.iter()
+        .map(|case| run_case(program, case, &spec.limits))
+        .collect();
+    let score = policy.score(&results);
+    Report { policy: policy.name(), results, score }
+}
+
+#[test]
+fn test_grade() {
+    let program = crate::parse_program("loop: GOTOZ 0 end
+    DEC 0
+    INC 1
+    GOTO loop
+    end: STOP".to_string()).unwrap();
+    let spec = TestSpec::parse("max_steps 50
+    case small
+    input 3 4
+    expect 1=7
+    case wrong
+    input 1 1
+    expect 0=0 1=3
+    # needs 4 * 10 + 2 steps
+    case slow
+    input 10 0
+    expect 1=10
+    case too slow
+    input 20 0
+    expect 1=20").unwrap();
+    assert_eq!(50, spec.limits.max_steps);
+
+    let report = grade(&program, &spec, &PartialCredit { points_per_case: 2.0 });
+    let outcomes: Vec<_> = report.results.iter().map(|r| r.outcome.clone()).collect();
+    assert_eq!(vec![
+        Outcome::Passed,
+        Outcome::WrongOutput { cell: 1, expected: 3, actual: Some(2) },
+        Outcome::Passed,
+        Outcome::StepLimitExceeded,
+    ], outcomes);
+    assert_eq!(Score { points: 4.0, max_points: 8.0 }, report.score);
+    assert_eq!(0.0, grade(&program, &spec, &AllOrNothing { points: 1.0 }).score.points);
+
+    let score = StepEfficiency { reference_steps: 21, weight: 0.5 }.score(&report.results);
+    assert_eq!(Score { points: 1.75, max_points: 4.0 }, score);
+
+    assert!(TestSpec::parse("input 1 2").is_err());
+    assert!(TestSpec::parse("case\nexpect 1").is_err());
+}
@@ -0,0 +1,161 @@
+//! Syntax highlighting grammars for editors, generated from the tables the
+//! parser itself uses: `MNEMONICS`, the directives of the body, modules, the
+//! header, the preprocessor and macros, and the names of dialects and cell
+//! arithmetics. Instructions of the classic dialect get another scope than
+//! the ones of the extended dialect.
+
+use std::convert::TryFrom;
+
+use crate::header::{self, Dialect};
+use crate::json::Value;
+use crate::transpile::Arithmetic;
+use crate::{link, macros, preprocess, source, Instruction, BANK, DIRECTIVES, MNEMONICS};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    /// a `.tmLanguage.json` for VS Code, Sublime Text and others
+    TextMate,
+    /// the `grammar.js` of a tree-sitter parser
+    TreeSitter,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 2] = ["textmate", "tree-sitter"];
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "textmate" => Some(Format::TextMate),
+            "tree-sitter" => Some(Format::TreeSitter),
+            _ => None,
+        }
+    }
+}
+
+pub fn generate(format: Format) -> String {
+    match format {
+        Format::TextMate => textmate(),
+        Format::TreeSitter => tree_sitter(),
+    }
+}
+
+/// The mnemonics the classic dialect allows.
+fn classic(mnemonic: &str, operands: usize) -> bool {
+    Instruction::try_from(format!("{}{}", mnemonic, " 0".repeat(operands)))
+        .is_ok_and(|instruction| Dialect::Classic.allows(&instruction))
+}
+
+fn alternatives(words: &[&str]) -> String {
+    let mut words: Vec<String> = words.iter().map(|word| word.replace('.', "\\.")).collect();
+    // the longest first, so that no word stops a longer one from matching
+    words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+    words.join("|")
+}
+
+fn pattern(name: &str, regex: String) -> Value {
+    Value::object(vec![("name", format!("{}.goto", name).into()), ("match", regex.into())])
+}
+
+/// The directives between instructions, with the ones of linked modules.
+fn body_directives() -> Vec<&'static str> {
+    DIRECTIVES.iter().chain(link::DIRECTIVES.iter()).cloned().collect()
+}
+
+/// The directives handled before parsing, with the ones of macros.
+fn preprocessor_directives() -> Vec<&'static str> {
+    preprocess::DIRECTIVES.iter().chain(macros::DIRECTIVES.iter()).cloned().collect()
+}
+
+pub fn textmate() -> String {
+    let (classic, extended): (Vec<_>, Vec<_>) = MNEMONICS.iter().partition(|(mnemonic, operands)| classic(mnemonic, *operands));
+    let names = |mnemonics: Vec<&(&str, usize)>| alternatives(&mnemonics.iter().map(|(mnemonic, _)| *mnemonic).collect::<Vec<_>>());
+    let values: Vec<&str> = Dialect::NAMES.iter().chain(Arithmetic::NAMES.iter()).cloned().collect();
+    let label = Value::object(vec![
+        ("match", format!("(?:^|\\{})\\s*([A-Za-z_][A-Za-z0-9_]*)\\s*(:)", source::SEPARATOR).into()),
+        ("captures", Value::object(vec![
+            ("1", Value::object(vec![("name", "entity.name.label.goto".into())])),
+            ("2", Value::object(vec![("name", "punctuation.separator.label.goto".into())])),
+        ])),
+    ]);
+    let grammar = Value::object(vec![
+        ("$schema", "https://raw.githubusercontent.com/martinring/tmlanguage/master/tmlanguage.json".into()),
+        ("name", "GOTO".into()),
+        ("scopeName", "source.goto".into()),
+        ("fileTypes", vec!["goto"].into()),
+        ("patterns", Value::Array(vec![
+            pattern("comment.line.semicolon", ";.*$".to_string()),
+            pattern("meta.preprocessor", format!("(?<![\\w.])({})\\b", alternatives(&preprocessor_directives()))),
+            pattern("keyword.other.include", format!("^\\s*({})\\s+<[^>]*>", macros::INCLUDE)),
+            pattern("keyword.other.header", format!("(?<![\\w.])({})\\b", alternatives(&header::DIRECTIVES))),
+            pattern("keyword.other.directive", format!("(?<![\\w.])({})\\b", alternatives(&body_directives()))),
+            label,
+            pattern("keyword.control.classic", format!("\\b({})\\b", names(classic))),
+            pattern("keyword.control.extended", format!("\\b({})\\b", names(extended))),
+            pattern("storage.modifier.bank", format!("\\b{}\\b", BANK)),
+            pattern("constant.language", format!("\\b({})\\b", alternatives(&values))),
+            pattern("constant.numeric", "\\b[0-9]+\\b".to_string()),
+            pattern("punctuation.separator.instruction", format!("\\{}", source::SEPARATOR)),
+            pattern("keyword.operator", "[-+*/%=()]".to_string()),
+            pattern("variable.other", "\\b[A-Za-z_][A-Za-z0-9_]*\\b".to_string()),
+        ])),
+    ]);
+    grammar.to_string() + "\n"
+}
+
+fn quoted(words: &[&str]) -> String {
+    words.iter().map(|word| format!("'{}'", word)).collect::<Vec<_>>().join(", ")
+}
+
+pub fn tree_sitter() -> String {
+    let instructions: Vec<String> = MNEMONICS.iter().map(|&(mnemonic, operands)| {
+        let node = if classic(mnemonic, operands) { "classic_mnemonic" } else { "extended_mnemonic" };
+        // STOP and NOP ignore the tokens after them
+        let operands = if operands == 0 { ", repeat($.operand)".to_string() } else { ", field('operand', $.operand)".repeat(operands) };
+        format!("      seq(field('mnemonic', alias('{}', $.{})){})", mnemonic, node, operands)
+    }).collect();
+    let directives: Vec<&str> = body_directives().into_iter().chain(header::DIRECTIVES.iter().cloned()).chain(preprocessor_directives()).collect();
+    format!(r#"// Generated by `goto grammar --format tree-sitter`, do not edit.
+module.exports = grammar({{
+  name: 'goto',
+  extras: $ => [/[ \t]/, $.comment],
+  word: $ => $.identifier,
+  rules: {{
+    source_file: $ => seq(optional($._line), repeat(seq(/\r?\n/, optional($._line)))),
+    _line: $ => choice($.directive, $.include, seq($.segment, repeat(seq('{separator}', $.segment)))),
+    segment: $ => choice(seq($.label, optional($._statement)), $._statement),
+    label: $ => seq(field('name', $.identifier), ':'),
+    _statement: $ => choice(seq(optional($.bank), $.instruction), $.macro_call),
+    bank: $ => seq('{bank}', $.operand),
+    instruction: $ => choice(
+{instructions}
+    ),
+    directive: $ => seq(field('name', $.directive_name), repeat(choice($.operand, '='))),
+    directive_name: $ => choice({directives}),
+    include: $ => seq('{include}', field('library', /<[^>\r\n]*>/)),
+    macro_call: $ => seq(field('name', $.identifier), repeat($.operand)),
+    operand: $ => choice($.number, $.identifier, $.expression),
+    expression: $ => /[A-Za-z0-9_]*[-+*\/%()][-+*\/%()A-Za-z0-9_]*/,
+    number: $ => /[0-9]+/,
+    identifier: $ => /[A-Za-z_][A-Za-z0-9_]*/,
+    comment: $ => token(seq(';', /.*/)),
+  }}
+}});
+"#, separator = source::SEPARATOR, bank = BANK, include = macros::INCLUDE, instructions = instructions.join(",\n"), directives = quoted(&directives))
+}
+
+#[test]
+fn test_grammar() {
+    let textmate = Value::parse(&textmate()).unwrap();
+    let patterns = textmate.get("patterns").and_then(Value::as_array).unwrap();
+    let regex = |name: &str| patterns.iter().find(|pattern| pattern.get("name").and_then(Value::as_str) == Some(name))
+        .and_then(|pattern| pattern.get("match")).and_then(Value::as_str).unwrap().to_string();
+    assert_eq!("\\b(GOTOZ|STOP|GOTO|INC|DEC)\\b", regex("keyword.control.classic.goto"));
+    assert_eq!("\\b(ASSERTZ|GOTONZ|ASSERT|STOREI|GOTOEQ|GOTOGT|PRINT|READ|OUTC|PUSH|RAND|NOP|POP)\\b", regex("keyword.control.extended.goto"));
+    assert_eq!("(?<![\\w.])(\\.export|\\.import|\\.const|\\.alias|\\.data|\\.init)\\b", regex("keyword.other.directive.goto"));
+
+    let tree_sitter = tree_sitter();
+    assert!(tree_sitter.contains("seq(field('mnemonic', alias('GOTOEQ', $.extended_mnemonic)), field('operand', $.operand), field('operand', $.operand), field('operand', $.operand))"));
+    assert!(tree_sitter.contains("seq(field('mnemonic', alias('STOP', $.classic_mnemonic)), repeat($.operand))"));
+    assert!(tree_sitter.contains("choice('.const', '.alias', '.data', '.init', '.export', '.import', '.dialect', '.memory', '.cells', '.ifdef', '.ifndef', '.else', '.endif', '.macro', '.endmacro')"));
+    assert!(tree_sitter.contains("include: $ => seq('INCLUDE', field('library', /<[^>\\r\\n]*>/)),"));
+    assert_eq!(Some(Format::TreeSitter), Format::from_name("tree-sitter"));
+}
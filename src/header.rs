@@ -0,0 +1,141 @@
+//! The header of a program: directives before its first instruction that
+//! say what the program needs, like `.dialect classic`, `.memory 64` or
+//! `.cells wrapping`, instead of the flags it has to be run with.
+
+use crate::transpile::Arithmetic;
+use crate::Instruction;
+
+/// Which instructions a program may use.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Dialect {
+    /// STOP, INC, DEC, GOTO and GOTOZ, the minimal model
+    Classic,
+    /// every instruction
+    Extended,
+}
+
+impl Dialect {
+    pub const NAMES: [&'static str; 2] = ["classic", "extended"];
+
+    pub fn from_name(name: &str) -> Option<Dialect> {
+        match name {
+            "classic" => Some(Dialect::Classic),
+            "extended" => Some(Dialect::Extended),
+            _ => None,
+        }
+    }
+
+    pub fn allows(self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::Stop | Instruction::Inc { .. } | Instruction::Dec { .. } | Instruction::Goto { .. } | Instruction::GotoZ { .. } => true,
+            _ => self == Dialect::Extended,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Header {
+    pub dialect: Option<Dialect>,
+    /// the number of cells of the memory
+    pub memory: Option<usize>,
+    /// what INC on the largest value and DEC on 0 do
+    pub cells: Option<Arithmetic>,
+}
+
+pub const DIRECTIVES: [&str; 3] = [".dialect", ".memory", ".cells"];
+
+pub fn is_header_directive(name: &str) -> bool {
+    DIRECTIVES.contains(&name)
+}
+
+impl Header {
+    /// Handles one of the header directives, each may come once.
+    pub fn parse_directive(&mut self, tokens: &[String]) -> Result<(), String> {
+        if tokens.len() != 2 {
+            return Err(format!("expected {} <value> in: {}", tokens[0], tokens.join(" ")));
+        }
+        let value = &tokens[1];
+        let declared = match tokens[0].as_str() {
+            ".dialect" => self.dialect.is_some(),
+            ".memory" => self.memory.is_some(),
+            ".cells" => self.cells.is_some(),
+            _ => return Err(format!("Unknown directive: {}", tokens[0])),
+        };
+        if declared {
+            return Err(format!("Duplicate directive: {}", tokens[0]));
+        }
+        match tokens[0].as_str() {
+            ".dialect" => self.dialect = Some(Dialect::from_name(value)
+                .ok_or_else(|| format!("Unknown dialect: {}, expected one of {}", value, Dialect::NAMES.join(", ")))?),
+            ".memory" => self.memory = Some(value.parse()
+                .map_err(|e| format!("{} is not a number of cells (reason: {:?})", value, e))?),
+            _ => self.cells = Some(Arithmetic::from_name(value)
+                .ok_or_else(|| format!("Unknown cells: {}, expected one of {}", value, Arithmetic::NAMES.join(", ")))?),
+        }
+        Ok(())
+    }
+
+    /// The dialect of the header and of the `--dialect` flag, which have to
+    /// agree when both are given.
+    pub fn dialect(&self, flag: Option<Dialect>) -> Result<Dialect, String> {
+        match (self.dialect, flag) {
+            (Some(dialect), Some(flag)) if dialect != flag => Err(format!("the program declares .dialect {} but --dialect is {}", Dialect::NAMES[dialect as usize], Dialect::NAMES[flag as usize])),
+            (dialect, flag) => Ok(dialect.or(flag).unwrap_or(Dialect::Extended)),
+        }
+    }
+
+    /// The arithmetic of the header and of the `--arithmetic` flag, which
+    /// have to agree when both are given.
+    pub fn arithmetic(&self, flag: Option<Arithmetic>) -> Result<Arithmetic, String> {
+        match (self.cells, flag) {
+            (Some(cells), Some(flag)) if cells != flag => Err(format!("the program declares .cells {} but --arithmetic is {}", Arithmetic::NAMES[cells as usize], Arithmetic::NAMES[flag as usize])),
+            (cells, flag) => Ok(cells.or(flag).unwrap_or(Arithmetic::Checked)),
+        }
+    }
+
+    /// Whether an input of that many cells fits into the declared memory.
+    pub fn check_input(&self, cells: usize) -> Result<(), String> {
+        match self.memory {
+            Some(memory) if cells > memory => Err(format!("the input has {} cells but the program declares .memory {}", cells, memory)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_header() {
+    let tokens = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<_>>();
+    let mut header = Header::default();
+    header.parse_directive(&tokens(".dialect classic")).unwrap();
+    header.parse_directive(&tokens(".memory 4")).unwrap();
+    header.parse_directive(&tokens(".cells wrapping")).unwrap();
+    assert_eq!(Header { dialect: Some(Dialect::Classic), memory: Some(4), cells: Some(Arithmetic::Wrapping) }, header);
+    assert_eq!(Err("Duplicate directive: .memory".to_string()), header.parse_directive(&tokens(".memory 5")));
+    assert!(header.parse_directive(&tokens(".cells signed")).is_err());
+    assert!(Header::default().parse_directive(&tokens(".memory")).is_err());
+
+    assert_eq!(Ok(Arithmetic::Wrapping), header.arithmetic(None));
+    assert_eq!(Ok(Arithmetic::Wrapping), header.arithmetic(Some(Arithmetic::Wrapping)));
+    assert_eq!(Err("the program declares .cells wrapping but --arithmetic is checked".to_string()), header.arithmetic(Some(Arithmetic::Checked)));
+    assert_eq!(Ok(Arithmetic::Checked), Header::default().arithmetic(None));
+    assert_eq!(Ok(Dialect::Classic), header.dialect(Some(Dialect::Classic)));
+    assert_eq!(Err("the program declares .dialect classic but --dialect is extended".to_string()), header.dialect(Some(Dialect::Extended)));
+    assert_eq!(Ok(Dialect::Extended), Header::default().dialect(None));
+    assert_eq!(Ok(()), header.check_input(4));
+    assert!(header.check_input(5).is_err());
+    assert!(!Dialect::Classic.allows(&Instruction::Print { cell: 0 }));
+    assert!(Dialect::Extended.allows(&Instruction::Print { cell: 0 }));
+
+    let program = crate::parse_program(".memory 3\n.dialect classic\n.init 1 = 5\nINC 0".to_string()).unwrap();
+    assert_eq!(vec![2, 5, 0], program.initial_memory(&[2]));
+    assert_eq!(Err("error in line 2: PRINT 0 is not in the classic dialect".to_string()),
+               crate::parse_program(".dialect classic\nPRINT 0".to_string()).map(|_| ()));
+    assert_eq!(Err("error in line 2: .memory has to come before the first instruction".to_string()),
+               crate::parse_program("INC 0\n.memory 3".to_string()).map(|_| ()));
+    assert!(crate::parse_program(".memory 1\n.data 1 2".to_string()).is_err());
+    let classic = crate::ParseOptions { dialect: Some(Dialect::Classic), ..crate::ParseOptions::default() };
+    assert!(crate::parse_program_with("INC 0\nGOTOZ 0 0".to_string(), &classic).is_ok());
+    assert_eq!(Err("error in line 2: RAND 0 3 is not in the classic dialect".to_string()),
+               crate::parse_program_with("INC 0\nRAND 0 3".to_string(), &classic).map(|_| ()));
+    assert!(crate::parse_program_with(".dialect extended\nSTOP".to_string(), &classic).is_err());
+}
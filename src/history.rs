@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+
+use crate::{GotoProgramState, StackChange, StepEffect};
+
+/// A bounded record of the last executed steps that allows undoing them.
+/// Only the effect of each step is kept, which is enough because every
+/// instruction changes at most one cell and the top of the stack.
+#[derive(Debug)]
+pub struct History {
+    effects: VecDeque<StepEffect>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History { effects: VecDeque::new(), capacity }
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn record(&mut self, effect: &StepEffect) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.effects.len() == self.capacity {
+            self.effects.pop_front();
+        }
+        self.effects.push_back(*effect);
+    }
+
+    /// Reverts the most recent recorded step, returns `false` if there is none.
+    pub fn step_back(&mut self, state: &mut GotoProgramState) -> bool {
+        match self.effects.pop_back() {
+            Some(effect) => {
+                state.program_counter = effect.program_counter;
+                if let Some(write) = effect.write {
+                    state.memory[write.cell] = write.old;
+                }
+                match effect.stack {
+                    Some(StackChange::Pushed) => {
+                        state.stack.pop();
+                    }
+                    Some(StackChange::Popped(value)) => state.stack.push(value),
+                    None => {}
+                }
+                state.steps -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[test]
+fn test_step_back() {
+    let program = crate::parse_program("loop: GOTOZ 0 end
+    DEC 0
+    INC 1
+    GOTO loop
+    end: STOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![3, 0]);
+    let mut history = History::new(5);
+    let mut snapshots = vec![];
+    loop {
+        snapshots.push((state.program_counter, state.memory.clone(), state.steps));
+        if state.step_with_hook(&mut |effect| history.record(effect)).unwrap() {
+            break;
+        }
+    }
+    assert_eq!(5, history.len());
+    for expected in snapshots.iter().rev().take(5) {
+        assert!(history.step_back(&mut state));
+        assert_eq!(expected, &(state.program_counter, state.memory.clone(), state.steps));
+    }
+    assert!(!history.step_back(&mut state));
+}
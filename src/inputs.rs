@@ -0,0 +1,90 @@
+//! Sets of inputs that are checked one by one, all cells in a range of cells
+//! take every value in a range of values.
+
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use crate::random::Random;
+use crate::{ranges, GotoProgram};
+
+/// Parses `a..b` with `b` excluded, or a single number.
+pub fn parse_range(text: &str) -> Result<Range<u64>, String> {
+    let invalid = || format!("invalid range {}, expected something like 0..10", text);
+    let mut bounds = text.splitn(2, "..");
+    let start: u64 = bounds.next().unwrap().trim().parse().map_err(|_| invalid())?;
+    let end = match bounds.next() {
+        Some(end) => end.trim().parse().map_err(|_| invalid())?,
+        None => start.checked_add(1).ok_or_else(invalid)?,
+    };
+    if end <= start {
+        return Err(format!("the range {} is empty", text));
+    }
+    Ok(start..end)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InputSpace {
+    pub cells: Range<usize>,
+    pub values: Range<u64>,
+    /// the memory size of every input, cells outside of `cells` are 0
+    pub size: usize,
+}
+
+impl InputSpace {
+    /// The inputs for `program` with a memory large enough for the cells it uses.
+    pub fn new(program: &GotoProgram, cells: Range<usize>, values: Range<u64>) -> InputSpace {
+        let used = ranges::used_cells(program).last().map(|cell| cell + 1).unwrap_or(0);
+        InputSpace { size: used.max(cells.end), cells, values }
+    }
+
+    /// How many inputs there are, `None` if more than fit into a `u64`.
+    pub fn count(&self) -> Option<u64> {
+        let values = self.values.end - self.values.start;
+        values.checked_pow(u32::try_from(self.cells.len()).ok()?)
+    }
+
+    /// Every input, counting up from the first cell like an odometer.
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u64>> + '_ {
+        let mut next = Some(vec![0; self.size]).map(|mut memory| {
+            for cell in self.cells.clone() {
+                memory[cell] = self.values.start;
+            }
+            memory
+        });
+        std::iter::from_fn(move || {
+            let current = next.take()?;
+            let mut following = current.clone();
+            for cell in self.cells.clone() {
+                following[cell] += 1;
+                if following[cell] < self.values.end {
+                    next = Some(following);
+                    break;
+                }
+                following[cell] = self.values.start;
+            }
+            Some(current)
+        })
+    }
+
+    /// An input with every cell picked at random from the values.
+    pub fn sample(&self, random: &mut Random) -> Vec<u64> {
+        let mut memory = vec![0; self.size];
+        for cell in self.cells.clone() {
+            memory[cell] = self.values.start + random.below(self.values.end - self.values.start);
+        }
+        memory
+    }
+}
+
+#[test]
+fn test_input_space() {
+    assert_eq!(Ok(0..3), parse_range("0..3"));
+    assert_eq!(Ok(7..8), parse_range("7"));
+    assert!(parse_range("3..3").is_err());
+    let space = InputSpace { cells: 1..3, values: parse_range("4..6").unwrap(), size: 4 };
+    assert_eq!(Some(4), space.count());
+    assert_eq!(vec![vec![0, 4, 4, 0], vec![0, 5, 4, 0], vec![0, 4, 5, 0], vec![0, 5, 5, 0]], space.iter().collect::<Vec<_>>());
+    let sample = space.sample(&mut Random::new(7));
+    assert!(space.iter().any(|input| input == sample));
+    assert_eq!(None, InputSpace { cells: 0..100, ..space }.count());
+}
@@ -0,0 +1,292 @@
+//! Translates a program into native code with Cranelift.
+//!
+//! The program becomes one function with a block for every instruction, jumps
+//! become branches between them and a switch on the program counter enters
+//! at the right one. Every block first spends one unit of fuel, so a run can
+//! be limited to a number of steps, and then works on the memory array
+//! directly. Cell numbers are checked against the memory size while
+//! compiling, which is why compiled code belongs to one memory size.
+
+use std::collections::HashMap;
+
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, Block, InstBuilder, MemFlags, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Switch, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+use crate::{GotoProgram, GotoProgramState, Instruction};
+
+const STOPPED: i64 = 0;
+const OUT_OF_FUEL: i64 = 1;
+const DEC_ZERO: i64 = 2;
+const OVERFLOW: i64 = 3;
+const CELL_OUTSIDE: i64 = 4;
+const PC_OUTSIDE: i64 = 5;
+
+/// Stores the remaining fuel and the program counter in `exit` and returns
+/// the exit status.
+type Entry = unsafe extern "C" fn(memory: *mut u64, fuel: u64, pc: u64, exit: *mut [u64; 2]) -> u64;
+
+struct Translator<'a, 'b> {
+    builder: FunctionBuilder<'a>,
+    memory: Value,
+    fuel: Variable,
+    exit: Block,
+    blocks: &'b [Block],
+    /// the blocks for jumps to a program counter outside of the program
+    outside: HashMap<usize, Block>,
+}
+
+impl Translator<'_, '_> {
+    fn target(&mut self, pc: usize) -> Block {
+        match self.blocks.get(pc) {
+            Some(block) => *block,
+            None => {
+                let builder = &mut self.builder;
+                *self.outside.entry(pc).or_insert_with(|| builder.create_block())
+            }
+        }
+    }
+
+    fn exit(&mut self, status: i64, pc: usize) {
+        let status = self.builder.ins().iconst(types::I64, status);
+        let pc = self.builder.ins().iconst(types::I64, pc as i64);
+        self.builder.ins().jump(self.exit, &[status, pc]);
+    }
+
+    /// Exits with the status if `condition` is not 0, else continues in a new block.
+    fn exit_if(&mut self, condition: Value, status: i64, pc: usize) {
+        let exit = self.builder.create_block();
+        let next = self.builder.create_block();
+        self.builder.ins().brif(condition, exit, &[], next, &[]);
+        self.builder.switch_to_block(exit);
+        self.exit(status, pc);
+        self.builder.switch_to_block(next);
+    }
+
+    /// Spends a unit of fuel, a failed step does not count so this comes after the checks.
+    fn spend(&mut self) {
+        let fuel = self.builder.use_var(self.fuel);
+        let fuel = self.builder.ins().iadd_imm(fuel, -1);
+        self.builder.def_var(self.fuel, fuel);
+    }
+
+    fn out_of_fuel(&mut self, pc: usize) {
+        let fuel = self.builder.use_var(self.fuel);
+        let empty = self.builder.ins().icmp_imm(IntCC::Equal, fuel, 0);
+        self.exit_if(empty, OUT_OF_FUEL, pc);
+    }
+
+    fn address(&mut self, cell: usize) -> Value {
+        self.builder.ins().iadd_imm(self.memory, cell as i64 * 8)
+    }
+
+    /// Returns `false` if the instruction has no translation.
+    fn instruction(&mut self, pc: usize, instruction: &Instruction, memory_size: usize) -> bool {
+        let next = self.target(pc + 1);
+        self.out_of_fuel(pc);
+        match *instruction {
+            Instruction::Stop => {
+                self.spend();
+                self.exit(STOPPED, pc);
+            }
+            Instruction::Nop => {
+                self.spend();
+                self.builder.ins().jump(next, &[]);
+            }
+            Instruction::Inc { cell } | Instruction::Dec { cell } | Instruction::GotoZ { condition_cell: cell, .. }
+                if cell >= memory_size => self.exit(CELL_OUTSIDE, pc),
+            Instruction::Inc { cell } | Instruction::Dec { cell } => {
+                let (limit, status, by) = match instruction {
+                    Instruction::Inc { .. } => (-1, OVERFLOW, 1),
+                    _ => (0, DEC_ZERO, -1),
+                };
+                let address = self.address(cell);
+                let value = self.builder.ins().load(types::I64, MemFlags::trusted(), address, 0);
+                let at_limit = self.builder.ins().icmp_imm(IntCC::Equal, value, limit);
+                self.exit_if(at_limit, status, pc);
+                self.spend();
+                let value = self.builder.ins().iadd_imm(value, by);
+                self.builder.ins().store(MemFlags::trusted(), value, address, 0);
+                self.builder.ins().jump(next, &[]);
+            }
+            Instruction::Goto { cell } => {
+                let target = self.target(cell);
+                self.spend();
+                self.builder.ins().jump(target, &[]);
+            }
+            Instruction::GotoZ { condition_cell, goto_cell } => {
+                let target = self.target(goto_cell);
+                let address = self.address(condition_cell);
+                let value = self.builder.ins().load(types::I64, MemFlags::trusted(), address, 0);
+                self.spend();
+                if target == next {
+                    self.builder.ins().jump(next, &[]);
+                } else {
+                    self.builder.ins().brif(value, next, &[], target, &[]);
+                }
+            }
+            Instruction::Assert { .. } | Instruction::Print { .. } | Instruction::Read { .. } | Instruction::OutC { .. }
+            | Instruction::Rand { .. } | Instruction::Push { .. } | Instruction::Pop { .. }
+            | Instruction::StoreI { .. } | Instruction::GotoNz { .. } | Instruction::GotoEq { .. }
+            | Instruction::GotoGt { .. } => return false,
+        }
+        true
+    }
+}
+
+pub struct Jit {
+    /// owns the code, `None` only while dropping
+    module: Option<Box<JITModule>>,
+    entry: Entry,
+    count: usize,
+    memory_size: usize,
+}
+
+impl Jit {
+    /// Returns `None` if the program uses ASSERT, console I/O, RAND or the
+    /// stack, which have no translation, or Cranelift does not support the
+    /// machine.
+    pub fn compile(program: &GotoProgram, memory_size: usize) -> Option<Jit> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").ok()?;
+        let isa = cranelift_native::builder().ok()?.finish(settings::Flags::new(flags)).ok()?;
+        let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+        let pointer = module.target_config().pointer_type();
+        let mut context = module.make_context();
+        let signature = &mut context.func.signature;
+        signature.params.extend([AbiParam::new(pointer), AbiParam::new(types::I64), AbiParam::new(types::I64), AbiParam::new(pointer)]);
+        signature.returns.push(AbiParam::new(types::I64));
+        let id = module.declare_function("run", Linkage::Local, &context.func.signature).ok()?;
+
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let params = builder.block_params(entry).to_vec();
+        let (memory, exit_cells) = (params[0], params[3]);
+        let fuel = Variable::from_u32(0);
+        builder.declare_var(fuel, types::I64);
+        builder.def_var(fuel, params[1]);
+
+        let count = program.instructions.len();
+        let blocks: Vec<Block> = (0..count).map(|_| builder.create_block()).collect();
+        // the program counter outside of the program, `run` does not enter there
+        let otherwise = builder.create_block();
+        let mut switch = Switch::new();
+        for (pc, block) in blocks.iter().enumerate() {
+            switch.set_entry(pc as u128, *block);
+        }
+        switch.emit(&mut builder, params[2], otherwise);
+
+        let exit = builder.create_block();
+        builder.append_block_param(exit, types::I64);
+        builder.append_block_param(exit, types::I64);
+        let mut translator = Translator { builder, memory, fuel, exit, blocks: &blocks, outside: HashMap::new() };
+        translator.builder.switch_to_block(otherwise);
+        let status = translator.builder.ins().iconst(types::I64, PC_OUTSIDE);
+        translator.builder.ins().jump(exit, &[status, params[2]]);
+        for (pc, instruction) in program.instructions.iter().enumerate() {
+            translator.builder.switch_to_block(blocks[pc]);
+            if !translator.instruction(pc, instruction, memory_size) {
+                return None;
+            }
+        }
+        let mut outside: Vec<(usize, Block)> = translator.outside.drain().collect();
+        outside.sort();
+        for (pc, block) in outside {
+            translator.builder.switch_to_block(block);
+            translator.out_of_fuel(pc);
+            translator.exit(PC_OUTSIDE, pc);
+        }
+
+        let mut builder = translator.builder;
+        builder.switch_to_block(exit);
+        let (status, pc) = (builder.block_params(exit)[0], builder.block_params(exit)[1]);
+        let fuel = builder.use_var(fuel);
+        builder.ins().store(MemFlags::trusted(), fuel, exit_cells, 0);
+        builder.ins().store(MemFlags::trusted(), pc, exit_cells, 8);
+        builder.ins().return_(&[status]);
+        builder.seal_all_blocks();
+        builder.finalize();
+
+        module.define_function(id, &mut context).ok()?;
+        module.clear_context(&mut context);
+        module.finalize_definitions().ok()?;
+        let entry = unsafe { std::mem::transmute::<*const u8, Entry>(module.get_finalized_function(id)) };
+        Some(Jit { module: Some(Box::new(module)), entry, count, memory_size })
+    }
+
+    /// Behaves like `GotoProgramState::run_with_limit`, the state must belong to
+    /// the compiled program and have the memory size it was compiled for.
+    pub fn run(&self, state: &mut GotoProgramState, max_steps: u64) -> Result<bool, String> {
+        assert_eq!(self.memory_size, state.memory.len(), "compiled for a different memory size");
+        if state.code_base.is_some() {
+            return Err("the JIT does not run programs in von Neumann mode".to_string());
+        }
+        let fuel = max_steps.saturating_sub(state.steps);
+        if state.program_counter >= self.count {
+            return match fuel {
+                0 => Ok(false),
+                _ => Err(format!("program counter {} is outside of the program", state.program_counter)),
+            };
+        }
+        let mut exit = [0u64; 2];
+        let status = unsafe { (self.entry)(state.memory.as_mut_ptr(), fuel, state.program_counter as u64, &mut exit) };
+        state.steps += fuel - exit[0];
+        state.program_counter = exit[1] as usize;
+        let pc = state.program_counter;
+        let outside = |cell: usize| format!("cell {} is outside of the memory of size {}", cell, self.memory_size);
+        match (status as i64, state.program.instructions.get(pc)) {
+            (STOPPED, _) => Ok(true),
+            (OUT_OF_FUEL, _) => Ok(false),
+            (PC_OUTSIDE, _) => Err(format!("program counter {} is outside of the program", pc)),
+            (DEC_ZERO, Some(Instruction::Dec { cell })) => Err(format!("DEC {} on a cell that is 0", cell)),
+            (OVERFLOW, Some(Instruction::Inc { cell })) => Err(format!("INC {} overflows", cell)),
+            (CELL_OUTSIDE, Some(Instruction::Inc { cell })) | (CELL_OUTSIDE, Some(Instruction::Dec { cell })) => Err(outside(*cell)),
+            (CELL_OUTSIDE, Some(Instruction::GotoZ { condition_cell, .. })) => Err(outside(*condition_cell)),
+            _ => unreachable!("unexpected exit {} at {}", status, pc),
+        }
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+#[test]
+fn test_jit_matches_interpreter() {
+    let program = crate::parse_program("loop: GOTOZ 0 end
+    DEC 0
+    INC 1
+    GOTO loop
+    end: DEC 2
+    INC 3
+    GOTOZ 2 9
+    STOP".to_string()).unwrap();
+    let cases = [
+        (vec![5, 2, 1, 0], 1000),
+        (vec![5, 2, 1, 0], 7),
+        (vec![5, 2, 1, 0], 0),
+        (vec![5, 2, 2, 0], 1000),
+        (vec![5, 2, 1, u64::MAX], 1000),
+        (vec![5, 2, 0, 0], 1000),
+        (vec![5, 2, 1], 1000),
+        (vec![5], 1000),
+    ];
+    for (memory, max_steps) in cases.iter().cloned() {
+        let jit = Jit::compile(&program, memory.len()).unwrap();
+        let mut interpreted = GotoProgramState::new(&program, memory.clone());
+        let mut compiled = GotoProgramState::new(&program, memory);
+        assert_eq!(interpreted.run_with_limit(max_steps).map_err(String::from), jit.run(&mut compiled, max_steps));
+        assert_eq!((interpreted.program_counter, interpreted.steps, &interpreted.memory),
+                   (compiled.program_counter, compiled.steps, &compiled.memory));
+    }
+}
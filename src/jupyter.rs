@@ -0,0 +1,549 @@
+//! A Jupyter kernel that runs every cell as a GOTO program and shows the
+//! final memory as a table.
+//!
+//! `goto jupyter-kernel --install` registers the kernel, Jupyter then starts
+//! it with a connection file. The kernel speaks the part of ZeroMQ it needs
+//! itself: ZMTP 3.0 with the NULL mechanism over TCP. A cell may start with
+//! magic lines, `%memory 2 5` for the initial memory, `%input 1 2` for READ
+//! and `%trace` for a table of the steps below the memory.
+
+use std::fs::{create_dir_all, read_to_string, write};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use goto::console::{Buffered, Console};
+use goto::events::StepEvent;
+use goto::json::Value;
+use goto::sandbox::{self, Limits, Outcome};
+use goto::sha256::{hex, hmac_sha256, sha256};
+use goto::{parse_program_errors, read_input, Error, GotoProgramState, ParseOptions, RegisterIndex};
+
+const PROTOCOL_VERSION: &str = "5.3";
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+/// Rows of the memory and trace tables, the rest is summarized.
+const MAX_ROWS: usize = 200;
+const MAX_TRACE_STEPS: u64 = 10_000;
+
+// ZMTP 3.0, see https://rfc.zeromq.org/spec/23/
+
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+fn greeting() -> [u8; 64] {
+    let mut greeting = [0; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting
+}
+
+fn write_frame(out: &mut impl Write, flags: u8, body: &[u8]) -> io::Result<()> {
+    if body.len() > 255 {
+        out.write_all(&[flags | LONG])?;
+        out.write_all(&(body.len() as u64).to_be_bytes())?;
+    } else {
+        out.write_all(&[flags, body.len() as u8])?;
+    }
+    out.write_all(body)
+}
+
+fn read_frame(input: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0];
+    input.read_exact(&mut flags)?;
+    let size = if flags[0] & LONG != 0 {
+        let mut size = [0; 8];
+        input.read_exact(&mut size)?;
+        u64::from_be_bytes(size)
+    } else {
+        let mut size = [0];
+        input.read_exact(&mut size)?;
+        u64::from(size[0])
+    };
+    let mut body = vec![];
+    input.take(size).read_to_end(&mut body)?;
+    if body.len() as u64 != size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok((flags[0], body))
+}
+
+/// Exchanges greetings and READY commands, the peer's are not checked.
+fn handshake(stream: &mut (impl Read + Write), socket_type: &str) -> io::Result<()> {
+    stream.write_all(&greeting())?;
+    let mut ready = vec![5];
+    ready.extend_from_slice(b"READY");
+    ready.push(11);
+    ready.extend_from_slice(b"Socket-Type");
+    ready.extend_from_slice(&(socket_type.len() as u32).to_be_bytes());
+    ready.extend_from_slice(socket_type.as_bytes());
+    write_frame(stream, COMMAND, &ready)?;
+    stream.flush()?;
+    let mut peer = [0; 64];
+    stream.read_exact(&mut peer)?;
+    if peer[0] != 0xff || peer[9] != 0x7f || peer[10] < 3 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the peer does not speak ZMTP 3"));
+    }
+    while read_frame(stream)?.0 & COMMAND == 0 {}
+    Ok(())
+}
+
+/// The frames of the next message, commands are skipped.
+fn read_message(input: &mut impl Read) -> io::Result<Vec<Vec<u8>>> {
+    let mut frames = vec![];
+    loop {
+        let (flags, body) = read_frame(input)?;
+        if flags & COMMAND != 0 {
+            continue;
+        }
+        frames.push(body);
+        if flags & MORE == 0 {
+            return Ok(frames);
+        }
+    }
+}
+
+fn write_message(out: &mut impl Write, frames: &[Vec<u8>]) -> io::Result<()> {
+    for (index, frame) in frames.iter().enumerate() {
+        write_frame(out, if index + 1 < frames.len() { MORE } else { 0 }, frame)?;
+    }
+    out.flush()
+}
+
+// the Jupyter messaging protocol
+
+struct Message {
+    identities: Vec<Vec<u8>>,
+    header: Value,
+    content: Value,
+}
+
+impl Message {
+    fn msg_type(&self) -> &str {
+        self.header.get("msg_type").and_then(Value::as_str).unwrap_or_default()
+    }
+}
+
+/// The date of the header, like 2024-01-31T12:00:00.000000Z.
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let days = (now.as_secs() / 86400) as i64;
+    let seconds = now.as_secs() % 86400;
+    // days to the civil date, after Howard Hinnant
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60, now.subsec_micros())
+}
+
+struct Signer {
+    key: Vec<u8>,
+    session: String,
+    sent: u64,
+}
+
+impl Signer {
+    fn signature(&self, parts: &[&[u8]]) -> String {
+        if self.key.is_empty() {
+            return String::new();
+        }
+        hex(&hmac_sha256(&self.key, parts))
+    }
+
+    fn parse(&self, mut frames: Vec<Vec<u8>>) -> Result<Message, String> {
+        let delimiter = frames.iter().position(|frame| frame == DELIMITER).ok_or("a message without <IDS|MSG>")?;
+        let rest = frames.split_off(delimiter + 1);
+        frames.pop();
+        if rest.len() < 5 {
+            return Err("a message with less than 5 frames".to_string());
+        }
+        let expected = self.signature(&[&rest[1], &rest[2], &rest[3], &rest[4]]);
+        if rest[0] != expected.as_bytes() {
+            return Err("a message with an invalid signature".to_string());
+        }
+        let json = |frame: &[u8]| Value::parse(&String::from_utf8_lossy(frame));
+        Ok(Message { identities: frames, header: json(&rest[1])?, content: json(&rest[4])? })
+    }
+
+    /// The frames of a message to the identities in answer to `parent`.
+    fn frames(&mut self, identities: Vec<Vec<u8>>, parent: Option<&Message>, msg_type: &str, content: Value) -> Vec<Vec<u8>> {
+        self.sent += 1;
+        let header = Value::object(vec![
+            ("msg_id", format!("{}-{}", self.session, self.sent).into()),
+            ("session", self.session.as_str().into()),
+            ("username", "goto".into()),
+            ("date", timestamp().into()),
+            ("msg_type", msg_type.into()),
+            ("version", PROTOCOL_VERSION.into()),
+        ]).to_string();
+        let parent = parent.map_or_else(|| "{}".to_string(), |parent| parent.header.to_string());
+        let (metadata, content) = ("{}".to_string(), content.to_string());
+        let signature = self.signature(&[header.as_bytes(), parent.as_bytes(), metadata.as_bytes(), content.as_bytes()]);
+        let mut frames = identities;
+        frames.push(DELIMITER.to_vec());
+        frames.extend(vec![signature, header, parent, metadata, content].into_iter().map(String::into_bytes));
+        frames
+    }
+}
+
+// running cells
+
+/// A `Buffered` console that is still readable after the run.
+#[derive(Debug, Clone, Default)]
+struct Shared(Arc<Mutex<Buffered>>);
+
+impl Console for Shared {
+    fn read(&mut self, cell: RegisterIndex) -> Result<u64, String> {
+        self.0.lock().map_err(|e| e.to_string())?.read(cell)
+    }
+
+    fn print(&mut self, value: u64) -> Result<(), String> {
+        self.0.lock().map_err(|e| e.to_string())?.print(value)
+    }
+
+    fn print_char(&mut self, cell: RegisterIndex, value: u64) -> Result<(), String> {
+        self.0.lock().map_err(|e| e.to_string())?.print_char(cell, value)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn table(head: &[&str], rows: Vec<Vec<String>>, total: usize) -> String {
+    let mut html = format!("<table><tr>{}</tr>", head.iter().map(|name| format!("<th>{}</th>", name)).collect::<String>());
+    for row in rows {
+        html += &format!("<tr>{}</tr>", row.iter().map(|cell| format!("<td>{}</td>", escape(cell))).collect::<String>());
+    }
+    if total > MAX_ROWS {
+        html += &format!("<tr><td colspan=\"{}\">and {} more</td></tr>", head.len(), total - MAX_ROWS);
+    }
+    html + "</table>"
+}
+
+/// What a cell printed and the data of its result, or the name and text of its error.
+struct Executed {
+    output: String,
+    result: Result<Value, (String, String)>,
+}
+
+fn execute(code: &str) -> Executed {
+    let fail = |name: &str, text: String| Executed { output: String::new(), result: Err((name.to_string(), text)) };
+    let (mut memory, mut input, mut trace) = (vec![], vec![], false);
+    // magic lines become empty lines, which keeps the line numbers of errors
+    let mut source = String::new();
+    for line in code.lines() {
+        let magic = line.trim();
+        let parsed = if let Some(cells) = magic.strip_prefix("%memory") {
+            read_input(cells.to_string()).map(|cells| memory = cells)
+        } else if let Some(cells) = magic.strip_prefix("%input") {
+            read_input(cells.to_string()).map(|cells| input = cells)
+        } else if magic == "%trace" {
+            trace = true;
+            Ok(())
+        } else {
+            source += line;
+            Ok(())
+        };
+        if let Err(e) = parsed {
+            return fail("MagicError", format!("{}: {}", magic, e));
+        }
+        source.push('\n');
+    }
+    let program = match parse_program_errors(source.clone(), &ParseOptions::default()) {
+        Ok(program) => program,
+        Err(errors) => return fail("ParseError", Error::Parse { errors, source }.to_string()),
+    };
+    let console = Shared::default();
+    console.0.lock().unwrap().input = input.into();
+    let mut state = GotoProgramState::new(&program, program.initial_memory(&memory));
+    state.console = Box::new(console.clone());
+    let limits = Limits { max_time: Duration::from_secs(10), max_cells: 1 << 20, ..Limits::default() };
+    let mut rows = vec![];
+    let outcome = if trace {
+        let mut steps = 0;
+        let mut events = state.events();
+        loop {
+            if steps == MAX_TRACE_STEPS {
+                break Outcome::Exceeded(sandbox::Limit::Steps(MAX_TRACE_STEPS));
+            }
+            steps += 1;
+            match events.next() {
+                Some(StepEvent::Executed { instruction, effect }) => {
+                    let write = effect.write.map(|write| format!("cell {}: {} → {}", write.cell, write.old, write.new)).unwrap_or_default();
+                    rows.push(vec![steps.to_string(), effect.program_counter.to_string(), instruction.to_string(), write]);
+                }
+                Some(StepEvent::Stopped { effect }) => {
+                    rows.push(vec![steps.to_string(), effect.program_counter.to_string(), "STOP".to_string(), String::new()]);
+                    break Outcome::Stopped;
+                }
+                Some(StepEvent::Failed { error, .. }) => break Outcome::Failed(error.to_string()),
+                None => break Outcome::Stopped,
+            }
+        }
+    } else {
+        state.run_with_limits(&limits)
+    };
+    let output = console.0.lock().unwrap().output.clone();
+    let text = match outcome {
+        Outcome::Stopped => format!("stopped after {} steps", state.steps),
+        Outcome::Failed(e) => return Executed { output, result: Err(("RuntimeError".to_string(), format!("error at pc {} after {} steps: {}", state.program_counter, state.steps, e))) },
+        Outcome::Exceeded(limit) => return Executed { output, result: Err(("LimitError".to_string(), format!("exceeded {} after {} steps", limit, state.steps))) },
+    };
+    let cells: Vec<_> = state.memory.iter().enumerate().take(MAX_ROWS).map(|(cell, value)| vec![cell.to_string(), value.to_string()]).collect();
+    let mut html = format!("<p>{}</p>{}", text, table(&["cell", "value"], cells, state.memory.len()));
+    let plain = format!("{}\n{}", text, state.memory.iter().enumerate().map(|(cell, value)| format!("{}: {}", cell, value)).collect::<Vec<_>>().join("\n"));
+    if trace {
+        let total = rows.len();
+        rows.truncate(MAX_ROWS);
+        html += &format!("<details><summary>trace of {} steps</summary>{}</details>", total, table(&["step", "pc", "instruction", "write"], rows, total));
+    }
+    Executed { output, result: Ok(Value::object(vec![("text/plain", plain.into()), ("text/html", html.into())])) }
+}
+
+struct Kernel {
+    signer: Signer,
+    execution_count: u64,
+    iopub: Vec<TcpStream>,
+}
+
+/// A message to answer on the socket it came from, or to publish.
+enum Outgoing {
+    Reply(Vec<Vec<u8>>),
+    Publish(Vec<Vec<u8>>),
+}
+
+impl Kernel {
+    fn publish(&mut self, parent: &Message, msg_type: &str, content: Value, out: &mut Vec<Outgoing>) {
+        let frames = self.signer.frames(vec![msg_type.as_bytes().to_vec()], Some(parent), msg_type, content);
+        out.push(Outgoing::Publish(frames));
+    }
+
+    fn reply(&mut self, parent: &Message, msg_type: &str, content: Value, out: &mut Vec<Outgoing>) {
+        let frames = self.signer.frames(parent.identities.clone(), Some(parent), msg_type, content);
+        out.push(Outgoing::Reply(frames));
+    }
+
+    /// The messages for a request, and whether the kernel shuts down.
+    fn handle(&mut self, request: &Message) -> (Vec<Outgoing>, bool) {
+        let mut out = vec![];
+        let status = |state: &str| Value::object(vec![("execution_state", state.into())]);
+        self.publish(request, "status", status("busy"), &mut out);
+        let mut shutdown = false;
+        match request.msg_type() {
+            "kernel_info_request" => {
+                let content = Value::object(vec![
+                    ("status", "ok".into()),
+                    ("protocol_version", PROTOCOL_VERSION.into()),
+                    ("implementation", "goto".into()),
+                    ("implementation_version", env!("CARGO_PKG_VERSION").into()),
+                    ("language_info", Value::object(vec![
+                        ("name", "goto".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                        ("mimetype", "text/x-goto".into()),
+                        ("file_extension", ".goto".into()),
+                    ])),
+                    ("banner", "GOTO, every cell is a program, see %memory, %input and %trace".into()),
+                ]);
+                self.reply(request, "kernel_info_reply", content, &mut out);
+            }
+            "execute_request" => {
+                let code = request.content.get("code").and_then(Value::as_str).unwrap_or_default().to_string();
+                let silent = request.content.get("silent").and_then(Value::as_bool).unwrap_or(false);
+                if !silent {
+                    self.execution_count += 1;
+                }
+                let count: Value = self.execution_count.into();
+                self.publish(request, "execute_input", Value::object(vec![("code", code.as_str().into()), ("execution_count", count.clone())]), &mut out);
+                let executed = execute(&code);
+                if !executed.output.is_empty() {
+                    self.publish(request, "stream", Value::object(vec![("name", "stdout".into()), ("text", executed.output.into())]), &mut out);
+                }
+                let reply = match executed.result {
+                    Ok(data) => {
+                        let result = vec![("execution_count", count.clone()), ("data", data), ("metadata", Value::object(vec![]))];
+                        self.publish(request, "execute_result", Value::object(result), &mut out);
+                        vec![("status", "ok".into()), ("execution_count", count), ("user_expressions", Value::object(vec![])), ("payload", Value::Array(vec![]))]
+                    }
+                    Err((name, text)) => {
+                        let error = vec![("ename", name.into()), ("evalue", text.as_str().into()), ("traceback", vec![text].into())];
+                        self.publish(request, "error", Value::object(error.clone()), &mut out);
+                        let mut reply = vec![("status", "error".into()), ("execution_count", count)];
+                        reply.extend(error);
+                        reply
+                    }
+                };
+                self.reply(request, "execute_reply", Value::object(reply), &mut out);
+            }
+            "is_complete_request" => self.reply(request, "is_complete_reply", Value::object(vec![("status", "complete".into())]), &mut out),
+            "comm_info_request" => self.reply(request, "comm_info_reply", Value::object(vec![("status", "ok".into()), ("comms", Value::object(vec![]))]), &mut out),
+            "shutdown_request" => {
+                let restart = request.content.get("restart").cloned().unwrap_or(Value::Bool(false));
+                self.reply(request, "shutdown_reply", Value::object(vec![("status", "ok".into()), ("restart", restart)]), &mut out);
+                shutdown = true;
+            }
+            "interrupt_request" => self.reply(request, "interrupt_reply", Value::object(vec![("status", "ok".into())]), &mut out),
+            _ => {}
+        }
+        self.publish(request, "status", status("idle"), &mut out);
+        (out, shutdown)
+    }
+}
+
+fn listen(ip: &str, port: u64, socket_type: &'static str, serve: impl Fn(TcpStream) -> io::Result<()> + Send + Sync + 'static) -> io::Result<()> {
+    let listener = TcpListener::bind(format!("{}:{}", ip, port))?;
+    let serve = Arc::new(serve);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let serve = serve.clone();
+            thread::spawn(move || {
+                let mut stream = stream;
+                if let Err(e) = handshake(&mut stream, socket_type).and_then(|()| serve(stream)) {
+                    if e.kind() != io::ErrorKind::UnexpectedEof {
+                        eprintln!("{} connection: {}", socket_type, e);
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn requests(kernel: Arc<Mutex<Kernel>>) -> impl Fn(TcpStream) -> io::Result<()> {
+    move |mut stream| loop {
+        let frames = read_message(&mut stream)?;
+        let mut kernel = kernel.lock().unwrap();
+        let request = match kernel.signer.parse(frames) {
+            Ok(request) => request,
+            Err(e) => {
+                eprintln!("ignored {}", e);
+                continue;
+            }
+        };
+        let (messages, shutdown) = kernel.handle(&request);
+        for message in messages {
+            match message {
+                Outgoing::Reply(frames) => write_message(&mut stream, &frames)?,
+                Outgoing::Publish(frames) => kernel.iopub.retain_mut(|subscriber| write_message(subscriber, &frames).is_ok()),
+            }
+        }
+        if shutdown {
+            std::process::exit(0);
+        }
+    }
+}
+
+/// Serves the sockets of the connection file until a shutdown request.
+pub fn run(connection_file: &str) -> Result<(), String> {
+    let connection = Value::parse(&read_to_string(connection_file).map_err(|e| format!("{}: {}", connection_file, e))?)?;
+    let text = |key: &str| connection.get(key).and_then(Value::as_str).map(str::to_string).ok_or(format!("the connection file has no {}", key));
+    let port = |key: &str| connection.get(key).and_then(Value::as_u64).ok_or(format!("the connection file has no {}", key));
+    if text("transport")? != "tcp" || connection.get("signature_scheme").and_then(Value::as_str).is_some_and(|scheme| scheme != "hmac-sha256") {
+        return Err("only the tcp transport and hmac-sha256 signatures are supported".to_string());
+    }
+    let ip = text("ip")?;
+    let seed = format!("{}{:?}", std::process::id(), SystemTime::now());
+    let signer = Signer { key: text("key")?.into_bytes(), session: hex(&sha256(seed.as_bytes())[..16]), sent: 0 };
+    let kernel = Arc::new(Mutex::new(Kernel { signer, execution_count: 0, iopub: vec![] }));
+    let bind = |e: io::Error| e.to_string();
+    let subscribers = kernel.clone();
+    listen(&ip, port("iopub_port")?, "PUB", move |stream| {
+        subscribers.lock().unwrap().iopub.push(stream);
+        Ok(())
+    }).map_err(bind)?;
+    listen(&ip, port("hb_port")?, "REP", |mut stream| loop {
+        let frames = read_message(&mut stream)?;
+        write_message(&mut stream, &frames)?;
+    }).map_err(bind)?;
+    listen(&ip, port("stdin_port")?, "ROUTER", |mut stream| loop {
+        read_message(&mut stream)?;
+    }).map_err(bind)?;
+    listen(&ip, port("control_port")?, "ROUTER", requests(kernel.clone())).map_err(bind)?;
+    let shell = TcpListener::bind(format!("{}:{}", ip, port("shell_port")?)).map_err(bind)?;
+    for stream in shell.incoming().flatten() {
+        let serve = requests(kernel.clone());
+        thread::spawn(move || {
+            let mut stream = stream;
+            if let Err(e) = handshake(&mut stream, "ROUTER").and_then(|()| serve(stream)) {
+                if e.kind() != io::ErrorKind::UnexpectedEof {
+                    eprintln!("ROUTER connection: {}", e);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Writes the kernel spec where Jupyter looks for the kernels of the user.
+pub fn install() -> Result<PathBuf, String> {
+    let data = std::env::var_os("JUPYTER_DATA_DIR").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share/jupyter")))
+        .ok_or("neither JUPYTER_DATA_DIR nor HOME is set")?;
+    let directory = data.join("kernels").join("goto");
+    create_dir_all(&directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    let executable = std::env::current_exe().map_err(|e| e.to_string())?;
+    let spec = Value::object(vec![
+        ("argv", vec![executable.to_string_lossy().to_string(), "jupyter-kernel".to_string(), "{connection_file}".to_string()].into()),
+        ("display_name", "GOTO".into()),
+        ("language", "goto".into()),
+    ]);
+    let path = directory.join("kernel.json");
+    write(&path, spec.to_string() + "\n").map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+#[test]
+fn test_kernel() {
+    let mut wire = vec![];
+    write_message(&mut wire, &[b"a".to_vec(), vec![7; 300]]).unwrap();
+    assert_eq!((MORE, vec![b'a']), read_frame(&mut &wire[..3]).unwrap());
+    assert_eq!(vec![b"a".to_vec(), vec![7; 300]], read_message(&mut &wire[..]).unwrap());
+
+    let mut kernel = Kernel { signer: Signer { key: b"secret".to_vec(), session: "s".to_string(), sent: 0 }, execution_count: 0, iopub: vec![] };
+    let mut client = Signer { key: b"secret".to_vec(), session: "c".to_string(), sent: 0 };
+    let mut request = |code: &str| {
+        let frames = client.frames(vec![b"id".to_vec()], None, "execute_request", Value::object(vec![("code", code.into())]));
+        let request = kernel.signer.parse(frames).unwrap();
+        let (out, _) = kernel.handle(&request);
+        out.into_iter().map(|message| {
+            let (reply, frames) = match message {
+                Outgoing::Reply(frames) => (true, frames),
+                Outgoing::Publish(frames) => (false, frames),
+            };
+            let message = kernel.signer.parse(frames).unwrap();
+            (reply, message.msg_type().to_string(), message.content)
+        }).collect::<Vec<_>>()
+    };
+
+    let messages = request("%memory 2 5\n%trace\nloop: GOTOZ 0 end\nDEC 0\nINC 1\nPRINT 1\nGOTO loop\nend: STOP");
+    let types: Vec<_> = messages.iter().map(|(reply, msg_type, _)| (*reply, msg_type.as_str())).collect();
+    assert_eq!(vec![(false, "status"), (false, "execute_input"), (false, "stream"), (false, "execute_result"), (true, "execute_reply"), (false, "status")], types);
+    assert_eq!(Some("6\n7\n"), messages[2].2.get("text").and_then(Value::as_str));
+    let data = messages[3].2.get("data").unwrap();
+    assert_eq!(Some("stopped after 12 steps\n0: 0\n1: 7"), data.get("text/plain").and_then(Value::as_str));
+    assert!(data.get("text/html").and_then(Value::as_str).unwrap().contains("<summary>trace of 12 steps</summary>"));
+    assert_eq!(Some(1), messages[4].2.get("execution_count").and_then(Value::as_u64));
+
+    let messages = request("INC 0\nGOTO nowhere");
+    assert_eq!(Some("ParseError"), messages[2].2.get("ename").and_then(Value::as_str));
+    assert_eq!(Some("error"), messages[3].2.get("status").and_then(Value::as_str));
+    assert!(messages[3].2.get("evalue").and_then(Value::as_str).unwrap().starts_with("error in line 2: Unknown label: nowhere"));
+    let messages = request("%memory 0\nDEC 0");
+    assert_eq!(Some("error at pc 0 after 0 steps: DEC 0 on a cell that is 0"), messages[2].2.get("evalue").and_then(Value::as_str));
+
+    let mut forged = client.frames(vec![], None, "execute_request", Value::object(vec![("code", "STOP".into())]));
+    forged[1] = b"0000".to_vec();
+    assert!(kernel.signer.parse(forged).is_err());
+    assert_eq!(27, timestamp().len());
+}
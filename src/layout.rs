@@ -0,0 +1,109 @@
+//! Dense memory layouts. The cells a program uses, including the ones it
+//! declares with `.data` and `.init`, are renumbered to `0..n` in their
+//! order and the others dropped, so a translation allocates only the `n`
+//! cells the program needs. The input and the final memory of the dense
+//! program are the used cells of the original ones, see `pack` and `unpack`.
+
+use std::fmt::{self, Write};
+
+use crate::{ranges, GotoProgram, RegisterIndex};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Layout {
+    /// the original cell of every cell of the dense memory
+    pub cells: Vec<RegisterIndex>,
+}
+
+impl Layout {
+    pub fn of(program: &GotoProgram) -> Layout {
+        let mut cells = ranges::used_cells(program);
+        cells.extend(program.data.iter().map(|&(cell, _)| cell));
+        cells.sort_unstable();
+        cells.dedup();
+        Layout { cells }
+    }
+
+    /// The number of cells of the dense memory.
+    pub fn size(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// How long the memory of the original program has to be so that every
+    /// cell it uses exists.
+    pub fn required_cells(&self) -> usize {
+        self.cells.last().map_or(0, |cell| cell + 1)
+    }
+
+    /// The cell of the dense memory in place of the original cell.
+    pub fn cell(&self, original: RegisterIndex) -> Option<RegisterIndex> {
+        self.cells.binary_search(&original).ok()
+    }
+
+    /// The program on the dense memory. Aliases of cells it does not use
+    /// are dropped, with several banks all of them as the cells are no
+    /// longer in banks.
+    pub fn apply(&self, program: &GotoProgram) -> GotoProgram {
+        let dense = |cell| self.cell(cell).ok_or_else(|| format!("cell {} is not in the layout", cell));
+        let instructions = program.instructions.iter()
+            .map(|instruction| instruction.map_cells(dense).expect("the layout has every used cell"))
+            .collect();
+        let aliases = if program.banks == 1 {
+            program.aliases.iter().filter_map(|(name, cell)| self.cell(*cell).map(|cell| (name.clone(), cell))).collect()
+        } else {
+            vec![]
+        };
+        let data = program.data.iter().map(|&(cell, value)| (self.cell(cell).unwrap(), value)).collect();
+        let mut header = program.header;
+        header.memory = header.memory.map(|_| self.size());
+        GotoProgram { instructions, source_lines: program.source_lines.clone(), banks: 1, aliases, data, header }
+    }
+
+    /// The dense memory with the used cells of `memory`, 0 where it is too short.
+    pub fn pack(&self, memory: &[u64]) -> Vec<u64> {
+        self.cells.iter().map(|&cell| memory.get(cell).cloned().unwrap_or(0)).collect()
+    }
+
+    /// `memory` with the cells of the dense memory put back in their places.
+    pub fn unpack(&self, dense: &[u64], memory: &[u64]) -> Vec<u64> {
+        let mut memory = memory.to_vec();
+        memory.resize(memory.len().max(self.required_cells()), 0);
+        for (&cell, &value) in self.cells.iter().zip(dense) {
+            memory[cell] = value;
+        }
+        memory
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} of {} cells", self.size(), self.required_cells())?;
+        let mut moved = String::new();
+        for (dense, &cell) in self.cells.iter().enumerate().filter(|(dense, cell)| *dense != **cell) {
+            write!(moved, "{}{} -> {}", if moved.is_empty() { "" } else { ", " }, cell, dense)?;
+        }
+        if !moved.is_empty() {
+            write!(f, ", moved {}", moved)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_layout() {
+    let program = crate::parse_program(".alias sum 7\n.alias unused 3\n.init 5 = 9\nloop: GOTOZ 2 end\nDEC 2\nINC sum\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let layout = Layout::of(&program);
+    assert_eq!(vec![2, 5, 7], layout.cells);
+    assert_eq!(8, layout.required_cells());
+    assert_eq!("3 of 8 cells, moved 2 -> 0, 5 -> 1, 7 -> 2", layout.to_string());
+
+    let dense = layout.apply(&program);
+    assert_eq!("GOTOZ 0 4, DEC 0, INC 2, GOTO 0, STOP",
+               dense.instructions.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "));
+    assert_eq!(vec![("sum".to_string(), 2)], dense.aliases);
+    assert_eq!(vec![(1, 9)], dense.data);
+
+    let input = program.initial_memory(&[1, 1, 3]);
+    let mut state = crate::GotoProgramState::new(&dense, dense.initial_memory(&layout.pack(&input)));
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    assert_eq!(vec![1, 1, 0, 0, 0, 9, 0, 3], layout.unpack(&state.memory, &input));
+}
@@ -0,0 +1,1263 @@
+pub mod banks;
+pub mod batch;
+pub mod brainfuck;
+pub mod bytecode;
+pub mod checkpoint;
+pub mod complexity;
+pub mod condition;
+pub mod console;
+pub mod contract;
+pub mod cycle;
+pub mod coverage;
+pub mod difftest;
+pub mod disasm;
+pub mod doc;
+pub mod equiv;
+pub mod error;
+pub mod events;
+pub mod expr;
+pub mod footprint;
+pub mod fingerprint;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod formatter;
+pub mod fuzz;
+pub mod gbc;
+pub mod header;
+pub mod godel;
+pub mod golden;
+pub mod grading;
+pub mod grammar;
+pub mod history;
+pub mod inputs;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod json;
+pub mod layout;
+pub mod link;
+pub mod lint;
+pub mod lower;
+pub mod macros;
+pub mod loop_lang;
+pub mod minimize;
+pub mod mutate;
+pub mod neumann;
+pub mod observer;
+pub mod optimize;
+pub mod pipeline;
+pub mod preprocess;
+pub mod profile;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod random;
+pub mod ranges;
+pub mod recursive;
+pub mod report;
+pub mod sandbox;
+pub mod sha256;
+pub mod snapshot;
+pub mod sourcemap;
+pub mod specialize;
+pub mod stall;
+pub mod stats;
+pub mod suite;
+pub mod superopt;
+pub mod termination;
+pub mod trace_diff;
+pub mod transpile;
+pub mod turing;
+pub mod urm;
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod while_lang;
+pub mod source;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+
+use goto_core::{Fault, Op};
+
+pub use error::{Error, RuntimeError};
+
+pub type RegisterIndex = usize;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Instruction {
+    Stop,
+    Inc { cell: RegisterIndex },
+    Dec { cell: RegisterIndex },
+    Goto { cell: RegisterIndex },
+    GotoZ { condition_cell: RegisterIndex, goto_cell: RegisterIndex },
+    /// jumps unless the cell is 0
+    GotoNz { condition_cell: RegisterIndex, goto_cell: RegisterIndex },
+    /// jumps if the two cells hold the same value
+    GotoEq { left: RegisterIndex, right: RegisterIndex, goto_cell: RegisterIndex },
+    /// jumps if the left cell holds a larger value than the right one
+    GotoGt { left: RegisterIndex, right: RegisterIndex, goto_cell: RegisterIndex },
+    /// fails the run unless the cell holds the value, `ASSERTZ cell` checks for 0
+    Assert { cell: RegisterIndex, value: u64 },
+    /// writes the value of the cell to the console of the run
+    Print { cell: RegisterIndex },
+    /// stores a number from the console of the run in the cell
+    Read { cell: RegisterIndex },
+    /// writes the value of the cell as a character, see `console::Encoding`
+    OutC { cell: RegisterIndex },
+    /// stores a pseudo-random number below `max` from the generator of the run
+    Rand { cell: RegisterIndex, max: u64 },
+    /// puts the value of the cell on the stack of the run
+    Push { cell: RegisterIndex },
+    /// moves the top of the stack into the cell, fails on an empty stack
+    Pop { cell: RegisterIndex },
+    /// sets the cell to the value, in von Neumann mode also cells of the program
+    StoreI { cell: RegisterIndex, value: u64 },
+    /// only moves on to the next instruction
+    Nop,
+}
+
+impl Instruction {
+    /// Where GOTO and the conditional jumps go to.
+    pub fn jump_target(&self) -> Option<RegisterIndex> {
+        match *self {
+            Instruction::Goto { cell } => Some(cell),
+            Instruction::GotoZ { goto_cell, .. } | Instruction::GotoNz { goto_cell, .. }
+            | Instruction::GotoEq { goto_cell, .. } | Instruction::GotoGt { goto_cell, .. } => Some(goto_cell),
+            _ => None,
+        }
+    }
+
+    /// The instruction with another jump target, other instructions stay.
+    pub fn with_jump_target(self, target: RegisterIndex) -> Instruction {
+        match self {
+            Instruction::Goto { .. } => Instruction::Goto { cell: target },
+            Instruction::GotoZ { condition_cell, .. } => Instruction::GotoZ { condition_cell, goto_cell: target },
+            Instruction::GotoNz { condition_cell, .. } => Instruction::GotoNz { condition_cell, goto_cell: target },
+            Instruction::GotoEq { left, right, .. } => Instruction::GotoEq { left, right, goto_cell: target },
+            Instruction::GotoGt { left, right, .. } => Instruction::GotoGt { left, right, goto_cell: target },
+            _ => self,
+        }
+    }
+
+    /// The instruction with every memory cell it uses passed through `map`,
+    /// jump targets stay.
+    pub fn map_cells(self, mut map: impl FnMut(RegisterIndex) -> Result<RegisterIndex, String>) -> Result<Instruction, String> {
+        Ok(match self {
+            Instruction::Inc { cell } => Instruction::Inc { cell: map(cell)? },
+            Instruction::Dec { cell } => Instruction::Dec { cell: map(cell)? },
+            Instruction::GotoZ { condition_cell, goto_cell } => Instruction::GotoZ { condition_cell: map(condition_cell)?, goto_cell },
+            Instruction::GotoNz { condition_cell, goto_cell } => Instruction::GotoNz { condition_cell: map(condition_cell)?, goto_cell },
+            Instruction::GotoEq { left, right, goto_cell } => Instruction::GotoEq { left: map(left)?, right: map(right)?, goto_cell },
+            Instruction::GotoGt { left, right, goto_cell } => Instruction::GotoGt { left: map(left)?, right: map(right)?, goto_cell },
+            Instruction::Assert { cell, value } => Instruction::Assert { cell: map(cell)?, value },
+            Instruction::Print { cell } => Instruction::Print { cell: map(cell)? },
+            Instruction::Read { cell } => Instruction::Read { cell: map(cell)? },
+            Instruction::OutC { cell } => Instruction::OutC { cell: map(cell)? },
+            Instruction::Rand { cell, max } => Instruction::Rand { cell: map(cell)?, max },
+            Instruction::Push { cell } => Instruction::Push { cell: map(cell)? },
+            Instruction::Pop { cell } => Instruction::Pop { cell: map(cell)? },
+            Instruction::StoreI { cell, value } => Instruction::StoreI { cell: map(cell)?, value },
+            Instruction::Stop | Instruction::Goto { .. } | Instruction::Nop => self,
+        })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Stop => write!(f, "STOP"),
+            Instruction::Inc { cell } => write!(f, "INC {}", cell),
+            Instruction::Dec { cell } => write!(f, "DEC {}", cell),
+            Instruction::Goto { cell } => write!(f, "GOTO {}", cell),
+            Instruction::GotoZ { condition_cell, goto_cell } => write!(f, "GOTOZ {} {}", condition_cell, goto_cell),
+            Instruction::GotoNz { condition_cell, goto_cell } => write!(f, "GOTONZ {} {}", condition_cell, goto_cell),
+            Instruction::GotoEq { left, right, goto_cell } => write!(f, "GOTOEQ {} {} {}", left, right, goto_cell),
+            Instruction::GotoGt { left, right, goto_cell } => write!(f, "GOTOGT {} {} {}", left, right, goto_cell),
+            Instruction::Assert { cell, value: 0 } => write!(f, "ASSERTZ {}", cell),
+            Instruction::Assert { cell, value } => write!(f, "ASSERT {} {}", cell, value),
+            Instruction::Print { cell } => write!(f, "PRINT {}", cell),
+            Instruction::Read { cell } => write!(f, "READ {}", cell),
+            Instruction::OutC { cell } => write!(f, "OUTC {}", cell),
+            Instruction::Rand { cell, max } => write!(f, "RAND {} {}", cell, max),
+            Instruction::Push { cell } => write!(f, "PUSH {}", cell),
+            Instruction::Pop { cell } => write!(f, "POP {}", cell),
+            Instruction::StoreI { cell, value } => write!(f, "STOREI {} {}", cell, value),
+            Instruction::Nop => write!(f, "NOP"),
+        }
+    }
+}
+
+fn parse_nr(text: &str) -> Result<RegisterIndex, String> {
+    text.parse::<RegisterIndex>()
+        .map_err(|e| format!("{} is not a number (reason: {:?})", text, e))
+}
+
+/// The names operands can use, see `expr`: labels in jump targets, aliases
+/// in cells and constants from `.const` in every operand.
+#[derive(Debug, Default)]
+struct Names {
+    labels: HashMap<String, RegisterIndex>,
+    aliases: HashMap<String, RegisterIndex>,
+    constants: HashMap<String, u64>,
+}
+
+impl Names {
+    fn eval(&self, text: &str, names: Option<&HashMap<String, RegisterIndex>>, kind: &str) -> Result<u64, String> {
+        expr::eval(text, |name| names.and_then(|names| names.get(name)).map(|&index| index as u64)
+            .or_else(|| self.constants.get(name).cloned())
+            .ok_or_else(|| format!("Unknown {}: {}", kind, name)))
+    }
+
+    fn index(&self, text: &str, names: Option<&HashMap<String, RegisterIndex>>, kind: &str) -> Result<RegisterIndex, String> {
+        let value = self.eval(text, names, kind)?;
+        RegisterIndex::try_from(value).map_err(|_| format!("{} is too large", value))
+    }
+
+    fn target(&self, text: &str) -> Result<RegisterIndex, String> {
+        self.index(text, Some(&self.labels), "label")
+    }
+
+    fn cell(&self, text: &str) -> Result<RegisterIndex, String> {
+        self.index(text, Some(&self.aliases), "alias")
+    }
+
+    fn value(&self, text: &str) -> Result<u64, String> {
+        self.eval(text, None, "constant")
+    }
+}
+
+/// What is wrong with an instruction, and at which of its tokens.
+struct TokenError {
+    token: usize,
+    message: String,
+    hint: Option<String>,
+}
+
+/// The mnemonics of the instructions with the number of their operands.
+pub const MNEMONICS: [(&str, usize); 18] = [
+    ("STOP", 0), ("NOP", 0),
+    ("INC", 1), ("DEC", 1), ("GOTO", 1), ("ASSERTZ", 1), ("PRINT", 1), ("READ", 1), ("OUTC", 1), ("PUSH", 1), ("POP", 1),
+    ("GOTOZ", 2), ("GOTONZ", 2), ("ASSERT", 2), ("RAND", 2), ("STOREI", 2),
+    ("GOTOEQ", 3), ("GOTOGT", 3),
+];
+
+/// The directives of the program body, see `parse_directive`.
+pub const DIRECTIVES: [&str; 4] = [".const", ".alias", ".data", ".init"];
+
+/// The prefix of an instruction on another memory bank, see `bank_prefix`.
+pub const BANK: &str = "BANK";
+
+/// The operand at `token`, with its error pointing at it.
+fn operand<T>(token: usize, result: Result<T, String>) -> Result<T, TokenError> {
+    result.map_err(|message| TokenError { token, message, hint: None })
+}
+
+fn parse_instruction<T: AsRef<str>>(tokens: &[T], names: &Names) -> Result<Instruction, TokenError> {
+    let tokens: Vec<&str> = tokens.iter().map(|t| t.as_ref()).collect();
+    let value = tokens.join(" ");
+    if tokens.is_empty() {
+        return Result::Err(TokenError { token: 0, message: format!("No tokens in: {}", value), hint: None });
+    }
+    let instruction_token = tokens[0];
+    let operands = match MNEMONICS.iter().find(|(mnemonic, _)| *mnemonic == instruction_token) {
+        Some(&(_, operands)) => operands,
+        None => {
+            let upper = instruction_token.to_uppercase();
+            let hint = MNEMONICS.iter().find(|(mnemonic, _)| *mnemonic == upper).map(|(mnemonic, _)| format!("did you mean {}?", mnemonic));
+            return Result::Err(TokenError { token: 0, message: format!("Unknown token: {}", tokens[0]), hint });
+        }
+    };
+    // STOP and NOP take no operands and ignore further tokens
+    if operands > 0 && tokens.len() != operands + 1 {
+        return Result::Err(TokenError {
+            // the first extra token, or the instruction when operands are missing
+            token: if tokens.len() > operands + 1 { operands + 1 } else { 0 },
+            message: format!("Not {} tokens in: {}", operands + 1, value),
+            hint: Some(format!("{} takes {} operand{}, found {}", instruction_token, operands, if operands == 1 { "" } else { "s" }, tokens.len() - 1)),
+        });
+    }
+    let cell = |token: usize| operand(token, names.cell(tokens[token]));
+    let target = |token: usize| operand(token, names.target(tokens[token]));
+    Result::Ok(match instruction_token {
+        "STOP" => Instruction::Stop,
+        "NOP" => Instruction::Nop,
+        "INC" => Instruction::Inc { cell: cell(1)? },
+        "DEC" => Instruction::Dec { cell: cell(1)? },
+        "GOTO" => Instruction::Goto { cell: target(1)? },
+        "ASSERTZ" => Instruction::Assert { cell: cell(1)?, value: 0 },
+        "PRINT" => Instruction::Print { cell: cell(1)? },
+        "READ" => Instruction::Read { cell: cell(1)? },
+        "OUTC" => Instruction::OutC { cell: cell(1)? },
+        "PUSH" => Instruction::Push { cell: cell(1)? },
+        "POP" => Instruction::Pop { cell: cell(1)? },
+        "GOTOZ" => Instruction::GotoZ { condition_cell: cell(1)?, goto_cell: target(2)? },
+        "GOTONZ" => Instruction::GotoNz { condition_cell: cell(1)?, goto_cell: target(2)? },
+        "GOTOEQ" => Instruction::GotoEq { left: cell(1)?, right: cell(2)?, goto_cell: target(3)? },
+        "GOTOGT" => Instruction::GotoGt { left: cell(1)?, right: cell(2)?, goto_cell: target(3)? },
+        _ => {
+            let cell = cell(1)?;
+            let value = operand(2, names.value(tokens[2]))?;
+            match instruction_token {
+                "ASSERT" => Instruction::Assert { cell, value },
+                "RAND" => Instruction::Rand { cell, max: value },
+                _ => Instruction::StoreI { cell, value },
+            }
+        }
+    })
+}
+
+impl TryFrom<String> for Instruction {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Instruction, Self::Error> {
+        let tokens: Vec<_> = value.split_whitespace().collect();
+        parse_instruction(&tokens, &Names::default()).map_err(|e| e.message)
+    }
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(Result::Ok(Instruction::Stop), Instruction::try_from("STOP".to_string()));
+    assert_eq!(Result::Ok(Instruction::Inc { cell: 42 }), Instruction::try_from("INC 42".to_string()));
+    assert_eq!(Result::Ok(Instruction::Dec { cell: 13 }), Instruction::try_from(" DEC 13 ".to_string()));
+    assert_eq!(Result::Ok(Instruction::Goto { cell: 0 }), Instruction::try_from(" GOTO  0".to_string()));
+    assert_eq!(Result::Ok(Instruction::GotoZ { condition_cell: 42, goto_cell: 0 }), Instruction::try_from("GOTOZ 42 0".to_string()));
+    assert_eq!(Result::Ok(Instruction::GotoNz { condition_cell: 4, goto_cell: 1 }), Instruction::try_from("GOTONZ 4 1".to_string()));
+    assert_eq!(Result::Ok(Instruction::GotoEq { left: 1, right: 2, goto_cell: 3 }), Instruction::try_from("GOTOEQ 1 2 3".to_string()));
+    assert_eq!(Result::Ok(Instruction::GotoGt { left: 2, right: 1, goto_cell: 0 }), Instruction::try_from("GOTOGT 2 1 0".to_string()));
+    assert!(Instruction::try_from("GOTOEQ 1 2".to_string()).is_err());
+    assert_eq!(Result::Ok(Instruction::Assert { cell: 2, value: 7 }), Instruction::try_from("ASSERT 2 7".to_string()));
+    assert_eq!(Result::Ok(Instruction::Assert { cell: 2, value: 0 }), Instruction::try_from("ASSERTZ 2".to_string()));
+    assert_eq!(Result::Ok(Instruction::Print { cell: 3 }), Instruction::try_from("PRINT 3".to_string()));
+    assert_eq!(Result::Ok(Instruction::Read { cell: 1 }), Instruction::try_from("READ 1".to_string()));
+    assert_eq!(Result::Ok(Instruction::OutC { cell: 4 }), Instruction::try_from("OUTC 4".to_string()));
+    assert_eq!(Result::Ok(Instruction::Rand { cell: 0, max: 6 }), Instruction::try_from("RAND 0 6".to_string()));
+    assert_eq!(Result::Ok(Instruction::Push { cell: 2 }), Instruction::try_from("PUSH 2".to_string()));
+    assert_eq!(Result::Ok(Instruction::StoreI { cell: 2, value: 9 }), Instruction::try_from("STOREI 2 9".to_string()));
+    assert_eq!(Result::Ok(Instruction::Pop { cell: 5 }), Instruction::try_from("POP 5".to_string()));
+
+    assert!(Instruction::try_from("".to_string()).is_err());
+    assert!(Instruction::try_from("INC 1 2 3 ".to_string()).is_err());
+    assert!(Instruction::try_from("what is this even".to_string()).is_err());
+}
+
+pub fn parse_commands(text: String) -> Result<Vec<Instruction>, String> {
+    parse_program(text).map(|program| program.instructions)
+}
+
+/// Takes `BANK n` off the front of an instruction, without it the
+/// instruction works on bank 0.
+fn bank_prefix(mut tokens: Vec<String>) -> Result<(usize, Vec<String>), String> {
+    if tokens[0] != BANK {
+        return Ok((0, tokens));
+    }
+    if tokens.len() < 3 {
+        return Err(format!("BANK needs a number and an instruction in: {}", tokens.join(" ")));
+    }
+    let bank = parse_nr(&tokens[1])?;
+    // the cells of the bank start at cell `bank` of the memory
+    bank.checked_add(1).filter(|banks| *banks <= banks::MAX_CELLS)
+        .ok_or_else(|| format!("BANK {} is too large, no memory has cell {}", bank, bank))?;
+    Ok((bank, tokens.split_off(2)))
+}
+
+/// A cell `.data` or `.init` may set, a memory of `usize::MAX` cells has no
+/// cell `usize::MAX`.
+fn data_cell(cell: RegisterIndex) -> Result<RegisterIndex, String> {
+    if cell == usize::MAX {
+        return Err(format!("cell {} is too large for a memory", cell));
+    }
+    Ok(cell)
+}
+
+/// Handles a line like `.alias counter 0`, `.data 5 0 12`, `.init 3 = 7` or
+/// `.const BASE 10`. `.data` appends its values to the memory declared so far.
+fn parse_directive(tokens: &[String], program: &mut GotoProgram, names: &mut Names) -> Result<(), String> {
+    match tokens[0].as_str() {
+        ".const" => {
+            if tokens.len() != 3 || !source::is_label_name(&tokens[1]) {
+                return Err(format!("expected .const <name> <value> in: {}", tokens.join(" ")));
+            }
+            let value = names.value(&tokens[2])?;
+            if names.constants.insert(tokens[1].clone(), value).is_some() {
+                return Err(format!("Duplicate constant: {}", tokens[1]));
+            }
+        }
+        ".alias" => {
+            if tokens.len() != 3 || !source::is_label_name(&tokens[1]) {
+                return Err(format!("expected .alias <name> <cell> in: {}", tokens.join(" ")));
+            }
+            if program.aliases.iter().any(|(name, _)| *name == tokens[1]) {
+                return Err(format!("Duplicate alias: {}", tokens[1]));
+            }
+            let cell = names.index(&tokens[2], None, "constant")?;
+            names.aliases.insert(tokens[1].clone(), cell);
+            program.aliases.push((tokens[1].clone(), cell));
+        }
+        ".data" => {
+            if tokens.len() < 2 {
+                return Err("expected .data <value>...".to_string());
+            }
+            let start = program.data.iter().map(|(cell, _)| cell + 1).max().unwrap_or(0);
+            for (offset, token) in tokens[1..].iter().enumerate() {
+                let cell = start.checked_add(offset).ok_or("the .data values run past the largest cell")?;
+                program.data.push((data_cell(cell)?, names.value(token)?));
+            }
+        }
+        ".init" => {
+            if tokens.len() != 4 || tokens[2] != "=" {
+                return Err(format!("expected .init <cell> = <value> in: {}", tokens.join(" ")));
+            }
+            let cell = data_cell(names.cell(&tokens[1])?)?;
+            program.data.push((cell, names.value(&tokens[3])?));
+        }
+        // a module that exports labels runs on its own, one that imports them only linked
+        ".export" => {}
+        ".import" => return Err(format!("{} is imported from another module, link the modules with goto link", tokens[1..].join(", "))),
+        _ => return Err(format!("Unknown directive: {}", tokens[0])),
+    }
+    Ok(())
+}
+
+/// What parsing a program depends on besides its text.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ParseOptions {
+    /// the names `.ifdef` looks for, see `preprocess`
+    pub defines: Vec<String>,
+    /// the dialect to check the program against, unless it declares one,
+    /// without either every instruction is allowed
+    pub dialect: Option<header::Dialect>,
+    /// every line without an instruction becomes a NOP, so instruction `n`
+    /// is on line `n + 1` and blank lines do not move absolute jump targets
+    pub nop_lines: bool,
+}
+
+pub fn parse_program(text: String) -> Result<GotoProgram, String> {
+    parse_program_with(text, &ParseOptions::default())
+}
+
+/// What is wrong with a line of a program, or with the whole program when
+/// it has no line. Segments are only named on lines with several of them.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseError {
+    pub line: Option<usize>,
+    pub segment: Option<usize>,
+    /// the bytes of the line with the offending token
+    pub span: Option<Range<usize>>,
+    pub message: String,
+    /// how to fix it, like `GOTOZ takes 2 operands, found 1`
+    pub hint: Option<String>,
+}
+
+impl ParseError {
+    fn program(message: String) -> ParseError {
+        ParseError { line: None, segment: None, span: None, message, hint: None }
+    }
+
+    /// The error with the line of `text` it is about, the offending token
+    /// underlined, and the hint.
+    pub fn render(&self, text: &str) -> String {
+        let mut shown = self.to_string();
+        if let Some(line) = self.line.and_then(|line| text.lines().nth(line - 1)) {
+            let number = self.line.unwrap().to_string();
+            let gutter = " ".repeat(number.len());
+            shown += &format!("\n{} |\n{} | {}", gutter, number, line);
+            if let Some(span) = self.span.clone().filter(|span| span.end <= line.len()) {
+                // tabs stay tabs so the carets line up with the token
+                let indent: String = line[..span.start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+                shown += &format!("\n{} | {}{}", gutter, indent, "^".repeat(line[span].chars().count()));
+            }
+            if let Some(hint) = &self.hint {
+                shown += &format!("\n{} = hint: {}", gutter, hint);
+            }
+        } else if let Some(hint) = &self.hint {
+            shown += &format!("\nhint: {}", hint);
+        }
+        shown
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line, self.segment) {
+            (Some(line), Some(segment)) => write!(f, "error in line {}, segment {}: {}", line, segment, self.message),
+            (Some(line), None) => write!(f, "error in line {}: {}", line, self.message),
+            (None, _) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Like `parse_program_errors`, with only the first error.
+pub fn parse_program_with(text: String, options: &ParseOptions) -> Result<GotoProgram, String> {
+    parse_program_errors(text, options).map_err(|errors| errors[0].to_string())
+}
+
+/// Parses the program and collects the errors of every line instead of
+/// stopping at the first one.
+pub fn parse_program_errors(text: String, options: &ParseOptions) -> Result<GotoProgram, Vec<ParseError>> {
+    let text = preprocess::preprocess(&text, &options.defines).map_err(|e| vec![ParseError::program(e)])?;
+    let mut program = GotoProgram { instructions: vec![], source_lines: vec![], banks: 1, aliases: vec![], data: vec![], header: Default::default() };
+    let mut errors = vec![];
+    let mut lines = vec![];
+    let mut names = Names::default();
+    let mut instruction_count = 0;
+    let mut has_instructions = false;
+    for (line_nr, line) in text.lines().enumerate() {
+        let segments = match source::parse_segments(line) {
+            Ok(segments) => segments,
+            Err(message) => {
+                errors.push(ParseError { line: Some(line_nr + 1), segment: None, span: None, message, hint: None });
+                continue;
+            }
+        };
+        let spans = source::token_spans(line);
+        let segment_count = segments.len();
+        let mut line_has_instructions = false;
+        for (segment_nr, line) in segments.into_iter().enumerate() {
+            let segment = Some(segment_nr + 1).filter(|_| segment_count > 1);
+            let mut spans = spans[segment_nr].clone();
+            let first = spans.first().cloned();
+            let error = |message| ParseError { line: Some(line_nr + 1), segment, span: first.clone(), message, hint: None };
+            if let Some(label) = &line.label {
+                if names.labels.insert(label.clone(), instruction_count).is_some() {
+                    errors.push(ParseError { span: None, ..error(format!("Duplicate label: {}", label)) });
+                }
+            }
+            if line.is_directive() && header::is_header_directive(&line.tokens[0]) {
+                let result = if has_instructions {
+                    Err(format!("{} has to come before the first instruction", line.tokens[0]))
+                } else {
+                    program.header.parse_directive(&line.tokens)
+                };
+                errors.extend(result.err().map(error));
+            } else if line.is_directive() {
+                errors.extend(parse_directive(&line.tokens, &mut program, &mut names).err().map(error));
+            } else if !line.tokens.is_empty() {
+                instruction_count += 1;
+                has_instructions = true;
+                line_has_instructions = true;
+                let token_count = line.tokens.len();
+                match bank_prefix(line.tokens) {
+                    Ok((bank, tokens)) => {
+                        let spans = spans.split_off(token_count - tokens.len());
+                        lines.push((line_nr, segment, bank, tokens, spans));
+                    }
+                    Err(message) => errors.push(error(message)),
+                }
+            }
+        }
+        if options.nop_lines && !line_has_instructions {
+            instruction_count += 1;
+            lines.push((line_nr, None, 0, vec![], vec![]));
+        }
+    }
+    program.banks = lines.iter().map(|line| line.2 + 1).max().unwrap_or(1);
+    for (cell, _) in &mut program.data {
+        match banks::flat_cell(0, *cell, program.banks) {
+            Ok(flat) => *cell = flat,
+            Err(message) => errors.push(ParseError::program(message)),
+        }
+    }
+    if let Some(memory) = program.header.memory {
+        if let Some((cell, _)) = program.data.iter().find(|(cell, _)| *cell >= memory) {
+            errors.push(ParseError::program(format!("cell {} is outside of the declared memory of {} cells", cell, memory)));
+        }
+    }
+    let dialect = program.header.dialect(options.dialect).unwrap_or_else(|message| {
+        errors.push(ParseError::program(message));
+        header::Dialect::Extended
+    });
+    for (line_nr, segment, bank, tokens, spans) in lines {
+        if tokens.is_empty() {
+            // a line without an instruction under `nop_lines`, allowed in every dialect
+            program.instructions.push(Instruction::Nop);
+            program.source_lines.push(line_nr + 1);
+            continue;
+        }
+        let error = |token: usize, message, hint| ParseError { line: Some(line_nr + 1), segment, span: spans.get(token).cloned(), message, hint };
+        let instruction = parse_instruction(&tokens, &names)
+            .and_then(|instruction| banks::in_bank(instruction, bank, program.banks).map_err(|message| TokenError { token: 0, message, hint: None }));
+        match instruction {
+            Ok(instruction) if !dialect.allows(&instruction) => {
+                errors.push(error(0, format!("{} is not in the {} dialect", instruction, header::Dialect::NAMES[dialect as usize]),
+                                  Some("the classic dialect has STOP, INC, DEC, GOTO and GOTOZ".to_string())));
+            }
+            Ok(instruction) => {
+                program.instructions.push(instruction);
+                program.source_lines.push(line_nr + 1);
+            }
+            Err(e) => errors.push(error(e.token, e.message, e.hint)),
+        }
+    }
+    if errors.is_empty() {
+        return Ok(program);
+    }
+    errors.sort_by_key(|error| (error.line.is_none(), error.line, error.segment));
+    Err(errors)
+}
+
+#[test]
+fn test_parse_commands() {
+    let input = "INC 1
+    DEC 2
+    GOTO 3
+    STOP";
+    let expected = vec![
+        Instruction::Inc { cell: 1 },
+        Instruction::Dec { cell: 2 },
+        Instruction::Goto { cell: 3 },
+        Instruction::Stop
+    ];
+    assert_eq!(Result::Ok(expected), parse_commands(input.to_string()));
+}
+
+#[test]
+fn test_parse_commands_with_labels_and_comments() {
+    let input = "; add cell 0 to cell 1
+    loop: GOTOZ 0 end ; finished?
+    DEC 0
+
+    INC 1
+    GOTO loop
+    end:
+    STOP";
+    let expected = vec![
+        Instruction::GotoZ { condition_cell: 0, goto_cell: 4 },
+        Instruction::Dec { cell: 0 },
+        Instruction::Inc { cell: 1 },
+        Instruction::Goto { cell: 0 },
+        Instruction::Stop
+    ];
+    assert_eq!(Result::Ok(expected), parse_commands(input.to_string()));
+
+    assert!(parse_commands("GOTO nowhere".to_string()).is_err());
+    assert!(parse_commands("INC nowhere".to_string()).is_err());
+    assert!(parse_commands("a: STOP\na: STOP".to_string()).is_err());
+}
+
+#[test]
+fn test_segments() {
+    let program = parse_program("loop: GOTOZ 0 end | DEC 0 | INC 1 | GOTO loop\nend: STOP".to_string()).unwrap();
+    assert_eq!(vec![
+        Instruction::GotoZ { condition_cell: 0, goto_cell: 4 },
+        Instruction::Dec { cell: 0 },
+        Instruction::Inc { cell: 1 },
+        Instruction::Goto { cell: 0 },
+        Instruction::Stop,
+    ], program.instructions);
+    assert_eq!(vec![1, 1, 1, 1, 2], program.source_lines);
+    assert_eq!(Err("error in line 2, segment 2: Not 2 tokens in: DEC".to_string()),
+               parse_program("INC 0\nINC 1 | DEC | STOP".to_string()).map(|_| ()));
+    assert_eq!(Err("error in line 1: Not 2 tokens in: DEC".to_string()), parse_program("DEC".to_string()).map(|_| ()));
+    assert!(parse_program("a: INC 0 | a: STOP".to_string()).is_err());
+}
+
+#[test]
+fn test_all_parse_errors() {
+    let errors = parse_program_errors("INC\nDEC 0\nGOTO nowhere | INC 0 | POP\na: STOP\na: STOP".to_string(), &ParseOptions::default()).unwrap_err();
+    let shown: Vec<_> = errors.iter().map(ParseError::to_string).collect();
+    assert_eq!(vec![
+        "error in line 1: Not 2 tokens in: INC",
+        "error in line 3, segment 1: Unknown label: nowhere",
+        "error in line 3, segment 3: Not 2 tokens in: POP",
+        "error in line 5: Duplicate label: a",
+    ], shown);
+    assert_eq!(Err(shown[0].clone()), parse_program("INC\nDEC 0\nGOTO nowhere".to_string()).map(|_| ()));
+    let errors = parse_program_errors(".memory 1\n.data 1 2\nINC x".to_string(), &ParseOptions::default()).unwrap_err();
+    assert_eq!(ParseError::program("cell 1 is outside of the declared memory of 1 cells".to_string()), errors[1]);
+}
+
+#[test]
+fn test_render_parse_error() {
+    let text = "INC 0\n\tloop: GOTOZ 0\nINC 1 | dec 2 | GOTO loop 3";
+    let errors = parse_program_errors(text.to_string(), &ParseOptions::default()).unwrap_err();
+    assert_eq!(Some(7..12), errors[0].span);
+    assert_eq!(Some("GOTOZ takes 2 operands, found 1".to_string()), errors[0].hint);
+    assert_eq!("error in line 2: Not 3 tokens in: GOTOZ 0\n  |\n2 | \tloop: GOTOZ 0\n  | \t      ^^^^^\n  = hint: GOTOZ takes 2 operands, found 1",
+               errors[0].render(text));
+    assert_eq!((Some(8..11), Some("did you mean DEC?".to_string())), (errors[1].span.clone(), errors[1].hint.clone()));
+    assert_eq!(Some(26..27), errors[2].span);
+    let errors = parse_program_errors("INC 0\nGOTOZ 0 nowhere".to_string(), &ParseOptions::default()).unwrap_err();
+    assert!(errors[0].render("INC 0\nGOTOZ 0 nowhere").ends_with("\n  |         ^^^^^^^"));
+    assert_eq!("no lines\nhint: x", ParseError { hint: Some("x".to_string()), ..ParseError::program("no lines".to_string()) }.render(""));
+}
+
+#[test]
+fn test_nop_lines() {
+    let input = ".dialect classic\n; count down\nloop: GOTOZ 0 7\nDEC 0\n\nGOTO loop\nend:\nSTOP";
+    let program = parse_program(input.to_string()).unwrap();
+    assert_eq!(vec![3, 4, 6, 8], program.source_lines);
+    let options = ParseOptions { nop_lines: true, ..ParseOptions::default() };
+    let program = parse_program_with(input.to_string(), &options).unwrap();
+    assert_eq!(vec![
+        Instruction::Nop,
+        Instruction::Nop,
+        Instruction::GotoZ { condition_cell: 0, goto_cell: 7 },
+        Instruction::Dec { cell: 0 },
+        Instruction::Nop,
+        Instruction::Goto { cell: 2 },
+        Instruction::Nop,
+        Instruction::Stop,
+    ], program.instructions);
+    assert_eq!((1..=8).collect::<Vec<_>>(), program.source_lines);
+    let mut state = GotoProgramState::new(&program, vec![2]);
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    assert_eq!(Ok(Instruction::Nop), parse_program("NOP".to_string()).map(|p| p.instructions[0]));
+}
+
+#[test]
+fn test_aliases() {
+    let program = parse_program(".alias counter 0
+    .alias result 1
+    loop: GOTOZ counter end
+    DEC counter
+    INC result
+    GOTO loop
+    end: STOP".to_string()).unwrap();
+    assert_eq!(vec![
+        Instruction::GotoZ { condition_cell: 0, goto_cell: 4 },
+        Instruction::Dec { cell: 0 },
+        Instruction::Inc { cell: 1 },
+        Instruction::Goto { cell: 0 },
+        Instruction::Stop
+    ], program.instructions);
+    assert_eq!(vec![3, 4, 5, 6, 7], program.source_lines);
+    assert_eq!("GOTOZ counter 4", program.show(&program.instructions[0]));
+    assert_eq!("mem[result]: 0 \u{2192} 1", program.show_write(&MemoryWrite { cell: 1, old: 0, new: 1 }));
+    assert_eq!("INC 2", program.show(&Instruction::Inc { cell: 2 }));
+    assert_eq!("NOP", program.show(&Instruction::Nop));
+    assert_eq!(Ok(1), program.parse_cell("result"));
+    assert_eq!(Ok(7), program.parse_cell("7"));
+    assert_eq!(Ok(vec![("result".to_string(), 1), ("2".to_string(), 2), ("counter".to_string(), 0)]), program.parse_output_cells("result, 2,0"));
+    assert!(program.parse_output_cells("result,").is_err());
+    assert!(parse_program(".alias a 0\n.alias a 1".to_string()).is_err());
+    assert!(parse_program(".alias 1 0".to_string()).is_err());
+    assert!(parse_program(".unknown".to_string()).is_err());
+}
+
+#[test]
+fn test_constants() {
+    let program = parse_program(".const BASE 10
+    .const TOP BASE*2+1
+    .alias last TOP-1
+    .init BASE+1 = (5*8)
+    loop: INC BASE+1
+    GOTOZ last loop+2
+    ASSERT BASE TOP%4
+    GOTO loop".to_string()).unwrap();
+    assert_eq!(vec![
+        Instruction::Inc { cell: 11 },
+        Instruction::GotoZ { condition_cell: 20, goto_cell: 2 },
+        Instruction::Assert { cell: 10, value: 1 },
+        Instruction::Goto { cell: 0 },
+    ], program.instructions);
+    assert_eq!(vec![(11, 40)], program.data);
+    assert_eq!(Err("error in line 1: Unknown label: nowhere".to_string()), parse_program("GOTO nowhere+1".to_string()).map(|_| ()));
+    assert_eq!(Err("error in line 1: negative value in expression: 0-1".to_string()), parse_program("INC 0-1".to_string()).map(|_| ()));
+    assert!(parse_program(".const A 1\n.const A 2".to_string()).is_err());
+    assert!(parse_program("RAND 0 later\n.const later 3".to_string()).is_ok());
+}
+
+#[test]
+fn test_data() {
+    let program = parse_program(".alias counter 4\n.data 5 0 12\n.init counter = 7\n.data 9\nSTOP".to_string()).unwrap();
+    assert_eq!(vec![(0, 5), (1, 0), (2, 12), (4, 7), (5, 9)], program.data);
+    assert_eq!(vec![5, 0, 12, 0, 7, 9], program.initial_memory(&[]));
+    assert_eq!(vec![1, 2, 12, 0, 7, 9, 3], program.initial_memory(&[1, 2, 12, 0, 7, 9, 3]));
+    assert_eq!(vec![1, 0, 12, 0, 7, 9], program.initial_memory(&[1]));
+    let banked = parse_program(".init 1 = 3\nBANK 1 STOP".to_string()).unwrap();
+    assert_eq!(vec![0, 0, 3], banked.initial_memory(&[]));
+    assert!(parse_program(".data x".to_string()).is_err());
+    assert!(parse_program(".init 1 7".to_string()).is_err());
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct GotoProgram {
+    pub instructions: Vec<Instruction>,
+    pub source_lines: Vec<usize>,
+    /// the number of memory banks, see `banks`
+    pub banks: usize,
+    /// the names of cells declared with `.alias`, in the order of their declarations
+    pub aliases: Vec<(String, RegisterIndex)>,
+    /// the initial values of cells declared with `.data` and `.init`, which
+    /// are cells of bank 0, a later value for a cell replaces an earlier one
+    pub data: Vec<(RegisterIndex, u64)>,
+    /// what the directives before the first instruction declare
+    pub header: header::Header,
+}
+
+impl GotoProgram {
+    /// The alias of the cell for showing it, or its number. Aliases name
+    /// cells of every bank, so programs with banks show numbers.
+    pub fn cell_name(&self, cell: RegisterIndex) -> String {
+        match self.aliases.iter().find(|(_, aliased)| *aliased == cell) {
+            Some((name, _)) if self.banks == 1 => name.clone(),
+            _ => cell.to_string(),
+        }
+    }
+
+    /// The instruction with the alias of its cell, as it could be in the source.
+    pub fn show(&self, instruction: &Instruction) -> String {
+        let text = instruction.to_string();
+        if self.aliases.is_empty() || matches!(instruction, Instruction::Stop | Instruction::Nop | Instruction::Goto { .. }) {
+            return text;
+        }
+        let mut tokens: Vec<_> = text.split(' ').map(String::from).collect();
+        tokens[1] = self.cell_name(tokens[1].parse().unwrap());
+        if let Instruction::GotoEq { .. } | Instruction::GotoGt { .. } = instruction {
+            tokens[2] = self.cell_name(tokens[2].parse().unwrap());
+        }
+        tokens.join(" ")
+    }
+
+    pub fn show_write(&self, write: &MemoryWrite) -> String {
+        format!("mem[{}]: {} \u{2192} {}", self.cell_name(write.cell), write.old, write.new)
+    }
+
+    /// How many cells `.memory`, `.data` and `.init` ask for.
+    pub fn declared_cells(&self) -> usize {
+        let data = self.data.iter().map(|(cell, _)| cell + 1).max().unwrap_or(0);
+        data.max(self.header.memory.unwrap_or(0))
+    }
+
+    /// The declared memory with the input in place of its first cells.
+    pub fn initial_memory(&self, input: &[u64]) -> Vec<u64> {
+        let mut memory = vec![0; self.declared_cells().max(input.len())];
+        for &(cell, value) in &self.data {
+            memory[cell] = value;
+        }
+        memory[..input.len()].copy_from_slice(input);
+        memory
+    }
+
+    /// A cell given by its alias or its number.
+    pub fn parse_cell(&self, text: &str) -> Result<RegisterIndex, String> {
+        Names { aliases: self.aliases.iter().cloned().collect(), ..Names::default() }.cell(text)
+    }
+
+    /// The cells of a list like `0,sum` with the names to show them with.
+    pub fn parse_output_cells(&self, text: &str) -> Result<Vec<(String, RegisterIndex)>, String> {
+        text.split(',').map(|cell| self.parse_cell(cell.trim()).map(|cell| (self.cell_name(cell), cell))).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MemoryWrite {
+    pub cell: RegisterIndex,
+    pub old: u64,
+    pub new: u64,
+}
+
+impl fmt::Display for MemoryWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mem[{}]: {} \u{2192} {}", self.cell, self.old, self.new)
+    }
+}
+
+/// What a single executed instruction did, as passed to step hooks.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StepEffect {
+    pub program_counter: RegisterIndex,
+    pub write: Option<MemoryWrite>,
+    pub stack: Option<StackChange>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StackChange {
+    Pushed,
+    /// with the value that was on top
+    Popped(u64),
+}
+
+/// Whether a step was a STOP, or why it failed.
+pub type StepResult = Result<bool, RuntimeError>;
+
+#[derive(Debug)]
+pub struct GotoProgramState<'a> {
+    pub program: &'a GotoProgram,
+    pub program_counter: RegisterIndex,
+    pub memory: Vec<u64>,
+    pub steps: u64,
+    /// for console I/O, a `console::Buffered` without input unless replaced
+    pub console: Box<dyn console::Console>,
+    /// for RAND, seeded with 0 unless replaced
+    pub random: random::Random,
+    /// for PUSH and POP, with the top at the end
+    pub stack: Vec<u64>,
+    /// in von Neumann mode the cell where the program starts in the memory,
+    /// see `neumann`
+    pub code_base: Option<RegisterIndex>,
+    /// told about every step the interpreter makes, see `observer`
+    pub observers: Vec<Box<dyn observer::ExecutionObserver>>,
+}
+
+/// The error of a fault of the core stepper running the instruction.
+fn runtime_error(fault: Fault, instruction: Instruction) -> RuntimeError {
+    match fault {
+        Fault::OutsideMemory { cell, size } => RuntimeError::OutsideMemory { cell, size },
+        Fault::Overflow { cell } => RuntimeError::Overflow { cell },
+        Fault::Underflow { cell } => RuntimeError::Underflow { cell },
+        Fault::AssertFailed { cell, actual, .. } => RuntimeError::AssertFailed { instruction, cell, actual },
+        Fault::OutsideProgram { program_counter } => RuntimeError::OutsideProgram { program_counter },
+        Fault::OperandTooLarge { .. } | Fault::NotAnOpcode { .. } | Fault::Host(_) => RuntimeError::Decode(fault.to_string()),
+    }
+}
+
+impl<'a> GotoProgramState<'a> {
+    pub fn new(program: &'a GotoProgram, memory: Vec<u64>) -> GotoProgramState<'a> {
+        GotoProgramState {
+            program,
+            program_counter: 0,
+            memory,
+            steps: 0,
+            console: Box::<console::Buffered>::default(),
+            random: random::Random::new(0),
+            stack: vec![],
+            code_base: None,
+            observers: vec![],
+        }
+    }
+
+    /// Asks the console for a cell beyond the memory, returns whether the
+    /// memory has it now.
+    fn fetch_missing(&mut self, cell: RegisterIndex) -> Result<bool, RuntimeError> {
+        if cell < self.memory.len() {
+            return Ok(true);
+        }
+        match self.console.missing_cell(cell).map_err(RuntimeError::Console)? {
+            Some(value) => {
+                self.memory.resize(cell + 1, 0);
+                self.memory[cell] = value;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn cell(&mut self, cell: RegisterIndex) -> Result<&mut u64, RuntimeError> {
+        self.fetch_missing(cell)?;
+        let size = self.memory.len();
+        self.memory.get_mut(cell).ok_or(RuntimeError::OutsideMemory { cell, size })
+    }
+
+    pub fn set_cell(&mut self, cell: RegisterIndex, value: u64) -> Result<(), RuntimeError> {
+        *self.cell(cell)? = value;
+        Ok(())
+    }
+
+    /// The instruction at the program counter, in von Neumann mode as it is
+    /// in the memory right now.
+    pub fn instruction(&self) -> Result<Instruction, RuntimeError> {
+        match self.code_base {
+            Some(code_base) => neumann::fetch(&self.memory, code_base, self.program_counter).map_err(RuntimeError::Decode),
+            None => self.program.instructions.get(self.program_counter).cloned()
+                .ok_or(RuntimeError::OutsideProgram { program_counter: self.program_counter }),
+        }
+    }
+
+    /// Executes the instruction at the program counter, returns `true` if it was a STOP.
+    pub fn step(&mut self) -> StepResult {
+        self.step_with_hook(&mut |_| {})
+    }
+
+    /// Like `step`, but calls `hook` with the effect of the instruction once it executed.
+    pub fn step_with_hook(&mut self, hook: &mut dyn FnMut(&StepEffect)) -> StepResult {
+        if self.observers.is_empty() {
+            return self.execute(hook);
+        }
+        let mut observers = std::mem::take(&mut self.observers);
+        let instruction = self.instruction();
+        if let Ok(instruction) = &instruction {
+            for observer in &mut observers {
+                observer.before_step(self, instruction);
+            }
+        }
+        let mut executed = None;
+        let result = self.execute(&mut |effect| {
+            executed = Some(*effect);
+            hook(effect);
+        });
+        if let (Some(effect), Ok(instruction), Ok(false)) = (executed, instruction, &result) {
+            let jumped = instruction.jump_target() == Some(self.program_counter) && self.program_counter != effect.program_counter + 1;
+            for observer in &mut observers {
+                if let Some(write) = &effect.write {
+                    observer.on_memory_write(write);
+                }
+                if jumped {
+                    observer.on_jump(effect.program_counter, self.program_counter);
+                }
+            }
+        }
+        self.observers = observers;
+        result
+    }
+
+    fn execute(&mut self, hook: &mut dyn FnMut(&StepEffect)) -> StepResult {
+        let program_counter = self.program_counter;
+        let instruction = self.instruction()?;
+        let mut write = None;
+        let mut stack = None;
+        match instruction {
+            Instruction::Print { cell } => {
+                let value = *self.cell(cell)?;
+                self.console.print(value).map_err(RuntimeError::Console)?;
+                self.program_counter += 1;
+            }
+            Instruction::Read { cell } => {
+                let old = *self.cell(cell)?;
+                let new = self.console.read(cell).map_err(RuntimeError::Console)?;
+                self.memory[cell] = new;
+                write = Some(MemoryWrite { cell, old, new });
+                self.program_counter += 1;
+            }
+            Instruction::OutC { cell } => {
+                let value = *self.cell(cell)?;
+                self.console.print_char(cell, value).map_err(RuntimeError::Console)?;
+                self.program_counter += 1;
+            }
+            Instruction::Rand { cell, max } => {
+                let old = *self.cell(cell)?;
+                if max == 0 {
+                    return Err(RuntimeError::EmptyRange { instruction });
+                }
+                let new = self.random.below(max);
+                self.memory[cell] = new;
+                write = Some(MemoryWrite { cell, old, new });
+                self.program_counter += 1;
+            }
+            Instruction::Push { cell } => {
+                let value = *self.cell(cell)?;
+                self.stack.push(value);
+                stack = Some(StackChange::Pushed);
+                self.program_counter += 1;
+            }
+            Instruction::Pop { cell } => {
+                let old = *self.cell(cell)?;
+                let new = self.stack.pop().ok_or(RuntimeError::EmptyStack { cell })?;
+                self.memory[cell] = new;
+                write = Some(MemoryWrite { cell, old, new });
+                stack = Some(StackChange::Popped(new));
+                self.program_counter += 1;
+            }
+            // everything that needs no host runs on the stepper of the no_std core
+            _ => {
+                let executed = loop {
+                    match goto_core::execute(Op::from(instruction), &mut self.memory, program_counter) {
+                        Err(Fault::OutsideMemory { cell, .. }) if self.fetch_missing(cell)? => {}
+                        result => break result,
+                    }
+                };
+                let executed = executed.map_err(|fault| runtime_error(fault, instruction))?;
+                write = executed.write.map(|(cell, old)| MemoryWrite { cell, old, new: self.memory[cell] });
+                match executed.next {
+                    Some(next) => self.program_counter = next,
+                    None => {
+                        self.steps += 1;
+                        hook(&StepEffect { program_counter, write, stack });
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+        self.steps += 1;
+        hook(&StepEffect { program_counter, write, stack });
+        Ok(false)
+    }
+
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            if let Some(instruction) = self.program.instructions.get(self.program_counter) {
+                println!("{:?}: {:?}", self.program_counter, instruction);
+                println!("mem: {:?}", self.memory);
+            }
+            if self.step()? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs until STOP or until `max_steps` instructions have been executed,
+    /// returns whether the program stopped.
+    pub fn run_with_limit(&mut self, max_steps: u64) -> Result<bool, RuntimeError> {
+        while self.steps < max_steps {
+            if self.step()? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The steps of the execution from here on, one at a time, see `events`.
+    pub fn events(&mut self) -> events::Events<'_, 'a> {
+        events::Events::new(self)
+    }
+
+    /// Runs under the ceilings for untrusted programs, see `sandbox`.
+    pub fn run_with_limits(&mut self, limits: &sandbox::Limits) -> sandbox::Outcome {
+        sandbox::run(self, limits)
+    }
+}
+
+#[test]
+fn test_step_with_hook() {
+    let program = parse_program("INC 1\nGOTOZ 0 0\nDEC 1\nSTOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![1, 5]);
+    let mut effects = vec![];
+    while !state.step_with_hook(&mut |effect| effects.push(*effect)).unwrap() {}
+    assert_eq!(vec![
+        StepEffect { program_counter: 0, write: Some(MemoryWrite { cell: 1, old: 5, new: 6 }), stack: None },
+        StepEffect { program_counter: 1, write: None, stack: None },
+        StepEffect { program_counter: 2, write: Some(MemoryWrite { cell: 1, old: 6, new: 5 }), stack: None },
+        StepEffect { program_counter: 3, write: None, stack: None },
+    ], effects);
+    assert_eq!("mem[1]: 5 \u{2192} 6", effects[0].write.unwrap().to_string());
+}
+
+#[test]
+fn test_step_assert() {
+    let program = parse_program("ASSERT 0 3\nASSERTZ 1\nASSERT 0 4\nSTOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![3, 0]);
+    assert_eq!((Ok(false), Ok(false)), (state.step(), state.step()));
+    let error = state.step().unwrap_err();
+    assert_eq!(RuntimeError::AssertFailed { instruction: Instruction::Assert { cell: 0, value: 4 }, cell: 0, actual: 3 }, error);
+    assert_eq!("ASSERT 0 4 failed, cell 0 is 3", error.to_string());
+    assert_eq!(2, state.program_counter);
+
+    let mut state = GotoProgramState::new(&program, vec![3, 1]);
+    state.step().unwrap();
+    let error = state.step().unwrap_err();
+    assert_eq!(RuntimeError::AssertFailed { instruction: Instruction::Assert { cell: 1, value: 0 }, cell: 1, actual: 1 }, error);
+    assert_eq!("ASSERTZ 1 failed, cell 1 is 1", error.to_string());
+}
+
+#[test]
+fn test_run_with_limit() {
+    let program = parse_program("loop: GOTOZ 0 end
+    DEC 0
+    INC 1
+    GOTO loop
+    end: STOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![3, 4]);
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    assert_eq!(vec![0, 7], state.memory);
+    assert_eq!(14, state.steps);
+
+    let mut state = GotoProgramState::new(&program, vec![3, 4]);
+    assert_eq!(Ok(false), state.run_with_limit(10));
+    assert_eq!(10, state.steps);
+
+    let mut state = GotoProgramState::new(&program, vec![3]);
+    assert!(state.run_with_limit(100).is_err());
+}
+
+#[test]
+fn test_print_and_read() {
+    let program = parse_program("READ 0\nREAD 1\nPRINT 1\nPRINT 0\nSTOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![0, 0]);
+    state.console = Box::new(console::Buffered { input: vec![5, 9].into(), ..console::Buffered::default() });
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    assert_eq!(vec![5, 9], state.memory);
+    assert_eq!("Buffered { input: [], output: \"9\\n5\\n\", encoding: Utf8 }", format!("{:?}", state.console));
+
+    let mut state = GotoProgramState::new(&program, vec![0, 0]);
+    assert_eq!(Err(RuntimeError::Console("READ 0: there is no more input".to_string())), state.step());
+    assert_eq!(0, state.program_counter);
+
+    let program = parse_program("OUTC 0\nOUTC 1\nOUTC 2\nSTOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![72, 105, 1 << 40]);
+    assert_eq!(Err(RuntimeError::Console(format!("OUTC 2: {} is not a character in utf-8", 1u64 << 40))), state.run_with_limit(100));
+    assert_eq!("Buffered { input: [], output: \"Hi\", encoding: Utf8 }", format!("{:?}", state.console));
+}
+
+#[test]
+fn test_push_and_pop() {
+    let program = parse_program("PUSH 0\nPUSH 1\nPOP 0\nPOP 1\nPOP 1".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![3, 4]);
+    let mut effects = vec![];
+    let result = loop {
+        match state.step_with_hook(&mut |effect| effects.push(effect.stack)) {
+            Ok(_) => {}
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(RuntimeError::EmptyStack { cell: 1 }, result);
+    assert_eq!("POP 1 on an empty stack", result.to_string());
+    assert_eq!((vec![4, 3], 4, vec![]), (state.memory, state.program_counter, state.stack));
+    assert_eq!(vec![Some(StackChange::Pushed), Some(StackChange::Pushed), Some(StackChange::Popped(4)), Some(StackChange::Popped(3))], effects);
+}
+
+#[test]
+fn test_rand() {
+    let program = parse_program("RAND 0 6\nRAND 1 6\nRAND 2 1\nSTOP".to_string()).unwrap();
+    let run = |seed| {
+        let mut state = GotoProgramState::new(&program, vec![9, 9, 9]);
+        state.random = random::Random::new(seed);
+        state.run_with_limit(10).unwrap();
+        state.memory
+    };
+    assert_eq!(run(3), run(3));
+    assert!(run(3)[..2].iter().all(|&value| value < 6));
+    assert_eq!(0, run(3)[2]);
+    assert_ne!(run(3), run(4));
+
+    let program = parse_program("RAND 0 0".to_string()).unwrap();
+    assert_eq!(Err("RAND 0 0 has no number below 0".to_string()), GotoProgramState::new(&program, vec![0]).step().map_err(String::from));
+}
+
+#[test]
+fn test_missing_cells() {
+    #[derive(Debug)]
+    struct Asking;
+
+    impl console::Console for Asking {
+        fn read(&mut self, cell: RegisterIndex) -> Result<u64, String> {
+            Err(format!("READ {}", cell))
+        }
+
+        fn print(&mut self, _: u64) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn print_char(&mut self, _: RegisterIndex, _: u64) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn missing_cell(&mut self, cell: RegisterIndex) -> Result<Option<u64>, String> {
+            Ok(Some(cell as u64 * 10))
+        }
+    }
+
+    let program = parse_program("INC 3\nDEC 1\nSTOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![1, 1]);
+    state.console = Box::new(Asking);
+    assert_eq!(Ok(true), state.run_with_limit(10));
+    assert_eq!(vec![1, 0, 0, 31], state.memory);
+}
+
+pub fn read_input(text: String) -> Result<Vec<u64>, String> {
+    let mut result = vec![];
+    for token in text.split([' ', '\n']).filter(|t| !t.is_empty()) {
+
+        let nr = token.parse::<u64>()
+            .map_err(|e| format!("Number parsing error: {}", e))?;
+        result.push(nr);
+    }
+    Ok(result)
+}
+
+/// The exit status a program leaves in `cell` for `--exit-from-cell`, values
+/// above 255 are reported as 255 so that they never look like success.
+pub fn exit_status(memory: &[u64], cell: RegisterIndex) -> Result<i32, String> {
+    let value = memory.get(cell)
+        .ok_or_else(|| format!("cell {} is outside of the memory of size {}", cell, memory.len()))?;
+    Ok((*value).min(255) as i32)
+}
+
+#[test]
+fn test_exit_status() {
+    assert_eq!(Ok(0), exit_status(&[5, 0], 1));
+    assert_eq!(Ok(5), exit_status(&[5, 0], 0));
+    assert_eq!(Ok(255), exit_status(&[256], 0));
+    assert!(exit_status(&[1], 1).is_err());
+}
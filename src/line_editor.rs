@@ -0,0 +1,300 @@
+use std::fs::{read_to_string, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+
+const HISTORY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    KillToEnd,
+    KillToStart,
+    KillWord,
+    Eof,
+    Ignored,
+}
+
+#[derive(Debug, Default)]
+struct EditState {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl EditState {
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn apply(&mut self, key: Key) {
+        match key {
+            Key::Char(c) => {
+                self.chars.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+            }
+            Key::Left if self.cursor > 0 => self.cursor -= 1,
+            Key::Right if self.cursor < self.chars.len() => self.cursor += 1,
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.chars.len(),
+            Key::KillToEnd => self.chars.truncate(self.cursor),
+            Key::KillToStart => {
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::KillWord => {
+                let mut start = self.cursor;
+                while start > 0 && self.chars[start - 1] == ' ' {
+                    start -= 1;
+                }
+                while start > 0 && self.chars[start - 1] != ' ' {
+                    start -= 1;
+                }
+                self.chars.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Puts the terminal into non-canonical mode without echo for as long as it lives.
+pub struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        RawMode::with_local_flags(libc::ICANON | libc::ECHO)
+    }
+
+    /// Like `enable`, but Ctrl+C and Ctrl+Z arrive as keys instead of signals.
+    pub fn without_signals() -> io::Result<RawMode> {
+        RawMode::with_local_flags(libc::ICANON | libc::ECHO | libc::ISIG)
+    }
+
+    fn with_local_flags(cleared: libc::tcflag_t) -> io::Result<RawMode> {
+        unsafe {
+            let mut original = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            raw.c_lflag &= !cleared;
+            raw.c_iflag &= !(libc::ICRNL | libc::IXON);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawMode { original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut buffer = [0u8];
+    match input.read(&mut buffer)? {
+        0 => Ok(None),
+        _ => Ok(Some(buffer[0])),
+    }
+}
+
+pub fn read_key(input: &mut impl Read) -> io::Result<Key> {
+    let byte = match read_byte(input)? {
+        Some(byte) => byte,
+        None => return Ok(Key::Eof),
+    };
+    Ok(match byte {
+        b'\r' | b'\n' => Key::Enter,
+        1 => Key::Home,
+        2 => Key::Left,
+        4 => Key::Eof,
+        5 => Key::End,
+        6 => Key::Right,
+        8 | 127 => Key::Backspace,
+        11 => Key::KillToEnd,
+        14 => Key::Down,
+        16 => Key::Up,
+        21 => Key::KillToStart,
+        23 => Key::KillWord,
+        27 => {
+            if read_byte(input)? != Some(b'[') {
+                return Ok(Key::Ignored);
+            }
+            match read_byte(input)? {
+                Some(b'A') => Key::Up,
+                Some(b'B') => Key::Down,
+                Some(b'C') => Key::Right,
+                Some(b'D') => Key::Left,
+                Some(b'H') => Key::Home,
+                Some(b'F') => Key::End,
+                Some(digit @ b'0'..=b'9') => {
+                    // sequences like ESC [ 3 ~
+                    while !matches!(read_byte(input)?, Some(b'~') | None) {}
+                    match digit {
+                        b'1' | b'7' => Key::Home,
+                        b'3' => Key::Delete,
+                        b'4' | b'8' => Key::End,
+                        _ => Key::Ignored,
+                    }
+                }
+                _ => Key::Ignored,
+            }
+        }
+        byte if byte < 32 => Key::Ignored,
+        byte if byte < 128 => Key::Char(byte as char),
+        byte => {
+            let length = match byte {
+                0xC0..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                _ => 4,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..length {
+                bytes.extend(read_byte(input)?);
+            }
+            match std::str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Ignored,
+            }
+        }
+    })
+}
+
+/// Reads lines from the terminal with cursor movement, basic emacs key
+/// bindings and a history that is persisted to `history_file`.
+pub struct LineEditor {
+    history: Vec<String>,
+    history_file: Option<PathBuf>,
+}
+
+impl LineEditor {
+    pub fn new(history_file: Option<PathBuf>) -> LineEditor {
+        let mut history: Vec<String> = history_file.as_ref()
+            .and_then(|path| read_to_string(path).ok())
+            .map(|text| text.lines().map(|l| l.to_string()).collect())
+            .unwrap_or_default();
+        if history.len() > HISTORY_LIMIT {
+            history.drain(..history.len() - HISTORY_LIMIT);
+        }
+        LineEditor { history, history_file }
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().map(|l| l.as_str()) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if let Some(path) = &self.history_file {
+            let file = OpenOptions::new().create(true).append(true).open(path);
+            if let Ok(mut file) = file {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    /// Returns `None` at the end of input.
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        if !atty::is(atty::Stream::Stdin) {
+            print!("{}", prompt);
+            io::stdout().flush()?;
+            let mut line = String::new();
+            return match io::stdin().lock().read_line(&mut line)? {
+                0 => Ok(None),
+                _ => Ok(Some(line.trim_end_matches(['\r', '\n']).to_string())),
+            };
+        }
+        let _raw = RawMode::enable()?;
+        let mut input = io::stdin();
+        let mut state = EditState::default();
+        let mut history_index = self.history.len();
+        let mut draft = String::new();
+        loop {
+            let mut out = io::stdout();
+            write!(out, "\r{}{}\x1b[K\r\x1b[{}C", prompt, state.text(),
+                   prompt.chars().count() + state.cursor)?;
+            out.flush()?;
+            match read_key(&mut input)? {
+                Key::Enter => {
+                    writeln!(out)?;
+                    return Ok(Some(state.text()));
+                }
+                Key::Eof if state.chars.is_empty() => {
+                    writeln!(out)?;
+                    return Ok(None);
+                }
+                Key::Eof => state.apply(Key::Delete),
+                Key::Up if history_index > 0 => {
+                    if history_index == self.history.len() {
+                        draft = state.text();
+                    }
+                    history_index -= 1;
+                    state.set(&self.history[history_index]);
+                }
+                Key::Down if history_index < self.history.len() => {
+                    history_index += 1;
+                    match self.history.get(history_index) {
+                        Some(line) => state.set(line),
+                        None => state.set(&draft),
+                    }
+                }
+                key => state.apply(key),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_edit_state() {
+    let mut state = EditState::default();
+    for key in "INC 1 2".chars().map(Key::Char) {
+        state.apply(key);
+    }
+    state.apply(Key::KillWord);
+    assert_eq!("INC 1 ", state.text());
+    state.apply(Key::Home);
+    state.apply(Key::Delete);
+    state.apply(Key::Char('D'));
+    state.apply(Key::Right);
+    state.apply(Key::Backspace);
+    state.apply(Key::Char('E'));
+    assert_eq!("DEC 1 ", state.text());
+    state.apply(Key::KillToStart);
+    assert_eq!(("C 1 ".to_string(), 0), (state.text(), state.cursor));
+}
+
+#[test]
+fn test_read_key() {
+    let mut input: &[u8] = b"\x1b[A\x1b[3~\xc3\xa4a\x01";
+    let keys: Vec<_> = (0..5).map(|_| read_key(&mut input).unwrap()).collect();
+    assert_eq!(vec![Key::Up, Key::Delete, Key::Char('ä'), Key::Char('a'), Key::Home], keys);
+    assert_eq!(Key::Eof, read_key(&mut input).unwrap());
+}
@@ -0,0 +1,214 @@
+//! Programs of several modules. A module makes labels available to others
+//! with `.export mul` and uses the labels of others with `.import mul`,
+//! which jump targets can name like labels of the module itself, also in
+//! expressions like `mul+1`.
+//!
+//! Linking puts the instructions of the modules one after another, the first
+//! module runs first and the others only by jumps into them, and merges their
+//! aliases, `.data`, `.init` and headers. The modules share the memory.
+
+use std::collections::BTreeMap;
+
+use crate::disasm::{self, DisasmOptions};
+use crate::header::{Dialect, Header};
+use crate::transpile::Arithmetic;
+use crate::{gbc, parse_program_with, preprocess, source, GotoProgram, ParseOptions, RegisterIndex};
+
+pub const DIRECTIVES: [&str; 2] = [".export", ".import"];
+
+/// Imported labels stand for these jump targets until linking, the labels
+/// of a module are `STRIDE` apart so an offset from one stays recognizable.
+const IMPORTS: RegisterIndex = RegisterIndex::MAX / 4;
+const STRIDE: RegisterIndex = 1 << 16;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Module {
+    pub name: String,
+    pub program: GotoProgram,
+    /// the exported labels with the index of their instruction in the module
+    pub exports: Vec<(String, usize)>,
+    pub imports: Vec<String>,
+}
+
+/// Parses a module, its `.export` lines become blank and its `.import`
+/// lines constants for the targets `link` replaces.
+pub fn parse_module(name: &str, text: &str, options: &ParseOptions) -> Result<Module, String> {
+    let text = preprocess::preprocess(text, &options.defines)?;
+    let (mut exported, mut imports) = (vec![], vec![]);
+    let mut lines = vec![];
+    for (line_nr, line) in text.lines().enumerate() {
+        let error = |e: String| format!("error in line {}: {}", line_nr + 1, e);
+        let segments = source::parse_segments(line).map_err(error)?;
+        let directive = segments.iter().find(|segment| segment.tokens.first().is_some_and(|token| DIRECTIVES.contains(&token.as_str())));
+        let directive = match directive {
+            Some(directive) => directive,
+            None => {
+                lines.push(line.to_string());
+                continue;
+            }
+        };
+        if segments.len() > 1 || directive.label.is_some() {
+            return Err(error(format!("{} has to be on a line of its own", directive.tokens[0])));
+        }
+        if directive.tokens.len() < 2 || !directive.tokens[1..].iter().all(|name| source::is_label_name(name)) {
+            return Err(error(format!("expected {} <label>... in: {}", directive.tokens[0], directive.tokens.join(" "))));
+        }
+        if directive.tokens[0] == ".export" {
+            exported.extend(directive.tokens[1..].iter().cloned());
+            lines.push(String::new());
+        } else {
+            let constants: Vec<String> = directive.tokens[1..].iter().map(|name| {
+                imports.push(name.clone());
+                format!(".const {} {}", name, IMPORTS + (imports.len() - 1) * STRIDE)
+            }).collect();
+            lines.push(constants.join(&format!(" {} ", source::SEPARATOR)));
+        }
+    }
+    let text = lines.join("\n");
+    let program = parse_program_with(text.clone(), options)?;
+    let labels = gbc::label_table(&text, &program);
+    if let Some(import) = imports.iter().find(|import| labels.iter().any(|(label, _)| label == *import)) {
+        return Err(format!("{} imports {} and has a label of that name", name, import));
+    }
+    let mut exports = vec![];
+    for export in exported {
+        match labels.iter().find(|(label, _)| *label == export) {
+            Some(&(_, index)) if index < program.instructions.len() => exports.push((export, index)),
+            Some(_) => return Err(format!("{} exports {}, which labels no instruction", name, export)),
+            None => return Err(format!("{} exports {}, which is no label", name, export)),
+        }
+    }
+    Ok(Module { name: name.to_string(), program, exports, imports })
+}
+
+/// The header of both programs, an error if they declare different ones.
+fn merge_headers(first: Header, second: Header) -> Result<Header, String> {
+    let dialect = match (first.dialect, second.dialect) {
+        (Some(a), Some(b)) if a != b => return Err(format!(".dialect {} and .dialect {}", Dialect::NAMES[a as usize], Dialect::NAMES[b as usize])),
+        (a, b) => a.or(b),
+    };
+    let cells = match (first.cells, second.cells) {
+        (Some(a), Some(b)) if a != b => return Err(format!(".cells {} and .cells {}", Arithmetic::NAMES[a as usize], Arithmetic::NAMES[b as usize])),
+        (a, b) => a.or(b),
+    };
+    let memory = first.memory.max(second.memory);
+    Ok(Header { dialect, memory, cells })
+}
+
+/// The program of all modules with the exported labels at their place in
+/// it. Every collision is reported: labels exported twice, imports nobody
+/// exports, aliases and data with different cells or values, and headers
+/// that do not agree.
+pub fn link(modules: &[Module]) -> Result<(GotoProgram, Vec<(String, usize)>), String> {
+    let mut errors = vec![];
+    let mut starts = vec![];
+    let mut exports: BTreeMap<&str, (usize, &str)> = BTreeMap::new();
+    let mut length = 0;
+    for module in modules {
+        starts.push(length);
+        for (name, index) in &module.exports {
+            if let Some((_, other)) = exports.insert(name, (length + index, &module.name)) {
+                errors.push(format!("{} is exported by {} and {}", name, other, module.name));
+            }
+        }
+        length += module.program.instructions.len();
+    }
+    let banks = modules.first().map_or(1, |module| module.program.banks);
+    let mut linked = GotoProgram { instructions: vec![], source_lines: vec![], banks, aliases: vec![], data: vec![], header: Header::default() };
+    for (module, start) in modules.iter().zip(starts) {
+        let program = &module.program;
+        if program.banks != banks {
+            errors.push(format!("{} has {} banks, {} has {}", modules[0].name, banks, module.name, program.banks));
+        }
+        match merge_headers(linked.header, program.header) {
+            Ok(header) => linked.header = header,
+            Err(e) => errors.push(format!("{} declares {}", module.name, e)),
+        }
+        for (name, cell) in &program.aliases {
+            match linked.aliases.iter().find(|(other, _)| other == name) {
+                Some((_, other)) if other != cell => errors.push(format!("{} makes {} an alias of {}, another module of {}", module.name, name, cell, other)),
+                Some(_) => {}
+                None => linked.aliases.push((name.clone(), *cell)),
+            }
+        }
+        for &(cell, value) in &program.data {
+            match linked.data.iter().find(|(other, _)| *other == cell) {
+                Some(&(_, other)) if other != value => errors.push(format!("{} sets cell {} to {}, another module to {}", module.name, cell, value, other)),
+                Some(_) => {}
+                None => linked.data.push((cell, value)),
+            }
+        }
+        for (&instruction, &line) in program.instructions.iter().zip(&program.source_lines) {
+            let target = match instruction.jump_target() {
+                Some(target) if target >= IMPORTS => {
+                    let (import, offset) = ((target - IMPORTS) / STRIDE, (target - IMPORTS) % STRIDE);
+                    let name = &module.imports[import];
+                    match exports.get(name.as_str()) {
+                        Some(&(index, _)) => index + offset,
+                        None => {
+                            errors.push(format!("{} imports {}, which no module exports", module.name, name));
+                            continue;
+                        }
+                    }
+                }
+                Some(target) => start + target,
+                None => 0,
+            };
+            linked.instructions.push(instruction.with_jump_target(target));
+            linked.source_lines.push(line);
+        }
+    }
+    errors.dedup();
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    let labels = exports.into_iter().map(|(name, (index, _))| (name.to_string(), index)).collect();
+    Ok((linked, labels))
+}
+
+/// Source of a linked program that `goto` runs and compiles like any other.
+pub fn to_source(program: &GotoProgram, labels: &[(String, usize)]) -> String {
+    let mut text = String::new();
+    if let Some(dialect) = program.header.dialect {
+        text += &format!(".dialect {}\n", Dialect::NAMES[dialect as usize]);
+    }
+    if let Some(memory) = program.header.memory {
+        text += &format!(".memory {}\n", memory);
+    }
+    if let Some(cells) = program.header.cells {
+        text += &format!(".cells {}\n", Arithmetic::NAMES[cells as usize]);
+    }
+    // the instructions name cells of all banks by their number, so aliases only fit a single bank
+    for (name, cell) in program.aliases.iter().filter(|_| program.banks == 1) {
+        text += &format!(".alias {} {}\n", name, cell);
+    }
+    for (cell, value) in &program.data {
+        text += &format!(".init {} = {}\n", cell, value);
+    }
+    text + &disasm::disassemble(program, labels, DisasmOptions { labels: true, indices: false })
+}
+
+#[test]
+fn test_link() {
+    let module = |name: &str, text: &str| parse_module(name, text, &ParseOptions::default()).unwrap();
+    let main = module("main.goto", ".import double\n.alias x 0\nGOTO double\nback: STOP\n.export back");
+    assert_eq!(vec!["double".to_string()], main.imports);
+    let lib = module("lib.goto", ".import back\n.export double\n.alias x 0\n.init 3 = 1\ndouble: GOTOZ x done\nDEC x\nINC 1\nINC 1\nGOTO double\ndone: GOTO back");
+    let (program, labels) = link(&[main.clone(), lib.clone()]).unwrap();
+    assert_eq!(vec![("back".to_string(), 1), ("double".to_string(), 2)], labels);
+    let mut state = crate::GotoProgramState::new(&program, program.initial_memory(&[3, 0]));
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    assert_eq!(vec![0, 6, 0, 1], state.memory);
+
+    let source = to_source(&program, &labels);
+    assert_eq!(".alias x 0\n.init 3 = 1\n    GOTO  double\nback:\n    STOP\ndouble:\n    GOTOZ 0 L7\n    DEC   0\n    INC   1\n    INC   1\n    GOTO  double\nL7:\n    GOTO  back\n", source);
+    assert_eq!(Ok(program.instructions), crate::parse_program(source).map(|program| program.instructions));
+
+    let other = module("other.goto", ".export double\n.alias x 1\n.dialect classic\ndouble: STOP");
+    assert_eq!(Err("double is exported by lib.goto and other.goto
+other.goto makes x an alias of 1, another module of 0".to_string()), link(&[main.clone(), lib, other]));
+    assert_eq!(Err("main.goto imports double, which no module exports".to_string()), link(&[main]));
+    assert!(parse_module("a.goto", ".export end\nSTOP\nend:", &ParseOptions::default()).is_err());
+    assert!(parse_module("a.goto", ".import a\na: STOP", &ParseOptions::default()).is_err());
+    assert!(crate::parse_program(".import a\nGOTO a".to_string()).is_err());
+}
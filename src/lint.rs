@@ -0,0 +1,182 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::{GotoProgram, Instruction, RegisterIndex};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LintCode {
+    Unreachable,
+    WrittenNeverRead,
+    ReadNeverWritten,
+    JumpToStop,
+}
+
+impl LintCode {
+    pub const ALL: [LintCode; 4] = [
+        LintCode::Unreachable,
+        LintCode::WrittenNeverRead,
+        LintCode::ReadNeverWritten,
+        LintCode::JumpToStop,
+    ];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            LintCode::Unreachable => "W001",
+            LintCode::WrittenNeverRead => "W002",
+            LintCode::ReadNeverWritten => "W003",
+            LintCode::JumpToStop => "W004",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<LintCode> {
+        LintCode::ALL.iter().cloned().find(|c| c.code() == code)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Warning {
+    pub code: LintCode,
+    pub instruction: RegisterIndex,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code.code(), self.message)
+    }
+}
+
+pub fn successors(program: &GotoProgram, pc: RegisterIndex) -> Vec<RegisterIndex> {
+    let next = match program.instructions[pc] {
+        Instruction::Stop => vec![],
+        Instruction::Inc { .. } | Instruction::Dec { .. } | Instruction::Assert { .. }
+        | Instruction::Print { .. } | Instruction::Read { .. } | Instruction::OutC { .. }
+        | Instruction::Rand { .. } | Instruction::Push { .. } | Instruction::Pop { .. }
+        | Instruction::StoreI { .. } | Instruction::Nop => vec![pc + 1],
+        Instruction::Goto { cell } => vec![cell],
+        Instruction::GotoZ { goto_cell, .. } | Instruction::GotoNz { goto_cell, .. }
+        | Instruction::GotoEq { goto_cell, .. } | Instruction::GotoGt { goto_cell, .. } => vec![goto_cell, pc + 1],
+    };
+    next.into_iter().filter(|&n| n < program.instructions.len()).collect()
+}
+
+pub fn reachable(program: &GotoProgram) -> Vec<bool> {
+    let mut seen = vec![false; program.instructions.len()];
+    let mut todo = vec![0];
+    while let Some(pc) = todo.pop() {
+        if pc >= seen.len() || seen[pc] {
+            continue;
+        }
+        seen[pc] = true;
+        todo.extend(successors(program, pc));
+    }
+    seen
+}
+
+fn unreachable_code(program: &GotoProgram, warnings: &mut Vec<Warning>) {
+    for (pc, reached) in reachable(program).into_iter().enumerate() {
+        if !reached {
+            warnings.push(Warning {
+                code: LintCode::Unreachable,
+                instruction: pc,
+                message: format!("unreachable instruction: {}", program.instructions[pc]),
+            });
+        }
+    }
+}
+
+fn unused_cells(program: &GotoProgram, warnings: &mut Vec<Warning>) {
+    let mut written = BTreeSet::new();
+    let mut read = BTreeSet::new();
+    for instruction in &program.instructions {
+        match *instruction {
+            // INC and DEC read the cell they change, like the result cells of most programs.
+            Instruction::Inc { cell } | Instruction::Dec { cell } => {
+                written.insert(cell);
+                read.insert(cell);
+            }
+            Instruction::Read { cell } | Instruction::Rand { cell, .. } | Instruction::Pop { cell }
+            | Instruction::StoreI { cell, .. } => { written.insert(cell); }
+            Instruction::GotoZ { condition_cell: cell, .. } | Instruction::GotoNz { condition_cell: cell, .. } | Instruction::Assert { cell, .. }
+            | Instruction::Print { cell } | Instruction::OutC { cell } | Instruction::Push { cell } => { read.insert(cell); }
+            Instruction::GotoEq { left, right, .. } | Instruction::GotoGt { left, right, .. } => {
+                read.insert(left);
+                read.insert(right);
+            }
+            _ => {}
+        }
+    }
+    let first_use = |cell: RegisterIndex| program.instructions.iter()
+        .position(|i| match *i {
+            Instruction::Inc { cell: c } | Instruction::Dec { cell: c } | Instruction::Read { cell: c }
+            | Instruction::Rand { cell: c, .. } | Instruction::Pop { cell: c } | Instruction::StoreI { cell: c, .. } => c == cell,
+            Instruction::GotoZ { condition_cell: c, .. } | Instruction::GotoNz { condition_cell: c, .. } | Instruction::Assert { cell: c, .. }
+            | Instruction::Print { cell: c } | Instruction::OutC { cell: c } | Instruction::Push { cell: c } => c == cell,
+            Instruction::GotoEq { left, right, .. } | Instruction::GotoGt { left, right, .. } => left == cell || right == cell,
+            _ => false
+        })
+        .unwrap_or(0);
+    for &cell in written.difference(&read) {
+        warnings.push(Warning {
+            code: LintCode::WrittenNeverRead,
+            instruction: first_use(cell),
+            message: format!("cell {} is written but never read", cell),
+        });
+    }
+    for &cell in read.difference(&written) {
+        warnings.push(Warning {
+            code: LintCode::ReadNeverWritten,
+            instruction: first_use(cell),
+            message: format!("cell {} is read but never written", cell),
+        });
+    }
+}
+
+fn jumps_to_stop(program: &GotoProgram, warnings: &mut Vec<Warning>) {
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        if let Instruction::Goto { cell } = *instruction {
+            if let Some(Instruction::Stop) = program.instructions.get(cell) {
+                warnings.push(Warning {
+                    code: LintCode::JumpToStop,
+                    instruction: pc,
+                    message: format!("{} jumps to STOP and can be replaced by STOP", instruction),
+                });
+            }
+        }
+    }
+}
+
+/// Runs all checks and returns the warnings whose code is not in `allowed`,
+/// ordered by instruction.
+pub fn lint(program: &GotoProgram, allowed: &[LintCode]) -> Vec<Warning> {
+    let mut warnings = vec![];
+    unreachable_code(program, &mut warnings);
+    unused_cells(program, &mut warnings);
+    jumps_to_stop(program, &mut warnings);
+    warnings.retain(|w| !allowed.contains(&w.code));
+    warnings.sort_by_key(|w| w.instruction);
+    warnings
+}
+
+#[test]
+fn test_lint() {
+    let program = crate::parse_program("loop: GOTOZ 0 end
+    DEC 0
+    INC 1
+    GOTO loop
+    STOREI 2 5
+    end: STOP
+    GOTO end".to_string()).unwrap();
+    let codes: Vec<_> = lint(&program, &[]).iter().map(|w| (w.code, w.instruction)).collect();
+    // Cell 1 only counts up, but it is the result and not written blindly.
+    assert_eq!(vec![
+        (LintCode::Unreachable, 4),
+        (LintCode::WrittenNeverRead, 4),
+        (LintCode::Unreachable, 6),
+        (LintCode::JumpToStop, 6),
+    ], codes);
+
+    let codes: Vec<_> = lint(&program, &[LintCode::Unreachable, LintCode::WrittenNeverRead])
+        .iter().map(|w| w.code).collect();
+    assert_eq!(vec![LintCode::JumpToStop], codes);
+}
@@ -0,0 +1,114 @@
+//! A compiler from the LOOP language to goto.
+//!
+//! LOOP programs use the statements of WHILE programs, but instead of
+//! `WHILE xi != 0` they only have `LOOP xi DO ... END`, which runs its body as
+//! often as `xi` says when the loop is entered. Every LOOP program stops, so
+//! the number of steps its translation takes can be worked out from the
+//! program structure before running it, which no WHILE program allows in
+//! general.
+
+use crate::while_lang::{self, Statement};
+
+pub fn parse(text: &str) -> Result<Vec<Statement>, String> {
+    while_lang::parse_statements(text, true)
+}
+
+pub fn to_goto(text: &str) -> Result<String, String> {
+    Ok(while_lang::compile(&parse(text)?, "LOOP"))
+}
+
+struct Bound {
+    cells: Vec<u64>,
+    steps: u64,
+    scratch: usize,
+}
+
+impl Bound {
+    fn add_steps(&mut self, steps: u64) {
+        self.steps = self.steps.saturating_add(steps);
+    }
+
+    /// The steps of `move_into` in the generated code: a GOTOZ, a DEC, the
+    /// INCs and a GOTO per unit and a final GOTOZ.
+    fn move_into(&mut self, from: usize, to: &[usize]) {
+        let value = std::mem::replace(&mut self.cells[from], 0);
+        self.add_steps(value.saturating_mul(to.len() as u64 + 3).saturating_add(1));
+        for &cell in to {
+            self.cells[cell] = self.cells[cell].saturating_add(value);
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement], depth: usize) {
+        for statement in statements {
+            match *statement {
+                Statement::Assign { target, source, add, constant } => {
+                    if target != source {
+                        self.move_into(target, &[]);
+                        self.move_into(source, &[target, self.scratch]);
+                        self.move_into(self.scratch, &[source]);
+                    }
+                    let value = self.cells[target];
+                    if add {
+                        self.add_steps(constant);
+                        self.cells[target] = value.saturating_add(constant);
+                    } else {
+                        // GOTOZ and DEC while the cell is not 0, only GOTOZ after that
+                        let decrements = value.min(constant);
+                        self.add_steps(constant.saturating_add(decrements));
+                        self.cells[target] = value - decrements;
+                    }
+                }
+                Statement::Loop { variable, ref body } => {
+                    let counter = self.scratch + 1 + depth;
+                    self.move_into(counter, &[]);
+                    self.move_into(variable, &[counter, self.scratch]);
+                    self.move_into(self.scratch, &[variable]);
+                    let mut remaining = self.cells[counter];
+                    while remaining > 0 {
+                        let (cells, steps) = (self.cells.clone(), self.steps);
+                        self.cells[counter] -= 1;
+                        self.add_steps(3);
+                        self.statements(body, depth + 1);
+                        self.cells[counter] += 1;
+                        remaining -= 1;
+                        if self.cells == cells {
+                            // every further iteration takes the same steps
+                            let iteration = self.steps - steps;
+                            self.add_steps(iteration.saturating_mul(remaining));
+                            remaining = 0;
+                        }
+                        self.cells[counter] = remaining;
+                    }
+                    // the GOTOZ that leaves the loop
+                    self.add_steps(1);
+                }
+                Statement::While { .. } => unreachable!("WHILE in a LOOP program"),
+            }
+        }
+    }
+}
+
+/// The number of steps the translation of `statements` takes on `memory`,
+/// including the final STOP. Missing cells count as 0.
+pub fn step_bound(statements: &[Statement], memory: &[u64]) -> u64 {
+    let mut cells = memory.to_vec();
+    cells.resize(cells.len().max(while_lang::memory_size(statements)), 0);
+    let mut bound = Bound { cells, steps: 1, scratch: while_lang::highest_variable(statements) + 1 };
+    bound.statements(statements, 0);
+    bound.steps
+}
+
+#[test]
+fn test_step_bound_matches_run() {
+    // x0 := x1 * x2, then x1 := x1 - 2
+    let source = "x0 := x3 + 0; LOOP x1 DO LOOP x2 DO x0 := x0 + 1 END END; x1 := x1 - 2";
+    let statements = parse(source).unwrap();
+    let program = crate::parse_program(to_goto(source).unwrap()).unwrap();
+    for memory in [vec![5, 3, 4, 0, 0, 0, 0], vec![0, 1, 0, 0, 0, 0, 0], vec![2, 0, 9, 0, 0, 0, 0]].iter() {
+        let mut state = crate::GotoProgramState::new(&program, memory.clone());
+        assert_eq!(Ok(true), state.run_with_limit(10_000));
+        assert_eq!(memory[1] * memory[2], state.memory[0]);
+        assert_eq!(step_bound(&statements, memory), state.steps);
+    }
+    assert!(parse("WHILE x1 != 0 DO x1 := x1 - 1 END").is_err());
+}
@@ -0,0 +1,148 @@
+//! Lowering the jumps of the extended dialect to the classic one. NOP
+//! becomes a GOTO to the next instruction and GOTONZ a GOTOZ over a GOTO. GOTOEQ and GOTOGT copy their two cells into
+//! scratch cells, restoring the originals through a third one, and count
+//! the copies down together. The three scratch cells lie above every cell
+//! the program uses or declares, they start at 0 and every comparison
+//! leaves them at 0 again.
+
+use crate::header::Dialect;
+use crate::{ranges, GotoProgram, Instruction, RegisterIndex};
+
+const COPY_LENGTH: usize = 9;
+
+/// How many classic instructions replace the instruction.
+fn length(instruction: &Instruction) -> usize {
+    match *instruction {
+        Instruction::GotoNz { .. } => 2,
+        Instruction::GotoEq { left, right, .. } if left == right => 1,
+        Instruction::GotoGt { left, right, .. } if left == right => 0,
+        Instruction::GotoEq { .. } => 2 * COPY_LENGTH + 13,
+        Instruction::GotoGt { .. } => 2 * COPY_LENGTH + 11,
+        _ => 1,
+    }
+}
+
+/// Adds `from` to `to` through `temp` and leaves `from` as it was.
+fn copy(code: &mut Vec<Instruction>, from: RegisterIndex, to: RegisterIndex, temp: RegisterIndex) {
+    let start = code.len();
+    code.extend_from_slice(&[
+        Instruction::GotoZ { condition_cell: from, goto_cell: start + 5 },
+        Instruction::Dec { cell: from },
+        Instruction::Inc { cell: to },
+        Instruction::Inc { cell: temp },
+        Instruction::Goto { cell: start },
+        Instruction::GotoZ { condition_cell: temp, goto_cell: start + COPY_LENGTH },
+        Instruction::Dec { cell: temp },
+        Instruction::Inc { cell: from },
+        Instruction::Goto { cell: start + 5 },
+    ]);
+}
+
+/// The program with only classic instructions, which computes the same
+/// memory on the cells of the original program.
+pub fn to_classic(program: &GotoProgram) -> Result<GotoProgram, String> {
+    let count = program.instructions.len();
+    let mut starts = vec![0];
+    for instruction in &program.instructions {
+        starts.push(starts.last().unwrap() + length(instruction));
+    }
+    let total = starts[count];
+    // targets outside of the program stay outside
+    let target = |cell: usize| if cell <= count { starts[cell] } else { cell - count + total };
+    let scratch = ranges::used_cells(program).last().map_or(0, |cell| cell + 1).max(program.declared_cells());
+    let (temp, x, y) = (scratch, scratch + 1, scratch + 2);
+
+    let mut lowered = GotoProgram { instructions: vec![], source_lines: vec![], ..program.clone() };
+    let mut compares = false;
+    for (&instruction, &line) in program.instructions.iter().zip(&program.source_lines) {
+        let code = &mut lowered.instructions;
+        let start = code.len();
+        match instruction {
+            Instruction::Goto { cell } => code.push(Instruction::Goto { cell: target(cell) }),
+            Instruction::Nop => code.push(Instruction::Goto { cell: start + 1 }),
+            Instruction::GotoZ { condition_cell, goto_cell } => code.push(Instruction::GotoZ { condition_cell, goto_cell: target(goto_cell) }),
+            Instruction::GotoNz { condition_cell, goto_cell } => code.extend_from_slice(&[
+                Instruction::GotoZ { condition_cell, goto_cell: start + 2 },
+                Instruction::Goto { cell: target(goto_cell) },
+            ]),
+            Instruction::GotoEq { left, right, goto_cell } if left == right => code.push(Instruction::Goto { cell: target(goto_cell) }),
+            Instruction::GotoGt { left, right, .. } if left == right => {}
+            Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => {
+                compares = true;
+                copy(code, left, x, temp);
+                copy(code, right, y, temp);
+                let down = code.len();
+                let end = start + length(&instruction);
+                let (x_zero, y_zero) = match instruction {
+                    Instruction::GotoEq { .. } => (down + 5, down + 9),
+                    _ => (down + 5, down + 8),
+                };
+                code.extend_from_slice(&[
+                    Instruction::GotoZ { condition_cell: x, goto_cell: x_zero },
+                    Instruction::GotoZ { condition_cell: y, goto_cell: y_zero },
+                    Instruction::Dec { cell: x },
+                    Instruction::Dec { cell: y },
+                    Instruction::Goto { cell: down },
+                ]);
+                if let Instruction::GotoEq { .. } = instruction {
+                    // x is 0: equal if y is as well, otherwise clear y and fall through
+                    let jump = end - 1;
+                    code.extend_from_slice(&[
+                        Instruction::GotoZ { condition_cell: y, goto_cell: jump },
+                        Instruction::GotoZ { condition_cell: y, goto_cell: end },
+                        Instruction::Dec { cell: y },
+                        Instruction::Goto { cell: down + 6 },
+                        Instruction::GotoZ { condition_cell: x, goto_cell: end },
+                        Instruction::Dec { cell: x },
+                        Instruction::Goto { cell: down + 9 },
+                        Instruction::Goto { cell: target(goto_cell) },
+                    ]);
+                } else {
+                    // x is 0 first: not greater, clear y; y is 0 first: greater, clear x and jump
+                    code.extend_from_slice(&[
+                        Instruction::GotoZ { condition_cell: y, goto_cell: end },
+                        Instruction::Dec { cell: y },
+                        Instruction::Goto { cell: down + 5 },
+                        Instruction::GotoZ { condition_cell: x, goto_cell: target(goto_cell) },
+                        Instruction::Dec { cell: x },
+                        Instruction::Goto { cell: down + 8 },
+                    ]);
+                }
+            }
+            other if Dialect::Classic.allows(&other) => code.push(other),
+            other => return Err(format!("error in line {}: {} has no classic form", line, other)),
+        }
+        lowered.source_lines.resize(code.len(), line);
+    }
+    if compares {
+        lowered.header.memory = Some(scratch + 3);
+    }
+    lowered.header.dialect = Some(Dialect::Classic);
+    Ok(lowered)
+}
+
+#[test]
+fn test_to_classic() {
+    let program = crate::parse_program("loop: GOTOEQ 0 1 equal
+        GOTOGT 0 1 greater
+        INC 0
+        NOP
+        GOTONZ 0 loop
+        equal: INC 2
+        GOTOGT 2 2 0
+        GOTOEQ 2 2 end
+        greater: INC 3
+        end: STOP".to_string()).unwrap();
+    let lowered = to_classic(&program).unwrap();
+    assert!(lowered.instructions.iter().all(|i| Dialect::Classic.allows(i)));
+    assert_eq!(Some(7), lowered.header.memory);
+    for input in [[0, 0, 0, 0], [3, 1, 0, 0], [1, 3, 0, 0], [4, 4, 0, 0], [0, 5, 0, 0]] {
+        let mut expected = crate::GotoProgramState::new(&program, program.initial_memory(&input));
+        assert_eq!(Ok(true), expected.run_with_limit(10_000));
+        let mut state = crate::GotoProgramState::new(&lowered, lowered.initial_memory(&input));
+        assert_eq!(Ok(true), state.run_with_limit(10_000));
+        assert_eq!(expected.memory[..4], state.memory[..4]);
+        assert_eq!(vec![0; 3], state.memory[4..]);
+    }
+    assert!(to_classic(&crate::parse_program("PRINT 0".to_string()).unwrap()).is_err());
+}
@@ -0,0 +1,197 @@
+//! Macros and the bundled library of them. Lines between `.macro NAME a b`
+//! and `.endmacro` define a macro, a later `NAME 3 sum` in place of an
+//! instruction stands for the lines of the macro with `a` and `b` replaced by
+//! `3` and `sum`. Labels of a macro get a name of their own in every
+//! expansion, and macros may use the macros defined before them.
+//!
+//! An expansion stays on the line of the macro, its instructions separated
+//! by `|`, so every line keeps its number. `INCLUDE <std/arith>` defines the
+//! macros of a library from `LIBRARIES`.
+
+use std::collections::HashMap;
+
+use crate::source::{self, SourceLine};
+use crate::{BANK, MNEMONICS};
+
+/// The libraries for `INCLUDE`, with their source.
+pub const LIBRARIES: [(&str, &str); 1] = [
+    ("std/arith", include_str!("std/arith.goto")),
+];
+
+pub const DIRECTIVES: [&str; 2] = [".macro", ".endmacro"];
+
+pub const INCLUDE: &str = "INCLUDE";
+
+/// How deep macros may use other macros, deeper expansions mean a macro
+/// that uses itself.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone)]
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<SourceLine>,
+    labels: Vec<String>,
+}
+
+pub fn library(name: &str) -> Option<&'static str> {
+    LIBRARIES.iter().find(|(library, _)| *library == name).map(|&(_, text)| text)
+}
+
+struct Expander {
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+}
+
+impl Expander {
+    fn define(&mut self, tokens: &[String], lines: &[&str]) -> Result<(), String> {
+        let name = tokens.get(1).ok_or("expected .macro <name> <parameter>...")?;
+        if !source::is_label_name(name) || MNEMONICS.iter().any(|(mnemonic, _)| mnemonic == name) || name == BANK || name == INCLUDE {
+            return Err(format!("{} cannot be the name of a macro", name));
+        }
+        if self.macros.contains_key(name) {
+            return Err(format!("Duplicate macro: {}", name));
+        }
+        let parameters = tokens[2..].to_vec();
+        if let Some(parameter) = parameters.iter().enumerate().find(|(i, p)| !source::is_label_name(p) || parameters[..*i].contains(p)) {
+            return Err(format!("{} cannot be a parameter of {}", parameter.1, name));
+        }
+        let mut body = vec![];
+        for line in lines {
+            body.extend(source::parse_segments(line)?.into_iter().filter(|segment| segment.label.is_some() || !segment.tokens.is_empty()));
+        }
+        let labels = body.iter().filter_map(|segment| segment.label.clone()).collect();
+        self.macros.insert(name.clone(), Macro { parameters, body, labels });
+        Ok(())
+    }
+
+    /// The segments a segment stands for, itself unless it uses a macro.
+    fn expand(&mut self, segment: SourceLine, depth: usize, segments: &mut Vec<String>) -> Result<(), String> {
+        let definition = match segment.tokens.first().and_then(|name| self.macros.get(name)) {
+            Some(definition) => definition.clone(),
+            None => {
+                let label = segment.label.map(|label| format!("{}: ", label)).unwrap_or_default();
+                segments.push(format!("{}{}", label, segment.tokens.join(" ")).trim_end().to_string());
+                return Ok(());
+            }
+        };
+        let name = &segment.tokens[0];
+        if depth == MAX_DEPTH {
+            return Err(format!("{} expands to itself", name));
+        }
+        let arguments = &segment.tokens[1..];
+        if arguments.len() != definition.parameters.len() {
+            return Err(format!("{} takes {} operands, found {}", name, definition.parameters.len(), arguments.len()));
+        }
+        if let Some(label) = segment.label {
+            segments.push(format!("{}:", label));
+        }
+        self.expansions += 1;
+        let prefix = format!("__{}{}_", name, self.expansions);
+        let rename = |token: &String| match definition.parameters.iter().position(|parameter| parameter == token) {
+            Some(index) => arguments[index].clone(),
+            None if definition.labels.contains(token) => format!("{}{}", prefix, token),
+            None => token.clone(),
+        };
+        for line in &definition.body {
+            let expanded = SourceLine { label: line.label.as_ref().map(|label| format!("{}{}", prefix, label)), tokens: line.tokens.iter().map(rename).collect(), comment: None };
+            self.expand(expanded, depth + 1, segments)?;
+        }
+        Ok(())
+    }
+
+    /// The text with the definitions blanked out and the macros expanded.
+    fn process(&mut self, text: &str) -> Result<String, String> {
+        let mut lines = vec![];
+        let all: Vec<&str> = text.lines().collect();
+        let mut line_nr = 0;
+        while line_nr < all.len() {
+            let line = all[line_nr];
+            let error = |e: String| format!("error in line {}: {}", line_nr + 1, e);
+            let segments = source::parse_segments(line).map_err(error)?;
+            let tokens = match segments.as_slice() {
+                [segment] if segment.label.is_none() => segment.tokens.clone(),
+                _ => vec![],
+            };
+            match tokens.first().map(String::as_str) {
+                Some(".macro") => {
+                    let end = (line_nr + 1..all.len()).find(|&end| source::parse_line(all[end]).is_ok_and(|line| line.tokens.first().is_some_and(|token| token == ".endmacro")))
+                        .ok_or_else(|| error(".macro without .endmacro".to_string()))?;
+                    self.define(&tokens, &all[line_nr + 1..end]).map_err(error)?;
+                    lines.extend(std::iter::repeat_n(String::new(), end - line_nr + 1));
+                    line_nr = end + 1;
+                    continue;
+                }
+                Some(".endmacro") => return Err(error(".endmacro without .macro".to_string())),
+                Some(INCLUDE) => {
+                    let name = match tokens.as_slice() {
+                        [_, name] if name.starts_with('<') && name.ends_with('>') => &name[1..name.len() - 1],
+                        _ => return Err(error(format!("expected {} <library> in: {}", INCLUDE, tokens.join(" ")))),
+                    };
+                    let library = library(name).ok_or_else(|| error(format!("Unknown library: {}, expected one of {}", name,
+                        LIBRARIES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", "))))?;
+                    self.process(library).map_err(|e| error(format!("in {}: {}", name, e)))?;
+                    lines.push(String::new());
+                }
+                _ if segments.iter().any(|segment| segment.tokens.first().is_some_and(|token| self.macros.contains_key(token))) => {
+                    let comment = segments.last().and_then(|segment| segment.comment.clone());
+                    let mut expanded = vec![];
+                    for segment in segments {
+                        self.expand(segment, 0, &mut expanded).map_err(error)?;
+                    }
+                    let code = expanded.join(&format!(" {} ", source::SEPARATOR));
+                    lines.push(match comment {
+                        Some(comment) => format!("{} {}", code, comment),
+                        None => code,
+                    });
+                }
+                _ => lines.push(line.to_string()),
+            }
+            line_nr += 1;
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Expands the macros of the text, see the module documentation.
+pub fn expand(text: &str) -> Result<String, String> {
+    Expander { macros: HashMap::new(), expansions: 0 }.process(text)
+}
+
+#[test]
+fn test_macros() {
+    let text = ".macro TWICE a\nINC a\nINC a\n.endmacro\n.macro DRAIN a b ; a into b\nloop: GOTOZ a done\nDEC a | TWICE b\nGOTO loop\ndone:\n.endmacro\nstart: DRAIN 0 1 ; doubles\nSTOP";
+    assert_eq!(Ok("\n\n\n\n\n\n\n\n\n\nstart: | __DRAIN1_loop: GOTOZ 0 __DRAIN1_done | DEC 0 | INC 1 | INC 1 | GOTO __DRAIN1_loop | __DRAIN1_done: ; doubles\nSTOP".to_string()), expand(text));
+    let program = crate::parse_program(text.to_string()).unwrap();
+    let mut state = crate::GotoProgramState::new(&program, vec![3, 0]);
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    assert_eq!(vec![0, 6], state.memory);
+
+    assert_eq!(Err("error in line 3: TWICE takes 1 operands, found 2".to_string()), expand(".macro TWICE a\n.endmacro\nTWICE 1 2"));
+    assert_eq!(Err("error in line 4: LOOP expands to itself".to_string()), expand(".macro LOOP\nLOOP\n.endmacro\nLOOP"));
+    assert!(expand(".macro INC a\n.endmacro").is_err());
+    assert!(expand(".macro A\n").is_err());
+    assert!(expand("INCLUDE <std/none>").is_err());
+}
+
+#[test]
+fn test_std_arith() {
+    let run = |call: &str, memory: Vec<u64>| {
+        let program = crate::parse_program(format!("INCLUDE <std/arith>\n.dialect classic\n{}\nSTOP", call)).unwrap();
+        let mut state = crate::GotoProgramState::new(&program, memory);
+        assert_eq!(Ok(true), state.run_with_limit(100_000));
+        state.memory
+    };
+    assert_eq!(vec![7, 0, 7, 0], run("COPY 0 2 3", vec![7, 0, 5, 0]));
+    assert_eq!(vec![7, 5, 12, 0], run("ADD 0 1 2 3", vec![7, 5, 1, 0]));
+    assert_eq!(vec![7, 5, 2, 0], run("SUB 0 1 2 3", vec![7, 5, 1, 0]));
+    assert_eq!(vec![5, 7, 0, 0], run("SUB 0 1 2 3", vec![5, 7, 1, 0]));
+    assert_eq!(vec![7, 5, 35, 0, 0], run("MUL 0 1 2 3 4", vec![7, 5, 1, 0, 0]));
+    assert_eq!(vec![17, 5, 3, 2, 0, 0], run("DIV 0 1 2 3 4 5", vec![17, 5, 0, 0, 0, 0]));
+    assert_eq!(vec![15, 5, 3, 0, 0, 0], run("DIV 0 1 2 3 4 5", vec![15, 5, 9, 9, 0, 0]));
+    assert_eq!(vec![4, 0, 0, 4, 0, 0], run("DIV 0 1 2 3 4 5", vec![4, 0, 0, 0, 0, 0]));
+    for (a, b) in [(3, 5), (5, 3), (4, 4), (0, 0)] {
+        assert_eq!(vec![a, b, (a > b) as u64, 0, 0], run("GREATER 0 1 2 3 4", vec![a, b, 7, 0, 0]));
+        assert_eq!(vec![a, b, (a == b) as u64, 0, 0], run("EQUAL 0 1 2 3 4", vec![a, b, 7, 0, 0]));
+        assert_eq!(vec![a, b, a.max(b), 0, 0], run("MAX 0 1 2 3 4", vec![a, b, 7, 0, 0]));
+    }
+}
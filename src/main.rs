@@ -1,196 +1,2296 @@
 extern crate clap;
 
-use std::convert::TryFrom;
-use std::fs::{read_to_string};
-
-use clap::{App, Arg};
-
-type RegisterIndex = usize;
-
-#[allow(dead_code)]
-#[derive(Debug, Eq, PartialEq)]
-enum Instruction {
-    Stop,
-    Inc { cell: RegisterIndex },
-    Dec { cell: RegisterIndex },
-    Goto { cell: RegisterIndex },
-    GotoZ { condition_cell: RegisterIndex, goto_cell: RegisterIndex },
-}
-
-fn parse_nr(text: &str) -> Result<RegisterIndex, String> {
-    text.parse::<RegisterIndex>()
-        .map_err(|e| format!("{} is not a number (reason: {:?})", text, e))
-}
-
-impl TryFrom<String> for Instruction {
-    type Error = String;
-
-    fn try_from(value: String) -> Result<Instruction, Self::Error> {
-        let tokens: Vec<_> = value.split(" ").filter(|t| t.len() > 0).collect();
-        if tokens.len() > 0 {
-            let instruction_token = tokens[0];
-            match instruction_token {
-                "STOP" => Result::Ok(Instruction::Stop),
-                "INC" | "DEC" | "GOTO" => {
-                    if tokens.len() == 2 {
-                        let cell = parse_nr(tokens[1])?;
-                        Result::Ok(match instruction_token {
-                            "INC" => Instruction::Inc { cell },
-                            "DEC" => Instruction::Dec { cell },
-                            "GOTO" => Instruction::Goto { cell },
-                            _ => panic!("this should not happen")
-                        })
-                    } else {
-                        Result::Err(format!("Not 2 tokens in: {}", value))
-                    }
-                }
-                "GOTOZ" => {
-                    if tokens.len() == 3 {
-                        let condition_cell = parse_nr(tokens[1])?;
-                        let goto_cell = parse_nr(tokens[2])?;
-                        Result::Ok(Instruction::GotoZ { condition_cell, goto_cell })
-                    } else {
-                        Result::Err(format!("Not 3 tokens in: {}", value))
-                    }
-                }
-                _ => Result::Err(format!("Unknown token: {}", tokens[0]))
+mod animation;
+mod bench;
+mod color;
+mod dap;
+mod debugger;
+mod jupyter;
+mod line_editor;
+mod manpage;
+mod progress;
+mod repl;
+mod rpc;
+mod serve;
+mod signals;
+mod tui;
+mod watch;
+
+use std::fs::{read_to_string, remove_file, write};
+use std::io::{Read, Write};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
+
+use goto::bytecode::Bytecode;
+use goto::checkpoint::{self, Checkpointer};
+use goto::condition::Condition;
+use goto::header::Dialect;
+use goto::json::Value;
+use goto::coverage::Coverage;
+use goto::cycle::CycleDetector;
+use goto::disasm::DisasmOptions;
+use goto::footprint::Footprint;
+use goto::fingerprint::Fingerprint;
+use goto::golden::{GoldenTrace, TraceEvent};
+use goto::grading::{self, GradingPolicy, TestSpec};
+use goto::profile::Profile;
+use goto::random::Random;
+use goto::report::Report;
+use goto::snapshot::{self, Snapshot, Status};
+use goto::stall::StallDetector;
+use goto::stats::Stats;
+use goto::error::Context;
+use goto::{banks, batch, brainfuck, complexity, contract, difftest, disasm, doc, equiv, formatter, fuzz, gbc, godel, grammar, inputs, layout, link, lint, loop_lang, lower, minimize, mutate, optimize, parse_program_errors, pipeline, parse_program_with, preprocess, ranges, read_input, recursive, sandbox, sourcemap, specialize, suite, superopt, termination, trace_diff, transpile, turing, urm, verify, while_lang, Error, GotoProgram, GotoProgramState, ParseOptions, RegisterIndex, RuntimeError};
+
+use crate::animation::Animation;
+use crate::color::Palette;
+use crate::progress::Progress;
+
+// what shells report for a process killed by SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+// how many steps run between looking for clients on the status socket
+const STATUS_SOCKET_INTERVAL: u64 = 10_000;
+// languages that can be translated to goto, `goto` itself first
+const LANGUAGES: [&str; 7] = ["goto", "brainfuck", "while", "loop", "urm", "turing", "recursive"];
+
+fn cli() -> App<'static, 'static> {
+    App::new("goto")
+        .version("1.0")
+        .about("Run a goto program")
+        .after_help("EXIT CODES:\n    1  a check failed, like lint warnings or a counterexample\n    2  the program does not parse\n    3  the run failed\n    4  a file cannot be read or written\n    5  a flag or an input is invalid\n  130  interrupted")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(Arg::with_name("source file")
+            .short("s")
+            .long("source")
+            .takes_value(true)
+            .required(true)
+            .help("the goto program source file or a .gbc file from goto compile --target bytecode"))
+        .arg(Arg::with_name("language")
+            .long("language")
+            .takes_value(true)
+            .possible_values(&LANGUAGES)
+            .default_value("goto")
+            .conflicts_with("watch")
+            .help("the language of the source file, other languages are translated to goto before running"))
+        .arg(Arg::with_name("define")
+            .short("D")
+            .long("define")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .conflicts_with("watch")
+            .help("a name for .ifdef blocks, which are left out unless their name is defined"))
+        .arg(Arg::with_name("dialect")
+            .long("dialect")
+            .takes_value(true)
+            .possible_values(&Dialect::NAMES)
+            .help("classic allows only STOP, INC, DEC, GOTO and GOTOZ, extended every instruction, the default unless the program has a .dialect"))
+        .arg(Arg::with_name("nop lines")
+            .long("nop-lines")
+            .conflicts_with("watch")
+            .help("turn every line without an instruction into a NOP, so instruction n is on line n + 1"))
+        .arg(Arg::with_name("step bound")
+            .long("step-bound")
+            .conflicts_with("resume")
+            .help("for LOOP programs, print how many steps the run takes on the input before running it"))
+        .arg(Arg::with_name("optimize")
+            .long("optimize")
+            .conflicts_with("resume")
+            .help("thread jumps, remove jumps to the next instruction and INC/DEC pairs that cancel out and fold INC/DEC into a STOREI right before them, before running"))
+        .arg(Arg::with_name("fold input")
+            .long("fold-input")
+            .conflicts_with("resume")
+            .help("run the start of the program up to its first loop on the input ahead of time and drop the instructions that cannot run afterwards"))
+        .arg(Arg::with_name("input")
+            .short("i")
+            .long("input")
+            .takes_value(true)
+            .conflicts_with("resume")
+            .help("the memory on which to goto program works, its cells replace the ones the program declares with .data and .init"))
+        .arg(Arg::with_name("bank input")
+            .long("bank-input")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .conflicts_with_all(&["resume", "inputs", "watch"])
+            .help("the memory of a bank of a program with BANK instructions, like 1=scratch.txt, --input is bank 0"))
+        .arg(Arg::with_name("resume")
+            .long("resume")
+            .takes_value(true)
+            .help("continue the run saved in this snapshot, or the newest checkpoint in this directory, instead of starting from an input"))
+        .arg(Arg::with_name("snapshot on exit")
+            .long("snapshot-on-exit")
+            .takes_value(true)
+            .help("save the state of the machine to this file when the run ends or is interrupted, to continue it with --resume"))
+        .arg(Arg::with_name("checkpoint every")
+            .long("checkpoint-every")
+            .takes_value(true)
+            .requires("checkpoint dir")
+            .help("save the state of the machine every N steps"))
+        .arg(Arg::with_name("checkpoint dir")
+            .long("checkpoint-dir")
+            .takes_value(true)
+            .requires("checkpoint every")
+            .help("the directory for checkpoints, resume from it with --resume"))
+        .arg(Arg::with_name("checkpoint keep")
+            .long("checkpoint-keep")
+            .takes_value(true)
+            .default_value("2")
+            .help("how many of the newest checkpoints are kept"))
+        .arg(Arg::with_name("status socket")
+            .long("status-socket")
+            .takes_value(true)
+            .help("report the progress of the run to every client that connects to this unix socket"))
+        .arg(Arg::with_name("watch")
+            .long("watch")
+            .conflicts_with("resume")
+            .requires("input")
+            .help("run the program again whenever the source or the input file changes"))
+        .arg(Arg::with_name("watch max steps")
+            .long("watch-max-steps")
+            .takes_value(true)
+            .default_value("1000000")
+            .help("give up on a run in watch mode after this many steps"))
+        .arg(Arg::with_name("inputs")
+            .long("inputs")
+            .takes_value(true)
+            .conflicts_with_all(&["input", "resume", "watch"])
+            .help("run the program on every input file in this directory and print a summary table"))
+        .arg(Arg::with_name("output dir")
+            .long("output-dir")
+            .takes_value(true)
+            .requires("inputs")
+            .help("write the final state of every run of --inputs as JSON to a file named after the input in this directory"))
+        .arg(Arg::with_name("batch max steps")
+            .long("batch-max-steps")
+            .takes_value(true)
+            .default_value("1000000")
+            .help("give up on a run of --inputs after this many steps"))
+        .arg(Arg::with_name("jobs")
+            .long("jobs")
+            .takes_value(true)
+            .requires("inputs")
+            .help("how many threads run the inputs of --inputs, the number of processors if missing"))
+        .arg(Arg::with_name("step delay")
+            .long("step-delay")
+            .takes_value(true)
+            .help("animate the run on a single line, waiting this long between steps, like 300ms or 1s"))
+        .arg(Arg::with_name("paused")
+            .long("paused")
+            .help("animate the run on a single line, waiting for Enter before each step"))
+        .arg(Arg::with_name("color")
+            .long("color")
+            .takes_value(true)
+            .possible_values(&["auto", "always", "never"])
+            .default_value("auto")
+            .help("highlight instructions, changed cells and errors, auto colors terminals unless NO_COLOR is set"))
+        .arg(Arg::with_name("von neumann")
+            .long("von-neumann")
+            .takes_value(true)
+            .value_name("code base")
+            .conflicts_with_all(&["fast", "resume", "inputs", "watch", "fold input", "optimize"])
+            .help("put the program into the memory from this cell on and decode every instruction from there, so that the program can rewrite itself"))
+        .arg(Arg::with_name("fast")
+            .long("fast")
+            .conflicts_with_all(&["step delay", "paused", "visualize", "stall detector", "detect cycles", "checkpoint every"])
+            .help("run compiled bytecode without printing a trace"))
+        .arg(Arg::with_name("jit")
+            .long("jit")
+            .requires("fast")
+            .help("translate the program to native code for --fast, needs the jit feature"))
+        .arg(Arg::with_name("profile")
+            .long("profile")
+            .conflicts_with("fast")
+            .help("count how often every source line runs and time every opcode, print a report after the run"))
+        .arg(Arg::with_name("coverage")
+            .long("coverage")
+            .conflicts_with("fast")
+            .help("print which instructions ran and the source with the lines that did not marked after the run"))
+        .arg(Arg::with_name("progress")
+            .long("progress")
+            .help("show the steps executed and steps per second on stderr while the program runs"))
+        .arg(Arg::with_name("visualize")
+            .long("visualize")
+            .help("draw the memory as a bar chart that is redrawn in place before every step"))
+        .arg(Arg::with_name("trace")
+            .long("trace")
+            .takes_value(true)
+            .possible_values(&["full", "diff", "none"])
+            .default_value("full")
+            .help("print the whole memory before every step, only the cells each step changed, or nothing"))
+        .arg(Arg::with_name("output encoding")
+            .long("output-encoding")
+            .takes_value(true)
+            .possible_values(&goto::console::Encoding::NAMES)
+            .default_value("utf-8")
+            .help("how OUTC writes the value of a cell as a character"))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .takes_value(true)
+            .default_value("0")
+            .help("the seed of the numbers RAND picks, runs with the same seed pick the same numbers"))
+        .arg(Arg::with_name("interactive")
+            .long("interactive")
+            .conflicts_with_all(&["fast", "inputs"])
+            .help("ask on the terminal for the value of every cell the program uses beyond the input instead of failing"))
+        .arg(Arg::with_name("save input")
+            .long("save-input")
+            .takes_value(true)
+            .requires("interactive")
+            .help("write the input with the answers of --interactive to this file, to run the program again on it"))
+        .arg(Arg::with_name("output cells")
+            .long("output-cells")
+            .takes_value(true)
+            .conflicts_with("watch")
+            .help("the cells that are the result of the program, like 0,2 or their aliases, printed and exported instead of the whole memory"))
+        .arg(Arg::with_name("exit from cell")
+            .long("exit-from-cell")
+            .takes_value(true)
+            .help("exit with the value of this cell when the program stops, 255 for larger values, for use in scripts"))
+        .arg(Arg::with_name("record trace")
+            .long("record-trace")
+            .takes_value(true)
+            .conflicts_with_all(&["fast", "resume"])
+            .help("write every step as a line of JSON to this file, to be used as a golden trace"))
+        .arg(Arg::with_name("expect trace")
+            .long("expect-trace")
+            .takes_value(true)
+            .conflicts_with_all(&["fast", "resume"])
+            .help("compare every step with this golden trace from --record-trace and fail on the first difference"))
+        .arg(Arg::with_name("fingerprint")
+            .long("fingerprint")
+            .conflicts_with_all(&["fast", "resume", "inputs", "watch"])
+            .help("print a hash of the steps and writes of the run with the result, equal for runs that were bit-for-bit identical"))
+        .arg(Arg::with_name("report")
+            .long("report")
+            .takes_value(true)
+            .conflicts_with_all(&["fast", "inputs", "watch"])
+            .help("write an HTML page with the listing, how often every line ran and the memory after every step to this file"))
+        .arg(Arg::with_name("export state")
+            .long("export-state")
+            .takes_value(true)
+            .help("write the final state of the machine as JSON to this file"))
+        .arg(Arg::with_name("detect cycles")
+            .long("detect-cycles")
+            .help("stop with an error when the machine returns to a state it was in before, since it will then never stop"))
+        .arg(Arg::with_name("stall detector")
+            .long("stall-detector")
+            .help("warn when execution stays within a few instructions without changing the memory"))
+        .arg(Arg::with_name("stall window")
+            .long("stall-window")
+            .takes_value(true)
+            .default_value("100000")
+            .help("the number of steps the stall detector looks at"))
+        .arg(Arg::with_name("stall pcs")
+            .long("stall-pcs")
+            .takes_value(true)
+            .default_value("8")
+            .help("the largest number of distinct instructions that still counts as stuck"))
+        .arg(Arg::with_name("stall action")
+            .long("stall-action")
+            .takes_value(true)
+            .possible_values(&["warn", "stop"])
+            .default_value("warn")
+            .help("whether a detected stall only prints a warning or stops the run"))
+        .subcommand(SubCommand::with_name("fmt")
+            .about("Rewrite goto source files into canonical form")
+            .arg(Arg::with_name("check")
+                .long("check")
+                .help("only check the formatting, exit with 1 if a file would change"))
+            .arg(Arg::with_name("files")
+                .multiple(true)
+                .required(true)
+                .help("the goto source files to format")))
+        .subcommand(SubCommand::with_name("lint")
+            .about("Report unreachable code, unused cells and other suspicious constructs")
+            .arg(Arg::with_name("allow")
+                .short("A")
+                .long("allow")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .possible_values(&["W001", "W002", "W003", "W004"])
+                .help("suppress warnings with this code"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file")))
+        .subcommand(SubCommand::with_name("analyze")
+            .about("Check properties of a program without running it, exits with 1 when one could not be shown")
+            .arg(Arg::with_name("termination")
+                .long("termination")
+                .required_unless_one(&["ranges", "memory"])
+                .help("try to prove that the program stops on every input"))
+            .arg(Arg::with_name("memory")
+                .long("memory")
+                .help("print as JSON how many cells the memory needs and which cells are inputs, outputs, scratch cells that end as 0 and data"))
+            .arg(Arg::with_name("ranges")
+                .long("ranges")
+                .help("print the range of every cell before each instruction and warn about DECs that always fail and GOTOZ branches that are never taken"))
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .requires("ranges")
+                .help("the memory to compute ranges for, all inputs if missing"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("stats")
+            .about("Count the instructions by opcode, jump targets, basic blocks, loops and their nesting and the cells of programs")
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("print a JSON object per program"))
+            .arg(Arg::with_name("source files")
+                .multiple(true)
+                .required(true)
+                .help("the goto program source files or .gbc files")))
+        .subcommand(SubCommand::with_name("verify")
+            .about("Check what a program reaches or its contract within a step bound on every input in a range, exits with 1 on a counterexample")
+            .arg(Arg::with_name("reaches")
+                .long("reaches")
+                .takes_value(true)
+                .required_unless_one(&["never reaches", "contract"])
+                .conflicts_with_all(&["never reaches", "contract"])
+                .help("the line of an instruction or stop, that every input has to reach"))
+            .arg(Arg::with_name("never reaches")
+                .long("never-reaches")
+                .takes_value(true)
+                .conflicts_with("contract")
+                .help("the line of an instruction or stop, that no input may reach"))
+            .arg(Arg::with_name("contract")
+                .long("contract")
+                .help("check the ;@ requires and ;@ ensures annotations of the program on every input"))
+            .arg(Arg::with_name("within")
+                .long("within")
+                .takes_value(true)
+                .default_value("100000")
+                .help("how many steps every run may take"))
+            .arg(Arg::with_name("cells")
+                .long("cells")
+                .takes_value(true)
+                .default_value("0..1")
+                .help("the input cells, like 0..3 for cells 0, 1 and 2, the other cells start at 0"))
+            .arg(Arg::with_name("range")
+                .long("range")
+                .takes_value(true)
+                .required(true)
+                .help("the values every input cell takes, like 0..50 for 0 to 49"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("equiv")
+            .about("Check that two programs compute the same outputs on every input in a range, exits with 1 if they differ")
+            .arg(Arg::with_name("outputs")
+                .long("outputs")
+                .takes_value(true)
+                .help("the output cells to compare, like 1..2, the whole memory if missing"))
+            .arg(Arg::with_name("within")
+                .long("within")
+                .takes_value(true)
+                .default_value("100000")
+                .help("how many steps every run may take"))
+            .arg(Arg::with_name("cells")
+                .long("cells")
+                .takes_value(true)
+                .default_value("0..1")
+                .help("the input cells, like 0..3 for cells 0, 1 and 2, the other cells start at 0"))
+            .arg(Arg::with_name("range")
+                .long("range")
+                .takes_value(true)
+                .required(true)
+                .help("the values every input cell takes, like 0..50 for 0 to 49"))
+            .arg(Arg::with_name("samples")
+                .long("samples")
+                .takes_value(true)
+                .help("compare this many random inputs instead of all of them"))
+            .arg(Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .default_value("1")
+                .help("the seed of the random inputs for --samples"))
+            .arg(Arg::with_name("jobs")
+                .long("jobs")
+                .takes_value(true)
+                .help("how many threads run the programs, the number of processors if missing"))
+            .arg(Arg::with_name("first")
+                .required(true)
+                .help("the first goto program source file or .gbc file"))
+            .arg(Arg::with_name("second")
+                .required(true)
+                .help("the second goto program source file or .gbc file")))
+        .subcommand(SubCommand::with_name("difftest")
+            .about("Check that the bytecode VM, the JIT and the program compiled from C end like the interpreter on every input in a range, exits with 1 if one differs")
+            .arg(Arg::with_name("within")
+                .long("within")
+                .takes_value(true)
+                .default_value("100000")
+                .help("how many steps the interpreter may take, inputs it does not finish are skipped"))
+            .arg(Arg::with_name("cells")
+                .long("cells")
+                .takes_value(true)
+                .default_value("0..1")
+                .help("the input cells, like 0..3 for cells 0, 1 and 2, the other cells start at 0"))
+            .arg(Arg::with_name("range")
+                .long("range")
+                .takes_value(true)
+                .required(true)
+                .help("the values every input cell takes, like 0..50 for 0 to 49"))
+            .arg(Arg::with_name("samples")
+                .long("samples")
+                .takes_value(true)
+                .help("check this many random inputs instead of all of them"))
+            .arg(Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .default_value("1")
+                .help("the seed of the random inputs for --samples"))
+            .arg(Arg::with_name("compiler")
+                .long("compiler")
+                .takes_value(true)
+                .default_value("cc")
+                .help("the C compiler for the C backend"))
+            .arg(Arg::with_name("no c")
+                .long("no-c")
+                .help("leave out the C backend"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("fuzz")
+            .about("Run a program on random inputs and report failed runs, runs that do not stop and output statistics, exits with 1 if a run failed")
+            .arg(Arg::with_name("cells")
+                .long("cells")
+                .takes_value(true)
+                .default_value("1")
+                .help("how many input cells get a random value, starting at cell 0, the other cells start at 0"))
+            .arg(Arg::with_name("max")
+                .long("max")
+                .takes_value(true)
+                .default_value("100")
+                .help("the largest value of an input cell"))
+            .arg(Arg::with_name("runs")
+                .long("runs")
+                .takes_value(true)
+                .default_value("1000")
+                .help("how many random inputs to run"))
+            .arg(Arg::with_name("within")
+                .long("within")
+                .takes_value(true)
+                .default_value("100000")
+                .help("how many steps a run may take before it counts as possibly not stopping"))
+            .arg(Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .default_value("1")
+                .help("the seed of the random inputs"))
+            .arg(Arg::with_name("jobs")
+                .long("jobs")
+                .takes_value(true)
+                .help("how many threads run the program, the number of processors if missing"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("dap")
+            .about("Serve the Debug Adapter Protocol on stdin and stdout"))
+        .subcommand(SubCommand::with_name("jupyter-kernel")
+            .about("Run as a Jupyter kernel that executes every cell as a program")
+            .arg(Arg::with_name("install")
+                .long("install")
+                .help("register the kernel with Jupyter instead"))
+            .arg(Arg::with_name("connection file")
+                .required_unless("install")
+                .help("the connection file Jupyter starts the kernel with")))
+        .subcommand(SubCommand::with_name("rpc")
+            .about("Serve JSON-RPC on stdin and stdout with the methods parse, reset, step, run and exit"))
+        .subcommand(limit_args(SubCommand::with_name("serve"))
+            .about("Serve a JSON API over HTTP that runs (POST /run) and checks (POST /check) programs under limits")
+            .arg(Arg::with_name("port")
+                .long("port")
+                .takes_value(true)
+                .default_value("8080")
+                .help("the TCP port to listen on"))
+            .arg(Arg::with_name("host")
+                .long("host")
+                .takes_value(true)
+                .default_value("127.0.0.1")
+                .help("the address to listen on, 0.0.0.0 for every interface")))
+        .subcommand(SubCommand::with_name("grade")
+            .about("Run a program against a test specification and score the results")
+            .arg(Arg::with_name("policy")
+                .long("policy")
+                .takes_value(true)
+                .possible_values(&["all-or-nothing", "partial", "efficiency"])
+                .default_value("partial")
+                .help("how the case results are turned into a score"))
+            .arg(Arg::with_name("reference steps")
+                .long("reference-steps")
+                .takes_value(true)
+                .help("step count that earns full efficiency points"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file"))
+            .arg(Arg::with_name("spec")
+                .required(true)
+                .help("the test specification")))
+        .subcommand(SubCommand::with_name("mutate")
+            .about("Run the cases of a test specification against small changes to a program and report the changes no case notices")
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file"))
+            .arg(Arg::with_name("spec")
+                .required(true)
+                .help("the test specification, which the program has to pass")))
+        .subcommand(SubCommand::with_name("test")
+            .about("Run every program that has a .test file next to it against its cases, exits with 1 if a case fails")
+            .arg(Arg::with_name("paths")
+                .multiple(true)
+                .default_value(".")
+                .help("the programs or directories to search for programs")))
+        .subcommand(SubCommand::with_name("superopt")
+            .about("Search for the shortest program that passes a test specification")
+            .arg(Arg::with_name("max length")
+                .long("max-length")
+                .takes_value(true)
+                .default_value("5")
+                .help("the longest program to try"))
+            .arg(Arg::with_name("cells")
+                .long("cells")
+                .takes_value(true)
+                .help("how many cells the programs may use, the cells of the specification if missing"))
+            .arg(Arg::with_name("within")
+                .long("within")
+                .takes_value(true)
+                .default_value("1000")
+                .help("how many steps a program may take on a case"))
+            .arg(Arg::with_name("jobs")
+                .long("jobs")
+                .takes_value(true)
+                .help("how many threads search, the number of processors if missing"))
+            .arg(Arg::with_name("stochastic")
+                .long("stochastic")
+                .takes_value(true)
+                .help("try this many random programs of each length instead of all of them"))
+            .arg(Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .default_value("1")
+                .help("the seed of the random programs for --stochastic"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the goto source, defaults to stdout"))
+            .arg(Arg::with_name("spec")
+                .required(true)
+                .help("the test specification")))
+        .subcommand(SubCommand::with_name("state-diff")
+            .about("Compare two states exported with --export-state")
+            .arg(Arg::with_name("first")
+                .required(true)
+                .help("the first exported state"))
+            .arg(Arg::with_name("second")
+                .required(true)
+                .help("the second exported state")))
+        .subcommand(SubCommand::with_name("pipeline")
+            .about("Run programs one after another, the final memory of each is the input of the next, exits with 1 when a stage does not stop")
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .help("the memory of the first program"))
+            .arg(Arg::with_name("map")
+                .long("map")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("the cells the memory of a stage passes on, like 2:3=0,sum=1 for cell 3 and sum of stage 1 as cells 0 and 1 of stage 2, the others are 0"))
+            .arg(Arg::with_name("max steps")
+                .long("max-steps")
+                .takes_value(true)
+                .default_value("1000000")
+                .help("give up on a stage after this many steps"))
+            .arg(Arg::with_name("source files")
+                .multiple(true)
+                .required(true)
+                .help("the goto programs in the order they run")))
+        .subcommand(SubCommand::with_name("trace-diff")
+            .about("Compare two traces written with --record-trace and show where they diverge, exits with 1 when they do")
+            .arg(Arg::with_name("align")
+                .long("align")
+                .takes_value(true)
+                .possible_values(&trace_diff::Align::NAMES)
+                .default_value("steps")
+                .help("compare step by step, or only the memory writes for programs that jump differently, like a program and its optimized version"))
+            .arg(Arg::with_name("context")
+                .short("C")
+                .long("context")
+                .takes_value(true)
+                .default_value("3")
+                .help("how many events to show before and after the divergence"))
+            .arg(Arg::with_name("first")
+                .required(true)
+                .help("the first trace"))
+            .arg(Arg::with_name("second")
+                .required(true)
+                .help("the second trace")))
+        .subcommand(SubCommand::with_name("debug")
+            .about("Debug a program with breakpoints from an interactive prompt")
+            .arg(Arg::with_name("history size")
+                .long("history-size")
+                .takes_value(true)
+                .default_value("100000")
+                .help("how many steps step-back can undo"))
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .help("the memory on which to goto program works"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("tui")
+            .about("Step through a program in a full-screen terminal debugger")
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .help("the memory on which to goto program works"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("compile")
+            .about("Translate a program into source code for another language")
+            .arg(Arg::with_name("target")
+                .long("target")
+                .takes_value(true)
+                .possible_values(&["c", "rust", "wasm", "llvm-ir", "brainfuck", "urm", "bytecode"])
+                .default_value("c")
+                .help("the language to translate to"))
+            .arg(Arg::with_name("define")
+                .short("D")
+                .long("define")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("a name for .ifdef blocks, which are left out unless their name is defined"))
+            .arg(Arg::with_name("dialect")
+                .long("dialect")
+                .takes_value(true)
+                .possible_values(&Dialect::NAMES)
+                .help("classic lowers NOP, GOTONZ, GOTOEQ and GOTOGT to STOP, INC, DEC, GOTO and GOTOZ before translating, comparisons need three more cells of memory"))
+            .arg(Arg::with_name("nop lines")
+                .long("nop-lines")
+                .help("turn every line without an instruction into a NOP, so instruction n is on line n + 1"))
+            .arg(Arg::with_name("arithmetic")
+                .long("arithmetic")
+                .takes_value(true)
+                .possible_values(&transpile::Arithmetic::NAMES)
+                .default_value("checked")
+                .help("whether INC overflowing and DEC on 0 stop with an error like the interpreter, wrap around or saturate"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the translation, defaults to stdout"))
+            .arg(Arg::with_name("minimize memory")
+                .long("minimize-memory")
+                .help("renumber the used cells to 0, 1, 2, ... and drop the others, the translation then takes and prints only the used cells in their order"))
+            .arg(Arg::with_name("source map")
+                .long("source-map")
+                .takes_value(true)
+                .help("also write a source map from the translation to the source lines, for every target but brainfuck and urm"))
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .help("bake this memory into the translation instead of reading it from the command line"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file")))
+        .subcommand(SubCommand::with_name("symbolize")
+            .about("Point the errors of a compiled program at the lines of its source with the map of goto compile --source-map")
+            .arg(Arg::with_name("source map")
+                .long("source-map")
+                .takes_value(true)
+                .required(true)
+                .help("the source map of the compiled program"))
+            .arg(Arg::with_name("pc")
+                .long("pc")
+                .takes_value(true)
+                .conflicts_with("file")
+                .help("only print the source and line of the instruction at this program counter, like a WebAssembly host reports it"))
+            .arg(Arg::with_name("file")
+                .help("what the compiled program wrote to stderr, defaults to stdin")))
+        .subcommand(SubCommand::with_name("import")
+            .about("Translate a program in another language into goto source")
+            .arg(Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .possible_values(&LANGUAGES[1..])
+                .required(true)
+                .help("the language of the program"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the goto source, defaults to stdout"))
+            .arg(Arg::with_name("file")
+                .required(true)
+                .help("the program to translate")))
+        .subcommand(SubCommand::with_name("grammar")
+            .about("Generate a syntax highlighting grammar for editors from the tokens the parser accepts")
+            .arg(Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&grammar::Format::NAMES)
+                .required(true)
+                .help("a TextMate grammar as JSON or the grammar.js of a tree-sitter parser"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the grammar, defaults to stdout")))
+        .subcommand(SubCommand::with_name("doc")
+            .about("Render the ;; doc comments of programs and libraries with their @param and @returns cells per routine")
+            .arg(Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&doc::Format::NAMES)
+                .default_value("markdown")
+                .help("Markdown, or one HTML page for all files"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the documentation, defaults to stdout"))
+            .arg(Arg::with_name("files")
+                .multiple(true)
+                .required(true)
+                .help("the programs and libraries to document, bundled libraries like <std/arith> by their name")))
+        .subcommand(SubCommand::with_name("link")
+            .about("Link modules with .export and .import into one program, the first module runs first")
+            .arg(Arg::with_name("define")
+                .short("D")
+                .long("define")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("a name for .ifdef blocks, which are left out unless their name is defined"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the source of the linked program, defaults to stdout"))
+            .arg(Arg::with_name("modules")
+                .multiple(true)
+                .required(true)
+                .help("the goto source files of the modules")))
+        .subcommand(SubCommand::with_name("disasm")
+            .about("Print the source of a parsed program or a .gbc file in canonical form")
+            .arg(Arg::with_name("labels")
+                .long("labels")
+                .help("jump to labels, named after their instruction where the program has no name for them"))
+            .arg(Arg::with_name("index")
+                .long("index")
+                .help("add the index of every instruction as a comment"))
+            .arg(Arg::with_name("file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("specialize")
+            .about("Print a program specialized for inputs where some cells are known")
+            .arg(Arg::with_name("known")
+                .long("known")
+                .takes_value(true)
+                .required(true)
+                .help("the known cells like 0=5,1=?, where ? marks an unknown cell, the known cells start at 0 in the specialized program"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the goto source, defaults to stdout"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("minimize")
+            .about("Shrink a program that fails on an input to a small program that fails the same way")
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .help("the memory on which the program fails"))
+            .arg(Arg::with_name("error")
+                .long("error")
+                .takes_value(true)
+                .conflicts_with("stops with")
+                .help("the failure is an error with a message that contains this text, an error like the one of the program if neither this nor --stops-with is given"))
+            .arg(Arg::with_name("stops with")
+                .long("stops-with")
+                .takes_value(true)
+                .help("the failure is a stop with a wrong result, described by a condition like \"[1] != 6\""))
+            .arg(Arg::with_name("within")
+                .long("within")
+                .takes_value(true)
+                .default_value("100000")
+                .help("how many steps every run may take"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the goto source, defaults to stdout"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("encode")
+            .about("Print the Gödel number of a program")
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file")))
+        .subcommand(SubCommand::with_name("decode")
+            .about("Print the program with a Gödel number")
+            .arg(Arg::with_name("number")
+                .help("the Gödel number, read from stdin if missing")))
+        .subcommand(SubCommand::with_name("bench")
+            .about("Time repeated runs of a program without tracing")
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .help("the memory on which to goto program works"))
+            .arg(Arg::with_name("runs")
+                .long("runs")
+                .takes_value(true)
+                .default_value("10")
+                .help("how often to run the program"))
+            .arg(Arg::with_name("duration")
+                .long("duration")
+                .takes_value(true)
+                .help("run the program again until this much time has passed, like 500ms or 3s, instead of --runs"))
+            .arg(Arg::with_name("engine")
+                .long("engine")
+                .takes_value(true)
+                .possible_values(&["interpreter", "bytecode", "jit"])
+                .default_value("bytecode")
+                .help("what executes the program"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(limit_args(SubCommand::with_name("sandbox"))
+            .about("Run an untrusted program under limits and print its final state as JSON, exits with 1 if it failed and 2 if it ran into a limit")
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .help("the file with the memory on which the program works, empty if missing"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file")))
+        .subcommand(SubCommand::with_name("complexity")
+            .about("Run a program with growing values in an input cell and fit linear, quadratic and exponential models to the steps")
+            .arg(Arg::with_name("input")
+                .short("i")
+                .long("input")
+                .takes_value(true)
+                .help("the memory of the runs apart from the varied cell"))
+            .arg(Arg::with_name("vary cell")
+                .long("vary-cell")
+                .takes_value(true)
+                .required(true)
+                .help("the input cell that takes the values"))
+            .arg(Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .default_value("0")
+                .help("the first value of the cell"))
+            .arg(Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .required(true)
+                .help("the last value of the cell"))
+            .arg(Arg::with_name("by")
+                .long("by")
+                .takes_value(true)
+                .default_value("1")
+                .help("how much the value grows from run to run"))
+            .arg(Arg::with_name("within")
+                .long("within")
+                .takes_value(true)
+                .default_value("100000000")
+                .help("how many steps a run may take"))
+            .arg(Arg::with_name("csv")
+                .long("csv")
+                .help("only print the values and steps as CSV, for plotting"))
+            .arg(Arg::with_name("source file")
+                .required(true)
+                .help("the goto program source file or a .gbc file")))
+        .subcommand(SubCommand::with_name("repl")
+            .about("Interactively enter instructions against a live memory")
+            .arg(Arg::with_name("history file")
+                .long("history-file")
+                .takes_value(true)
+                .help("where to keep the input history, defaults to ~/.goto_history")))
+        .subcommand(SubCommand::with_name("completions")
+            .about("Generate the shell completions of goto")
+            .arg(Arg::with_name("shell")
+                .possible_values(&["bash", "zsh", "fish"])
+                .required(true)
+                .help("the shell to complete in"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the completions, defaults to stdout")))
+        .subcommand(SubCommand::with_name("manpage")
+            .about("Generate the man page of goto with all subcommands in roff")
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("where to write the man page, defaults to stdout")))
+}
+
+fn cli_arguments() -> ArgMatches<'static> {
+    cli().get_matches()
+}
+
+fn fmt_command(matches: &ArgMatches) -> Result<(), Error> {
+    let check = matches.is_present("check");
+    let mut unformatted = false;
+    for file in matches.values_of("files").unwrap() {
+        let text = read_to_string(file).context("reading code")?;
+        let formatted = formatter::format_source(&text)
+            .map_err(|e| invalid(&format!("parsing {}", file), &e))?;
+        if formatted != text {
+            unformatted = true;
+            if check {
+                println!("{} is not formatted", file);
+            } else {
+                write(file, formatted).context("writing code")?;
             }
+        }
+    }
+    if check && unformatted {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn lint_command(matches: &ArgMatches) -> Result<(), Error> {
+    let source_file = matches.value_of("source file").unwrap();
+    let allowed: Vec<_> = matches.values_of("allow").into_iter().flatten()
+        .filter_map(lint::LintCode::from_code)
+        .collect();
+    let program_code = read_to_string(source_file).context("reading code")?;
+    let program = parse_source(program_code, &ParseOptions::default())?;
+    let warnings = lint::lint(&program, &allowed);
+    for warning in &warnings {
+        println!("{}:{}: {}", source_file, program.source_lines[warning.instruction], warning);
+    }
+    if !warnings.is_empty() {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn analyze_command(matches: &ArgMatches) -> Result<(), Error> {
+    let source_file = matches.value_of("source file").unwrap();
+    let (_, program) = load_program(source_file)?;
+    let mut clean = true;
+    if matches.is_present("memory") {
+        println!("{}", Footprint::of(&program).to_json());
+    }
+    if matches.is_present("ranges") {
+        let input = matches.value_of("input").map(|_| optional_input(matches)).transpose()?;
+        let input = input.map(|input| program.initial_memory(&input));
+        let ranges = ranges::analyze(&program, input.as_deref());
+        print!("{}", ranges.listing(&program));
+        for (pc, warning) in ranges.warnings(&program) {
+            println!("{}:{}: {}", source_file, program.source_lines[pc], warning);
+            clean = false;
+        }
+    }
+    if matches.is_present("termination") {
+        let loops = termination::loops(&program);
+        print!("{}", termination::report(&program, &loops));
+        clean &= loops.iter().all(|l| l.decreasing_cell.is_some());
+    }
+    if !clean {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn stats_command(matches: &ArgMatches) -> Result<(), Error> {
+    let files: Vec<&str> = matches.values_of("source files").unwrap().collect();
+    for (index, &file) in files.iter().enumerate() {
+        let stats = Stats::of(&load_program(file)?.1);
+        if matches.is_present("json") {
+            let mut json = vec![("file".to_string(), file.into())];
+            if let Value::Object(fields) = stats.to_json() {
+                json.extend(fields);
+            }
+            println!("{}", Value::Object(json));
+        } else if files.len() > 1 {
+            print!("{}{}:\n{}", if index > 0 { "\n" } else { "" }, file, stats);
         } else {
-            Result::Err(format!("No tokens in: {}", value))
+            print!("{}", stats);
         }
     }
+    Ok(())
 }
 
-#[test]
-fn test_parse() {
-    assert_eq!(Result::Ok(Instruction::Stop), Instruction::try_from("STOP".to_string()));
-    assert_eq!(Result::Ok(Instruction::Inc { cell: 42 }), Instruction::try_from("INC 42".to_string()));
-    assert_eq!(Result::Ok(Instruction::Dec { cell: 13 }), Instruction::try_from(" DEC 13 ".to_string()));
-    assert_eq!(Result::Ok(Instruction::Goto { cell: 0 }), Instruction::try_from(" GOTO  0".to_string()));
-    assert_eq!(Result::Ok(Instruction::GotoZ { condition_cell: 42, goto_cell: 0 }), Instruction::try_from("GOTOZ 42 0".to_string()));
+fn input_space(matches: &ArgMatches, program: &GotoProgram) -> Result<inputs::InputSpace, Error> {
+    let cells = inputs::parse_range(matches.value_of("cells").unwrap()).context("parsing --cells")?;
+    let values = inputs::parse_range(matches.value_of("range").unwrap()).context("parsing --range")?;
+    Ok(inputs::InputSpace::new(program, cells.start as usize..cells.end as usize, values))
+}
 
-    assert!(Instruction::try_from("".to_string()).is_err());
-    assert!(Instruction::try_from("INC 1 2 3 ".to_string()).is_err());
-    assert!(Instruction::try_from("what is this even".to_string()).is_err());
+fn verify_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (source, program) = load_program(matches.value_of("source file").unwrap())?;
+    if matches.is_present("contract") {
+        return verify_contract(matches, &source, &program);
+    }
+    let reaches = matches.is_present("reaches");
+    let target = matches.value_of("reaches").or_else(|| matches.value_of("never reaches")).unwrap();
+    let target = verify::Target::parse(target).context("parsing target")?;
+    let within = matches.value_of("within").unwrap().parse().context("parsing --within")?;
+    let inputs = input_space(matches, &program)?;
+    match verify::check(&program, target, reaches, &inputs, within).context("verifying program")? {
+        Ok(checked) => println!("verified: {} inputs {} the {} within {} steps", checked,
+                                if reaches { "all reach" } else { "never reach" }, target, within),
+        Err(counterexample) => {
+            match counterexample.run {
+                verify::Run::Reached { step } => println!("counterexample: input {:?} reaches the {} at step {}",
+                                                          counterexample.input, target, step),
+                verify::Run::Missed { ending } => println!("counterexample: input {:?} does not reach the {}, it {}",
+                                                           counterexample.input, target, ending),
+            }
+            exit(1);
+        }
+    }
+    Ok(())
 }
 
-fn parse_commands(text: String) -> Result<Vec<Instruction>, String> {
-    let mut result = vec![];
-    for (line_nr, line) in text.lines().enumerate() {
-        let instruction = Instruction::try_from(line.to_string())
-            .map_err(|e| format!("error in line {}: {}", line_nr + 1, e))?;
-        result.push(instruction)
+fn verify_contract(matches: &ArgMatches, source: &str, program: &GotoProgram) -> Result<(), Error> {
+    let contract = contract::Contract::parse(source).context("parsing annotations")?;
+    if contract.is_empty() {
+        eprintln!("the program has no ;@ requires or ;@ ensures annotations");
+        exit(1);
     }
-    Ok(result)
+    let within = matches.value_of("within").unwrap().parse().context("parsing --within")?;
+    let inputs = input_space(matches, program)?;
+    match contract::check(program, &contract, &inputs, within).context("verifying contract")? {
+        Ok(checked) => {
+            println!("verified: the contract holds for {} inputs, {} are ruled out by requires", checked.stopped, checked.excluded);
+            if checked.unfinished > 0 {
+                println!("not checked: {} inputs are still running after {} steps", checked.unfinished, within);
+            }
+        }
+        Err(counterexample) => {
+            println!("counterexample: input {:?}: {}", counterexample.input, counterexample.problem);
+            exit(1);
+        }
+    }
+    Ok(())
 }
 
-#[test]
-fn test_parse_commands() {
-    let input = "INC 1
-    DEC 2
-    GOTO 3
-    STOP";
-    let expected = vec![
-        Instruction::Inc { cell: 1 },
-        Instruction::Dec { cell: 2 },
-        Instruction::Goto { cell: 3 },
-        Instruction::Stop
-    ];
-    assert_eq!(Result::Ok(expected), parse_commands(input.to_string()));
+/// The `--jobs` argument, the number of processors if missing.
+fn jobs(matches: &ArgMatches) -> Result<usize, Error> {
+    Ok(match matches.value_of("jobs") {
+        Some(jobs) => jobs.parse().context("parsing --jobs")?,
+        None => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+    })
 }
 
-#[derive(Debug)]
-struct GotoProgram {
-    instructions: Vec<Instruction>
+fn equiv_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (_, first) = load_program(matches.value_of("first").unwrap())?;
+    let (_, second) = load_program(matches.value_of("second").unwrap())?;
+    let outputs = matches.value_of("outputs").map(|outputs| inputs::parse_range(outputs).context("parsing --outputs"))
+        .transpose()?.map(|cells| cells.start as usize..cells.end as usize);
+    let within = matches.value_of("within").unwrap().parse().context("parsing --within")?;
+    let mut inputs = input_space(matches, &first)?;
+    inputs.size = inputs.size.max(input_space(matches, &second)?.size);
+    let compared = match matches.value_of("samples") {
+        Some(samples) => {
+            let samples = samples.parse().context("parsing --samples")?;
+            let mut random = Random::new(matches.value_of("seed").unwrap().parse().context("parsing --seed")?);
+            equiv::check(&first, &second, (0..samples).map(|_| inputs.sample(&mut random)), outputs.as_ref(), within, jobs(matches)?)
+        }
+        None => equiv::check(&first, &second, inputs.iter(), outputs.as_ref(), within, jobs(matches)?),
+    };
+    match compared {
+        Ok(compared) => {
+            println!("equivalent on {} inputs", compared.inputs);
+            if compared.unfinished > 0 {
+                println!("note: {} of them were not compared, a program is still running after {} steps", compared.unfinished, within);
+            }
+        }
+        Err(divergence) => {
+            println!("the programs differ on input {:?}", divergence.input);
+            println!("  {}: {}", matches.value_of("first").unwrap(), divergence.first);
+            println!("  {}: {}", matches.value_of("second").unwrap(), divergence.second);
+            exit(1);
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug)]
-struct GotoProgramState<'a> {
-    program: &'a GotoProgram,
-    program_counter: RegisterIndex,
-    memory: Vec<u64>,
+fn difftest_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (_, program) = load_program(matches.value_of("source file").unwrap())?;
+    let within = matches.value_of("within").unwrap().parse().context("parsing --within")?;
+    let inputs = input_space(matches, &program)?;
+    let compiler = if matches.is_present("no c") { None } else { matches.value_of("compiler") };
+    let samples: Option<usize> = matches.value_of("samples").map(|samples| samples.parse().context("parsing --samples")).transpose()?;
+    let seed = matches.value_of("seed").unwrap().parse().context("parsing --seed")?;
+    let dir = std::env::temp_dir().join(format!("goto-difftest-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).context("creating a temporary directory")?;
+    let backends = difftest::backends(&program, inputs.size, compiler, &dir);
+    let checked = backends.and_then(|backends| {
+        let names: Vec<_> = backends.iter().map(|backend| backend.to_string()).collect();
+        println!("backends: interpreter, {}", names.join(", "));
+        match samples {
+            Some(samples) => {
+                let mut random = Random::new(seed);
+                difftest::check(&program, &backends, (0..samples).map(|_| inputs.sample(&mut random)), within)
+            }
+            None => difftest::check(&program, &backends, inputs.iter(), within),
+        }
+    });
+    let _ = std::fs::remove_dir_all(&dir);
+    match checked.context("running a backend")? {
+        Ok(compared) => {
+            println!("all backends agree on {} inputs", compared.inputs);
+            if compared.unfinished > 0 {
+                println!("note: {} of them were not checked, the interpreter is still running after {} steps", compared.unfinished, within);
+            }
+        }
+        Err(mismatch) => {
+            println!("MISMATCH on input {:?}", mismatch.input);
+            println!("  interpreter: {}", mismatch.expected);
+            println!("  {}: {}", mismatch.backend, mismatch.actual);
+            exit(1);
+        }
+    }
+    Ok(())
 }
 
-impl GotoProgramState<'_> {
-    fn run(&mut self) {
-        loop {
-            println!("{:?}: {:?}", self.program_counter, self.program.instructions[self.program_counter]);
-            println!("mem: {:?}", self.memory);
-            match self.program.instructions[self.program_counter] {
-                Instruction::Stop => {
-                    break;
-                }
-                Instruction::Inc { cell } => {
-                    self.memory[cell] += 1;
-                    self.program_counter += 1;
-                }
-                Instruction::Dec { cell } => {
-                    self.memory[cell] -= 1;
-                    self.program_counter += 1;
-                }
-                Instruction::Goto { cell } => {
-                    self.program_counter = cell;
-                }
-                Instruction::GotoZ { condition_cell, goto_cell } => {
-                    if self.memory[condition_cell] == 0 {
-                        self.program_counter = goto_cell;
-                    } else {
-                        self.program_counter += 1;
-                    }
+fn fuzz_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (_, program) = load_program(matches.value_of("source file").unwrap())?;
+    let cells = matches.value_of("cells").unwrap().parse().context("parsing --cells")?;
+    let max: u64 = matches.value_of("max").unwrap().parse().context("parsing --max")?;
+    let inputs = inputs::InputSpace::new(&program, 0..cells, 0..max.checked_add(1).ok_or_else(|| invalid("parsing --max", "it is too large"))?);
+    let settings = fuzz::Fuzz {
+        runs: matches.value_of("runs").unwrap().parse().context("parsing --runs")?,
+        max_steps: matches.value_of("within").unwrap().parse().context("parsing --within")?,
+        seed: matches.value_of("seed").unwrap().parse().context("parsing --seed")?,
+        jobs: jobs(matches)?,
+    };
+    let report = fuzz::fuzz(&program, &inputs, settings).context("fuzzing")?;
+    print!("{}", report);
+    if report.failed() > 0 {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn grade_command(matches: &ArgMatches) -> Result<(), Error> {
+    let source_file = matches.value_of("source file").unwrap();
+    let program_code = read_to_string(source_file).context("reading code")?;
+    let program = parse_source(program_code, &ParseOptions::default())?;
+    let spec_text = read_to_string(matches.value_of("spec").unwrap()).context("reading test specification")?;
+    let spec = TestSpec::parse(&spec_text).context("parsing test specification")?;
+    let policy: Box<dyn GradingPolicy> = match matches.value_of("policy").unwrap() {
+        "all-or-nothing" => Box::new(grading::AllOrNothing { points: 1.0 }),
+        "efficiency" => {
+            let reference_steps = matches.value_of("reference steps")
+                .ok_or_else(|| invalid("choosing the policy", "--reference-steps is required for the efficiency policy"))?
+                .parse()
+                .context("parsing --reference-steps")?;
+            Box::new(grading::StepEfficiency { reference_steps, weight: 0.5 })
+        }
+        _ => Box::new(grading::PartialCredit { points_per_case: 1.0 }),
+    };
+    let report = grading::grade(&program, &spec, policy.as_ref());
+    println!("{}", report);
+    if report.results.iter().any(|r| !r.passed()) {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn mutate_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (_, program) = load_program(matches.value_of("source file").unwrap())?;
+    let spec_text = read_to_string(matches.value_of("spec").unwrap()).context("reading test specification")?;
+    let spec = TestSpec::parse(&spec_text).context("parsing test specification")?;
+    let results = mutate::run(&program, &spec).context("testing mutants")?;
+    print!("{}", mutate::report(&program, &results));
+    Ok(())
+}
+
+fn test_command(matches: &ArgMatches) -> Result<(), Error> {
+    let paths: Vec<_> = matches.values_of("paths").unwrap().map(PathBuf::from).collect();
+    let programs = suite::discover(&paths).context("searching for programs")?;
+    let (mut passed, mut failed) = (0, 0);
+    for (program, spec) in &programs {
+        match suite::run_file(program, spec) {
+            Ok(reports) => for report in reports {
+                if report.outcome == grading::Outcome::Passed {
+                    println!("test {}: {} ... ok", program.display(), report.name);
+                    passed += 1;
+                } else {
+                    println!("test {}: {} ... FAILED", program.display(), report.name);
+                    print!("{}", suite::explain(&report));
+                    failed += 1;
                 }
+            },
+            Err(e) => {
+                println!("test {} ... FAILED", program.display());
+                println!("    {}", e);
+                failed += 1;
             }
         }
     }
+    println!();
+    println!("test result: {}. {} passed; {} failed; {} programs", if failed == 0 { "ok" } else { "FAILED" },
+             passed, failed, programs.len());
+    if failed > 0 {
+        exit(1);
+    }
+    Ok(())
 }
 
-fn read_input(text: String) -> Result<Vec<u64>, String> {
-    let mut result = vec![];
-    for token in text.split(|c| c==' ' || c == '\n').filter(|t| t.len() > 0) {
+fn superopt_command(matches: &ArgMatches) -> Result<(), Error> {
+    let spec_text = read_to_string(matches.value_of("spec").unwrap()).context("reading test specification")?;
+    let spec = TestSpec::parse(&spec_text).context("parsing test specification")?;
+    let search = superopt::Search {
+        cells: match matches.value_of("cells") {
+            Some(cells) => cells.parse().context("parsing --cells")?,
+            None => superopt::spec_cells(&spec),
+        },
+        max_length: matches.value_of("max length").unwrap().parse().context("parsing --max-length")?,
+        max_steps: matches.value_of("within").unwrap().parse().context("parsing --within")?,
+    };
+    let found = match matches.value_of("stochastic") {
+        Some(tries) => {
+            let tries = tries.parse().context("parsing --stochastic")?;
+            superopt::stochastic(&spec, &search, tries, matches.value_of("seed").unwrap().parse().context("parsing --seed")?)
+        }
+        None => {
+            superopt::exhaustive(&spec, &search, jobs(matches)?).context("searching")?
+        }
+    };
+    let program = match found {
+        Some(program) => program,
+        None => {
+            eprintln!("no program with at most {} instructions passes the specification", search.max_length);
+            exit(1);
+        }
+    };
+    eprintln!("found a program with {} instructions", program.instructions.len());
+    let source = disasm::disassemble(&program, &[], DisasmOptions { labels: true, indices: false });
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, source).context("writing output")?,
+        None => print!("{}", source),
+    }
+    Ok(())
+}
 
-        let nr = token.parse::<u64>()
-            .map_err(|e| format!("Number parsing error: {}", e))?;
-        result.push(nr);
+fn pipeline_command(matches: &ArgMatches) -> Result<(), Error> {
+    let files: Vec<&str> = matches.values_of("source files").unwrap().collect();
+    let mut stages = vec![];
+    for file in &files {
+        stages.push(pipeline::Stage { program: load_program(file)?.1, map: None });
+    }
+    for map in matches.values_of("map").into_iter().flatten() {
+        let (stage, cells) = map.split_once(':').ok_or_else(|| invalid("parsing --map", "it needs a stage and cells, like 2:3=0"))?;
+        let stage: usize = stage.parse().context("parsing the stage of --map")?;
+        if stage < 2 || stage > stages.len() {
+            return Err(invalid("parsing --map", &format!("stage {} gets no memory from a stage before it", stage)));
+        }
+        let map = pipeline::parse_map(cells, &stages[stage - 2].program, &stages[stage - 1].program).context("parsing --map")?;
+        stages[stage - 1].map = Some(map);
+    }
+    let max_steps = matches.value_of("max steps").unwrap().parse().context("parsing --max-steps")?;
+    let runs = pipeline::run(&stages, &optional_input(matches)?, max_steps);
+    for (stage, (file, run)) in files.iter().zip(&runs).enumerate() {
+        match run.status {
+            Status::Error(_) => println!("stage {} {}: {} steps, {}", stage + 1, file, run.steps, run.status),
+            _ => println!("stage {} {}: {} steps, {}: {:?}", stage + 1, file, run.steps, run.status, run.memory),
+        }
     }
-    Ok(result)
+    let last = runs.last().unwrap();
+    if runs.len() < files.len() || last.status != Status::Stopped {
+        exit(1);
+    }
+    println!("result: {:?}", last.memory);
+    Ok(())
 }
 
-fn cli_arguments() -> (String, String) {
-    let matches = App::new("goto")
-        .version("1.0")
-        .about("Run a goto program")
-        .arg(Arg::with_name("source file")
-            .short("s")
-            .long("source")
+fn trace_diff_command(matches: &ArgMatches) -> Result<(), Error> {
+    let first_file = matches.value_of("first").unwrap();
+    let second_file = matches.value_of("second").unwrap();
+    let read = |file: &str| {
+        let text = read_to_string(file).context("reading trace")?;
+        goto::golden::parse_trace(&text).map_err(|e| invalid(&format!("parsing {}", file), &e))
+    };
+    let (first, second) = (read(first_file)?, read(second_file)?);
+    let align = trace_diff::Align::from_name(matches.value_of("align").unwrap()).unwrap();
+    let context = matches.value_of("context").unwrap().parse().context("parsing --context")?;
+    print!("{}", trace_diff::report((first_file, second_file), &first, &second, align, context));
+    if trace_diff::diverge(&first, &second, align).is_some() {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn state_diff_command(matches: &ArgMatches) -> Result<(), Error> {
+    let first_file = matches.value_of("first").unwrap();
+    let second_file = matches.value_of("second").unwrap();
+    let read = |file: &str| {
+        let text = read_to_string(file).context("reading state")?;
+        Snapshot::parse(&text).map_err(|e| invalid(&format!("parsing {}", file), &e))
+    };
+    let (first, second) = (read(first_file)?, read(second_file)?);
+    let changed = snapshot::changed_cells(&first.memory, &second.memory);
+    let show = |value: Option<u64>| value.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string());
+    let mut rows = vec![
+        ("".to_string(), first_file.to_string(), second_file.to_string()),
+        ("status".to_string(), first.status.to_string(), second.status.to_string()),
+        ("steps".to_string(), first.steps.to_string(), second.steps.to_string()),
+        ("pc".to_string(), first.program_counter.to_string(), second.program_counter.to_string()),
+    ];
+    for &(cell, a, b) in &changed {
+        rows.push((format!("cell {}", cell), show(a), show(b)));
+    }
+    let width = |column: fn(&(String, String, String)) -> &String| rows.iter().map(|r| column(r).len()).max().unwrap();
+    let (name_width, first_width) = (width(|r| &r.0), width(|r| &r.1));
+    for (name, a, b) in &rows {
+        let marker = if a != b && !name.is_empty() { "*" } else { " " };
+        println!("{} {:name_width$}  {:first_width$}  {}", marker, name, a, b,
+                 name_width = name_width, first_width = first_width);
+    }
+    let cells = first.memory.len().max(second.memory.len());
+    println!("{} of {} cells differ", changed.len(), cells);
+    if first != second {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn optional_input(matches: &ArgMatches) -> Result<Vec<u64>, Error> {
+    Ok(match matches.value_of("input") {
+        Some(input_file) => {
+            let input_text = read_to_string(input_file).context("reading input")?;
+            read_input(input_text).context("parsing input")?
+        }
+        None => vec![],
+    })
+}
+
+/// The memory of a run, with the inputs of the banks woven into it when the
+/// program has banks.
+fn program_input(matches: &ArgMatches, program: &GotoProgram) -> Result<Vec<u64>, Error> {
+    let input = optional_input(matches)?;
+    program.header.check_input(input.len()).context("loading input")?;
+    if program.banks == 1 && !matches.is_present("bank input") {
+        return Ok(program.initial_memory(&input));
+    }
+    // only the banks with cells, there may be far more banks than cells
+    let mut memories: Vec<(usize, Vec<u64>)> = banks::split(&program.initial_memory(&[]), program.banks).into_iter().enumerate().collect();
+    if memories.is_empty() {
+        memories.push((0, vec![]));
+    }
+    let declared = &mut memories[0].1;
+    declared.resize(declared.len().max(input.len()), 0);
+    declared[..input.len()].copy_from_slice(&input);
+    for bank_input in matches.values_of("bank input").into_iter().flatten() {
+        let (bank, file) = bank_input.split_once('=')
+            .ok_or_else(|| invalid("parsing --bank-input", "it needs a bank and a file, like 1=scratch.txt"))?;
+        let bank: usize = bank.parse().context("parsing the bank of --bank-input")?;
+        if bank >= program.banks {
+            return Err(invalid("parsing --bank-input", &format!("the program has no bank {}", bank)));
+        }
+        let input_text = read_to_string(file).context("reading bank input")?;
+        let memory = read_input(input_text).context("parsing bank input")?;
+        match memories.iter_mut().find(|(other, _)| *other == bank) {
+            Some((_, other)) => *other = memory,
+            None => memories.push((bank, memory)),
+        }
+    }
+    banks::join(&memories, program.banks).context("loading input")
+}
+
+fn compile_command(matches: &ArgMatches) -> Result<(), Error> {
+    let source_file = matches.value_of("source file").unwrap();
+    let program_code = read_to_string(source_file).context("reading code")?;
+    let program_code = preprocess::preprocess(&program_code, &parse_options(matches).defines).context("parsing code")?;
+    let mut options = parse_options(matches);
+    let classic = options.dialect.take() == Some(Dialect::Classic);
+    let mut program = parse_source(program_code.clone(), &options)?;
+    if classic {
+        program = lower::to_classic(&program).context("lowering code")?;
+    }
+    let mut memory = matches.value_of("input").map(|_| optional_input(matches)).transpose()?
+        .map(|input| if classic { program.initial_memory(&input) } else { input });
+    if matches.is_present("minimize memory") {
+        let layout = layout::Layout::of(&program);
+        eprintln!("memory: {}", layout);
+        program = layout.apply(&program);
+        memory = memory.map(|memory| layout.pack(&memory));
+    }
+    let flag = Some(matches.value_of("arithmetic").unwrap()).filter(|_| matches.occurrences_of("arithmetic") > 0);
+    let arithmetic = program.header.arithmetic(flag.and_then(transpile::Arithmetic::from_name)).context("choosing the arithmetic")?;
+    let text = |(code, offsets): (String, Vec<usize>)| {
+        let positions = sourcemap::text_positions(&code, &offsets);
+        (code.into_bytes(), Some(positions))
+    };
+    let binary = |(code, offsets): (Vec<u8>, Vec<usize>)| (code, Some(offsets.into_iter().map(|offset| (0, offset)).collect()));
+    let target = matches.value_of("target").unwrap();
+    let (code, positions) = match target {
+        "rust" => text(transpile::to_rust_mapped(&program, memory.as_deref(), arithmetic)),
+        "brainfuck" => (transpile_without_options(memory, arithmetic, "brainfuck", || brainfuck::from_goto(&program))?.into_bytes(), None),
+        "urm" => (transpile_without_options(memory, arithmetic, "urm", || urm::from_goto(&program).map(|urm| urm::to_text(&urm)))?.into_bytes(), None),
+        "llvm-ir" => {
+            if arithmetic != transpile::Arithmetic::Checked {
+                return Err(invalid("compiling program", "--arithmetic is not supported for llvm-ir"));
+            }
+            text(transpile::to_llvm_ir_mapped(&program, memory.as_deref()))
+        }
+        "wasm" => binary(transpile_without_options(memory, arithmetic, "wasm", || transpile::to_wasm_mapped(&program))?),
+        "bytecode" => binary(transpile_without_options(memory, arithmetic, "bytecode", || {
+            let source_path = std::fs::canonicalize(source_file).map(|path| path.display().to_string())
+                .unwrap_or_else(|_| source_file.to_string());
+            let labels = gbc::label_table(&program_code, &program);
+            Ok(gbc::BytecodeFile { source_path, labels, program: program.clone() }.to_bytes_mapped())
+        })?),
+        _ => text(transpile::to_c_mapped(&program, memory.as_deref(), arithmetic)),
+    };
+    let map = match (matches.value_of("source map"), positions) {
+        (None, _) => None,
+        (Some(_), None) => return Err(invalid("compiling program", &format!("--source-map is not supported for {}", target))),
+        (Some(map_file), Some(generated)) => {
+            let file = matches.value_of("output").unwrap_or("-").to_string();
+            Some((map_file, sourcemap::SourceMap { file, source: source_file.to_string(), lines: program.source_lines.clone(), generated }))
+        }
+    };
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, code).context("writing output")?,
+        None => std::io::stdout().write_all(&code).context("writing output")?,
+    }
+    if let Some((map_file, map)) = map {
+        write(map_file, map.to_json().to_string() + "\n").context("writing source map")?;
+    }
+    Ok(())
+}
+
+fn transpile_without_options<T>(memory: Option<Vec<u64>>, arithmetic: transpile::Arithmetic, target: &str,
+                                translate: impl FnOnce() -> Result<T, String>) -> Result<T, Error> {
+    if memory.is_some() || arithmetic != transpile::Arithmetic::Checked {
+        return Err(invalid("compiling program", &format!("--input and --arithmetic are not supported for {}", target)));
+    }
+    translate().context("translating code")
+}
+
+fn to_goto(language: &str, text: String) -> Result<String, String> {
+    match language {
+        "goto" => Ok(text),
+        "brainfuck" => brainfuck::to_goto(&text),
+        "while" => while_lang::to_goto(&text),
+        "loop" => loop_lang::to_goto(&text),
+        "urm" => urm::to_goto(&text),
+        "turing" => turing::to_goto(&text),
+        "recursive" => recursive::to_goto(&text),
+        other => Err(format!("unknown language {}", other)),
+    }
+}
+
+fn symbolize_command(matches: &ArgMatches) -> Result<(), Error> {
+    let map = read_to_string(matches.value_of("source map").unwrap()).context("reading source map")?;
+    let map = sourcemap::SourceMap::parse(&map).context("parsing source map")?;
+    if let Some(pc) = matches.value_of("pc") {
+        let pc = pc.parse().context("parsing --pc")?;
+        let line = map.line(pc).ok_or_else(|| invalid("symbolizing", &format!("pc {} is outside of the program", pc)))?;
+        println!("{}:{}", map.source, line);
+        return Ok(());
+    }
+    let text = match matches.value_of("file") {
+        Some(file) => read_to_string(file).context("reading errors")?,
+        None => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text).context("reading errors")?;
+            text
+        }
+    };
+    print!("{}", map.symbolize(&text));
+    Ok(())
+}
+
+fn import_command(matches: &ArgMatches) -> Result<(), Error> {
+    let text = read_to_string(matches.value_of("file").unwrap()).context("reading program")?;
+    let code = to_goto(matches.value_of("from").unwrap(), text).context("translating program")?;
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, code).context("writing output")?,
+        None => print!("{}", code),
+    }
+    Ok(())
+}
+
+fn grammar_command(matches: &ArgMatches) -> Result<(), Error> {
+    let format = grammar::Format::from_name(matches.value_of("format").unwrap()).unwrap();
+    let grammar = grammar::generate(format);
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, grammar).context("writing output")?,
+        None => print!("{}", grammar),
+    }
+    Ok(())
+}
+
+fn doc_command(matches: &ArgMatches) -> Result<(), Error> {
+    let format = doc::Format::from_name(matches.value_of("format").unwrap()).unwrap();
+    let mut files = vec![];
+    for file in matches.values_of("files").unwrap() {
+        let library = file.strip_prefix('<').and_then(|name| name.strip_suffix('>'));
+        let text = match library {
+            Some(name) => goto::macros::library(name).map(str::to_string)
+                .ok_or_else(|| invalid("reading code", &format!("Unknown library: {}", name)))?,
+            None => read_to_string(file).context("reading code")?,
+        };
+        files.push((library.unwrap_or(file).to_string(), doc::extract(&text).context(&format!("reading the doc comments of {}", file))?));
+    }
+    let documentation = doc::render(format, &files);
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, documentation).context("writing output")?,
+        None => print!("{}", documentation),
+    }
+    Ok(())
+}
+
+fn completions_command(matches: &ArgMatches) -> Result<(), Error> {
+    let shell = matches.value_of("shell").unwrap().parse::<Shell>().unwrap();
+    let mut completions = vec![];
+    cli().gen_completions_to("goto", shell, &mut completions);
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, completions).context("writing output")?,
+        None => std::io::stdout().write_all(&completions).context("writing output")?,
+    }
+    Ok(())
+}
+
+fn manpage_command(matches: &ArgMatches) -> Result<(), Error> {
+    let page = manpage::render(&cli());
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, page).context("writing output")?,
+        None => print!("{}", page),
+    }
+    Ok(())
+}
+
+fn link_command(matches: &ArgMatches) -> Result<(), Error> {
+    let mut modules = vec![];
+    for file in matches.values_of("modules").unwrap() {
+        let text = read_to_string(file).context("reading code")?;
+        modules.push(link::parse_module(file, &text, &parse_options(matches)).context(&format!("parsing {}", file))?);
+    }
+    let (program, labels) = link::link(&modules).context("linking modules")?;
+    let source = link::to_source(&program, &labels);
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, source).context("writing output")?,
+        None => print!("{}", source),
+    }
+    Ok(())
+}
+
+fn disasm_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (program_code, program) = load_program(matches.value_of("file").unwrap())?;
+    let options = DisasmOptions { labels: matches.is_present("labels"), indices: matches.is_present("index") };
+    print!("{}", disasm::disassemble(&program, &gbc::label_table(&program_code, &program), options));
+    Ok(())
+}
+
+fn specialize_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (_, program) = load_program(matches.value_of("source file").unwrap())?;
+    let known = specialize::parse_known(matches.value_of("known").unwrap()).context("parsing --known")?;
+    let residual = specialize::specialize(&program, &known).context("specializing program")?;
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, residual).context("writing output")?,
+        None => print!("{}", residual),
+    }
+    Ok(())
+}
+
+fn minimize_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (_, program) = load_program(matches.value_of("source file").unwrap())?;
+    let input = program.initial_memory(&optional_input(matches)?);
+    let within = matches.value_of("within").unwrap().parse().context("parsing --within")?;
+    let failure = match (matches.value_of("error"), matches.value_of("stops with")) {
+        (Some(text), _) => minimize::Failure::Error(text.to_string()),
+        (_, Some(condition)) => minimize::Failure::StopsWith(Condition::parse(condition).context("parsing --stops-with")?),
+        _ => minimize::Failure::same_error(&program, &input, within).context("running program")?,
+    };
+    let minimized = minimize::minimize(&program, &input, &failure, within).context("minimizing program")?;
+    eprintln!("minimized from {} to {} instructions in {} runs", program.instructions.len(),
+              minimized.program.instructions.len(), minimized.runs);
+    let source = disasm::disassemble(&minimized.program, &[], DisasmOptions { labels: true, indices: false });
+    match matches.value_of("output") {
+        Some(output_file) => write(output_file, source).context("writing output")?,
+        None => print!("{}", source),
+    }
+    Ok(())
+}
+
+fn encode_command(matches: &ArgMatches) -> Result<(), Error> {
+    let program_code = read_to_string(matches.value_of("source file").unwrap()).context("reading code")?;
+    let program = parse_source(program_code, &ParseOptions::default())?;
+    println!("{}", godel::encode(&program).context("encoding program")?);
+    Ok(())
+}
+
+fn decode_command(matches: &ArgMatches) -> Result<(), Error> {
+    let text = match matches.value_of("number") {
+        Some(number) => number.to_string(),
+        None => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text).context("reading number")?;
+            text
+        }
+    };
+    let number = godel::Natural::parse(text.trim()).context("parsing number")?;
+    for instruction in godel::decode(&number).context("decoding number")? {
+        println!("{}", instruction);
+    }
+    Ok(())
+}
+
+/// Reads goto source or a `.gbc` file, returns the source text and the program.
+fn load_program(source_file: &str) -> Result<(String, GotoProgram), Error> {
+    load_program_with(source_file, &ParseOptions::default())
+}
+
+fn parse_options(matches: &ArgMatches) -> ParseOptions {
+    ParseOptions {
+        defines: matches.values_of("define").into_iter().flatten().map(String::from).collect(),
+        dialect: matches.value_of("dialect").and_then(Dialect::from_name),
+        nop_lines: matches.is_present("nop lines"),
+    }
+}
+
+fn parse_source(program_code: String, options: &ParseOptions) -> Result<GotoProgram, Error> {
+    parse_program_errors(program_code.clone(), options).map_err(|errors| Error::Parse { errors, source: program_code })
+}
+
+fn invalid(context: &str, reason: &str) -> Error {
+    Error::Invalid { context: context.to_string(), reason: reason.to_string() }
+}
+
+fn load_program_with(source_file: &str, options: &ParseOptions) -> Result<(String, GotoProgram), Error> {
+    let bytes = std::fs::read(source_file).context("reading code")?;
+    if gbc::is_bytecode_file(&bytes) {
+        let file = gbc::BytecodeFile::from_bytes(&bytes).context("loading bytecode")?;
+        // the source file may have changed since it was compiled
+        let source = read_to_string(&file.source_path).ok()
+            .filter(|text| parse_program_with(text.clone(), options).ok().as_ref() == Some(&file.program))
+            .unwrap_or_else(|| file.reconstructed_source());
+        return Ok((source, file.program));
+    }
+    let program_code = String::from_utf8(bytes).map_err(|e| invalid("reading code", &e.to_string()))?;
+    let program = parse_source(program_code.clone(), options)?;
+    Ok((program_code, program))
+}
+
+/// The flags for the `sandbox::Limits` of untrusted programs.
+fn limit_args<'a, 'b>(command: App<'a, 'b>) -> App<'a, 'b> {
+    command
+        .arg(Arg::with_name("max steps")
+            .long("max-steps")
             .takes_value(true)
-            .required(true)
-            .help("the goto program source file"))
-        .arg(Arg::with_name("input")
-            .short("i")
-            .long("input")
+            .default_value("10000000")
+            .help("how many steps the program may take"))
+        .arg(Arg::with_name("max time")
+            .long("max-time")
             .takes_value(true)
-            .required(true)
-            .help("the memory on which to goto program works"))
-        .get_matches();
+            .default_value("1s")
+            .help("how long the program may run, like 300ms or 2s"))
+        .arg(Arg::with_name("max cells")
+            .long("max-cells")
+            .takes_value(true)
+            .default_value("1000")
+            .help("how many memory cells the input may have"))
+        .arg(Arg::with_name("max snapshot bytes")
+            .long("max-snapshot-bytes")
+            .takes_value(true)
+            .default_value("65536")
+            .help("how large the printed final state may be"))
+        .arg(Arg::with_name("max source bytes")
+            .long("max-source-bytes")
+            .takes_value(true)
+            .default_value("1048576")
+            .help("how large the source file may be"))
+}
+
+fn parse_limits(matches: &ArgMatches) -> Result<sandbox::Limits, Error> {
+    let parse = |name: &str, flag: &str| -> Result<usize, Error> { matches.value_of(name).unwrap().parse().context(&format!("parsing --{}", flag)) };
+    Ok(sandbox::Limits {
+        max_steps: parse("max steps", "max-steps")? as u64,
+        max_time: animation::parse_delay(matches.value_of("max time").unwrap()).context("parsing --max-time")?,
+        max_cells: parse("max cells", "max-cells")?,
+        max_snapshot_bytes: parse("max snapshot bytes", "max-snapshot-bytes")?,
+        max_source_bytes: parse("max source bytes", "max-source-bytes")?,
+    })
+}
+
+fn sandbox_command(matches: &ArgMatches) -> Result<(), Error> {
+    let limits = parse_limits(matches)?;
+    let open = |path: &str| std::fs::File::open(path).map_err(|e| sandbox::Outcome::Failed(format!("{}: {}", path, e)));
+    let loaded = open(matches.value_of("source file").unwrap()).and_then(|source| match matches.value_of("input") {
+        Some(input) => sandbox::load(source, open(input)?, &limits),
+        None => sandbox::load(source, std::io::empty(), &limits),
+    });
+    let (json, outcome) = sandbox::execute(loaded, &limits);
+    println!("{}", json);
+    exit(match outcome {
+        sandbox::Outcome::Stopped => 0,
+        sandbox::Outcome::Failed(_) => 1,
+        sandbox::Outcome::Exceeded(_) => 2,
+    })
+}
+
+fn jupyter_kernel_command(matches: &ArgMatches) -> Result<(), Error> {
+    if matches.is_present("install") {
+        let path = jupyter::install().context("installing the kernel")?;
+        println!("installed the kernel spec {}", path.display());
+        return Ok(());
+    }
+    jupyter::run(matches.value_of("connection file").unwrap()).context("running the kernel")
+}
+
+fn serve_command(matches: &ArgMatches) -> Result<(), Error> {
+    let limits = parse_limits(matches)?;
+    let port: u16 = matches.value_of("port").unwrap().parse().context("parsing --port")?;
+    let address = format!("{}:{}", matches.value_of("host").unwrap(), port);
+    serve::serve(&address, limits).context(&format!("serving on {}", address))
+}
+
+fn complexity_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (_, program) = load_program(matches.value_of("source file").unwrap())?;
+    let input = program.initial_memory(&matches.value_of("input").map(|_| optional_input(matches)).transpose()?.unwrap_or_default());
+    let cell = matches.value_of("vary cell").unwrap().parse().context("parsing --vary-cell")?;
+    let parse = |name: &str| -> Result<u64, Error> { matches.value_of(name).unwrap().parse().context(&format!("parsing --{}", name)) };
+    let (from, to, by) = (parse("from")?, parse("to")?, parse("by")?);
+    if by == 0 {
+        return Err(invalid("parsing --by", "it must be at least 1"));
+    }
+    let values = (from..=to).step_by(by as usize);
+    let measurements = complexity::measure(&program, &input, cell, values, parse("within")?).context("measuring")?;
+    if matches.is_present("csv") {
+        println!("n,steps");
+        for m in &measurements {
+            match &m.steps {
+                Ok(steps) => println!("{},{}", m.n, steps),
+                Err(_) => println!("{},", m.n),
+            }
+        }
+        return Ok(());
+    }
+    let width = measurements.iter().map(|m| m.n.to_string().len()).max().unwrap_or(1);
+    println!("{:>width$}  steps", "n", width = width);
+    for m in &measurements {
+        match &m.steps {
+            Ok(steps) => println!("{:>width$}  {}", m.n, steps, width = width),
+            Err(e) => println!("{:>width$}  {}", m.n, e, width = width),
+        }
+    }
+    let fits = complexity::fit(&measurements);
+    if fits.is_empty() {
+        println!("too few finished runs to fit a model");
+        return Ok(());
+    }
+    println!();
+    for (model, r_squared) in &fits {
+        println!("{:<12} R² = {:.4}  steps ≈ {}", model.name(), r_squared, model);
+    }
+    if let Some(best) = complexity::best_fit(&fits) {
+        println!("best fit: {}", best.name());
+    }
+    Ok(())
+}
+
+fn bench_command(matches: &ArgMatches) -> Result<(), Error> {
+    let (_, program) = load_program(matches.value_of("source file").unwrap())?;
+    let memory = program.initial_memory(&optional_input(matches)?);
+    let budget = match matches.value_of("duration") {
+        Some(duration) => bench::Budget::Duration(animation::parse_delay(duration).context("parsing --duration")?),
+        None => bench::Budget::Runs(matches.value_of("runs").unwrap().parse().context("parsing --runs")?),
+    };
+    let report = bench::run(&program, &memory, matches.value_of("engine").unwrap(), budget)
+        .context("running program")?;
+    println!("{}", report.summary());
+    Ok(())
+}
+
+fn tui_command(matches: &ArgMatches) -> Result<(), Error> {
     let source_file = matches.value_of("source file").unwrap();
-    let input_file = matches.value_of("input").unwrap();
-    (source_file.to_string(), input_file.to_string())
+    let (program_code, program) = load_program(source_file)?;
+    let memory = program.initial_memory(&optional_input(matches)?);
+    tui::run(source_file, &program_code, &program, memory).context("drawing the terminal")?;
+    Ok(())
 }
 
-fn main() {
-    let (source_file, input_file) = cli_arguments();
-    let program_code = read_to_string(source_file).expect("Error while reading code");
-    let instructions = parse_commands(program_code)
-        .expect("Error while parsing code");
-    let input_text = read_to_string(input_file).expect("Error while reading input");
-    let memory = read_input(input_text).expect("Error while parsing input");
-    let program = GotoProgram { instructions };
+fn debug_command(matches: &ArgMatches) -> Result<(), Error> {
+    let source_file = matches.value_of("source file").unwrap();
+    let (program_code, program) = load_program(source_file)?;
+    let memory = program.initial_memory(&optional_input(matches)?);
+    let history_size = matches.value_of("history size").unwrap().parse().context("parsing --history-size")?;
+    debugger::run(&program_code, &program, memory, history_size);
+    Ok(())
+}
+
+fn status_report(state: &GotoProgramState) -> String {
+    format!("step {}, pc {}, memory: {}", state.steps, state.program_counter, snapshot::memory_summary(&state.memory))
+}
+
+// how many of the most executed lines --profile reports
+const PROFILE_LINES: usize = 20;
+
+// steps between checks for Ctrl+C, status requests and progress in --fast runs
+const FAST_SLICE: u64 = 1 << 24;
+
+/// Runs a state up to a step limit like `GotoProgramState::run_with_limit`.
+type SliceRunner = Box<dyn FnMut(&mut GotoProgramState, u64) -> Result<bool, String>>;
+
+#[cfg(feature = "jit")]
+fn run_slice_compiled(state: &mut GotoProgramState) -> SliceRunner {
+    match goto::jit::Jit::compile(state.program, state.memory.len()) {
+        Some(jit) => Box::new(move |state, max_steps| jit.run(state, max_steps)),
+        None => {
+            eprintln!("note: the program cannot be translated to native code, using bytecode");
+            run_slice_bytecode(state)
+        }
+    }
+}
+
+#[cfg(not(feature = "jit"))]
+fn run_slice_compiled(state: &mut GotoProgramState) -> SliceRunner {
+    eprintln!("note: goto was built without the jit feature, using bytecode");
+    run_slice_bytecode(state)
+}
+
+fn run_slice_bytecode(state: &mut GotoProgramState) -> SliceRunner {
+    let bytecode = Bytecode::compile(state.program);
+    Box::new(move |state, max_steps| bytecode.as_ref().map_err(|e| e.clone())?.run(state, max_steps))
+}
+
+fn answer_status_clients(listener: &UnixListener, state: &GotoProgramState) {
+    while let Ok((mut client, _)) = listener.accept() {
+        let _ = writeln!(client, "{}", status_report(state));
+    }
+}
+
+/// Runs the state on bytecode or native code, between slices of steps it
+/// looks for Ctrl+C and status requests and refreshes the progress line.
+fn run_fast(state: &mut GotoProgramState, jit: bool, mut progress: Option<&mut Progress>, status_socket: Option<&UnixListener>, interrupted: &mut bool) -> Result<(), String> {
+    let mut run_slice = if jit { run_slice_compiled(state) } else { run_slice_bytecode(state) };
+    loop {
+        if signals::interrupted() {
+            *interrupted = true;
+            return Ok(());
+        }
+        if signals::take_status_request() {
+            eprintln!("{}", status_report(state));
+        }
+        if let Some(listener) = status_socket {
+            answer_status_clients(listener, state);
+        }
+        if let Some(progress) = progress.as_mut() {
+            progress.refresh(state.steps);
+        }
+        let max_steps = state.steps.saturating_add(FAST_SLICE);
+        if run_slice(state, max_steps)? {
+            return Ok(());
+        }
+    }
+}
+
+fn output_cells(matches: &ArgMatches, program: &GotoProgram) -> Result<Vec<(String, RegisterIndex)>, Error> {
+    matches.value_of("output cells").map_or(Ok(vec![]), |text| program.parse_output_cells(text)).context("parsing --output-cells")
+}
+
+fn run_batch(source_file: &str, inputs: &Path, matches: &ArgMatches) -> Result<(), Error> {
+    let (_, program) = load_program_with(source_file, &parse_options(matches))?;
+    let bytecode = Bytecode::compile(&program).context("compiling program")?;
+    let max_steps = matches.value_of("batch max steps").unwrap().parse().context("parsing --batch-max-steps")?;
+    let output_dir = matches.value_of("output dir").map(Path::new);
+    if let Some(output_dir) = output_dir {
+        std::fs::create_dir_all(output_dir).context("creating output directory")?;
+    }
+    let cells = output_cells(matches, &program)?;
+    let inputs = batch::input_files(inputs).context("listing inputs")?;
+    let snapshots = batch::run_files(&program, &bytecode, &inputs, max_steps, jobs(matches)?);
+    let mut runs = vec![];
+    for (input, snapshot) in inputs.iter().zip(snapshots) {
+        if let Some(output_dir) = output_dir {
+            let result = output_dir.join(input.file_stem().unwrap()).with_extension("json");
+            let json = if cells.is_empty() { snapshot.to_json() } else { snapshot.to_json_with_result(&cells) };
+            write(result, json.to_string() + "\n").context("writing result")?;
+        }
+        runs.push((input.file_name().unwrap().to_string_lossy().to_string(), snapshot));
+    }
+    print!("{}", batch::summary(&runs, &cells));
+    if runs.iter().any(|(_, snapshot)| snapshot.status != Status::Stopped) {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn run_command(matches: &ArgMatches) -> Result<(), Error> {
+    let source_file = matches.value_of("source file").unwrap();
+    if matches.is_present("watch") {
+        let max_steps = matches.value_of("watch max steps").unwrap().parse().context("parsing --watch-max-steps")?;
+        watch::run(source_file, matches.value_of("input").unwrap(), max_steps);
+        return Ok(());
+    }
+    if let Some(inputs) = matches.value_of("inputs") {
+        return run_batch(source_file, Path::new(inputs), matches);
+    }
+    let language = matches.value_of("language").unwrap();
+    if matches.is_present("step bound") && language != "loop" {
+        return Err(invalid("parsing --step-bound", "it needs --language loop, other programs may not stop"));
+    }
+    let mut statements = None;
+    let (program_code, program) = if language == "goto" {
+        load_program_with(source_file, &parse_options(matches))?
+    } else {
+        let program_code = read_to_string(source_file).context("reading code")?;
+        if matches.is_present("step bound") {
+            statements = Some(loop_lang::parse(&program_code).context("translating code")?);
+        }
+        let program_code = to_goto(language, program_code)
+            .context("translating code")?;
+        let program = parse_source(program_code.clone(), &parse_options(matches))?;
+        (program_code, program)
+    };
+    let program = if matches.is_present("optimize") {
+        let (optimized, stats) = optimize::optimize(&program);
+        println!("optimized: {}", stats);
+        optimized
+    } else {
+        program
+    };
+    if let Some(cells) = program.header.cells.filter(|&cells| cells != transpile::Arithmetic::Checked) {
+        return Err(invalid("running program", &format!("the program declares .cells {}, only goto compile can translate it with that arithmetic", transpile::Arithmetic::NAMES[cells as usize])));
+    }
+    let given = ["input", "resume", "interactive", "bank input"].iter().any(|arg| matches.is_present(arg));
+    if !given && program.declared_cells() == 0 {
+        return Err(invalid("running program", "--input is required unless the program declares its memory with .memory, .data or .init"));
+    }
+    let mut folded_run = None;
+    let program = if matches.is_present("fold input") {
+        let folded = optimize::fold_prefix(&program, program_input(matches, &program)?);
+        println!("folded {} steps into the input, eliminated {} instructions", folded.folded_steps, folded.eliminated_instructions);
+        folded_run = Some((folded.memory, folded.start, folded.folded_steps));
+        folded.program
+    } else {
+        program
+    };
     println!("program = {:?}", program);
-    let mut state = GotoProgramState {
-        program: &program,
-        program_counter: 0,
-        memory
+    let mut state = match matches.value_of("resume") {
+        Some(resume) => {
+            let snapshot_file = if Path::new(resume).is_dir() {
+                checkpoint::latest(Path::new(resume)).context("looking for checkpoints")?
+            } else {
+                PathBuf::from(resume)
+            };
+            let snapshot_text = read_to_string(snapshot_file).context("reading snapshot")?;
+            let snapshot = Snapshot::parse(&snapshot_text).context("parsing snapshot")?;
+            let state = snapshot.restore(&program).context("resuming snapshot")?;
+            println!("resuming at step {}", state.steps);
+            state
+        }
+        None => match folded_run {
+            // continue where the folded prefix ended and count its steps
+            Some((memory, start, steps)) => {
+                let mut state = GotoProgramState::new(&program, memory);
+                state.program_counter = start;
+                state.steps = steps;
+                state
+            }
+            None => GotoProgramState::new(&program, program_input(matches, &program)?),
+        },
     };
+    if let Some(code_base) = matches.value_of("von neumann") {
+        let code_base = code_base.parse().context("parsing --von-neumann")?;
+        state.memory = goto::neumann::load(&program, std::mem::take(&mut state.memory), code_base)
+            .context("loading the program into the memory")?;
+        state.code_base = Some(code_base);
+    }
+    let cells = output_cells(matches, &program)?;
+    if let Some((name, _)) = cells.iter().find(|(_, cell)| *cell >= state.memory.len()) {
+        return Err(invalid("parsing --output-cells", &format!("cell {} is beyond the memory of {} cells", name, state.memory.len())));
+    }
+    let encoding = goto::console::Encoding::from_name(matches.value_of("output encoding").unwrap()).unwrap();
+    state.console = Box::new(goto::console::Terminal::new(encoding, matches.is_present("interactive")));
+    let initial_input = state.memory.clone();
+    state.random = goto::random::Random::new(matches.value_of("seed").unwrap().parse().context("parsing --seed")?);
     println!("input: {:?}", state.memory);
-    state.run();
-    println!("result: {:?}", state.memory);
+    if let Some(statements) = statements {
+        println!("step bound: {}", loop_lang::step_bound(&statements, &state.memory));
+    }
+    let mut stall_detector = if matches.is_present("stall detector") {
+        let window = matches.value_of("stall window").unwrap().parse().context("parsing --stall-window")?;
+        let pcs = matches.value_of("stall pcs").unwrap().parse().context("parsing --stall-pcs")?;
+        Some(StallDetector::new(window, pcs))
+    } else {
+        None
+    };
+    let stop_on_stall = matches.value_of("stall action") == Some("stop");
+    let mut cycle_detector = if matches.is_present("detect cycles") { Some(CycleDetector::new()) } else { None };
+    let mut checkpointer = matches.value_of("checkpoint dir").map(|dir| {
+        let every = matches.value_of("checkpoint every").unwrap().parse().context("parsing --checkpoint-every")?;
+        let keep = matches.value_of("checkpoint keep").unwrap().parse().context("parsing --checkpoint-keep")?;
+        Checkpointer::new(PathBuf::from(dir), every, keep).context("preparing checkpoints")
+    }).transpose()?;
+    let status_socket = matches.value_of("status socket").map(|path| {
+        let _ = remove_file(path);
+        let listener = UnixListener::bind(path).context("creating the status socket")?;
+        listener.set_nonblocking(true).context("creating the status socket")?;
+        Ok(listener)
+    }).transpose()?;
+    signals::catch_interrupt();
+    signals::catch_status_request();
+    let animated = ["step delay", "paused", "visualize"].iter().any(|arg| matches.is_present(arg));
+    let mut animation = if animated {
+        let delay = matches.value_of("step delay").map(animation::parse_delay)
+            .unwrap_or(Ok(Duration::from_millis(0)))
+            .context("parsing --step-delay")?;
+        Some(Animation { delay, paused: matches.is_present("paused"), visualize: matches.is_present("visualize"), drawn_lines: 0 })
+    } else {
+        None
+    };
+    let trace = matches.value_of("trace").unwrap();
+    let palette = Palette::new(matches.value_of("color").unwrap(), atty::Stream::Stdout);
+    let error_palette = Palette::new(matches.value_of("color").unwrap(), atty::Stream::Stderr);
+    let mut progress = if matches.is_present("progress") { Progress::new() } else { None };
+    let mut profile = if matches.is_present("profile") { Some(Profile::new(&program)) } else { None };
+    let coverage = if matches.is_present("coverage") { Some(Arc::new(Mutex::new(Coverage::new(&program)))) } else { None };
+    if let Some(coverage) = &coverage {
+        state.observers.push(Box::new(coverage.clone()));
+    }
+    let fingerprint = if matches.is_present("fingerprint") { Some(Arc::new(Mutex::new(Fingerprint::default()))) } else { None };
+    if let Some(fingerprint) = &fingerprint {
+        state.observers.push(Box::new(fingerprint.clone()));
+    }
+    let report = matches.value_of("report").map(|_| Arc::new(Mutex::new(Report::new(&program, &state.memory))));
+    if let Some(report) = &report {
+        state.observers.push(Box::new(report.clone()));
+    }
+    let mut golden = matches.value_of("expect trace").map(|path| {
+        let text = read_to_string(path).context("reading golden trace")?;
+        GoldenTrace::parse(&text).context("parsing golden trace")
+    }).transpose()?;
+    let mut recorded_trace = matches.value_of("record trace").map(|_| String::new());
+    let mut last_write = None;
+    let mut interrupted = false;
+    let result = if matches.is_present("fast") {
+        run_fast(&mut state, matches.is_present("jit"), progress.as_mut(), status_socket.as_ref(), &mut interrupted).map_err(RuntimeError::Backend)
+    } else {
+        loop {
+            if signals::interrupted() {
+                interrupted = true;
+                break Ok(());
+            }
+            if signals::take_status_request() {
+                eprintln!("{}", status_report(&state));
+            }
+            if let Some(listener) = status_socket.as_ref().filter(|_| state.steps.is_multiple_of(STATUS_SOCKET_INTERVAL)) {
+                answer_status_clients(listener, &state);
+            }
+            if let Some(animation) = animation.as_mut() {
+                animation.frame(&state).context("animating the run")?;
+            } else if let (Ok(instruction), "full") = (state.instruction(), trace) {
+                let shown = if program.aliases.is_empty() { format!("{:?}", instruction) } else { program.show(&instruction) };
+                println!("{:?}: {}", state.program_counter, palette.instruction(&shown));
+                println!("mem: {}", palette.memory(&state.memory, last_write));
+            }
+            if let Some(stall) = stall_detector.as_mut().and_then(|d| d.observe(&state)) {
+                if stop_on_stall {
+                    break Err(RuntimeError::Stalled(stall.to_string()));
+                }
+                eprintln!("{}", error_palette.warning(&format!("warning: {}", stall)));
+            }
+            if let Some(cycle) = cycle_detector.as_mut().and_then(|d| d.observe(&state)) {
+                break Err(RuntimeError::Stalled(cycle.to_string()));
+            }
+            if let Some(checkpointer) = checkpointer.as_mut() {
+                if let Err(e) = checkpointer.observe(&state) {
+                    eprintln!("{}", error_palette.warning(&format!("warning: {}", e)));
+                }
+            }
+            if let Some(progress) = progress.as_mut() {
+                progress.tick(state.steps);
+            }
+            let print_diff = animation.is_none() && trace == "diff";
+            // in von Neumann mode the program in the memory may differ from the parsed one
+            let executed = if print_diff || golden.is_some() || recorded_trace.is_some() { state.instruction().ok() } else { None };
+            let (steps, program_counter) = (state.steps, state.program_counter);
+            let started = profile.as_ref().map(|_| Instant::now());
+            let mut step_effect = None;
+            let step = state.step_with_hook(&mut |effect| {
+                step_effect = Some(*effect);
+                last_write = effect.write.map(|write| write.cell);
+                if print_diff {
+                    let instruction = palette.instruction(&executed.map(|instruction| program.show(&instruction)).unwrap_or_default());
+                    match effect.write {
+                        Some(write) => println!("step {}: {} \u{2192} {}", steps + 1, instruction, palette.changed(&program.show_write(&write))),
+                        None => println!("step {}: {}", steps + 1, instruction),
+                    }
+                }
+            });
+            if let (Some(profile), Some(started), Ok(_)) = (profile.as_mut(), started, &step) {
+                profile.record(&program, program_counter, state.program_counter, started.elapsed());
+            }
+            if let (Some(effect), true) = (step_effect, golden.is_some() || recorded_trace.is_some()) {
+                let instruction = executed.map(|instruction| instruction.to_string()).unwrap_or_default();
+                let event = TraceEvent::new(state.steps, &effect, instruction, state.program_counter);
+                if let Some(recorded_trace) = recorded_trace.as_mut() {
+                    *recorded_trace += &(event.to_json().to_string() + "\n");
+                }
+                if let Err(divergence) = golden.as_mut().map_or(Ok(()), |golden| golden.observe(&event)) {
+                    break Err(RuntimeError::Diverged(divergence.to_string()));
+                }
+            }
+            match step {
+                Ok(true) => break Ok(()),
+                Ok(false) => {}
+                Err(e) => break Err(e),
+            }
+        }
+    };
+    let result = match (result, &golden) {
+        (Ok(()), Some(golden)) if !interrupted => golden.finish().map_err(|divergence| RuntimeError::Diverged(divergence.to_string())),
+        (result, _) => result,
+    };
+    if let (Some(path), Some(recorded_trace)) = (matches.value_of("record trace"), &recorded_trace) {
+        write(path, recorded_trace).context("writing trace")?;
+    }
+    if let Some(path) = matches.value_of("save input") {
+        let input = goto::console::completed_input(&initial_input, state.console.answers());
+        let text = input.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ");
+        write(path, text + "\n").context("saving the input")?;
+    }
+    if let Some(progress) = &progress {
+        progress.finish();
+    }
+    if animation.is_some() {
+        println!();
+    }
+    if let Some(path) = matches.value_of("status socket") {
+        let _ = remove_file(path);
+    }
+    if let Some(profile) = &profile {
+        print!("{}", profile.report(&program, PROFILE_LINES));
+    }
+    if let Some(coverage) = coverage.as_ref().and_then(|coverage| coverage.lock().ok()) {
+        print!("{}", coverage.annotated_source(&program, &program_code));
+        println!("{}", coverage.summary(&program));
+    }
+    let status = match &result {
+        Ok(()) if interrupted => Status::Running,
+        Ok(()) => Status::Stopped,
+        Err(e) => Status::Error(e.to_string()),
+    };
+    for export_file in matches.value_of("export state").into_iter().chain(matches.value_of("snapshot on exit")) {
+        let snapshot = Snapshot::of(&state, status.clone());
+        let json = if cells.is_empty() { snapshot.to_json() } else { snapshot.to_json_with_result(&cells) };
+        write(export_file, json.to_string() + "\n")
+            .context("exporting state")?;
+    }
+    if let (Some(path), Some(report)) = (matches.value_of("report"), report.as_ref().and_then(|report| report.lock().ok())) {
+        write(path, report.to_html(source_file, &program_code, &status.to_string())).context("writing the report")?;
+    }
+    if interrupted {
+        eprintln!("interrupted at pc {} after {} steps", state.program_counter, state.steps);
+        eprintln!("mem: {:?}", state.memory);
+        exit(INTERRUPTED_EXIT_CODE);
+    }
+    if let Err(error) = result {
+        return Err(Error::Runtime { program_counter: state.program_counter, steps: state.steps, error });
+    }
+    if !cells.is_empty() {
+        println!("result: {}", snapshot::show_output_cells(&state.memory, &cells));
+    } else {
+        println!("result: {:?}", state.memory);
+    }
+    if let Some(fingerprint) = fingerprint.as_ref().and_then(|fingerprint| fingerprint.lock().ok()) {
+        println!("fingerprint: {}", *fingerprint);
+    }
+    if program.banks > 1 && cells.is_empty() {
+        for (bank, memory) in banks::split(&state.memory, program.banks).iter().enumerate() {
+            println!("bank {}: {:?}", bank, memory);
+        }
+    }
+    if let Some(cell) = matches.value_of("exit from cell") {
+        let cell = cell.parse().context("parsing --exit-from-cell")?;
+        exit(goto::exit_status(&state.memory, cell).context("reading the exit status")?);
+    }
+    Ok(())
+}
+
+fn main() {
+    let matches = cli_arguments();
+    let result = match matches.subcommand() {
+        ("fmt", Some(matches)) => fmt_command(matches),
+        ("lint", Some(matches)) => lint_command(matches),
+        ("analyze", Some(matches)) => analyze_command(matches),
+        ("stats", Some(matches)) => stats_command(matches),
+        ("verify", Some(matches)) => verify_command(matches),
+        ("equiv", Some(matches)) => equiv_command(matches),
+        ("difftest", Some(matches)) => difftest_command(matches),
+        ("fuzz", Some(matches)) => fuzz_command(matches),
+        ("grade", Some(matches)) => grade_command(matches),
+        ("mutate", Some(matches)) => mutate_command(matches),
+        ("superopt", Some(matches)) => superopt_command(matches),
+        ("test", Some(matches)) => test_command(matches),
+        ("dap", Some(_)) => {
+            dap::run();
+            Ok(())
+        }
+        ("serve", Some(matches)) => serve_command(matches),
+        ("jupyter-kernel", Some(matches)) => jupyter_kernel_command(matches),
+        ("rpc", Some(_)) => rpc::run().context("serving JSON-RPC"),
+        ("state-diff", Some(matches)) => state_diff_command(matches),
+        ("pipeline", Some(matches)) => pipeline_command(matches),
+        ("trace-diff", Some(matches)) => trace_diff_command(matches),
+        ("tui", Some(matches)) => tui_command(matches),
+        ("compile", Some(matches)) => compile_command(matches),
+        ("symbolize", Some(matches)) => symbolize_command(matches),
+        ("import", Some(matches)) => import_command(matches),
+        ("grammar", Some(matches)) => grammar_command(matches),
+        ("doc", Some(matches)) => doc_command(matches),
+        ("completions", Some(matches)) => completions_command(matches),
+        ("manpage", Some(matches)) => manpage_command(matches),
+        ("link", Some(matches)) => link_command(matches),
+        ("disasm", Some(matches)) => disasm_command(matches),
+        ("specialize", Some(matches)) => specialize_command(matches),
+        ("minimize", Some(matches)) => minimize_command(matches),
+        ("encode", Some(matches)) => encode_command(matches),
+        ("decode", Some(matches)) => decode_command(matches),
+        ("debug", Some(matches)) => debug_command(matches),
+        ("bench", Some(matches)) => bench_command(matches),
+        ("complexity", Some(matches)) => complexity_command(matches),
+        ("sandbox", Some(matches)) => sandbox_command(matches),
+        ("repl", Some(matches)) => {
+            let history_file = matches.value_of("history file").map(PathBuf::from)
+                .or_else(repl::history_file);
+            repl::run(history_file);
+            Ok(())
+        }
+        _ => run_command(&matches),
+    };
+    if let Err(error) = result {
+        let palette = Palette::new(matches.value_of("color").unwrap(), atty::Stream::Stderr);
+        eprintln!("{}", palette.error(&error.to_string()));
+        exit(error.exit_code());
+    }
 }
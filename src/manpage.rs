@@ -0,0 +1,116 @@
+use clap::App;
+
+/// The help clap shows for the arguments, unwrapped so roff can fill it.
+fn help(app: &App<'static, 'static>, args: &[&str]) -> String {
+    let app = app.clone().set_term_width(0);
+    match app.get_matches_from_safe(args.iter().chain(&["--help"])) {
+        Err(e) => e.message,
+        Ok(_) => String::new(),
+    }
+}
+
+/// The sections of a help text with their lines, the lines before the first
+/// heading under an empty one.
+fn sections(help: &str) -> Vec<(String, Vec<String>)> {
+    let mut sections = vec![(String::new(), vec![])];
+    for line in help.lines() {
+        if !line.starts_with(' ') && line.ends_with(':') {
+            sections.push((line.trim_end_matches(':').to_string(), vec![]));
+        } else if !line.trim().is_empty() {
+            sections.last_mut().unwrap().1.push(line.trim_end().to_string());
+        }
+    }
+    sections
+}
+
+/// The term and description of a line like `    -o, --output <output>    where to...`.
+fn entry(line: &str) -> (String, String) {
+    let line = line.trim();
+    match line.find("  ") {
+        Some(pos) => (line[..pos].to_string(), line[pos..].trim().to_string()),
+        None => (line.to_string(), String::new()),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with('.') || text.starts_with('\'') { format!("\\&{}", text) } else { text }
+}
+
+/// The entries of a section as a list, lines indented deeper continue the
+/// entry before them.
+fn entries(lines: &[String]) -> String {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let first = lines.first().map_or(0, |line| indent(line));
+    let mut text = String::new();
+    for line in lines {
+        if indent(line) > first {
+            text += &format!("{}\n", escape(line.trim()));
+            continue;
+        }
+        let (term, description) = entry(line);
+        text += &format!(".TP\n\\fB{}\\fR\n", escape(&term));
+        if !description.is_empty() {
+            text += &format!("{}\n", escape(&description));
+        }
+    }
+    text
+}
+
+/// The man page of the app with a section for every subcommand, generated
+/// from the help clap shows so both always agree.
+pub fn render(app: &App<'static, 'static>) -> String {
+    let name = app.get_name().to_string();
+    let main = sections(&help(app, &[&name]));
+    let header: Vec<&String> = main[0].1.iter().collect();
+    let about = header.get(1).map_or(String::new(), |about| escape(about));
+    let version = header.first().map_or(name.clone(), |version| escape(version));
+    let mut page = format!(".TH {} 1 \"\" \"{}\"\n.SH NAME\n{} \\- {}\n", name.to_uppercase(), version, name, about);
+    let mut subcommands = vec![];
+    for (heading, lines) in &main[1..] {
+        match heading.as_str() {
+            "USAGE" => page += &format!(".SH SYNOPSIS\n{}\n", lines.iter().map(|line| escape(line.trim())).collect::<Vec<_>>().join("\n.br\n")),
+            "SUBCOMMANDS" => {
+                page += &format!(".SH COMMANDS\n{}", entries(lines));
+                subcommands = lines.iter().map(|line| entry(line).0).filter(|subcommand| subcommand != "help").collect();
+            }
+            _ => page += &format!(".SH {}\n{}", heading, entries(lines)),
+        }
+    }
+    for subcommand in subcommands {
+        let sections = sections(&help(app, &[&name, &subcommand]));
+        page += &format!(".SS \"{} {}\"\n", name, escape(&subcommand));
+        if let Some(about) = sections[0].1.get(1) {
+            page += &format!("{}\n", escape(about));
+        }
+        for (heading, lines) in &sections[1..] {
+            let mut heading = heading.to_lowercase();
+            heading[..1].make_ascii_uppercase();
+            match heading.as_str() {
+                "Usage" => page += &format!(".PP\n\\fBUsage:\\fR {}\n", escape(lines.join(" ").trim())),
+                _ => page += &format!(".PP\n\\fB{}:\\fR\n.RS\n{}.RE\n", heading, entries(lines)),
+            }
+        }
+    }
+    page
+}
+
+#[test]
+fn test_manpage() {
+    use clap::{Arg, SubCommand};
+
+    let app = App::new("tool")
+        .version("1.0")
+        .about("Does things")
+        .arg(Arg::with_name("input").short("i").long("input").takes_value(true).help("the file to read, defaults to stdin"))
+        .subcommand(SubCommand::with_name("fmt")
+            .about("Format .tool files")
+            .arg(Arg::with_name("check").long("check").help("only check")));
+    let page = render(&app);
+    assert!(page.starts_with(".TH TOOL 1 \"\" \"tool 1.0\"\n.SH NAME\ntool \\- Does things\n.SH SYNOPSIS\ntool [OPTIONS] [SUBCOMMAND]\n"), "{}", page);
+    assert!(page.contains(".SH OPTIONS\n.TP\n\\fB\\-i, \\-\\-input <input>\\fR\nthe file to read, defaults to stdin\n"), "{}", page);
+    assert!(page.contains(".SH COMMANDS\n.TP\n\\fBfmt\\fR\nFormat .tool files\n"), "{}", page);
+    assert!(page.contains(".SS \"tool fmt\"\nFormat .tool files\n.PP\n\\fBUsage:\\fR tool fmt [FLAGS]\n"), "{}", page);
+    assert!(page.contains(".PP\n\\fBFlags:\\fR\n.RS\n.TP\n\\fB\\-\\-check\\fR\nonly check\n"), "{}", page);
+    assert!(!page.contains(".SS \"tool help\""));
+}
@@ -0,0 +1,140 @@
+//! Shrinks a program that fails on an input to a small program that still
+//! fails the same way, by delta debugging: chunks of instructions are
+//! removed while the failure remains, with the chunks halving down to single
+//! instructions, and then GOTOZs are simplified to GOTOs.
+
+use crate::condition::Condition;
+use crate::optimize::remove;
+use crate::{GotoProgram, GotoProgramState, Instruction};
+
+/// What counts as the failure to preserve.
+#[derive(Debug, Clone)]
+pub enum Failure {
+    /// the run ends with an error that contains the text
+    Error(String),
+    /// the run ends with an error of the same kind, see `error_kind`
+    ErrorKind(String),
+    /// the run stops and the condition holds on the final memory
+    StopsWith(Condition),
+}
+
+/// The error message with every number replaced by `N`, so that
+/// `DEC 3 on a cell that is 0` and `DEC 1 on a cell that is 0` are alike.
+pub fn error_kind(message: &str) -> String {
+    let mut kind = String::new();
+    for c in message.chars() {
+        if !c.is_ascii_digit() {
+            kind.push(c);
+        } else if !kind.ends_with('N') {
+            kind.push('N');
+        }
+    }
+    kind
+}
+
+impl Failure {
+    /// The kind of error the program ends with on the input.
+    pub fn same_error(program: &GotoProgram, input: &[u64], within: u64) -> Result<Failure, String> {
+        let mut state = GotoProgramState::new(program, input.to_vec());
+        match state.run_with_limit(within) {
+            Err(e) => Ok(Failure::ErrorKind(error_kind(&e.to_string()))),
+            Ok(true) => Err("the program stops without an error on the input".to_string()),
+            Ok(false) => Err(format!("the program is still running after {} steps on the input", within)),
+        }
+    }
+
+    pub fn holds(&self, program: &GotoProgram, input: &[u64], within: u64) -> bool {
+        let mut state = GotoProgramState::new(program, input.to_vec());
+        match (self, state.run_with_limit(within)) {
+            (Failure::Error(text), Err(e)) => e.to_string().contains(text.as_str()),
+            (Failure::ErrorKind(kind), Err(e)) => error_kind(&e.to_string()) == *kind,
+            (Failure::StopsWith(condition), Ok(true)) => condition.eval(&state) == Ok(true),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Minimized {
+    pub program: GotoProgram,
+    /// how many candidate programs were run
+    pub runs: u64,
+}
+
+pub fn minimize(program: &GotoProgram, input: &[u64], failure: &Failure, within: u64) -> Result<Minimized, String> {
+    if !failure.holds(program, input, within) {
+        return Err("the program does not fail that way on the input".to_string());
+    }
+    let mut program = program.clone();
+    let mut runs = 0;
+    let mut fails = |candidate: &GotoProgram| {
+        runs += 1;
+        failure.holds(candidate, input, within)
+    };
+    loop {
+        let before = program.instructions.len();
+        let mut chunk = (before / 2).max(1);
+        loop {
+            let mut start = 0;
+            while start < program.instructions.len() {
+                let count = program.instructions.len();
+                let removed: Vec<_> = (0..count).map(|pc| pc >= start && pc < start + chunk).collect();
+                let mut candidate = program.clone();
+                remove(&mut candidate, &removed);
+                if fails(&candidate) {
+                    program = candidate;
+                } else {
+                    start += chunk;
+                }
+            }
+            if chunk == 1 {
+                break;
+            }
+            chunk /= 2;
+        }
+        let mut simplified = false;
+        for pc in 0..program.instructions.len() {
+            if let Instruction::GotoZ { goto_cell, .. } = program.instructions[pc] {
+                let mut candidate = program.clone();
+                candidate.instructions[pc] = Instruction::Goto { cell: goto_cell };
+                if fails(&candidate) {
+                    program = candidate;
+                    simplified = true;
+                }
+            }
+        }
+        if !simplified && program.instructions.len() == before {
+            break;
+        }
+    }
+    Ok(Minimized { program, runs })
+}
+
+#[test]
+fn test_minimize() {
+    let program = crate::parse_program("INC 1
+        INC 1
+        loop: GOTOZ 0 end
+        DEC 0
+        INC 2
+        GOTO loop
+        end: INC 3
+        DEC 1
+        DEC 1
+        DEC 1
+        STOP".to_string()).unwrap();
+    assert_eq!("DEC N on a cell that is N", error_kind("DEC 12 on a cell that is 0"));
+    let failure = Failure::same_error(&program, &[3, 0, 0, 0], 1000).unwrap();
+    let minimized = minimize(&program, &[3, 0, 0, 0], &failure, 1000).unwrap();
+    assert_eq!(vec![Instruction::Dec { cell: 1 }], minimized.program.instructions);
+    assert_eq!(vec![10], minimized.program.source_lines);
+
+    let mut stopping = program.clone();
+    stopping.instructions.remove(9);
+    stopping.source_lines.remove(9);
+    let failure = Failure::StopsWith(Condition::parse("[2] == 3").unwrap());
+    let minimized = minimize(&stopping, &[3, 0, 0, 0], &failure, 1000).unwrap();
+    assert_eq!(crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 2\nGOTO loop\nend: STOP".to_string()).unwrap().instructions,
+               minimized.program.instructions);
+    assert!(minimize(&program, &[3, 0, 0, 0], &Failure::Error("INC".to_string()), 1000).is_err());
+}
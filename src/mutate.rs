@@ -0,0 +1,118 @@
+//! Mutation testing: small changes to a program that a good test
+//! specification notices. Each mutant is run against the cases of a
+//! specification, a mutant that passes all of them survives and points at
+//! behavior the tests do not check.
+
+use std::fmt::Write;
+
+use crate::grading::{run_case, TestSpec};
+use crate::{ranges, GotoProgram, Instruction};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Mutant {
+    pub pc: usize,
+    pub mutated: Instruction,
+}
+
+/// Every mutant of the program: INC and DEC swapped, jump targets moved by
+/// one, GOTOZs testing another cell the program uses, GOTONZ turned into
+/// GOTOZ and GOTOEQ and GOTOGT swapped.
+pub fn mutants(program: &GotoProgram) -> Vec<Mutant> {
+    let count = program.instructions.len();
+    let cells = ranges::used_cells(program);
+    let mut mutants = vec![];
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        let mut add = |mutated| mutants.push(Mutant { pc, mutated });
+        let nearby = |target: usize| [target.checked_sub(1), Some(target + 1)].iter().flatten().cloned()
+            .filter(|t| *t <= count).collect::<Vec<_>>();
+        match *instruction {
+            Instruction::Inc { cell } => add(Instruction::Dec { cell }),
+            Instruction::Dec { cell } => add(Instruction::Inc { cell }),
+            Instruction::Goto { cell } => for target in nearby(cell) {
+                add(Instruction::Goto { cell: target });
+            },
+            Instruction::GotoZ { condition_cell, goto_cell } => {
+                for target in nearby(goto_cell) {
+                    add(Instruction::GotoZ { condition_cell, goto_cell: target });
+                }
+                for &other in cells.iter().filter(|c| **c != condition_cell) {
+                    add(Instruction::GotoZ { condition_cell: other, goto_cell });
+                }
+            }
+            Instruction::GotoNz { condition_cell, goto_cell } => {
+                for target in nearby(goto_cell) {
+                    add(Instruction::GotoNz { condition_cell, goto_cell: target });
+                }
+                add(Instruction::GotoZ { condition_cell, goto_cell });
+            }
+            Instruction::GotoEq { left, right, goto_cell } => {
+                for target in nearby(goto_cell) {
+                    add(Instruction::GotoEq { left, right, goto_cell: target });
+                }
+                add(Instruction::GotoGt { left, right, goto_cell });
+            }
+            Instruction::GotoGt { left, right, goto_cell } => {
+                for target in nearby(goto_cell) {
+                    add(Instruction::GotoGt { left, right, goto_cell: target });
+                }
+                add(Instruction::GotoEq { left, right, goto_cell });
+            }
+            Instruction::Stop | Instruction::Assert { .. } | Instruction::Print { .. } | Instruction::Read { .. }
+            | Instruction::OutC { .. } | Instruction::Rand { .. } | Instruction::Push { .. } | Instruction::Pop { .. }
+            | Instruction::StoreI { .. } | Instruction::Nop => {}
+        }
+    }
+    mutants
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MutantResult {
+    pub mutant: Mutant,
+    /// the first case that failed, `None` if the mutant survived
+    pub killed_by: Option<String>,
+}
+
+/// Runs every mutant against the cases, which the program itself has to pass.
+pub fn run(program: &GotoProgram, spec: &TestSpec) -> Result<Vec<MutantResult>, String> {
+    if let Some(failed) = spec.cases.iter().map(|case| run_case(program, case, &spec.limits)).find(|r| !r.passed()) {
+        return Err(format!("the program itself fails {}: {}", failed.name, failed.outcome));
+    }
+    Ok(mutants(program).into_iter().map(|mutant| {
+        let mut mutated = program.clone();
+        mutated.instructions[mutant.pc] = mutant.mutated;
+        let killed_by = spec.cases.iter()
+            .find(|case| !run_case(&mutated, case, &spec.limits).passed())
+            .map(|case| case.name.clone());
+        MutantResult { mutant, killed_by }
+    }).collect())
+}
+
+pub fn report(program: &GotoProgram, results: &[MutantResult]) -> String {
+    let killed = results.iter().filter(|r| r.killed_by.is_some()).count();
+    let score = if results.is_empty() { 100.0 } else { 100.0 * killed as f64 / results.len() as f64 };
+    let mut report = format!("mutation score: {} of {} mutants killed ({:.1}%)\n", killed, results.len(), score);
+    for result in results.iter().filter(|r| r.killed_by.is_none()) {
+        let Mutant { pc, mutated } = result.mutant;
+        writeln!(report, "  survived: line {}: {} -> {}", program.source_lines[pc], program.instructions[pc], mutated).unwrap();
+    }
+    report
+}
+
+#[test]
+fn test_mutate() {
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    // 2 targets and 1 other cell for the GOTOZ, 2 swaps and 1 target for the GOTO to 0
+    assert_eq!(6, mutants(&program).len());
+
+    let weak = TestSpec::parse("max_steps 1000\ncase\ninput 0 0\nexpect 1=0").unwrap();
+    // only the moved exits of the loop are noticed, by running forever or past the end
+    assert_eq!("mutation score: 2 of 6 mutants killed (33.3%)
+  survived: line 1: GOTOZ 0 4 -> GOTOZ 1 4
+  survived: line 2: DEC 0 -> INC 0
+  survived: line 3: INC 1 -> DEC 1
+  survived: line 4: GOTO 0 -> GOTO 1
+", report(&program, &run(&program, &weak).unwrap()));
+    let strong = TestSpec::parse("max_steps 1000\ncase zero\ninput 0 0\nexpect 0=0 1=0\ncase three\ninput 3 1\nexpect 0=0 1=4").unwrap();
+    assert!(run(&program, &strong).unwrap().iter().all(|r| r.killed_by.is_some()));
+    assert!(run(&program, &TestSpec::parse("case\ninput 1 0\nexpect 1=2").unwrap()).is_err());
+}
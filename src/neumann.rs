@@ -0,0 +1,143 @@
+//! The von Neumann mode, in which the program lies in the memory it works
+//! on and can rewrite itself. Every instruction takes three cells starting
+//! at the code base: its opcode as in the `.gbc` format, with 12 for
+//! STOREI, 13 for GOTONZ and 16 for NOP, and up to two operands, unused
+//! ones are 0. GOTOEQ and GOTOGT have three operands and cannot be loaded.
+//! The machine decodes the instruction at the program counter from these
+//! cells before every step, so whatever a step writes there is what runs next.
+
+use goto_core::Op;
+
+use crate::{GotoProgram, Instruction, RegisterIndex};
+
+pub use goto_core::CELLS_PER_INSTRUCTION;
+
+fn encode(instruction: Instruction) -> Result<[u64; CELLS_PER_INSTRUCTION], String> {
+    Ok(match instruction {
+        Instruction::Stop => [0, 0, 0],
+        Instruction::Inc { cell } => [1, cell as u64, 0],
+        Instruction::Dec { cell } => [2, cell as u64, 0],
+        Instruction::Goto { cell } => [3, cell as u64, 0],
+        Instruction::GotoZ { condition_cell, goto_cell } => [4, condition_cell as u64, goto_cell as u64],
+        Instruction::Assert { cell, value } => [5, cell as u64, value],
+        Instruction::Print { cell } => [6, cell as u64, 0],
+        Instruction::Read { cell } => [7, cell as u64, 0],
+        Instruction::OutC { cell } => [8, cell as u64, 0],
+        Instruction::Rand { cell, max } => [9, cell as u64, max],
+        Instruction::Push { cell } => [10, cell as u64, 0],
+        Instruction::Pop { cell } => [11, cell as u64, 0],
+        Instruction::StoreI { cell, value } => [12, cell as u64, value],
+        Instruction::GotoNz { condition_cell, goto_cell } => [13, condition_cell as u64, goto_cell as u64],
+        Instruction::Nop => [16, 0, 0],
+        Instruction::GotoEq { .. } | Instruction::GotoGt { .. } => return Err(format!("{} has three operands, which do not fit into {} cells", instruction, CELLS_PER_INSTRUCTION)),
+    })
+}
+
+/// The cells of the program, to be put at the code base.
+pub fn image(program: &GotoProgram) -> Result<Vec<u64>, String> {
+    let mut cells = vec![];
+    for &instruction in &program.instructions {
+        cells.extend_from_slice(&encode(instruction)?);
+    }
+    Ok(cells)
+}
+
+/// The memory of a run with the input below the code base and the program from there on.
+pub fn load(program: &GotoProgram, mut input: Vec<u64>, code_base: RegisterIndex) -> Result<Vec<u64>, String> {
+    if input.len() > code_base {
+        return Err(format!("the input of {} cells reaches into the program at cell {}", input.len(), code_base));
+    }
+    input.resize(code_base, 0);
+    input.extend(image(program)?);
+    Ok(input)
+}
+
+impl From<Op> for Instruction {
+    fn from(op: Op) -> Instruction {
+        match op {
+            Op::Stop => Instruction::Stop,
+            Op::Inc(cell) => Instruction::Inc { cell },
+            Op::Dec(cell) => Instruction::Dec { cell },
+            Op::Goto(cell) => Instruction::Goto { cell },
+            Op::GotoZ(condition_cell, goto_cell) => Instruction::GotoZ { condition_cell, goto_cell },
+            Op::Assert(cell, value) => Instruction::Assert { cell, value },
+            Op::Print(cell) => Instruction::Print { cell },
+            Op::Read(cell) => Instruction::Read { cell },
+            Op::OutC(cell) => Instruction::OutC { cell },
+            Op::Rand(cell, max) => Instruction::Rand { cell, max },
+            Op::Push(cell) => Instruction::Push { cell },
+            Op::Pop(cell) => Instruction::Pop { cell },
+            Op::StoreI(cell, value) => Instruction::StoreI { cell, value },
+            Op::GotoNz(condition_cell, goto_cell) => Instruction::GotoNz { condition_cell, goto_cell },
+            Op::GotoEq(left, right, goto_cell) => Instruction::GotoEq { left, right, goto_cell },
+            Op::GotoGt(left, right, goto_cell) => Instruction::GotoGt { left, right, goto_cell },
+            Op::Nop => Instruction::Nop,
+        }
+    }
+}
+
+impl From<Instruction> for Op {
+    fn from(instruction: Instruction) -> Op {
+        match instruction {
+            Instruction::Stop => Op::Stop,
+            Instruction::Inc { cell } => Op::Inc(cell),
+            Instruction::Dec { cell } => Op::Dec(cell),
+            Instruction::Goto { cell } => Op::Goto(cell),
+            Instruction::GotoZ { condition_cell, goto_cell } => Op::GotoZ(condition_cell, goto_cell),
+            Instruction::Assert { cell, value } => Op::Assert(cell, value),
+            Instruction::Print { cell } => Op::Print(cell),
+            Instruction::Read { cell } => Op::Read(cell),
+            Instruction::OutC { cell } => Op::OutC(cell),
+            Instruction::Rand { cell, max } => Op::Rand(cell, max),
+            Instruction::Push { cell } => Op::Push(cell),
+            Instruction::Pop { cell } => Op::Pop(cell),
+            Instruction::StoreI { cell, value } => Op::StoreI(cell, value),
+            Instruction::GotoNz { condition_cell, goto_cell } => Op::GotoNz(condition_cell, goto_cell),
+            Instruction::GotoEq { left, right, goto_cell } => Op::GotoEq(left, right, goto_cell),
+            Instruction::GotoGt { left, right, goto_cell } => Op::GotoGt(left, right, goto_cell),
+            Instruction::Nop => Op::Nop,
+        }
+    }
+}
+
+/// Decodes the instruction for the program counter from the memory with the
+/// decoder of the no_std core, any value in the cells is either an
+/// instruction or an error.
+pub fn fetch(memory: &[u64], code_base: RegisterIndex, program_counter: RegisterIndex) -> Result<Instruction, String> {
+    goto_core::decode(memory, code_base, program_counter).map(Instruction::from).map_err(|fault| fault.to_string())
+}
+
+#[test]
+fn test_neumann() {
+    // turns the INC 0 in the loop into an INC 2 after the first round
+    let program = crate::parse_program("loop: GOTOZ 1 end
+        INC 0
+        STOREI 8 2
+        DEC 1
+        GOTO loop
+        end: STOP".to_string()).unwrap();
+    let memory = load(&program, vec![0, 3], 4).unwrap();
+    assert_eq!(vec![0, 3, 0, 0, 4, 1, 5, 1, 0, 0], memory[..10].to_vec());
+    for (pc, &instruction) in program.instructions.iter().enumerate() {
+        assert_eq!(Ok(instruction), fetch(&memory, 4, pc));
+    }
+    let mut state = crate::GotoProgramState::new(&program, memory);
+    state.code_base = Some(4);
+    while !state.step().unwrap() {}
+    assert_eq!(vec![1, 0, 2, 0], state.memory[..4].to_vec());
+    assert_eq!(Ok(Instruction::Inc { cell: 2 }), fetch(&state.memory, 4, 1));
+
+    assert_eq!(Err("program counter 6 is outside of the program".to_string()), fetch(&state.memory, 4, 6));
+    assert_eq!(Err("cell 1: 14 is not an opcode".to_string()), fetch(&[0, 14, 0, 0], 1, 0));
+    assert!(load(&program, vec![1; 5], 4).is_err());
+    let compare = crate::parse_program("GOTOEQ 0 1 0".to_string()).unwrap();
+    assert!(load(&compare, vec![], 4).is_err());
+    assert_eq!(compare.instructions[0], Instruction::from(Op::from(compare.instructions[0])));
+
+    // the core machine runs the same image on a fixed memory
+    let mut machine = goto_core::Machine::<22>::new([0; 22], 4);
+    machine.memory.copy_from_slice(&load(&program, vec![0, 3], 4).unwrap());
+    assert_eq!(Some(Ok(())), machine.run(100));
+    assert_eq!(state.memory, machine.memory.to_vec());
+    assert_eq!((state.program_counter, state.steps), (machine.program_counter, machine.steps));
+}
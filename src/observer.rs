@@ -0,0 +1,82 @@
+//! Watching the interpreter from the outside. Observers registered on a
+//! `GotoProgramState` hear about every step, write and taken jump, so
+//! tracing, coverage or watchpoints need no code in the interpreter loop.
+//! The bytecode and JIT backends do not call them.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::{GotoProgramState, Instruction, MemoryWrite, RegisterIndex};
+
+pub trait ExecutionObserver: fmt::Debug + Send {
+    /// Before the instruction at the program counter runs.
+    fn before_step(&mut self, _state: &GotoProgramState, _instruction: &Instruction) {}
+
+    fn on_memory_write(&mut self, _write: &MemoryWrite) {}
+
+    /// After a jump to `to` was taken.
+    fn on_jump(&mut self, _from: RegisterIndex, _to: RegisterIndex) {}
+}
+
+/// A shared observer, so that the caller keeps a handle to look at it after
+/// the run.
+impl<T: ExecutionObserver> ExecutionObserver for Arc<Mutex<T>> {
+    fn before_step(&mut self, state: &GotoProgramState, instruction: &Instruction) {
+        if let Ok(mut observer) = self.lock() {
+            observer.before_step(state, instruction);
+        }
+    }
+
+    fn on_memory_write(&mut self, write: &MemoryWrite) {
+        if let Ok(mut observer) = self.lock() {
+            observer.on_memory_write(write);
+        }
+    }
+
+    fn on_jump(&mut self, from: RegisterIndex, to: RegisterIndex) {
+        if let Ok(mut observer) = self.lock() {
+            observer.on_jump(from, to);
+        }
+    }
+}
+
+/// Collects the writes to some cells.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Watchpoints {
+    pub cells: Vec<RegisterIndex>,
+    pub hits: Vec<MemoryWrite>,
+}
+
+impl ExecutionObserver for Watchpoints {
+    fn on_memory_write(&mut self, write: &MemoryWrite) {
+        if self.cells.contains(&write.cell) {
+            self.hits.push(*write);
+        }
+    }
+}
+
+#[test]
+fn test_observers() {
+    #[derive(Debug, Default)]
+    struct Jumps(Vec<(RegisterIndex, RegisterIndex)>);
+
+    impl ExecutionObserver for Jumps {
+        fn on_jump(&mut self, from: RegisterIndex, to: RegisterIndex) {
+            self.0.push((from, to));
+        }
+    }
+
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![2, 0]);
+    let watchpoints = Arc::new(Mutex::new(Watchpoints { cells: vec![1], hits: vec![] }));
+    let jumps = Arc::new(Mutex::new(Jumps::default()));
+    let coverage = Arc::new(Mutex::new(crate::coverage::Coverage::new(&program)));
+    state.observers.push(Box::new(watchpoints.clone()));
+    state.observers.push(Box::new(jumps.clone()));
+    state.observers.push(Box::new(coverage.clone()));
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    assert_eq!(vec![MemoryWrite { cell: 1, old: 0, new: 1 }, MemoryWrite { cell: 1, old: 1, new: 2 }], watchpoints.lock().unwrap().hits);
+    assert_eq!(vec![(3, 0), (3, 0), (0, 4)], jumps.lock().unwrap().0);
+    assert!(coverage.lock().unwrap().executed.iter().all(|&executed| executed));
+    assert_eq!(3, state.observers.len());
+}
@@ -0,0 +1,304 @@
+//! Peephole rewrites that make programs take fewer steps.
+//!
+//! The passes run until none of them changes anything:
+//!
+//! - jumps to a `GOTO` jump to its target instead, a `GOTO` to a `STOP` stops
+//! - jumps to the next instruction are removed
+//! - an `INC x` directly followed by `DEC x` is removed unless something jumps
+//!   to the `DEC`, x may be outside of the memory or `ranges` finds that x may
+//!   be `u64::MAX`, where the `INC` fails. Only the cells the program declares
+//!   are surely in the memory.
+//! - `INC x` and `DEC x` directly after `STOREI x v` become part of the value
+//!   it stores, as long as nothing jumps between them and no `DEC` would fail
+//!
+//! Only the extended dialect has `STOREI`, and no instruction adds to a cell,
+//! so runs of `INC` and `DEC` without a `STOREI` before them stay. The
+//! optimized program computes the same memory and fails where the original
+//! one fails, in fewer steps.
+//!
+//! When the input is known, `fold_prefix` runs the start of the program up to
+//! the first instruction that would run a second time ahead of time. The
+//! memory it computed becomes the input of what is left of the program, which
+//! continues at `start` after `folded_steps` steps like the original run.
+
+use std::fmt;
+
+use crate::{lint, ranges, GotoProgram, GotoProgramState, Instruction};
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Stats {
+    pub threaded_jumps: usize,
+    pub removed_jumps: usize,
+    pub removed_pairs: usize,
+    pub merged_writes: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "threaded {} jumps, removed {} jumps to the next instruction and {} INC/DEC pairs, merged {} INC/DEC into STOREI",
+               self.threaded_jumps, self.removed_jumps, self.removed_pairs, self.merged_writes)
+    }
+}
+
+fn target(instruction: &Instruction) -> Option<usize> {
+    instruction.jump_target()
+}
+
+fn set_target(instruction: &mut Instruction, target: usize) {
+    match instruction {
+        Instruction::Goto { cell } | Instruction::GotoZ { goto_cell: cell, .. } | Instruction::GotoNz { goto_cell: cell, .. }
+        | Instruction::GotoEq { goto_cell: cell, .. } | Instruction::GotoGt { goto_cell: cell, .. } => *cell = target,
+        _ => {}
+    }
+}
+
+fn thread_jumps(instructions: &mut [Instruction]) -> usize {
+    let mut threaded = 0;
+    for pc in 0..instructions.len() {
+        let original = match target(&instructions[pc]) {
+            Some(target) => target,
+            None => continue,
+        };
+        let mut final_target = original;
+        // a GOTO cycle never leaves, so give up after visiting every instruction once
+        for _ in 0..instructions.len() {
+            match instructions.get(final_target) {
+                Some(&Instruction::Goto { cell }) if cell != final_target => final_target = cell,
+                _ => break,
+            }
+        }
+        if let (Instruction::Goto { .. }, Some(Instruction::Stop)) = (&instructions[pc], instructions.get(final_target)) {
+            instructions[pc] = Instruction::Stop;
+            threaded += 1;
+        } else if final_target != original {
+            set_target(&mut instructions[pc], final_target);
+            threaded += 1;
+        }
+    }
+    threaded
+}
+
+/// Removes the instructions marked in `removed` and moves jump targets along.
+pub(crate) fn remove(program: &mut GotoProgram, removed: &[bool]) {
+    let count = program.instructions.len();
+    let mut new_index = Vec::with_capacity(count + 1);
+    let mut kept = 0;
+    for is_removed in removed {
+        new_index.push(kept);
+        if !is_removed {
+            kept += 1;
+        }
+    }
+    new_index.push(kept);
+    let removed_count = count - kept;
+    let mut instructions = vec![];
+    let mut source_lines = vec![];
+    let kept_instructions = program.instructions.iter().zip(&program.source_lines).zip(removed).filter(|(_, removed)| !**removed);
+    for ((&instruction, &line), _) in kept_instructions {
+        let mut instruction = instruction;
+        if let Some(target) = target(&instruction) {
+            // targets outside of the program stay outside
+            set_target(&mut instruction, if target <= count { new_index[target] } else { target - removed_count });
+        }
+        instructions.push(instruction);
+        source_lines.push(line);
+    }
+    program.instructions = instructions;
+    program.source_lines = source_lines;
+}
+
+pub fn optimize(program: &GotoProgram) -> (GotoProgram, Stats) {
+    let mut program = program.clone();
+    let mut stats = Stats::default();
+    loop {
+        let threaded = thread_jumps(&mut program.instructions);
+        stats.threaded_jumps += threaded;
+        let count = program.instructions.len();
+        let jumped_to: Vec<_> = (0..=count).map(|pc| program.instructions.iter().any(|i| target(i) == Some(pc))).collect();
+        let analysis = ranges::analyze(&program, None);
+        let declared = program.declared_cells();
+        let mut removed = vec![false; count];
+        let mut pc = 0;
+        while pc < count {
+            match (program.instructions[pc], program.instructions.get(pc + 1)) {
+                (instruction, _) if target(&instruction) == Some(pc + 1) => {
+                    removed[pc] = true;
+                    stats.removed_jumps += 1;
+                }
+                (Instruction::Inc { cell }, Some(&Instruction::Dec { cell: next }))
+                    if cell == next && !jumped_to[pc + 1] && cell < declared
+                        && analysis.get(pc, cell).is_none_or(|range| range.high < u64::MAX) =>
+                {
+                    removed[pc] = true;
+                    removed[pc + 1] = true;
+                    stats.removed_pairs += 1;
+                    pc += 1;
+                }
+                (Instruction::StoreI { cell, mut value }, _) => {
+                    let mut next = pc + 1;
+                    while next < count && !jumped_to[next] {
+                        value = match program.instructions[next] {
+                            Instruction::Inc { cell: other } if other == cell && value < u64::MAX => value + 1,
+                            Instruction::Dec { cell: other } if other == cell && value > 0 => value - 1,
+                            _ => break,
+                        };
+                        removed[next] = true;
+                        stats.merged_writes += 1;
+                        next += 1;
+                    }
+                    program.instructions[pc] = Instruction::StoreI { cell, value };
+                    pc = next - 1;
+                }
+                _ => {}
+            }
+            pc += 1;
+        }
+        if threaded == 0 && !removed.contains(&true) {
+            return (program, stats);
+        }
+        remove(&mut program, &removed);
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Folded {
+    /// the instructions that can still run, starting where the folded prefix ended
+    pub program: GotoProgram,
+    /// the memory at the end of the folded prefix
+    pub memory: Vec<u64>,
+    /// where the folded prefix ended, past the jump that leads there from 0
+    pub start: usize,
+    pub folded_steps: u64,
+    pub eliminated_instructions: usize,
+}
+
+fn only_changes_memory(instruction: &Instruction) -> bool {
+    matches!(instruction, Instruction::Inc { .. } | Instruction::Dec { .. } | Instruction::Goto { .. }
+                          | Instruction::GotoZ { .. } | Instruction::GotoNz { .. } | Instruction::GotoEq { .. }
+                          | Instruction::GotoGt { .. } | Instruction::Assert { .. } | Instruction::StoreI { .. })
+}
+
+/// Runs the part of `program` on `memory` before the first instruction that
+/// would run twice, and removes the instructions that cannot run afterwards.
+/// A `STOP`, an instruction with effects beyond the memory or one that fails
+/// is left to the remaining program.
+pub fn fold_prefix(program: &GotoProgram, memory: Vec<u64>) -> Folded {
+    let count = program.instructions.len();
+    let mut state = GotoProgramState::new(program, memory);
+    let mut visited = vec![false; count];
+    while state.program_counter < count && !visited[state.program_counter] {
+        visited[state.program_counter] = true;
+        // a failing step leaves the state as it was
+        if !only_changes_memory(&program.instructions[state.program_counter]) || state.step().is_err() {
+            break;
+        }
+    }
+    let start = state.program_counter;
+    let mut reachable = vec![false; count];
+    let mut pending = vec![start];
+    while let Some(pc) = pending.pop() {
+        if pc < count && !reachable[pc] {
+            reachable[pc] = true;
+            pending.extend(lint::successors(program, pc));
+        }
+    }
+    let removed: Vec<_> = reachable.iter().map(|r| !r).collect();
+    let mut residual = program.clone();
+    // point a jump at the start that `remove` can move along with it
+    residual.instructions.push(Instruction::Goto { cell: start });
+    residual.source_lines.push(program.source_lines.get(start).cloned().unwrap_or(0));
+    remove(&mut residual, &[removed.as_slice(), &[false]].concat());
+    let entry = residual.instructions.pop().unwrap();
+    let line = residual.source_lines.pop().unwrap();
+    let mut start = 0;
+    if entry != (Instruction::Goto { cell: 0 }) {
+        residual.instructions.insert(0, entry);
+        residual.source_lines.insert(0, line);
+        for instruction in &mut residual.instructions {
+            if let Some(target) = target(instruction) {
+                set_target(instruction, target + 1);
+            }
+        }
+        start = target(&residual.instructions[0]).unwrap();
+    }
+    Folded {
+        program: residual,
+        memory: state.memory,
+        start,
+        folded_steps: state.steps,
+        eliminated_instructions: removed.iter().filter(|r| **r).count(),
+    }
+}
+
+#[test]
+fn test_optimize() {
+    let source = "start: GOTOZ 0 done
+        DEC 0
+        INC 1
+        DEC 1
+        GOTOZ 1 skip
+        skip: INC 1
+        GOTO back
+        back: GOTO start
+        done: GOTO end
+        end: STOP";
+    let program = crate::parse_program(source.to_string()).unwrap();
+    let (optimized, stats) = optimize(&program);
+    // cell 1 grows without bound in the loop, so its INC/DEC pair stays
+    let expected = crate::parse_program("GOTOZ 0 8\nDEC 0\nINC 1\nDEC 1\nINC 1\nGOTO 0\nGOTO 0\nSTOP\nSTOP".to_string()).unwrap();
+    assert_eq!(expected.instructions, optimized.instructions);
+    assert_eq!(vec![1, 2, 3, 4, 6, 7, 8, 9, 10], optimized.source_lines);
+    assert_eq!(Stats { threaded_jumps: 3, removed_jumps: 1, removed_pairs: 0, merged_writes: 0 }, stats);
+
+    let mut before = crate::GotoProgramState::new(&program, vec![4, 0]);
+    let mut after = crate::GotoProgramState::new(&optimized, vec![4, 0]);
+    assert_eq!(Ok(true), before.run_with_limit(1000));
+    assert_eq!(Ok(true), after.run_with_limit(1000));
+    assert_eq!(before.memory, after.memory);
+    assert!(after.steps < before.steps);
+
+    // INC x DEC x fails when x is u64::MAX or outside of the memory, so only a
+    // declared cell known to be smaller loses the pair
+    let optimized = |source: &str| optimize(&crate::parse_program(source.to_string()).unwrap());
+    assert_eq!(0, optimized(".memory 1\nINC 0\nDEC 0\nSTOP").1.removed_pairs);
+    assert_eq!(1, optimized(".memory 1\nRAND 0 5\nINC 0\nDEC 0\nSTOP").1.removed_pairs);
+    let (outside, stats) = optimized(".memory 1\nRAND 0 5\nINC 1\nDEC 1\nSTOP");
+    assert_eq!(0, stats.removed_pairs);
+    let mut state = GotoProgramState::new(&outside, vec![0]);
+    assert!(state.run_with_limit(10).is_err());
+
+    let (merged, stats) = optimized("STOREI 0 2\nINC 0\nINC 0\nDEC 0\nINC 1\nSTOREI 1 0\nDEC 1\nSTOP");
+    assert_eq!(crate::parse_program("STOREI 0 3\nINC 1\nSTOREI 1 0\nDEC 1\nSTOP".to_string()).unwrap().instructions, merged.instructions);
+    assert_eq!(3, stats.merged_writes);
+}
+
+#[test]
+fn test_fold_prefix() {
+    let program = crate::parse_program("INC 1\nINC 1\nGOTOZ 2 loop\nINC 0\nloop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let folded = fold_prefix(&program, vec![2, 0, 0]);
+    let expected = crate::parse_program("GOTOZ 0 4\nDEC 0\nINC 1\nGOTO 0\nSTOP".to_string()).unwrap();
+    assert_eq!(expected.instructions, folded.program.instructions);
+    assert_eq!((vec![1, 3, 0], 7, 4), (folded.memory.clone(), folded.folded_steps, folded.eliminated_instructions));
+    let mut state = GotoProgramState::new(&folded.program, folded.memory);
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    assert_eq!(vec![0, 4, 0], state.memory);
+    let mut original = GotoProgramState::new(&program, vec![2, 0, 0]);
+    assert_eq!(Ok(true), original.run_with_limit(100));
+    assert_eq!((0, original.steps), (folded.start, folded.folded_steps + state.steps));
+
+    // the remaining program starts in the middle, so it jumps there first
+    let program = crate::parse_program("GOTO b\na: DEC 0\nb: GOTOZ 0 end\nGOTO a\nend: STOP".to_string()).unwrap();
+    let folded = fold_prefix(&program, vec![2]);
+    let expected = crate::parse_program("GOTO 2\nDEC 0\nGOTOZ 0 4\nGOTO 1\nSTOP".to_string()).unwrap();
+    assert_eq!(expected.instructions, folded.program.instructions);
+    assert_eq!(vec![3, 2, 3, 4, 5], folded.program.source_lines);
+    assert_eq!((vec![1], 4, 1), (folded.memory.clone(), folded.folded_steps, folded.eliminated_instructions));
+    // continuing at `start` skips the jump there, so the steps add up
+    let mut state = GotoProgramState::new(&folded.program, folded.memory);
+    state.program_counter = folded.start;
+    state.steps = folded.folded_steps;
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    let mut original = GotoProgramState::new(&program, vec![2]);
+    assert_eq!(Ok(true), original.run_with_limit(100));
+    assert_eq!((2, original.memory, original.steps), (folded.start, state.memory, state.steps));
+}
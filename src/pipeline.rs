@@ -0,0 +1,96 @@
+//! Programs run one after another, the final memory of one is the input of
+//! the next. A map between two stages picks cells of the final memory and
+//! puts them where the next program expects its input, the other cells of
+//! the input are 0.
+
+use crate::bytecode::Bytecode;
+use crate::footprint::Footprint;
+use crate::snapshot::{Snapshot, Status};
+use crate::{GotoProgram, GotoProgramState, RegisterIndex};
+
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub program: GotoProgram,
+    /// the cells of the previous memory and where they go, all of them in
+    /// place if `None`
+    pub map: Option<Vec<(RegisterIndex, RegisterIndex)>>,
+}
+
+/// Parses a map like `2=0,sum=1`, the cells on the left are cells of
+/// `from`, the ones on the right of `to`, both may be aliases.
+pub fn parse_map(text: &str, from: &GotoProgram, to: &GotoProgram) -> Result<Vec<(RegisterIndex, RegisterIndex)>, String> {
+    text.split(',').map(|pair| {
+        let (source, target) = pair.split_once('=').ok_or_else(|| format!("{} is no pair of cells like 2=0", pair.trim()))?;
+        Ok((from.parse_cell(source.trim())?, to.parse_cell(target.trim())?))
+    }).collect()
+}
+
+/// The memory with the cells of the map moved, cells beyond it read as 0.
+pub fn remap(memory: &[u64], map: &[(RegisterIndex, RegisterIndex)]) -> Vec<u64> {
+    let mut mapped = vec![0; map.iter().map(|&(_, to)| to + 1).max().unwrap_or(0)];
+    for &(from, to) in map {
+        mapped[to] = memory.get(from).cloned().unwrap_or(0);
+    }
+    mapped
+}
+
+fn run_stage(program: &GotoProgram, input: &[u64], max_steps: u64) -> Snapshot {
+    let memory = program.initial_memory(input);
+    let failed = |error: String| Snapshot { status: Status::Error(error), program_counter: 0, steps: 0, memory: memory.clone(), stack: vec![] };
+    if let Err(e) = Footprint::of(program).check_memory(memory.len()) {
+        return failed(format!("invalid input: {}", e));
+    }
+    let bytecode = match Bytecode::compile(program) {
+        Ok(bytecode) => bytecode,
+        Err(e) => return failed(e),
+    };
+    let mut state = GotoProgramState::new(program, memory.clone());
+    let status = match bytecode.run(&mut state, max_steps) {
+        Ok(true) => Status::Stopped,
+        Ok(false) => Status::StepLimit,
+        Err(e) => Status::Error(e),
+    };
+    Snapshot::of(&state, status)
+}
+
+/// The final state of every stage up to the first one that did not stop,
+/// each stage may take `max_steps` steps.
+pub fn run(stages: &[Stage], input: &[u64], max_steps: u64) -> Vec<Snapshot> {
+    let mut runs: Vec<Snapshot> = vec![];
+    for stage in stages {
+        let input = match (runs.last(), &stage.map) {
+            (None, _) => input.to_vec(),
+            (Some(previous), Some(map)) => remap(&previous.memory, map),
+            (Some(previous), None) => previous.memory.clone(),
+        };
+        let snapshot = run_stage(&stage.program, &input, max_steps);
+        let stopped = snapshot.status == Status::Stopped;
+        runs.push(snapshot);
+        if !stopped {
+            break;
+        }
+    }
+    runs
+}
+
+#[test]
+fn test_pipeline() {
+    // adds cell 1 to cell 0, then doubles cell 0 into cell 1
+    let add = crate::parse_program(".alias sum 0\nloop: GOTOZ 1 end\nDEC 1\nINC sum\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let double = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let stages = vec![Stage { program: add.clone(), map: None }, Stage { program: double.clone(), map: None }];
+    let runs = run(&stages, &[2, 3], 1000);
+    assert_eq!((vec![5, 0], vec![0, 10]), (runs[0].memory.clone(), runs[1].memory.clone()));
+
+    // the sum goes to cell 0 of the doubling program, which has no cell 1 then
+    let map = parse_map("sum=0", &add, &double).unwrap();
+    assert_eq!(vec![(0, 0)], map);
+    assert_eq!(vec![0, 0, 7], remap(&[7, 1], &[(0, 2), (5, 1)]));
+    let stages = vec![Stage { program: add.clone(), map: None }, Stage { program: double, map: Some(map) }];
+    let runs = run(&stages, &[2, 3], 1000);
+    assert_eq!(Status::Error("invalid input: the program uses cell 1 and needs 2 cells, the memory has 1".to_string()), runs[1].status);
+
+    let runs = run(&[Stage { program: add.clone(), map: None }, Stage { program: add, map: None }], &[2, 3], 5);
+    assert_eq!((1, Status::StepLimit), (runs.len(), runs[0].status.clone()));
+    assert!(parse_map("0", &crate::parse_program(String::new()).unwrap(), &crate::parse_program(String::new()).unwrap()).is_err());
+}
@@ -0,0 +1,77 @@
+//! Conditional parts of a program. The lines between `.ifdef NAME` and
+//! `.endif` only count when NAME is defined, for example with `--define NAME`,
+//! the ones between `.ifndef NAME` and `.endif` only when it is not, and
+//! `.else` switches to the other case. Lines that do not count become blank,
+//! so every line keeps its number. Macros are expanded afterwards, see
+//! `macros`.
+
+use crate::{macros, source};
+
+struct Block {
+    line_nr: usize,
+    outer_active: bool,
+    condition: bool,
+    in_else: bool,
+}
+
+impl Block {
+    fn active(&self) -> bool {
+        self.outer_active && self.condition
+    }
+}
+
+pub const DIRECTIVES: [&str; 4] = [".ifdef", ".ifndef", ".else", ".endif"];
+
+/// The text with the lines of the blocks whose condition does not hold and
+/// the conditional directives themselves blanked out.
+pub fn preprocess(text: &str, defines: &[String]) -> Result<String, String> {
+    let mut blocks: Vec<Block> = vec![];
+    let mut lines = vec![];
+    for (line_nr, line) in text.lines().enumerate() {
+        let error = |e: &str| format!("error in line {}: {}", line_nr + 1, e);
+        let tokens = source::parse_line(line).map(|line| line.tokens).unwrap_or_default();
+        let active = blocks.last().is_none_or(Block::active);
+        match tokens.first().map(String::as_str) {
+            Some(directive @ ".ifdef") | Some(directive @ ".ifndef") => {
+                if tokens.len() != 2 {
+                    return Err(error(&format!("expected {} <name> in: {}", directive, tokens.join(" "))));
+                }
+                let defined = defines.contains(&tokens[1]);
+                blocks.push(Block { line_nr, outer_active: active, condition: defined == (directive == ".ifdef"), in_else: false });
+            }
+            Some(".else") => {
+                let block = blocks.last_mut().ok_or_else(|| error(".else without .ifdef"))?;
+                if block.in_else {
+                    return Err(error("Duplicate .else"));
+                }
+                block.condition = !block.condition;
+                block.in_else = true;
+            }
+            Some(".endif") => {
+                blocks.pop().ok_or_else(|| error(".endif without .ifdef"))?;
+            }
+            _ if active => {
+                lines.push(line);
+                continue;
+            }
+            _ => {}
+        }
+        lines.push("");
+    }
+    match blocks.last() {
+        Some(block) => Err(format!("error in line {}: .ifdef without .endif", block.line_nr + 1)),
+        None => macros::expand(&lines.join("\n")),
+    }
+}
+
+#[test]
+fn test_preprocess() {
+    let text = "INC 0\n.ifdef DEBUG\nPRINT 0\n.ifndef QUIET\nPRINT 1\n.endif\n.else\nINC 1\n.endif\nSTOP";
+    assert_eq!(Ok("INC 0\n\n\n\n\n\n\nINC 1\n\nSTOP".to_string()), preprocess(text, &[]));
+    assert_eq!(Ok("INC 0\n\nPRINT 0\n\nPRINT 1\n\n\n\n\nSTOP".to_string()), preprocess(text, &["DEBUG".to_string()]));
+    assert_eq!(Ok("INC 0\n\nPRINT 0\n\n\n\n\n\n\nSTOP".to_string()), preprocess(text, &["DEBUG".to_string(), "QUIET".to_string()]));
+    assert_eq!(Err("error in line 2: .ifdef without .endif".to_string()), preprocess("INC 0\n.ifdef DEBUG", &[]));
+    assert_eq!(Err("error in line 1: .endif without .ifdef".to_string()), preprocess(".endif", &[]));
+    assert!(preprocess(".ifdef\n.endif", &[]).is_err());
+    assert!(preprocess(".ifdef A\n.else\n.else\n.endif", &[]).is_err());
+}
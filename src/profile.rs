@@ -0,0 +1,151 @@
+//! Counts how often every instruction runs and how long each opcode takes.
+//!
+//! Jumps to the same or a lower address are back edges, each time one is
+//! taken the loop from its target up to the jump runs another iteration.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::{GotoProgram, Instruction};
+
+const OPCODES: [&str; 17] = ["INC", "DEC", "GOTO", "GOTOZ", "STOP", "ASSERT", "PRINT", "READ", "OUTC", "RAND", "PUSH", "POP", "STOREI", "GOTONZ", "GOTOEQ", "GOTOGT", "NOP"];
+
+fn opcode(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::Inc { .. } => 0,
+        Instruction::Dec { .. } => 1,
+        Instruction::Goto { .. } => 2,
+        Instruction::GotoZ { .. } => 3,
+        Instruction::Stop => 4,
+        Instruction::Assert { .. } => 5,
+        Instruction::Print { .. } => 6,
+        Instruction::Read { .. } => 7,
+        Instruction::OutC { .. } => 8,
+        Instruction::Rand { .. } => 9,
+        Instruction::Push { .. } => 10,
+        Instruction::Pop { .. } => 11,
+        Instruction::StoreI { .. } => 12,
+        Instruction::GotoNz { .. } => 13,
+        Instruction::GotoEq { .. } => 14,
+        Instruction::GotoGt { .. } => 15,
+        Instruction::Nop => 16,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// how often the instruction at each program counter ran
+    pub executions: Vec<u64>,
+    opcode_steps: [u64; OPCODES.len()],
+    opcode_time: [Duration; OPCODES.len()],
+    /// how often the jump from the first to the second program counter was taken
+    pub back_edges: BTreeMap<(usize, usize), u64>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HotLoop {
+    pub start: usize,
+    pub end: usize,
+    pub iterations: u64,
+    /// cells changed by instructions in the loop that ran
+    pub modified_cells: Vec<usize>,
+}
+
+impl Profile {
+    pub fn new(program: &GotoProgram) -> Profile {
+        Profile {
+            executions: vec![0; program.instructions.len()],
+            opcode_steps: [0; OPCODES.len()],
+            opcode_time: [Duration::default(); OPCODES.len()],
+            back_edges: BTreeMap::new(),
+        }
+    }
+
+    /// Records that the instruction at `program_counter` ran, took `elapsed`
+    /// and continued at `next`.
+    pub fn record(&mut self, program: &GotoProgram, program_counter: usize, next: usize, elapsed: Duration) {
+        self.executions[program_counter] += 1;
+        let instruction = &program.instructions[program_counter];
+        let opcode = opcode(instruction);
+        self.opcode_steps[opcode] += 1;
+        self.opcode_time[opcode] += elapsed;
+        let jumped = instruction.jump_target() == Some(next);
+        if jumped && next <= program_counter {
+            *self.back_edges.entry((program_counter, next)).or_insert(0) += 1;
+        }
+    }
+
+    /// The loops of all back edges that were taken, most iterations first.
+    pub fn hot_loops(&self, program: &GotoProgram) -> Vec<HotLoop> {
+        let mut loops: Vec<_> = self.back_edges.iter().map(|(&(end, start), &iterations)| {
+            let modified_cells: BTreeSet<_> = (start..=end)
+                .filter(|pc| self.executions[*pc] > 0)
+                .filter_map(|pc| match program.instructions[pc] {
+                    Instruction::Inc { cell } | Instruction::Dec { cell } => Some(cell),
+                    _ => None,
+                })
+                .collect();
+            HotLoop { start, end, iterations, modified_cells: modified_cells.into_iter().collect() }
+        }).collect();
+        loops.sort_by_key(|l| std::cmp::Reverse(l.iterations));
+        loops
+    }
+
+    /// The `lines` most executed source lines and hottest loops and the time per opcode, most first.
+    pub fn report(&self, program: &GotoProgram, lines: usize) -> String {
+        let total: u64 = self.executions.iter().sum();
+        let share = |count: u64| if total == 0 { 0.0 } else { 100.0 * count as f64 / total as f64 };
+        let mut report = format!("profile of {} steps\n", total);
+        let mut hottest: Vec<_> = (0..self.executions.len()).filter(|pc| self.executions[*pc] > 0).collect();
+        hottest.sort_by_key(|pc| std::cmp::Reverse(self.executions[*pc]));
+        report += "     line       count      %  instruction\n";
+        for &pc in hottest.iter().take(lines) {
+            writeln!(report, "{:>9} {:>11} {:>6.2}  {}", program.source_lines[pc], self.executions[pc],
+                     share(self.executions[pc]), program.instructions[pc]).unwrap();
+        }
+        if hottest.len() > lines {
+            writeln!(report, "{:>9} more lines", hottest.len() - lines).unwrap();
+        }
+        let loops = self.hot_loops(program);
+        if !loops.is_empty() {
+            report += "    lines  iterations  modified cells\n";
+            for hot in loops.iter().take(lines) {
+                let cells: Vec<_> = hot.modified_cells.iter().map(|c| c.to_string()).collect();
+                let span = format!("{}-{}", program.source_lines[hot.start], program.source_lines[hot.end]);
+                writeln!(report, "{:>9} {:>11}  {}", span, hot.iterations, cells.join(", ")).unwrap();
+            }
+        }
+        let mut opcodes: Vec<_> = (0..OPCODES.len()).filter(|o| self.opcode_steps[*o] > 0).collect();
+        opcodes.sort_by_key(|o| std::cmp::Reverse(self.opcode_time[*o]));
+        report += "   opcode       count      %        time\n";
+        for o in opcodes {
+            writeln!(report, "{:>9} {:>11} {:>6.2} {:>11.3?}", OPCODES[o], self.opcode_steps[o], share(self.opcode_steps[o]),
+                     self.opcode_time[o]).unwrap();
+        }
+        report
+    }
+}
+
+#[test]
+fn test_profile() {
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let mut profile = Profile::new(&program);
+    let mut state = crate::GotoProgramState::new(&program, vec![3]);
+    loop {
+        let pc = state.program_counter;
+        let stopped = state.step().unwrap();
+        profile.record(&program, pc, state.program_counter, Duration::from_nanos(1));
+        if stopped {
+            break;
+        }
+    }
+    assert_eq!(vec![4, 3, 3, 1], profile.executions);
+    let report = profile.report(&program, 2);
+    assert!(report.starts_with("profile of 11 steps\n"));
+    assert!(report.contains("        1           4  36.36  GOTOZ 0 3\n"));
+    assert!(report.contains("        2 more lines\n"));
+    assert!(report.contains("    GOTOZ           4  36.36"));
+    assert_eq!(vec![HotLoop { start: 0, end: 2, iterations: 3, modified_cells: vec![0] }], profile.hot_loops(&program));
+    assert!(report.contains("      1-3           3  0\n"));
+}
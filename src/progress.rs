@@ -0,0 +1,60 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+// looking at the clock on every step would slow the run down noticeably
+const CLOCK_CHECK_STEPS: u64 = 4096;
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn progress_line(steps: u64, elapsed: Duration) -> String {
+    let rate = steps as f64 / elapsed.as_secs_f64().max(0.001);
+    format!("{} steps  {:.0} steps/s  {}", steps, rate, format_duration(elapsed))
+}
+
+/// A progress line on stderr that is refreshed about once a second.
+pub struct Progress {
+    started: Instant,
+    last_draw: Instant,
+}
+
+impl Progress {
+    /// Returns `None` when stderr is not a terminal.
+    pub fn new() -> Option<Progress> {
+        if !atty::is(atty::Stream::Stderr) {
+            return None;
+        }
+        let now = Instant::now();
+        Some(Progress { started: now, last_draw: now })
+    }
+
+    pub fn tick(&mut self, steps: u64) {
+        if steps.is_multiple_of(CLOCK_CHECK_STEPS) {
+            self.refresh(steps);
+        }
+    }
+
+    /// Redraws the line if it is due, for callers that already check rarely.
+    pub fn refresh(&mut self, steps: u64) {
+        if self.last_draw.elapsed() < REFRESH_INTERVAL {
+            return;
+        }
+        self.last_draw = Instant::now();
+        eprint!("\r{}\x1b[K", progress_line(steps, self.started.elapsed()));
+        let _ = io::stderr().flush();
+    }
+
+    pub fn finish(&self) {
+        eprint!("\r\x1b[K");
+    }
+}
+
+#[test]
+fn test_progress_line() {
+    assert_eq!("3000000 steps  1000000 steps/s  0:00:03", progress_line(3_000_000, Duration::from_secs(3)));
+    assert_eq!("7 steps  7000 steps/s  0:00:00", progress_line(7, Duration::from_secs(0)));
+    assert_eq!("1:01:01", format_duration(Duration::from_secs(3661)));
+}
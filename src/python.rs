@@ -0,0 +1,166 @@
+//! The Python module `goto`, for driving the interpreter from notebooks and
+//! autograders without running the binary.
+//!
+//! Build it with `cargo build --release --features extension-module` and
+//! copy `target/release/libgoto.so` to `goto.so` on the Python path, or use
+//! `maturin build --features extension-module`. The `python` feature alone
+//! links libpython, which the tests need.
+//!
+//! ```python
+//! program = goto.Program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP")
+//! run = program.run([2, 5], max_steps=1000)
+//! run.memory, run.pc, run.steps, run.stopped
+//! for step in program.steps([2, 5]):
+//!     print(step.pc, step.instruction, step.write)
+//! ```
+
+use std::sync::Arc;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::events::{Events, StepEvent};
+use crate::{parse_program, GotoProgram, GotoProgramState, StepEffect};
+
+create_exception!(goto, GotoError, PyRuntimeError, "Executing an instruction failed.");
+
+#[pyclass(frozen)]
+struct Program {
+    program: Arc<GotoProgram>,
+}
+
+/// The end of a run, `stopped` is false if it ran out of steps.
+#[pyclass(frozen, get_all)]
+#[derive(Debug)]
+struct Run {
+    memory: Vec<u64>,
+    pc: usize,
+    steps: u64,
+    stopped: bool,
+}
+
+/// One executed instruction, `write` is `(cell, old, new)` if it wrote a cell.
+#[pyclass(frozen, get_all)]
+struct Step {
+    pc: usize,
+    instruction: String,
+    write: Option<(usize, u64, u64)>,
+    stopped: bool,
+}
+
+#[pymethods]
+impl Program {
+    #[new]
+    fn new(source: &str) -> PyResult<Program> {
+        let program = parse_program(source.to_string()).map_err(PyValueError::new_err)?;
+        Ok(Program { program: Arc::new(program) })
+    }
+
+    #[pyo3(signature = (memory, max_steps=None))]
+    fn run(&self, py: Python<'_>, memory: Vec<u64>, max_steps: Option<u64>) -> PyResult<Run> {
+        py.allow_threads(|| run_program(&self.program, memory, max_steps).map_err(GotoError::new_err))
+    }
+
+    /// The steps of a run as an iterator, which raises `GotoError` where the run fails.
+    fn steps(&self, memory: Vec<u64>) -> Steps {
+        let program = self.program.clone();
+        // the state borrows the program from the `Arc` next to it in `Steps`,
+        // which keeps it alive and in place until the state is dropped first
+        let borrowed: &'static GotoProgram = unsafe { &*Arc::as_ptr(&program) };
+        Steps { state: GotoProgramState::new(borrowed, memory), done: false, _program: program }
+    }
+
+    fn __len__(&self) -> usize {
+        self.program.instructions.len()
+    }
+
+    fn __str__(&self) -> String {
+        self.program.instructions.iter().map(|instruction| format!("{}\n", instruction)).collect()
+    }
+}
+
+fn run_program(program: &GotoProgram, memory: Vec<u64>, max_steps: Option<u64>) -> Result<Run, String> {
+    let mut state = GotoProgramState::new(program, memory);
+    let stopped = state.run_with_limit(max_steps.unwrap_or(u64::MAX)).map_err(|e| format!("error at pc {}: {}", state.program_counter, e))?;
+    Ok(Run { memory: state.memory, pc: state.program_counter, steps: state.steps, stopped })
+}
+
+#[pyclass(unsendable)]
+struct Steps {
+    // declared before the program, so dropped before it
+    state: GotoProgramState<'static>,
+    done: bool,
+    _program: Arc<GotoProgram>,
+}
+
+#[pymethods]
+impl Steps {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<Step>> {
+        self.next_step().map_err(GotoError::new_err)
+    }
+}
+
+impl Steps {
+    fn next_step(&mut self) -> Result<Option<Step>, String> {
+        if self.done {
+            return Ok(None);
+        }
+        let instruction = self.state.instruction().map(|instruction| instruction.to_string()).unwrap_or_default();
+        let step = |effect: StepEffect, stopped| Step {
+            pc: effect.program_counter,
+            instruction: instruction.clone(),
+            write: effect.write.map(|write| (write.cell, write.old, write.new)),
+            stopped,
+        };
+        let event = Events::new(&mut self.state).next();
+        self.done = !matches!(event, Some(StepEvent::Executed { .. }));
+        match event {
+            Some(StepEvent::Executed { effect, .. }) => Ok(Some(step(effect, false))),
+            Some(StepEvent::Stopped { effect }) => Ok(Some(step(effect, true))),
+            Some(StepEvent::Failed { program_counter, error }) => Err(format!("error at pc {}: {}", program_counter, error)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[pymodule]
+fn goto(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Program>()?;
+    m.add_class::<Run>()?;
+    m.add_class::<Step>()?;
+    m.add_class::<Steps>()?;
+    m.add("GotoError", m.py().get_type::<GotoError>())?;
+    Ok(())
+}
+
+#[test]
+fn test_python() {
+    let program = Program::new("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP").unwrap();
+    assert_eq!(5, program.__len__());
+    let run = run_program(&program.program, vec![2, 5], Some(1000)).unwrap();
+    assert_eq!((vec![0, 7], 4, 10, true), (run.memory, run.pc, run.steps, run.stopped));
+    let run = run_program(&program.program, vec![2, 5], Some(3)).unwrap();
+    assert_eq!((vec![1, 6], 3, false), (run.memory, run.pc, run.stopped));
+    let failing = parse_program("DEC 0".to_string()).unwrap();
+    assert!(run_program(&failing, vec![0], None).unwrap_err().starts_with("error at pc 0: "));
+
+    let mut steps = program.steps(vec![1, 0]);
+    let mut seen = vec![];
+    while let Some(step) = steps.next_step().unwrap() {
+        seen.push((step.pc, step.instruction, step.write, step.stopped));
+    }
+    assert_eq!(vec![
+        (0, "GOTOZ 0 4".to_string(), None, false),
+        (1, "DEC 0".to_string(), Some((0, 1, 0)), false),
+        (2, "INC 1".to_string(), Some((1, 0, 1)), false),
+        (3, "GOTO 0".to_string(), None, false),
+        (0, "GOTOZ 0 4".to_string(), None, false),
+        (4, "STOP".to_string(), None, true),
+    ], seen);
+    assert_eq!(Ok(None), steps.next_step().map(|step| step.map(|step| step.pc)));
+}
@@ -0,0 +1,272 @@
+//! Abstract interpretation of programs over intervals: for every instruction
+//! the range each cell can hold when it runs, for all inputs or a known one.
+//!
+//! Ranges only grow while the analysis revisits an instruction, after
+//! `WIDEN_AFTER` visits a growing bound jumps to 0 or to unbounded so that
+//! loops do not have to be followed round by round.
+
+use std::fmt::{self, Write};
+
+use crate::{GotoProgram, Instruction, RegisterIndex};
+
+const WIDEN_AFTER: u32 = 3;
+
+/// The values `low..=high` with `u64::MAX` as the high end of a cell that may
+/// hold any value, since no `INC` can go beyond it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Interval {
+    pub low: u64,
+    pub high: u64,
+}
+
+impl Interval {
+    pub const ANY: Interval = Interval { low: 0, high: u64::MAX };
+
+    fn exactly(value: u64) -> Interval {
+        Interval { low: value, high: value }
+    }
+
+    fn join(self, other: Interval) -> Interval {
+        Interval { low: self.low.min(other.low), high: self.high.max(other.high) }
+    }
+
+    fn widen(self, next: Interval) -> Interval {
+        Interval {
+            low: if next.low < self.low { 0 } else { self.low },
+            high: if next.high > self.high { u64::MAX } else { self.high },
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.low, self.high) {
+            (low, u64::MAX) => write!(f, "{}..", low),
+            (low, high) if low == high => write!(f, "{}", low),
+            (low, high) => write!(f, "{}..{}", low, high),
+        }
+    }
+}
+
+/// The ranges of every cell the program uses before each instruction, `None`
+/// for instructions that never run.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Ranges {
+    pub cells: Vec<RegisterIndex>,
+    pub at: Vec<Option<Vec<Interval>>>,
+}
+
+/// The cells that instructions of the program read or write, in order.
+pub fn used_cells(program: &GotoProgram) -> Vec<RegisterIndex> {
+    let mut cells: Vec<_> = program.instructions.iter().flat_map(|i| match *i {
+        Instruction::Inc { cell } | Instruction::Dec { cell } | Instruction::GotoZ { condition_cell: cell, .. }
+        | Instruction::GotoNz { condition_cell: cell, .. } | Instruction::Assert { cell, .. } | Instruction::Print { cell }
+        | Instruction::Read { cell } | Instruction::OutC { cell } | Instruction::Rand { cell, .. } | Instruction::Push { cell }
+        | Instruction::Pop { cell } | Instruction::StoreI { cell, .. } => vec![cell],
+        Instruction::GotoEq { left, right, .. } | Instruction::GotoGt { left, right, .. } => vec![left, right],
+        Instruction::Stop | Instruction::Goto { .. } | Instruction::Nop => vec![],
+    }).collect();
+    cells.sort_unstable();
+    cells.dedup();
+    cells
+}
+
+/// The states after the instruction at `pc` runs with the cells in `before`,
+/// with the instruction they continue at.
+fn transfer(instruction: Instruction, pc: usize, slot: impl Fn(RegisterIndex) -> usize, before: &[Interval]) -> Vec<(usize, Vec<Interval>)> {
+    let with = |cell: RegisterIndex, interval: Interval| {
+        let mut after = before.to_vec();
+        after[slot(cell)] = interval;
+        after
+    };
+    match instruction {
+        Instruction::Stop => vec![],
+        Instruction::Inc { cell } => {
+            let Interval { low, high } = before[slot(cell)];
+            if low == u64::MAX {
+                return vec![];
+            }
+            vec![(pc + 1, with(cell, Interval { low: low + 1, high: if high == u64::MAX { high } else { high + 1 } }))]
+        }
+        Instruction::Dec { cell } => {
+            let Interval { low, high } = before[slot(cell)];
+            if high == 0 {
+                return vec![];
+            }
+            vec![(pc + 1, with(cell, Interval { low: low.max(1) - 1, high: if high == u64::MAX { high } else { high - 1 } }))]
+        }
+        Instruction::Goto { cell } => vec![(cell, before.to_vec())],
+        Instruction::GotoZ { condition_cell, goto_cell } => {
+            let Interval { low, high } = before[slot(condition_cell)];
+            let mut next = vec![];
+            if low == 0 {
+                next.push((goto_cell, with(condition_cell, Interval::exactly(0))));
+            }
+            if high > 0 {
+                next.push((pc + 1, with(condition_cell, Interval { low: low.max(1), high })));
+            }
+            next
+        }
+        Instruction::GotoNz { condition_cell, goto_cell } => {
+            let Interval { low, high } = before[slot(condition_cell)];
+            let mut next = vec![];
+            if high > 0 {
+                next.push((goto_cell, with(condition_cell, Interval { low: low.max(1), high })));
+            }
+            if low == 0 {
+                next.push((pc + 1, with(condition_cell, Interval::exactly(0))));
+            }
+            next
+        }
+        Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => {
+            let (l, r) = (before[slot(left)], before[slot(right)]);
+            let (jumps, falls_through) = match instruction {
+                _ if left == right => (matches!(instruction, Instruction::GotoEq { .. }), matches!(instruction, Instruction::GotoGt { .. })),
+                Instruction::GotoEq { .. } => (l.low <= r.high && r.low <= l.high, !(l.low == l.high && l == r)),
+                _ => (l.high > r.low, l.low <= r.high),
+            };
+            let mut next = vec![];
+            if jumps {
+                next.push((goto_cell, before.to_vec()));
+            }
+            if falls_through {
+                next.push((pc + 1, before.to_vec()));
+            }
+            next
+        }
+        Instruction::Assert { cell, value } => {
+            let Interval { low, high } = before[slot(cell)];
+            if value < low || value > high {
+                return vec![];
+            }
+            vec![(pc + 1, with(cell, Interval::exactly(value)))]
+        }
+        Instruction::Print { .. } | Instruction::OutC { .. } | Instruction::Push { .. } | Instruction::Nop => vec![(pc + 1, before.to_vec())],
+        Instruction::Read { cell } | Instruction::Pop { cell } => vec![(pc + 1, with(cell, Interval::ANY))],
+        Instruction::Rand { max: 0, .. } => vec![],
+        Instruction::Rand { cell, max } => vec![(pc + 1, with(cell, Interval { low: 0, high: max - 1 }))],
+        Instruction::StoreI { cell, value } => vec![(pc + 1, with(cell, Interval::exactly(value)))],
+    }
+}
+
+/// Runs the analysis from the start of the program, on `input` if it is
+/// known. Cells missing from the input are taken to be 0.
+pub fn analyze(program: &GotoProgram, input: Option<&[u64]>) -> Ranges {
+    let cells = used_cells(program);
+    let slot = |cell: RegisterIndex| cells.binary_search(&cell).unwrap();
+    let count = program.instructions.len();
+    let mut at: Vec<Option<Vec<Interval>>> = vec![None; count];
+    let mut visits = vec![0; count];
+    if count > 0 {
+        at[0] = Some(cells.iter().map(|&cell| match input {
+            Some(memory) => Interval::exactly(memory.get(cell).cloned().unwrap_or(0)),
+            None => Interval::ANY,
+        }).collect());
+    }
+    let mut todo = vec![0];
+    while let Some(pc) = todo.pop() {
+        let before = match at.get(pc) {
+            Some(Some(before)) => before.clone(),
+            _ => continue,
+        };
+        for (next, after) in transfer(program.instructions[pc], pc, slot, &before) {
+            if next >= count {
+                continue;
+            }
+            let merged = match &at[next] {
+                None => after,
+                Some(old) => {
+                    let joined: Vec<_> = old.iter().zip(&after).map(|(o, a)| o.join(*a)).collect();
+                    if visits[next] < WIDEN_AFTER {
+                        joined
+                    } else {
+                        old.iter().zip(&joined).map(|(o, j)| o.widen(*j)).collect()
+                    }
+                }
+            };
+            if at[next].as_ref() != Some(&merged) {
+                at[next] = Some(merged);
+                visits[next] += 1;
+                todo.push(next);
+            }
+        }
+    }
+    Ranges { cells, at }
+}
+
+impl Ranges {
+    pub fn get(&self, pc: usize, cell: RegisterIndex) -> Option<Interval> {
+        let slot = self.cells.binary_search(&cell).ok()?;
+        Some(self.at[pc].as_ref()?[slot])
+    }
+
+    /// DECs and ASSERTs that fail whenever they run and GOTOZ branches that are
+    /// never taken, with the index of the instruction.
+    pub fn warnings(&self, program: &GotoProgram) -> Vec<(usize, String)> {
+        let mut warnings = vec![];
+        for (pc, instruction) in program.instructions.iter().enumerate() {
+            match *instruction {
+                Instruction::Dec { cell } => if let Some(Interval { high: 0, .. }) = self.get(pc, cell) {
+                    warnings.push((pc, format!("DEC {} always fails, the cell is always 0 here", cell)));
+                },
+                Instruction::Assert { cell, value } => match self.get(pc, cell) {
+                    Some(Interval { low, high }) if value < low || value > high =>
+                        warnings.push((pc, format!("{} always fails, cell {} is {} here", instruction, cell, Interval { low, high }))),
+                    _ => {}
+                },
+                Instruction::GotoZ { condition_cell, .. } => match self.get(pc, condition_cell) {
+                    Some(Interval { high: 0, .. }) =>
+                        warnings.push((pc, format!("GOTOZ always jumps, cell {} is always 0 here", condition_cell))),
+                    Some(Interval { low, .. }) if low > 0 =>
+                        warnings.push((pc, format!("GOTOZ never jumps, cell {} is at least {} here", condition_cell, low))),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        warnings
+    }
+
+    /// Every instruction with the ranges of the cells before it runs.
+    pub fn listing(&self, program: &GotoProgram) -> String {
+        let mut listing = String::new();
+        for (pc, instruction) in program.instructions.iter().enumerate() {
+            let ranges = match &self.at[pc] {
+                Some(intervals) => self.cells.iter().zip(intervals)
+                    .map(|(cell, interval)| format!("{}: {}", cell, interval))
+                    .collect::<Vec<_>>().join(", "),
+                None => "never runs".to_string(),
+            };
+            writeln!(listing, "{:>5}  {:<16} {}", program.source_lines[pc], instruction.to_string(), ranges).unwrap();
+        }
+        listing
+    }
+}
+
+#[test]
+fn test_ranges() {
+    let program = crate::parse_program("INC 1
+        loop: GOTOZ 0 end
+        DEC 0
+        GOTOZ 1 loop
+        GOTO loop
+        end: GOTOZ 0 done
+        DEC 0
+        done: STOP".to_string()).unwrap();
+    let ranges = analyze(&program, None);
+    assert_eq!(Some(vec![Interval::ANY, Interval { low: 1, high: u64::MAX }]), ranges.at[1]);
+    assert_eq!(None, ranges.at[6]);
+    assert_eq!(vec![
+        (3, "GOTOZ never jumps, cell 1 is at least 1 here".to_string()),
+        (5, "GOTOZ always jumps, cell 0 is always 0 here".to_string()),
+    ], ranges.warnings(&program));
+    assert!(ranges.listing(&program).contains("    3  DEC 0            0: 1.., 1: 1..\n"));
+
+    let known = analyze(&program, Some(&[2, 0]));
+    assert_eq!(Some(vec![Interval { low: 0, high: 2 }, Interval::exactly(1)]), known.at[1]);
+
+    let underflow = crate::parse_program("GOTOZ 0 end\nGOTO 0\nend: DEC 0\nSTOP".to_string()).unwrap();
+    assert_eq!(vec![(2, "DEC 0 always fails, the cell is always 0 here".to_string())], analyze(&underflow, None).warnings(&underflow));
+    let assertion = crate::parse_program("ASSERT 0 2\nINC 0\nASSERT 0 4\nSTOP".to_string()).unwrap();
+    assert_eq!(vec![(2, "ASSERT 0 4 always fails, cell 0 is 3 here".to_string())], analyze(&assertion, None).warnings(&assertion));
+}
@@ -0,0 +1,283 @@
+//! A compiler from μ-recursive function definitions to goto.
+//!
+//! Definitions are one per line, `;` starts a comment, and the last one is compiled:
+//!
+//! ```text
+//! add = rec(proj(1, 1), comp(succ, proj(2, 3)))
+//! mul = rec(zero(1), comp(add, proj(2, 3), proj(3, 3)))
+//! ```
+//!
+//! - `zero(n)` is 0 for `n` arguments, `succ` is `x + 1`
+//! - `proj(i, n)` is the `i`-th of `n` arguments
+//! - `comp(f, g1, ..., gm)` is `f(g1(x...), ..., gm(x...))`
+//! - `rec(g, h)` is `f` with `f(0, x...) = g(x...)` and `f(y + 1, x...) = h(y, f(y, x...), x...)`
+//! - `min(f)` is the smallest `y` with `f(y, x...) = 0`, it does not stop if there is none
+//!
+//! The definitions become WHILE statements, primitive recursion a LOOP and
+//! minimization a WHILE loop, which `while_lang` compiles to goto. The
+//! arguments are in cells 1 to n, all other cells have to start at 0, and
+//! the result is written to cell 0.
+
+use std::convert::TryFrom;
+
+use crate::while_lang::{self, Statement};
+use crate::RegisterIndex;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Function {
+    Zero(usize),
+    Successor,
+    Projection(usize, usize),
+    Composition(Box<Function>, Vec<Function>),
+    Recursion(Box<Function>, Box<Function>),
+    Minimization(Box<Function>),
+}
+
+impl Function {
+    pub fn arity(&self) -> Result<usize, String> {
+        match self {
+            Function::Zero(n) => Ok(*n),
+            Function::Successor => Ok(1),
+            Function::Projection(i, n) if *i >= 1 && i <= n => Ok(*n),
+            Function::Projection(i, n) => Err(format!("proj({}, {}) does not exist", i, n)),
+            Function::Composition(f, gs) => {
+                if f.arity()? != gs.len() {
+                    return Err(format!("composition of a function with {} arguments with {} functions", f.arity()?, gs.len()));
+                }
+                let arities = gs.iter().map(|g| g.arity()).collect::<Result<Vec<_>, _>>()?;
+                match arities.split_first() {
+                    Some((first, rest)) if rest.iter().all(|a| a == first) => Ok(*first),
+                    Some(_) => Err("composition of functions with different numbers of arguments".to_string()),
+                    None => Err("composition needs at least one inner function".to_string()),
+                }
+            }
+            Function::Recursion(g, h) => {
+                if h.arity()? != g.arity()? + 2 {
+                    return Err(format!("recursion needs a step function with {} arguments, not {}", g.arity()? + 2, h.arity()?));
+                }
+                Ok(g.arity()? + 1)
+            }
+            Function::Minimization(f) => f.arity()?.checked_sub(1).ok_or_else(|| "minimization of a constant".to_string()),
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    for c in line.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+        if !c.is_whitespace() {
+            tokens.push(c.to_string());
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    definitions: &'a [(String, Function)],
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("unexpected end of line")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            ref token if token == expected => Ok(()),
+            token => Err(format!("expected {}, found {}", expected, token)),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        let token = self.next()?;
+        token.parse().map_err(|_| format!("expected a number, found {}", token))
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Function>, String> {
+        self.expect("(")?;
+        let mut arguments = vec![self.function()?];
+        while self.tokens.get(self.pos).map(|t| t.as_str()) == Some(",") {
+            self.pos += 1;
+            arguments.push(self.function()?);
+        }
+        self.expect(")")?;
+        Ok(arguments)
+    }
+
+    fn function(&mut self) -> Result<Function, String> {
+        let name = self.next()?;
+        match name.as_str() {
+            "zero" => {
+                self.expect("(")?;
+                let n = self.number()?;
+                self.expect(")")?;
+                Ok(Function::Zero(n))
+            }
+            "succ" => Ok(Function::Successor),
+            "proj" => {
+                self.expect("(")?;
+                let i = self.number()?;
+                self.expect(",")?;
+                let n = self.number()?;
+                self.expect(")")?;
+                Ok(Function::Projection(i, n))
+            }
+            "comp" => {
+                let mut arguments = self.arguments()?;
+                let f = arguments.remove(0);
+                Ok(Function::Composition(Box::new(f), arguments))
+            }
+            "rec" => match <[Function; 2]>::try_from(self.arguments()?) {
+                Ok([g, h]) => Ok(Function::Recursion(Box::new(g), Box::new(h))),
+                Err(_) => Err("rec needs two functions".to_string()),
+            },
+            "min" => match <[Function; 1]>::try_from(self.arguments()?) {
+                Ok([f]) => Ok(Function::Minimization(Box::new(f))),
+                Err(_) => Err("min needs one function".to_string()),
+            },
+            _ => self.definitions.iter().rev().find(|(n, _)| *n == name).map(|(_, f)| f.clone())
+                .ok_or_else(|| format!("unknown function {}", name)),
+        }
+    }
+}
+
+/// Parses all definitions, names can only refer to earlier definitions.
+pub fn parse(text: &str) -> Result<Vec<(String, Function)>, String> {
+    let mut definitions: Vec<(String, Function)> = vec![];
+    for (line_number, line) in text.lines().enumerate() {
+        let tokens = tokenize(line.split(';').next().unwrap());
+        if tokens.is_empty() {
+            continue;
+        }
+        let definition = (|| {
+            let mut parser = Parser { tokens, pos: 0, definitions: &definitions };
+            let name = parser.next()?;
+            parser.expect("=")?;
+            let function = parser.function()?;
+            if let Some(token) = parser.tokens.get(parser.pos) {
+                return Err(format!("unexpected {}", token));
+            }
+            function.arity()?;
+            Ok((name, function))
+        })().map_err(|e: String| format!("error in line {}: {}", line_number + 1, e))?;
+        definitions.push(definition);
+    }
+    Ok(definitions)
+}
+
+struct Compiler {
+    next_variable: RegisterIndex,
+    // never written, so always 0
+    zero: RegisterIndex,
+}
+
+fn assign(target: RegisterIndex, source: RegisterIndex, constant: u64) -> Statement {
+    Statement::Assign { target, source, add: true, constant }
+}
+
+impl Compiler {
+    fn fresh(&mut self) -> RegisterIndex {
+        self.next_variable += 1;
+        self.next_variable - 1
+    }
+
+    /// Statements that set `result` to `function` applied to `arguments`, which they leave alone.
+    fn compile(&mut self, function: &Function, arguments: &[RegisterIndex], result: RegisterIndex) -> Vec<Statement> {
+        match function {
+            Function::Zero(_) => vec![assign(result, self.zero, 0)],
+            Function::Successor => vec![assign(result, arguments[0], 1)],
+            Function::Projection(i, _) => vec![assign(result, arguments[i - 1], 0)],
+            Function::Composition(f, gs) => {
+                let mut statements = vec![];
+                let values: Vec<_> = gs.iter().map(|_| self.fresh()).collect();
+                for (g, value) in gs.iter().zip(&values) {
+                    statements.extend(self.compile(g, arguments, *value));
+                }
+                statements.extend(self.compile(f, &values, result));
+                statements
+            }
+            Function::Recursion(g, h) => {
+                let (counter, accumulator, step) = (self.fresh(), self.fresh(), self.fresh());
+                let mut statements = self.compile(g, &arguments[1..], accumulator);
+                statements.push(assign(counter, self.zero, 0));
+                let mut step_arguments = vec![counter, accumulator];
+                step_arguments.extend_from_slice(&arguments[1..]);
+                let mut body = self.compile(h, &step_arguments, step);
+                body.push(assign(accumulator, step, 0));
+                body.push(assign(counter, counter, 1));
+                statements.push(Statement::Loop { variable: arguments[0], body });
+                statements.push(assign(result, accumulator, 0));
+                statements
+            }
+            Function::Minimization(f) => {
+                let (candidate, value) = (self.fresh(), self.fresh());
+                let mut f_arguments = vec![candidate];
+                f_arguments.extend_from_slice(arguments);
+                let mut statements = vec![assign(candidate, self.zero, 0)];
+                statements.extend(self.compile(f, &f_arguments, value));
+                let mut body = vec![assign(candidate, candidate, 1)];
+                body.extend(self.compile(f, &f_arguments, value));
+                statements.push(Statement::While { variable: value, body });
+                statements.push(assign(result, candidate, 0));
+                statements
+            }
+        }
+    }
+}
+
+/// Compiles the last definition into goto source.
+pub fn to_goto(text: &str) -> Result<String, String> {
+    let definitions = parse(text)?;
+    let (name, function) = definitions.last().ok_or("no function is defined")?;
+    let arity = function.arity()?;
+    let mut compiler = Compiler { next_variable: arity + 2, zero: arity + 1 };
+    let arguments: Vec<_> = (1..=arity).collect();
+    let statements = compiler.compile(function, &arguments, 0);
+    Ok(format!("; {} with the arguments in cells 1 to {} and the result in cell 0\n{}",
+               name, arity, while_lang::compile(&statements, "recursive function definitions")))
+}
+
+#[test]
+fn test_recursive_functions() {
+    let source = "add = rec(proj(1, 1), comp(succ, proj(2, 3)))
+        mul = rec(zero(1), comp(add, proj(2, 3), proj(3, 3))) ; mul(y, x) = y * x
+        pred = rec(zero(0), proj(1, 2))
+        ; sub(a, b) = a - b or 0, the smallest y with a - (y + b) = 0
+        sub = min(comp(rec(proj(1, 1), comp(pred, proj(2, 3))), comp(add, proj(1, 3), proj(3, 3)), proj(2, 3)))";
+    let definitions = parse(source).unwrap();
+    let names: Vec<_> = definitions.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(vec!["add", "mul", "pred", "sub"], names);
+
+    let run = |text: &str, arguments: &[u64]| {
+        let program = crate::parse_program(to_goto(text).unwrap()).unwrap();
+        let mut memory = vec![0; 64];
+        memory[1..=arguments.len()].copy_from_slice(arguments);
+        let mut state = crate::GotoProgramState::new(&program, memory);
+        assert_eq!(Ok(true), state.run_with_limit(10_000_000));
+        state.memory[0]
+    };
+    let lines: Vec<_> = source.lines().collect();
+    assert_eq!(12, run(&lines[..2].join("\n"), &[3, 4]));
+    assert_eq!(0, run(&lines[..3].join("\n"), &[0]));
+    assert_eq!(4, run(&lines[..3].join("\n"), &[5]));
+    assert_eq!(3, run(source, &[7, 4]));
+    assert_eq!(0, run(source, &[2, 5]));
+
+    assert!(parse("f = comp(succ, zero(1), zero(1))").is_err());
+    assert!(parse("f = g").is_err());
+}
@@ -0,0 +1,280 @@
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+use goto::{parse_program, read_input, source, Instruction};
+
+use crate::line_editor::LineEditor;
+
+const HELP: &str = "Enter goto instructions to append them to the buffer and apply them to the memory,
+several on one line are separated by |, like INC 0 | INC 0.
+:list                     show the instruction buffer
+:memory                   show the memory
+:print <cell>             show one cell
+:set <cell> <value>       change one cell
+:labels                   show the labels defined so far and where they point
+:save <source> [<input>]  write the buffer and the memory to files
+:load <source> [<input>]  replace the buffer and the memory with the contents of files
+:help                     show this help
+:quit                     leave the repl";
+
+#[derive(Debug, Default)]
+pub struct Repl {
+    lines: Vec<String>,
+    memory: Vec<u64>,
+}
+
+fn input_path(source: &str, input: Option<&str>) -> PathBuf {
+    match input {
+        Some(input) => PathBuf::from(input),
+        None => Path::new(source).with_extension("input"),
+    }
+}
+
+fn format_memory(memory: &[u64]) -> String {
+    memory.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn parse_cell(text: &str) -> Result<usize, String> {
+    text.parse().map_err(|_| format!("{} is not a cell", text))
+}
+
+impl Repl {
+    fn cell(&self, cell: usize) -> u64 {
+        self.memory.get(cell).cloned().unwrap_or(0)
+    }
+
+    fn set(&mut self, cell: usize, value: u64) {
+        if cell >= self.memory.len() {
+            self.memory.resize(cell + 1, 0);
+        }
+        self.memory[cell] = value;
+    }
+
+    /// Applies the instruction to the memory and explains what it did.
+    fn apply(&mut self, instruction: &Instruction) -> Result<String, String> {
+        Ok(match *instruction {
+            Instruction::Stop => "STOP: the program would end here".to_string(),
+            Instruction::Nop => "NOP: nothing happens".to_string(),
+            Instruction::Inc { cell } => {
+                let old = self.cell(cell);
+                let new = old.checked_add(1).ok_or_else(|| format!("cell {} would overflow", cell))?;
+                self.set(cell, new);
+                format!("INC {}: [{}] {} -> {}", cell, cell, old, new)
+            }
+            Instruction::Dec { cell } => {
+                let old = self.cell(cell);
+                if old == 0 {
+                    return Err(format!("cell {} is already 0", cell));
+                }
+                self.set(cell, old - 1);
+                format!("DEC {}: [{}] {} -> {}", cell, cell, old, old - 1)
+            }
+            Instruction::Goto { cell } => format!("GOTO {}: would jump to instruction {}", cell, cell),
+            Instruction::GotoZ { condition_cell, goto_cell } => {
+                let value = self.cell(condition_cell);
+                if value == 0 {
+                    format!("GOTOZ {} {}: [{}] is 0, would jump to instruction {}",
+                            condition_cell, goto_cell, condition_cell, goto_cell)
+                } else {
+                    format!("GOTOZ {} {}: [{}] is {}, would continue with the next instruction",
+                            condition_cell, goto_cell, condition_cell, value)
+                }
+            }
+            Instruction::GotoNz { condition_cell, goto_cell } => {
+                let value = self.cell(condition_cell);
+                if value != 0 {
+                    format!("GOTONZ {} {}: [{}] is {}, would jump to instruction {}",
+                            condition_cell, goto_cell, condition_cell, value, goto_cell)
+                } else {
+                    format!("GOTONZ {} {}: [{}] is 0, would continue with the next instruction",
+                            condition_cell, goto_cell, condition_cell)
+                }
+            }
+            Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => {
+                let (l, r) = (self.cell(left), self.cell(right));
+                let jumps = if let Instruction::GotoEq { .. } = instruction { l == r } else { l > r };
+                let outcome = if jumps { format!("would jump to instruction {}", goto_cell) } else { "would continue with the next instruction".to_string() };
+                format!("{}: [{}] is {} and [{}] is {}, {}", instruction, left, l, right, r, outcome)
+            }
+            Instruction::Assert { cell, value } => {
+                let actual = self.cell(cell);
+                if actual != value {
+                    return Err(format!("{} would fail, [{}] is {}", instruction, cell, actual));
+                }
+                format!("{}: [{}] is {}, as expected", instruction, cell, actual)
+            }
+            Instruction::Print { cell } => format!("PRINT {}: would print {}", cell, self.cell(cell)),
+            Instruction::Read { cell } => format!("READ {}: would read a number into [{}]", cell, cell),
+            Instruction::StoreI { cell, value } => {
+                let old = self.cell(cell);
+                self.set(cell, value);
+                format!("STOREI {} {}: [{}] {} -> {}", cell, value, cell, old, value)
+            }
+            Instruction::Push { cell } => format!("PUSH {}: would push {}", cell, self.cell(cell)),
+            Instruction::Pop { cell } => format!("POP {}: would move the top of the stack into [{}]", cell, cell),
+            Instruction::Rand { cell, max } => format!("RAND {} {}: would store a random number below {} in [{}]", cell, max, max, cell),
+            Instruction::OutC { cell } => match std::char::from_u32(self.cell(cell) as u32).filter(|_| self.cell(cell) <= u32::MAX as u64) {
+                Some(c) => format!("OUTC {}: would write {:?}", cell, c),
+                None => return Err(format!("OUTC {}: {} is not a character", cell, self.cell(cell))),
+            },
+        })
+    }
+
+    fn add_line(&mut self, line: &str) -> Result<Option<String>, String> {
+        let known = parse_program(self.lines.join("\n"))?.instructions.len();
+        let mut lines = self.lines.clone();
+        lines.push(line.to_string());
+        let program = parse_program(lines.join("\n"))?;
+        let memory = self.memory.clone();
+        let mut explanations = vec![];
+        for instruction in &program.instructions[known..] {
+            match self.apply(instruction) {
+                Ok(explanation) => explanations.push(explanation),
+                Err(e) => {
+                    self.memory = memory;
+                    return Err(e);
+                }
+            }
+        }
+        self.lines = lines;
+        Ok(Some(explanations.join("\n")).filter(|explanation| !explanation.is_empty()))
+    }
+
+    /// Labels with the index of the instruction they name, in order of definition.
+    fn labels(&self) -> Vec<(String, usize)> {
+        let mut labels = vec![];
+        let mut instructions = 0;
+        for line in &self.lines {
+            for parsed in source::parse_segments(line).unwrap_or_default() {
+                if let Some(label) = parsed.label {
+                    labels.push((label, instructions));
+                }
+                if !parsed.tokens.is_empty() {
+                    instructions += 1;
+                }
+            }
+        }
+        labels
+    }
+
+    fn save(&self, source: &str, input: Option<&str>) -> Result<(), String> {
+        let mut code = self.lines.join("\n");
+        code.push('\n');
+        write(source, code).map_err(|e| format!("cannot write {}: {}", source, e))?;
+        let input = input_path(source, input);
+        write(&input, format_memory(&self.memory) + "\n")
+            .map_err(|e| format!("cannot write {}: {}", input.display(), e))
+    }
+
+    fn load(&mut self, source: &str, input: Option<&str>) -> Result<(), String> {
+        let code = read_to_string(source).map_err(|e| format!("cannot read {}: {}", source, e))?;
+        parse_program(code.clone())?;
+        let input_file = input_path(source, input);
+        let memory = match read_to_string(&input_file) {
+            Ok(text) => read_input(text)?,
+            Err(_) if input.is_none() => vec![],
+            Err(e) => return Err(format!("cannot read {}: {}", input_file.display(), e)),
+        };
+        self.lines = code.lines().map(|l| l.to_string()).collect();
+        self.memory = memory;
+        Ok(())
+    }
+
+    /// Handles one line of input, returns `false` when the repl should exit.
+    pub fn eval(&mut self, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            [":quit"] | [":q"] => return Ok(false),
+            [":help"] => println!("{}", HELP),
+            [":list"] => {
+                for (nr, line) in self.lines.iter().enumerate() {
+                    println!("{:>4} {}", nr + 1, line);
+                }
+            }
+            [":memory"] => println!("mem: {:?}", self.memory),
+            [":print", cell] => {
+                let cell = parse_cell(cell)?;
+                println!("[{}] = {}", cell, self.cell(cell));
+            }
+            [":set", cell, value] => {
+                let value = value.parse().map_err(|_| format!("{} is not a value", value))?;
+                self.set(parse_cell(cell)?, value);
+                println!("mem: {:?}", self.memory);
+            }
+            [":labels"] => {
+                for (label, instruction) in self.labels() {
+                    println!("{} -> instruction {}", label, instruction);
+                }
+            }
+            [":save", source] => self.save(source, None)?,
+            [":save", source, input] => self.save(source, Some(input))?,
+            [":load", source] => self.load(source, None)?,
+            [":load", source, input] => self.load(source, Some(input))?,
+            [command, ..] if command.starts_with(':') => {
+                return Err(format!("unknown command or wrong arguments: {}", line.trim()));
+            }
+            _ => {
+                if let Some(explanation) = self.add_line(line)? {
+                    println!("{}", explanation);
+                }
+                println!("mem: {:?}", self.memory);
+            }
+        }
+        Ok(true)
+    }
+}
+
+pub fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".goto_history"))
+}
+
+pub fn run(history_file: Option<PathBuf>) {
+    let mut editor = LineEditor::new(history_file);
+    let mut repl = Repl::default();
+    println!("goto repl, type :help for help");
+    while let Ok(Some(line)) = editor.read_line("goto> ") {
+        editor.add_history(&line);
+        match repl.eval(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("error: {}", e),
+        }
+    }
+}
+
+#[test]
+fn test_repl_save_load() {
+    let mut repl = Repl::default();
+    assert_eq!(Ok(true), repl.eval("start: INC 2"));
+    assert_eq!(Ok(true), repl.eval("INC 0 ; comment"));
+    assert!(repl.eval("DEC 1").is_err());
+    assert!(repl.eval("GOTO nowhere").is_err());
+    assert_eq!(Ok(true), repl.eval("GOTO start"));
+    assert_eq!(vec![1, 0, 1], repl.memory);
+    assert_eq!(Ok(true), repl.eval("end:"));
+    assert_eq!(Ok(true), repl.eval(":set 4 2"));
+    assert_eq!(vec![1, 0, 1, 0, 2], repl.memory);
+    assert!(repl.eval(":set x 2").is_err());
+    assert_eq!(vec![("start".to_string(), 0), ("end".to_string(), 3)], repl.labels());
+    assert_eq!(Ok("GOTOZ 1 0: [1] is 0, would jump to instruction 0".to_string()),
+               repl.apply(&Instruction::GotoZ { condition_cell: 1, goto_cell: 0 }));
+    assert!(repl.eval("INC 1 | DEC 3").is_err());
+    assert_eq!(vec![1, 0, 1, 0, 2], repl.memory);
+    assert_eq!(Ok(true), repl.eval("INC 1 | again: INC 1"));
+    assert_eq!(vec![1, 2, 1, 0, 2], repl.memory);
+    assert_eq!(("again".to_string(), 4), repl.labels()[2]);
+
+    let dir = std::env::temp_dir().join(format!("goto-repl-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("session.goto");
+    let source = source.to_str().unwrap();
+    assert_eq!(Ok(true), repl.eval(&format!(":save {}", source)));
+
+    let mut loaded = Repl::default();
+    assert_eq!(Ok(true), loaded.eval(&format!(":load {}", source)));
+    assert_eq!(repl.lines, loaded.lines);
+    assert_eq!(repl.memory, loaded.memory);
+    assert_eq!(Ok(false), loaded.eval(":quit"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,231 @@
+//! A standalone HTML page of a run to explore it in a browser afterwards:
+//! the listing with how often every line ran, and a scrubber over the steps
+//! that shows the memory after each of them.
+//!
+//! The page only holds the writes of the steps and replays them on the
+//! initial memory. Values are strings in it, JavaScript numbers would round
+//! large cells. Of longer runs the first `MAX_STEPS` steps are recorded, the
+//! heatmap counts all of them.
+
+use crate::doc::escape_html;
+use crate::json::Value;
+use crate::observer::ExecutionObserver;
+use crate::{GotoProgram, GotoProgramState, Instruction, MemoryWrite, RegisterIndex};
+
+pub const MAX_STEPS: usize = 100_000;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Report {
+    source_lines: Vec<usize>,
+    initial_memory: Vec<u64>,
+    /// the program counter and write of the recorded steps
+    steps: Vec<(RegisterIndex, Option<MemoryWrite>)>,
+    /// how often every instruction ran
+    counts: Vec<u64>,
+    total_steps: u64,
+}
+
+impl Report {
+    pub fn new(program: &GotoProgram, initial_memory: &[u64]) -> Report {
+        Report {
+            source_lines: program.source_lines.clone(),
+            initial_memory: initial_memory.to_vec(),
+            steps: vec![],
+            counts: vec![0; program.instructions.len()],
+            total_steps: 0,
+        }
+    }
+
+    /// How often the lines of a source with `lines` lines ran.
+    fn line_counts(&self, lines: usize) -> Vec<u64> {
+        let mut counts = vec![0; lines];
+        for (&line, &count) in self.source_lines.iter().zip(&self.counts) {
+            if let Some(line_count) = counts.get_mut(line - 1) {
+                *line_count += count;
+            }
+        }
+        counts
+    }
+
+    fn to_json(&self, source: &str, status: &str) -> Value {
+        let lines: Vec<&str> = source.lines().collect();
+        let values = |memory: &[u64]| Value::Array(memory.iter().map(|value| value.to_string().into()).collect());
+        let steps = self.steps.iter().map(|&(pc, write)| {
+            let line = self.source_lines.get(pc).cloned().unwrap_or(0);
+            let mut step = vec![pc.into(), line.into()];
+            if let Some(write) = write {
+                step.extend(vec![write.cell.into(), write.old.to_string().into(), write.new.to_string().into()]);
+            }
+            Value::Array(step)
+        }).collect();
+        Value::object(vec![
+            ("status", status.into()),
+            ("lines", Value::Array(lines.iter().map(|&line| line.into()).collect())),
+            ("line_counts", Value::Array(self.line_counts(lines.len()).into_iter().map(Value::from).collect())),
+            ("initial_memory", values(&self.initial_memory)),
+            ("steps", Value::Array(steps)),
+            ("total_steps", self.total_steps.into()),
+        ])
+    }
+
+    /// The page for the run of the program with the source, titled after
+    /// its file.
+    pub fn to_html(&self, title: &str, source: &str, status: &str) -> String {
+        // a </script> in a string of the program would end the script early
+        let data = self.to_json(source, status).to_string().replace("</", "<\\/");
+        PAGE.replace("{title}", &escape_html(title)).replace("{data}", &data)
+    }
+}
+
+impl ExecutionObserver for Report {
+    fn before_step(&mut self, state: &GotoProgramState, _instruction: &Instruction) {
+        self.total_steps += 1;
+        if let Some(count) = self.counts.get_mut(state.program_counter) {
+            *count += 1;
+        }
+        if self.steps.len() < MAX_STEPS {
+            self.steps.push((state.program_counter, None));
+        }
+    }
+
+    fn on_memory_write(&mut self, write: &MemoryWrite) {
+        if self.total_steps <= MAX_STEPS as u64 {
+            if let Some((_, step)) = self.steps.last_mut() {
+                *step = Some(*write);
+            }
+        }
+    }
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body { font-family: sans-serif; margin: 1em; }
+#controls { margin: 1em 0; }
+#scrubber { width: 40em; vertical-align: middle; }
+#main { display: flex; gap: 2em; align-items: flex-start; }
+table { border-collapse: collapse; font-family: monospace; }
+#listing td { padding: 0 0.5em; white-space: pre; }
+#listing td.number, #listing td.count { text-align: right; color: #777; }
+#listing tr.current td { outline: 2px solid #c22; }
+#memory td, #memory th { border: 1px solid #ccc; padding: 0.1em 0.6em; text-align: right; }
+#memory td.written { background: #fd7; }
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p id="summary"></p>
+<div id="controls">
+<button id="previous">&larr;</button>
+<input type="range" id="scrubber" min="0" value="0">
+<button id="next">&rarr;</button>
+<span id="position"></span>
+</div>
+<div id="main">
+<table id="listing"></table>
+<table id="memory"></table>
+</div>
+<script>
+const run = {data};
+const scrubber = document.getElementById('scrubber');
+const listing = document.getElementById('listing');
+const memoryTable = document.getElementById('memory');
+
+const hottest = run.line_counts.reduce((a, b) => Math.max(a, b), 1);
+run.lines.forEach((text, i) => {
+  const row = listing.insertRow();
+  const count = run.line_counts[i];
+  const cells = [i + 1, count || '', text].map(content => {
+    const cell = row.insertCell();
+    cell.textContent = content;
+    return cell;
+  });
+  cells[0].className = 'number';
+  cells[1].className = 'count';
+  if (count) {
+    cells[2].style.background = 'hsla(10, 90%, 55%, ' + (0.1 + 0.6 * count / hottest) + ')';
+  }
+});
+
+// the memory after every 1000th step, so that scrubbing far replays few writes
+const every = 1000;
+const checkpoints = [run.initial_memory.slice()];
+let memory = run.initial_memory.slice();
+run.steps.forEach((step, i) => {
+  if (step.length > 2) memory[step[2]] = step[4];
+  if ((i + 1) % every === 0) checkpoints.push(memory.slice());
+});
+
+function memoryAfter(steps) {
+  const base = Math.floor(steps / every);
+  const memory = checkpoints[base].slice();
+  for (let i = base * every; i < steps; i++) {
+    const step = run.steps[i];
+    if (step.length > 2) memory[step[2]] = step[4];
+  }
+  return memory;
+}
+
+function show(steps) {
+  scrubber.value = steps;
+  const step = steps > 0 ? run.steps[steps - 1] : null;
+  let position = 'step ' + steps + ' of ' + run.steps.length;
+  if (step) {
+    position += ', pc ' + step[0] + ' on line ' + step[1];
+    if (step.length > 2) position += ', mem[' + step[2] + ']: ' + step[3] + ' → ' + step[4];
+  }
+  document.getElementById('position').textContent = position;
+  Array.from(listing.rows).forEach((row, i) => row.className = step && step[1] === i + 1 ? 'current' : '');
+  memoryTable.innerHTML = '';
+  const header = memoryTable.insertRow();
+  const values = memoryTable.insertRow();
+  memoryAfter(steps).forEach((value, cell) => {
+    const th = document.createElement('th');
+    th.textContent = cell;
+    header.appendChild(th);
+    const td = values.insertCell();
+    td.textContent = value;
+    if (step && step.length > 2 && step[2] === cell) td.className = 'written';
+  });
+}
+
+let summary = run.total_steps + ' steps, ' + run.status;
+if (run.steps.length < run.total_steps) summary += ', the first ' + run.steps.length + ' steps are recorded';
+document.getElementById('summary').textContent = summary;
+scrubber.max = run.steps.length;
+scrubber.addEventListener('input', () => show(Number(scrubber.value)));
+const move = by => show(Math.min(run.steps.length, Math.max(0, Number(scrubber.value) + by)));
+document.getElementById('previous').addEventListener('click', () => move(-1));
+document.getElementById('next').addEventListener('click', () => move(1));
+document.addEventListener('keydown', event => {
+  if (event.key === 'ArrowLeft') move(-1);
+  if (event.key === 'ArrowRight') move(1);
+});
+show(0);
+</script>
+</body>
+</html>
+"#;
+
+#[test]
+fn test_report() {
+    use std::sync::{Arc, Mutex};
+
+    let source = "; doubles cell 0 into cell 1\nloop: GOTOZ 0 end\nDEC 0 | INC 1 | INC 1\nGOTO loop\nend: STOP";
+    let program = crate::parse_program(source.to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![2, 0]);
+    let report = Arc::new(Mutex::new(Report::new(&program, &state.memory)));
+    state.observers.push(Box::new(report.clone()));
+    assert_eq!(Ok(true), state.run_with_limit(100));
+    let report = report.lock().unwrap();
+    assert_eq!((12, vec![3, 2, 2, 2, 2, 1]), (report.total_steps, report.counts.clone()));
+    assert_eq!(vec![0, 3, 6, 2, 1], report.line_counts(5));
+    assert_eq!((1, Some(MemoryWrite { cell: 0, old: 2, new: 1 })), report.steps[1]);
+    let json = report.to_json(source, "stopped").to_string();
+    assert!(json.contains(r#""line_counts":[0,3,6,2,1],"initial_memory":["2","0"],"steps":[[0,2],[1,3,0,"2","1"],[2,3,1,"0","1"],"#));
+    let html = report.to_html("a</script>.goto", "</script>", "stopped");
+    assert!(html.contains("<title>a&lt;/script&gt;.goto</title>") && html.contains(r#""lines":["<\/script>"]"#));
+}
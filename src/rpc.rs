@@ -0,0 +1,220 @@
+//! A JSON-RPC 2.0 session on stdin and stdout that keeps a parsed program
+//! and its machine between requests. Messages are either one per line or
+//! framed with a `Content-Length` header like in the Language Server
+//! Protocol, answers use the framing of their request.
+//!
+//! The methods are `parse` with `source` and an optional `memory`, `reset`
+//! with an optional `memory` and `seed`, `step` with an optional `count` and
+//! `run` with an optional `max_steps`. All but `parse` answer with the state
+//! as `goto run --export-state` writes it, `exit` ends the session.
+
+use std::io::{self, BufRead, Write};
+
+use goto::json::Value;
+use goto::random::Random;
+use goto::snapshot::{Snapshot, Status};
+use goto::{parse_program_errors, Error, GotoProgram, ParseOptions};
+
+const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// the program does not parse or cannot run
+const GOTO_ERROR: i64 = -32000;
+
+type RpcError = (i64, String);
+
+/// The next message and whether it came with a `Content-Length` header.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<(String, bool)>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    let header = |line: &str| line.split_once(':').filter(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length")));
+    let length = match header(&line) {
+        Some(length) => length?,
+        None => return Ok(Some((line.trim().to_string(), false))),
+    };
+    // the other headers up to the blank line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some((String::from_utf8_lossy(&body).into_owned(), true)))
+}
+
+struct Session {
+    program: Option<GotoProgram>,
+    /// the machine between requests
+    machine: Snapshot,
+    random: Random,
+}
+
+fn param<'v>(params: Option<&'v Value>, key: &str) -> Option<&'v Value> {
+    params.and_then(|params| params.get(key))
+}
+
+fn number(params: Option<&Value>, key: &str, default: u64) -> Result<u64, RpcError> {
+    match param(params, key) {
+        None => Ok(default),
+        Some(value) => value.as_u64().ok_or_else(|| (INVALID_PARAMS, format!("\"{}\" is not a number", key))),
+    }
+}
+
+fn memory(params: Option<&Value>) -> Result<Vec<u64>, RpcError> {
+    match param(params, "memory") {
+        None => Ok(vec![]),
+        Some(memory) => memory.as_array().and_then(|cells| cells.iter().map(Value::as_u64).collect())
+            .ok_or_else(|| (INVALID_PARAMS, "\"memory\" is not an array of numbers".to_string())),
+    }
+}
+
+impl Session {
+    fn new() -> Session {
+        let machine = Snapshot { status: Status::Running, program_counter: 0, steps: 0, memory: vec![], stack: vec![] };
+        Session { program: None, machine, random: Random::new(0) }
+    }
+
+    fn call(&mut self, method: &str, params: Option<&Value>) -> Result<Value, RpcError> {
+        match method {
+            "parse" => {
+                let source = param(params, "source").and_then(Value::as_str)
+                    .ok_or_else(|| (INVALID_PARAMS, "parse needs a \"source\" string".to_string()))?;
+                let memory = memory(params)?;
+                let program = parse_program_errors(source.to_string(), &ParseOptions::default())
+                    .map_err(|errors| (GOTO_ERROR, Error::Parse { errors, source: source.to_string() }.to_string()))?;
+                let instructions = program.instructions.len();
+                self.program = Some(program);
+                self.reset(memory, 0)?;
+                Ok(Value::object(vec![("instructions", instructions.into())]))
+            }
+            "reset" => {
+                self.reset(memory(params)?, number(params, "seed", 0)?)?;
+                Ok(self.machine.to_json())
+            }
+            "step" => self.advance(number(params, "count", 1)?, Status::Running),
+            "run" => self.advance(number(params, "max_steps", DEFAULT_MAX_STEPS)?, Status::StepLimit),
+            _ => Err((METHOD_NOT_FOUND, format!("there is no method {}", method))),
+        }
+    }
+
+    fn reset(&mut self, memory: Vec<u64>, seed: u64) -> Result<(), RpcError> {
+        let program = self.program.as_ref().ok_or_else(|| (GOTO_ERROR, "no program was parsed".to_string()))?;
+        self.machine = Snapshot { status: Status::Running, program_counter: 0, steps: 0, memory: program.initial_memory(&memory), stack: vec![] };
+        self.random = Random::new(seed);
+        Ok(())
+    }
+
+    /// Runs at most `max_steps` steps, `limit` is the status if they run out.
+    fn advance(&mut self, max_steps: u64, limit: Status) -> Result<Value, RpcError> {
+        let program = self.program.as_ref().ok_or_else(|| (GOTO_ERROR, "no program was parsed".to_string()))?;
+        if self.machine.status == Status::Stopped {
+            return Err((GOTO_ERROR, "the program stopped, reset it to run it again".to_string()));
+        }
+        let mut state = self.machine.restore(program).map_err(|e| (GOTO_ERROR, e))?;
+        state.random = self.random.clone();
+        let status = match state.run_with_limit(state.steps.saturating_add(max_steps)) {
+            Ok(true) => Status::Stopped,
+            Ok(false) => limit,
+            Err(e) => Status::Error(e.to_string()),
+        };
+        self.random = state.random.clone();
+        self.machine = Snapshot::of(&state, status);
+        Ok(self.machine.to_json())
+    }
+
+    /// The answer to a message, none for a notification.
+    fn answer(&mut self, message: &str) -> (Option<Value>, bool) {
+        let request = match Value::parse(message) {
+            Ok(request) => request,
+            Err(e) => return (Some(response(Value::Null, Err((PARSE_ERROR, e)))), false),
+        };
+        let id = request.get("id").cloned();
+        let method = match request.get("method").and_then(Value::as_str) {
+            Some(method) => method,
+            None => return (Some(response(id.unwrap_or(Value::Null), Err((INVALID_REQUEST, "the request has no method".to_string())))), false),
+        };
+        let exit = method == "exit";
+        let result = if exit { Ok(Value::Null) } else { self.call(method, request.get("params")) };
+        (id.map(|id| response(id, result)), exit)
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    let outcome = match result {
+        Ok(result) => ("result", result),
+        Err((code, message)) => ("error", Value::object(vec![("code", code.into()), ("message", message.into())])),
+    };
+    Value::object(vec![("jsonrpc", "2.0".into()), ("id", id), outcome])
+}
+
+fn serve(reader: &mut impl BufRead, out: &mut impl Write) -> io::Result<()> {
+    let mut session = Session::new();
+    while let Some((message, framed)) = read_message(reader)? {
+        let (answer, exit) = session.answer(&message);
+        if let Some(answer) = answer {
+            let answer = answer.to_string();
+            if framed {
+                write!(out, "Content-Length: {}\r\n\r\n{}", answer.len(), answer)?;
+            } else {
+                writeln!(out, "{}", answer)?;
+            }
+            out.flush()?;
+        }
+        if exit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn run() -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    serve(&mut stdin.lock(), &mut stdout.lock())
+}
+
+#[test]
+fn test_rpc_session() {
+    let body = r#"{"jsonrpc":"2.0","id":3,"method":"run"}"#;
+    let input = format!(r#"{{"jsonrpc":"2.0","id":1,"method":"parse","params":{{"source":"loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP","memory":[2,5]}}}}
+{{"jsonrpc":"2.0","id":2,"method":"step","params":{{"count":3}}}}
+Content-Length: {}
+
+{}{{"jsonrpc":"2.0","id":4,"method":"step"}}
+{{"jsonrpc":"2.0","method":"reset","params":{{"memory":[1]}}}}
+{{"jsonrpc":"2.0","id":5,"method":"run","params":{{"max_steps":2}}}}
+{{"jsonrpc":"2.0","id":6,"method":"parse","params":{{"source":"GOTO x"}}}}
+{{"jsonrpc":"2.0","id":7,"method":"jump"}}
+{{"jsonrpc":"2.0","id":8,"method":"exit"}}
+{{"jsonrpc":"2.0","id":9,"method":"run"}}
+"#, body.len(), body);
+    let mut out = vec![];
+    serve(&mut input.as_bytes(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let framed = r#"{"jsonrpc":"2.0","id":3,"result":{"version":1,"status":"stopped","program_counter":4,"steps":10,"memory":[0,7]}}"#;
+    assert_eq!(vec![
+        r#"{"jsonrpc":"2.0","id":1,"result":{"instructions":5}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","id":2,"result":{"version":1,"status":"running","program_counter":3,"steps":3,"memory":[1,6]}}"#.to_string(),
+        format!("Content-Length: {}", framed.len()),
+        String::new(),
+        framed.to_string() + r#"{"jsonrpc":"2.0","id":4,"error":{"code":-32000,"message":"the program stopped, reset it to run it again"}}"#,
+        r#"{"jsonrpc":"2.0","id":5,"result":{"version":1,"status":"step_limit","program_counter":2,"steps":2,"memory":[0]}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","id":6,"error":{"code":-32000,"message":"error in line 1: Unknown label: x\n  |\n1 | GOTO x\n  |      ^"}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","id":7,"error":{"code":-32601,"message":"there is no method jump"}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","id":8,"result":null}"#.to_string(),
+    ], out.lines().map(str::to_string).collect::<Vec<_>>());
+}
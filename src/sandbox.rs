@@ -0,0 +1,214 @@
+//! Running untrusted programs under ceilings on steps, wall time, memory
+//! cells and the size of the exported state. Nothing here panics on any
+//! program or input, and every allocation is bounded by a limit.
+
+use std::fmt;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use crate::bytecode::Bytecode;
+use crate::snapshot::{Snapshot, Status};
+use crate::{parse_program, read_input, GotoProgram, GotoProgramState};
+
+/// How many steps run between two looks at the clock.
+const TIME_SLICE: u64 = 100_000;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Limits {
+    pub max_steps: u64,
+    pub max_time: Duration,
+    pub max_cells: usize,
+    /// of the state exported as JSON
+    pub max_snapshot_bytes: usize,
+    pub max_source_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_steps: 10_000_000,
+            max_time: Duration::from_secs(1),
+            max_cells: 1000,
+            max_snapshot_bytes: 64 * 1024,
+            max_source_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Limit {
+    Steps(u64),
+    Time(Duration),
+    Cells(usize),
+    SnapshotBytes(usize),
+    SourceBytes(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Steps(steps) => write!(f, "the limit of {} steps", steps),
+            Limit::Time(time) => write!(f, "the time limit of {:?}", time),
+            Limit::Cells(cells) => write!(f, "the limit of {} memory cells", cells),
+            Limit::SnapshotBytes(bytes) => write!(f, "the limit of {} bytes for the exported state", bytes),
+            Limit::SourceBytes(bytes) => write!(f, "the limit of {} bytes for the source", bytes),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Outcome {
+    Stopped,
+    Failed(String),
+    Exceeded(Limit),
+}
+
+impl Outcome {
+    /// The status of the snapshot, running into a limit other than the step
+    /// limit counts as an error.
+    pub fn status(&self) -> Status {
+        match self {
+            Outcome::Stopped => Status::Stopped,
+            Outcome::Failed(e) => Status::Error(e.clone()),
+            Outcome::Exceeded(Limit::Steps(_)) => Status::StepLimit,
+            Outcome::Exceeded(limit) => Status::Error(format!("exceeded {}", limit)),
+        }
+    }
+}
+
+/// Reads at most `max_bytes` from the reader, more is an error.
+pub fn read_limited(reader: impl Read, max_bytes: usize, limit: Limit) -> Result<String, Outcome> {
+    let mut bytes = vec![];
+    reader.take(max_bytes as u64 + 1).read_to_end(&mut bytes).map_err(|e| Outcome::Failed(e.to_string()))?;
+    if bytes.len() > max_bytes {
+        return Err(Outcome::Exceeded(limit));
+    }
+    String::from_utf8(bytes).map_err(|e| Outcome::Failed(e.to_string()))
+}
+
+/// Parses the program and the input within the limits, an input may have
+/// up to `max_cells` cells of 20 digits each.
+pub fn load(source: impl Read, input: impl Read, limits: &Limits) -> Result<(GotoProgram, Vec<u64>), Outcome> {
+    let source = read_limited(source, limits.max_source_bytes, Limit::SourceBytes(limits.max_source_bytes))?;
+    let input_bytes = limits.max_cells.saturating_mul(21).saturating_add(1);
+    let input = read_limited(input, input_bytes, Limit::Cells(limits.max_cells))?;
+    let program = parse_program(source).map_err(Outcome::Failed)?;
+    let memory = read_input(input).map_err(|e| Outcome::Failed(format!("invalid input: {}", e)))?;
+    layout(program, &memory, limits)
+}
+
+/// Parses the program and lays out its memory on the input within the limits.
+pub fn prepare(source: String, input: &[u64], limits: &Limits) -> Result<(GotoProgram, Vec<u64>), Outcome> {
+    if source.len() > limits.max_source_bytes {
+        return Err(Outcome::Exceeded(Limit::SourceBytes(limits.max_source_bytes)));
+    }
+    if input.len() > limits.max_cells {
+        return Err(Outcome::Exceeded(Limit::Cells(limits.max_cells)));
+    }
+    layout(parse_program(source).map_err(Outcome::Failed)?, input, limits)
+}
+
+fn layout(program: GotoProgram, input: &[u64], limits: &Limits) -> Result<(GotoProgram, Vec<u64>), Outcome> {
+    if input.len().max(program.declared_cells()) > limits.max_cells {
+        return Err(Outcome::Exceeded(Limit::Cells(limits.max_cells)));
+    }
+    let memory = program.initial_memory(input);
+    Ok((program, memory))
+}
+
+/// Runs the state on the bytecode VM until it stops, fails or runs into a
+/// limit. The values on the stack count as cells.
+pub fn run(state: &mut GotoProgramState, limits: &Limits) -> Outcome {
+    if state.memory.len() > limits.max_cells {
+        return Outcome::Exceeded(Limit::Cells(limits.max_cells));
+    }
+    let bytecode = match Bytecode::compile(state.program) {
+        Ok(bytecode) => bytecode,
+        Err(e) => return Outcome::Failed(e),
+    };
+    let started = Instant::now();
+    while state.steps < limits.max_steps {
+        if started.elapsed() > limits.max_time {
+            return Outcome::Exceeded(Limit::Time(limits.max_time));
+        }
+        let slice_end = state.steps.saturating_add(TIME_SLICE).min(limits.max_steps);
+        let result = bytecode.run(state, slice_end);
+        if state.memory.len() + state.stack.len() > limits.max_cells {
+            return Outcome::Exceeded(Limit::Cells(limits.max_cells));
+        }
+        match result {
+            Ok(true) => return Outcome::Stopped,
+            Ok(false) => {}
+            Err(e) => return Outcome::Failed(e),
+        }
+    }
+    Outcome::Exceeded(Limit::Steps(limits.max_steps))
+}
+
+/// The final state as JSON, if it fits into the snapshot limit.
+pub fn snapshot_json(state: &GotoProgramState, outcome: &Outcome, limits: &Limits) -> Result<String, Limit> {
+    let json = Snapshot::of(state, outcome.status()).to_json().to_string();
+    if json.len() > limits.max_snapshot_bytes {
+        return Err(Limit::SnapshotBytes(limits.max_snapshot_bytes));
+    }
+    Ok(json)
+}
+
+/// Runs what `load` or `prepare` returned and gives the final state as
+/// JSON, a state too large for it is replaced by one without the memory.
+pub fn execute(loaded: Result<(GotoProgram, Vec<u64>), Outcome>, limits: &Limits) -> (String, Outcome) {
+    let empty = GotoProgram { instructions: vec![], source_lines: vec![], banks: 1, aliases: vec![], data: vec![], header: Default::default() };
+    let (program, memory, outcome) = match loaded {
+        Ok((program, memory)) => (program, memory, None),
+        Err(outcome) => (empty, vec![], Some(outcome)),
+    };
+    let mut state = GotoProgramState::new(&program, memory);
+    let outcome = outcome.unwrap_or_else(|| state.run_with_limits(limits));
+    match snapshot_json(&state, &outcome, limits) {
+        Ok(json) => (json, outcome),
+        Err(limit) => {
+            let outcome = Outcome::Exceeded(limit);
+            let snapshot = Snapshot { status: outcome.status(), program_counter: state.program_counter, steps: state.steps, memory: vec![], stack: vec![] };
+            (snapshot.to_json().to_string(), outcome)
+        }
+    }
+}
+
+#[test]
+fn test_sandbox() {
+    let limits = Limits { max_steps: 1000, max_cells: 3, max_snapshot_bytes: 100, max_source_bytes: 100, ..Limits::default() };
+    let (program, memory) = load("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".as_bytes(), "3 4".as_bytes(), &limits).unwrap();
+    let mut state = GotoProgramState::new(&program, memory);
+    assert_eq!(Outcome::Stopped, state.run_with_limits(&limits));
+    assert_eq!(Ok(r#"{"version":1,"status":"stopped","program_counter":4,"steps":14,"memory":[0,7]}"#.to_string()),
+               snapshot_json(&state, &Outcome::Stopped, &limits));
+
+    let forever = parse_program("loop: INC 0\nGOTO loop".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&forever, vec![0]);
+    assert_eq!(Outcome::Exceeded(Limit::Steps(1000)), run(&mut state, &limits));
+    assert_eq!(1000, state.steps);
+    let mut state = GotoProgramState::new(&forever, vec![0]);
+    let timed = Limits { max_steps: u64::MAX, max_time: Duration::from_millis(20), ..limits };
+    assert_eq!(Outcome::Exceeded(Limit::Time(Duration::from_millis(20))), run(&mut state, &timed));
+    let pushing = parse_program("loop: PUSH 0\nGOTO loop".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&pushing, vec![0]);
+    assert_eq!(Outcome::Exceeded(Limit::Cells(3)), run(&mut state, &limits));
+
+    assert_eq!(Err(Outcome::Exceeded(Limit::Cells(3))), load("STOP".as_bytes(), "1 2 3 4".as_bytes(), &limits));
+    assert_eq!(Err(Outcome::Exceeded(Limit::Cells(3))), load(".init 1000000000000 = 1".as_bytes(), "".as_bytes(), &limits));
+    assert!(matches!(load(".init 18446744073709551615 = 1".as_bytes(), "".as_bytes(), &limits), Err(Outcome::Failed(_))));
+    assert!(matches!(load(".init 18446744073709551614 = 1\n.data 2".as_bytes(), "".as_bytes(), &limits), Err(Outcome::Failed(_))));
+    assert_eq!(vec![5, 0, 7], load(".data 4 0 7".as_bytes(), "5".as_bytes(), &limits).unwrap().1);
+    assert_eq!(Err(Outcome::Exceeded(Limit::Cells(3))), load("STOP".as_bytes(), "0 ".repeat(1000).as_bytes(), &limits));
+    assert_eq!(Err(Outcome::Exceeded(Limit::SourceBytes(100))), load("INC 0\n".repeat(100).as_bytes(), "".as_bytes(), &limits));
+    let wide = GotoProgramState::new(&program, vec![u64::MAX; 3]);
+    let small = Limits { max_snapshot_bytes: 50, ..limits };
+    assert_eq!(Err(Limit::SnapshotBytes(50)), snapshot_json(&wide, &Outcome::Stopped, &small));
+    assert_eq!(Status::Error("exceeded the limit of 3 memory cells".to_string()), Outcome::Exceeded(Limit::Cells(3)).status());
+
+    assert_eq!(Err(Outcome::Exceeded(Limit::Cells(3))), prepare("STOP".to_string(), &[1, 2, 3, 4], &limits));
+    let (json, outcome) = execute(prepare("INC 1\nSTOP".to_string(), &[4, 0], &limits), &limits);
+    assert_eq!((r#"{"version":1,"status":"stopped","program_counter":1,"steps":2,"memory":[4,1]}"#, Outcome::Stopped), (json.as_str(), outcome));
+    let (json, outcome) = execute(Err(Outcome::Failed("no".to_string())), &Limits { max_snapshot_bytes: 1000, ..limits });
+    assert_eq!((r#"{"version":1,"status":"error","error":"no","program_counter":0,"steps":0,"memory":[]}"#, Outcome::Failed("no".to_string())), (json.as_str(), outcome));
+}
@@ -0,0 +1,240 @@
+//! A small HTTP/1.1 server with a JSON API for running programs remotely,
+//! one thread and one request per connection, at most `MAX_CONNECTIONS` at
+//! once. Further connections are answered with 503 right away.
+//!
+//! `POST /run` takes `{"source": ..., "memory": [...], "limits": {...}}` and
+//! answers with the final state as `goto sandbox` prints it. The limits of a
+//! request, `max_steps`, `max_time_ms` and `max_cells`, can only lower the
+//! limits of the server. `POST /check` takes `{"source": ...}` and answers
+//! with `{"ok": true, "instructions": n, "required_cells": m}`, where `m` is
+//! the length the memory needs for every cell the program uses, or the parse
+//! errors.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use goto::json::Value;
+use goto::layout::Layout;
+use goto::sandbox::{self, Limits};
+use goto::{parse_program_errors, ParseOptions};
+
+/// How long reading a request or writing a response may stall.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// Counts a connection as open until it is dropped.
+struct Open(Arc<AtomicUsize>);
+
+impl Drop for Open {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn serve(address: &str, limits: Limits) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+    let open = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            open.fetch_sub(1, Ordering::SeqCst);
+            let busy = format!("the server handles {} connections already", MAX_CONNECTIONS);
+            if let Err(e) = stream.set_write_timeout(Some(IO_TIMEOUT)).and_then(|_| answer(stream, 503, &error(&busy))) {
+                eprintln!("{}", e);
+            }
+            continue;
+        }
+        let open = Open(open.clone());
+        thread::spawn(move || {
+            let _open = open;
+            if let Err(e) = connection(stream, &limits) {
+                eprintln!("{}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn connection(stream: TcpStream, limits: &Limits) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    // the source, 21 bytes for each cell of the memory and some room for the rest
+    let max_body = limits.max_source_bytes.saturating_add(limits.max_cells.saturating_mul(21)).saturating_add(4096);
+    let (status, body) = match read_request(&mut BufReader::new(&stream), max_body) {
+        Ok((method, path, body)) => respond(&method, &path, &body, limits),
+        Err((status, message)) => (status, error(&message)),
+    };
+    answer(stream, status, &body)
+}
+
+fn answer(mut stream: TcpStream, status: u16, body: &str) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+        Access-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: POST, OPTIONS\r\n\
+        Access-Control-Allow-Headers: Content-Type\r\nConnection: close\r\n\r\n{}",
+        status, reason(status), body.len(), body)?;
+    stream.flush()
+}
+
+type HttpError = (u16, String);
+
+fn read_request(reader: &mut impl BufRead, max_body: usize) -> Result<(String, String, String), HttpError> {
+    let bad = |message: &str| (400, message.to_string());
+    let mut head = vec![];
+    loop {
+        let mut line = String::new();
+        let read = reader.by_ref().take(MAX_HEADER_BYTES as u64).read_line(&mut line).map_err(|e| (400, e.to_string()))?;
+        if read == 0 {
+            return Err(bad("the request ended in its header"));
+        }
+        if line.trim_end().is_empty() {
+            break;
+        }
+        head.push(line.trim_end().to_string());
+        if head.iter().map(|line| line.len()).sum::<usize>() > MAX_HEADER_BYTES {
+            return Err((431, "the header is too large".to_string()));
+        }
+    }
+    let mut request_line = head.first().ok_or_else(|| bad("the request is empty"))?.split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().ok_or_else(|| bad("the request line has no path"))?.to_string();
+    let length = head.iter().skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>().map_err(|_| bad("invalid Content-Length")))
+        .transpose()?
+        .unwrap_or(0);
+    if length > max_body {
+        return Err((413, format!("the body is larger than {} bytes", max_body)));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|e| (400, e.to_string()))?;
+    let body = String::from_utf8(body).map_err(|_| bad("the body is not UTF-8"))?;
+    Ok((method, path, body))
+}
+
+/// The status and the JSON body of the answer to a request.
+pub fn respond(method: &str, path: &str, body: &str, limits: &Limits) -> (u16, String) {
+    let handler: fn(&Value, &Limits) -> Result<String, String> = match path {
+        "/run" => run,
+        "/check" => check,
+        _ => return (404, error(&format!("there is no {}, only POST /run and POST /check", path))),
+    };
+    match method {
+        "POST" => {}
+        // the preflight of a browser
+        "OPTIONS" => return (204, String::new()),
+        _ => return (405, error(&format!("{} only takes POST", path))),
+    }
+    match Value::parse(body).map_err(|e| format!("invalid JSON: {}", e)).and_then(|request| handler(&request, limits)) {
+        Ok(answer) => (200, answer),
+        Err(message) => (400, error(&message)),
+    }
+}
+
+fn error(message: &str) -> String {
+    Value::object(vec![("error", message.into())]).to_string()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Bad Request",
+    }
+}
+
+fn source(request: &Value) -> Result<String, String> {
+    request.get("source").and_then(Value::as_str).map(str::to_string).ok_or_else(|| "the request has no \"source\" string".to_string())
+}
+
+fn run(request: &Value, limits: &Limits) -> Result<String, String> {
+    let source = source(request)?;
+    let memory = match request.get("memory") {
+        None => vec![],
+        Some(memory) => memory.as_array().and_then(|cells| cells.iter().map(Value::as_u64).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| "\"memory\" is not an array of numbers".to_string())?,
+    };
+    let mut limits = *limits;
+    if let Some(requested) = request.get("limits") {
+        let limit = |name: &str| match requested.get(name) {
+            None => Ok(None),
+            Some(value) => value.as_u64().map(Some).ok_or_else(|| format!("the limit {} is not a number", name)),
+        };
+        if let Some(max_steps) = limit("max_steps")? {
+            limits.max_steps = limits.max_steps.min(max_steps);
+        }
+        if let Some(max_time) = limit("max_time_ms")? {
+            limits.max_time = limits.max_time.min(Duration::from_millis(max_time));
+        }
+        if let Some(max_cells) = limit("max_cells")? {
+            limits.max_cells = limits.max_cells.min(max_cells as usize);
+        }
+    }
+    Ok(sandbox::execute(sandbox::prepare(source, &memory, &limits), &limits).0)
+}
+
+fn check(request: &Value, limits: &Limits) -> Result<String, String> {
+    let source = source(request)?;
+    if source.len() > limits.max_source_bytes {
+        return Err(format!("the source is larger than {} bytes", limits.max_source_bytes));
+    }
+    Ok(match parse_program_errors(source.clone(), &ParseOptions::default()) {
+        Ok(program) => Value::object(vec![
+            ("ok", true.into()),
+            ("instructions", program.instructions.len().into()),
+            ("required_cells", Layout::of(&program).required_cells().into()),
+        ]),
+        Err(errors) => Value::object(vec![
+            ("ok", false.into()),
+            ("errors", Value::Array(errors.iter().map(|error| Value::object(vec![
+                ("line", error.line.map_or(Value::Null, Value::from)),
+                ("message", error.message.as_str().into()),
+                ("hint", error.hint.as_deref().map_or(Value::Null, Value::from)),
+                ("rendered", error.render(&source).into()),
+            ])).collect())),
+        ]),
+    }.to_string())
+}
+
+#[test]
+fn test_respond() {
+    let limits = Limits { max_steps: 1000, ..Limits::default() };
+    let source = r#""loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP""#;
+    let (status, body) = respond("POST", "/run", &format!(r#"{{"source": {}, "memory": [2, 5]}}"#, source), &limits);
+    assert_eq!((200, r#"{"version":1,"status":"stopped","program_counter":4,"steps":10,"memory":[0,7]}"#), (status, body.as_str()));
+    let (_, body) = respond("POST", "/run", &format!(r#"{{"source": {}, "memory": [2, 5], "limits": {{"max_steps": 3}}}}"#, source), &limits);
+    assert!(body.contains(r#""status":"step_limit""#), "{}", body);
+    let (_, body) = respond("POST", "/run", r#"{"source": "loop: GOTO loop", "limits": {"max_steps": 1000000}}"#, &limits);
+    assert!(body.contains(r#""status":"step_limit","program_counter":0,"steps":1000"#), "{}", body);
+
+    assert_eq!((200, r#"{"ok":true,"instructions":5,"required_cells":2}"#.to_string()), respond("POST", "/check", &format!(r#"{{"source": {}}}"#, source), &limits));
+    let (status, body) = respond("POST", "/check", r#"{"source": "INC 0\nGOTO nowhere"}"#, &limits);
+    assert_eq!(200, status);
+    assert!(body.starts_with(r#"{"ok":false,"errors":[{"line":2,"message":"Unknown label: nowhere""#), "{}", body);
+
+    assert_eq!(400, respond("POST", "/run", "{", &limits).0);
+    assert_eq!(400, respond("POST", "/run", r#"{"source": "STOP", "memory": [-1]}"#, &limits).0);
+    assert_eq!(405, respond("GET", "/run", "", &limits).0);
+    assert_eq!(404, respond("POST", "/", "", &limits).0);
+
+    let request = "POST /check HTTP/1.1\r\nHost: x\r\ncontent-length: 18\r\n\r\n{\"source\": \"STOP\"}";
+    assert_eq!(Ok(("POST".to_string(), "/check".to_string(), "{\"source\": \"STOP\"}".to_string())), read_request(&mut request.as_bytes(), 100));
+    assert_eq!(413, read_request(&mut request.as_bytes(), 10).unwrap_err().0);
+}
@@ -0,0 +1,35 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static STATUS_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_status_request(_: libc::c_int) {
+    STATUS_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Makes Ctrl+C set a flag for the run loop to check instead of killing the process.
+pub fn catch_interrupt() {
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Makes SIGUSR1 request a status report instead of terminating the process.
+pub fn catch_status_request() {
+    unsafe {
+        libc::signal(libc::SIGUSR1, on_status_request as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// True once for every SIGUSR1 received since the last call.
+pub fn take_status_request() -> bool {
+    STATUS_REQUESTED.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,213 @@
+use std::fmt;
+
+use crate::json::Value;
+use crate::{GotoProgram, GotoProgramState, RegisterIndex};
+
+const VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Status {
+    Running,
+    Stopped,
+    StepLimit,
+    Error(String),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Running => write!(f, "running"),
+            Status::Stopped => write!(f, "stopped"),
+            Status::StepLimit => write!(f, "step limit reached"),
+            Status::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+/// The state of a machine at some point of a run, as exported to JSON.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snapshot {
+    pub status: Status,
+    pub program_counter: RegisterIndex,
+    pub steps: u64,
+    pub memory: Vec<u64>,
+    /// of `PUSH`, the top last
+    pub stack: Vec<u64>,
+}
+
+impl Snapshot {
+    pub fn of(state: &GotoProgramState, status: Status) -> Snapshot {
+        Snapshot {
+            status,
+            program_counter: state.program_counter,
+            steps: state.steps,
+            memory: state.memory.clone(),
+            stack: state.stack.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let (status, error) = match &self.status {
+            Status::Running => ("running", None),
+            Status::Stopped => ("stopped", None),
+            Status::StepLimit => ("step_limit", None),
+            Status::Error(e) => ("error", Some(e.clone())),
+        };
+        let mut fields = vec![("version", VERSION.into()), ("status", status.into())];
+        if let Some(error) = error {
+            fields.push(("error", error.into()));
+        }
+        fields.push(("program_counter", self.program_counter.into()));
+        fields.push(("steps", self.steps.into()));
+        fields.push(("memory", self.memory.clone().into()));
+        if !self.stack.is_empty() {
+            fields.push(("stack", self.stack.clone().into()));
+        }
+        Value::object(fields)
+    }
+
+    /// The JSON with the values of the output cells in `result`.
+    pub fn to_json_with_result(&self, cells: &[(String, RegisterIndex)]) -> Value {
+        let mut json = self.to_json();
+        if let Value::Object(fields) = &mut json {
+            fields.push(("result".to_string(), output_cells_json(&self.memory, cells)));
+        }
+        json
+    }
+
+    pub fn from_json(value: &Value) -> Result<Snapshot, String> {
+        let number = |key: &str| value.get(key).and_then(|v| v.as_u64())
+            .ok_or_else(|| format!("missing or invalid {}", key));
+        if number("version")? != VERSION {
+            return Err(format!("unsupported snapshot version {}", number("version")?));
+        }
+        let status = match value.get("status").and_then(|s| s.as_str()) {
+            Some("running") => Status::Running,
+            Some("stopped") => Status::Stopped,
+            Some("step_limit") => Status::StepLimit,
+            Some("error") => Status::Error(value.get("error").and_then(|e| e.as_str()).unwrap_or("").to_string()),
+            _ => return Err("missing or invalid status".to_string()),
+        };
+        let memory = value.get("memory").and_then(|m| m.as_array())
+            .ok_or("missing or invalid memory")?
+            .iter()
+            .map(|v| v.as_u64().ok_or("missing or invalid memory"))
+            .collect::<Result<_, _>>()?;
+        let stack = match value.get("stack") {
+            None => vec![],
+            Some(stack) => stack.as_array().ok_or("invalid stack")?
+                .iter()
+                .map(|v| v.as_u64().ok_or("invalid stack"))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Snapshot {
+            status,
+            program_counter: number("program_counter")? as RegisterIndex,
+            steps: number("steps")?,
+            memory,
+            stack,
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Snapshot, String> {
+        Snapshot::from_json(&Value::parse(text)?)
+    }
+
+    /// Rebuilds the machine so a run can continue where the snapshot was taken.
+    pub fn restore<'a>(&self, program: &'a GotoProgram) -> Result<GotoProgramState<'a>, String> {
+        if self.status == Status::Stopped {
+            return Err("the snapshot was taken after the program stopped".to_string());
+        }
+        if self.program_counter >= program.instructions.len() {
+            return Err(format!("program counter {} is outside of the program", self.program_counter));
+        }
+        let mut state = GotoProgramState::new(program, self.memory.clone());
+        state.program_counter = self.program_counter;
+        state.steps = self.steps;
+        state.stack = self.stack.clone();
+        Ok(state)
+    }
+}
+
+/// A one-line overview of a memory that may be too large to print.
+pub fn memory_summary(memory: &[u64]) -> String {
+    const SHOWN: usize = 8;
+    let nonzero = memory.iter().filter(|v| **v != 0).count();
+    let mut summary = format!("{} cells, {} nonzero", memory.len(), nonzero);
+    if let Some((cell, max)) = memory.iter().enumerate().max_by_key(|&(cell, v)| (v, std::cmp::Reverse(cell))) {
+        summary += &format!(", max {} in [{}]", max, cell);
+    }
+    let shown: Vec<_> = memory.iter().take(SHOWN).map(|v| v.to_string()).collect();
+    let more = if memory.len() > SHOWN { ", ..." } else { "" };
+    summary + &format!(": [{}{}]", shown.join(", "), more)
+}
+
+/// The output cells of a memory like `sum = 5, 2 = 0`, `?` for cells beyond it.
+pub fn show_output_cells(memory: &[u64], cells: &[(String, RegisterIndex)]) -> String {
+    let values: Vec<String> = cells.iter()
+        .map(|(name, cell)| format!("{} = {}", name, memory.get(*cell).map_or("?".to_string(), u64::to_string)))
+        .collect();
+    values.join(", ")
+}
+
+/// The output cells of a memory as an object, `null` for cells beyond it.
+pub fn output_cells_json(memory: &[u64], cells: &[(String, RegisterIndex)]) -> Value {
+    Value::object(cells.iter().map(|(name, cell)| (name.as_str(), memory.get(*cell).map_or(Value::Null, |&value| value.into()))).collect())
+}
+
+/// Cells that differ between two memories, a missing cell is `None`.
+pub fn changed_cells(a: &[u64], b: &[u64]) -> Vec<(RegisterIndex, Option<u64>, Option<u64>)> {
+    (0..a.len().max(b.len()))
+        .map(|cell| (cell, a.get(cell).cloned(), b.get(cell).cloned()))
+        .filter(|(_, a, b)| a != b)
+        .collect()
+}
+
+#[test]
+fn test_changed_cells() {
+    assert_eq!(vec![(1, Some(2), Some(3)), (3, Some(0), None)], changed_cells(&[1, 2, 0, 0], &[1, 3, 0]));
+}
+
+#[test]
+fn test_memory_summary() {
+    assert_eq!("0 cells, 0 nonzero: []", memory_summary(&[]));
+    assert_eq!("10 cells, 4 nonzero, max 7 in [2]: [0, 1, 7, 0, 0, 0, 7, 0, ...]",
+               memory_summary(&[0, 1, 7, 0, 0, 0, 7, 0, 0, 2]));
+}
+
+#[test]
+fn test_output_cells() {
+    let cells = vec![("sum".to_string(), 2), ("0".to_string(), 0), ("9".to_string(), 9)];
+    assert_eq!("sum = 7, 0 = 1, 9 = ?", show_output_cells(&[1, 0, 7], &cells));
+    assert_eq!(r#"{"sum":7,"0":1,"9":null}"#, output_cells_json(&[1, 0, 7], &cells).to_string());
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let snapshot = Snapshot {
+        status: Status::Error("DEC 1 on a cell that is 0".to_string()),
+        program_counter: 3,
+        steps: 42,
+        memory: vec![0, u64::MAX, 7],
+        stack: vec![5, 1],
+    };
+    assert_eq!(Ok(snapshot.clone()), Snapshot::parse(&snapshot.to_json().to_string()));
+    assert!(Snapshot::parse(r#"{"version":2}"#).is_err());
+}
+
+#[test]
+fn test_restore() {
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let mut state = GotoProgramState::new(&program, vec![3, 0]);
+    for _ in 0..6 {
+        state.step().unwrap();
+    }
+    let snapshot = Snapshot::parse(&Snapshot::of(&state, Status::Running).to_json().to_string()).unwrap();
+    let mut resumed = snapshot.restore(&program).unwrap();
+    assert_eq!((2, 6, vec![1, 1]), (resumed.program_counter, resumed.steps, resumed.memory.clone()));
+    state.run().unwrap();
+    resumed.run().unwrap();
+    assert_eq!((state.steps, state.memory), (resumed.steps, resumed.memory));
+    assert!(Snapshot { status: Status::Stopped, ..snapshot.clone() }.restore(&program).is_err());
+    assert!(Snapshot { program_counter: 5, ..snapshot }.restore(&program).is_err());
+}
@@ -0,0 +1,109 @@
+use std::ops::Range;
+
+/// The syntactic parts of a single line of goto source: an optional `label:`,
+/// the instruction tokens and an optional trailing `; comment`.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct SourceLine {
+    pub label: Option<String>,
+    pub tokens: Vec<String>,
+    pub comment: Option<String>,
+}
+
+impl SourceLine {
+    pub fn is_blank(&self) -> bool {
+        self.label.is_none() && self.tokens.is_empty() && self.comment.is_none()
+    }
+
+    /// Whether the line is a directive like `.alias counter 0` rather than an instruction.
+    pub fn is_directive(&self) -> bool {
+        self.tokens.first().is_some_and(|token| token.starts_with('.'))
+    }
+}
+
+pub fn is_label_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false
+    }
+}
+
+/// Separates the instructions of a line like `INC 1 | DEC 2 | GOTOZ 2 9`,
+/// `;` already starts comments.
+pub const SEPARATOR: char = '|';
+
+pub fn parse_line(line: &str) -> Result<SourceLine, String> {
+    let (code, comment) = match line.find(';') {
+        Some(pos) => (&line[..pos], Some(line[pos..].trim_end().to_string())),
+        None => (line, None),
+    };
+    let first_segment = code.find(SEPARATOR).map_or(code, |pos| &code[..pos]);
+    let (label, code) = match first_segment.find(':') {
+        Some(pos) => {
+            let name = code[..pos].trim();
+            if !is_label_name(name) {
+                return Result::Err(format!("Invalid label name: {}", name));
+            }
+            (Some(name.to_string()), &code[pos + 1..])
+        }
+        None => (None, code),
+    };
+    let tokens = code.split_whitespace().map(|t| t.to_string()).collect();
+    Ok(SourceLine { label, tokens, comment })
+}
+
+/// The segments of a line separated by `SEPARATOR`, each with its own
+/// label. The comment belongs to the last one.
+pub fn parse_segments(line: &str) -> Result<Vec<SourceLine>, String> {
+    let (code, comment) = match line.find(';') {
+        Some(pos) => (&line[..pos], &line[pos..]),
+        None => (line, ""),
+    };
+    let mut segments = code.split(SEPARATOR).map(parse_line).collect::<Result<Vec<_>, _>>()?;
+    if let Some(last) = segments.last_mut() {
+        last.comment = parse_line(comment)?.comment;
+    }
+    Ok(segments)
+}
+
+/// The byte ranges of the instruction tokens of every segment of the line,
+/// in the same order as the tokens of `parse_segments`.
+pub fn token_spans(line: &str) -> Vec<Vec<Range<usize>>> {
+    let code = line.find(';').map_or(line, |pos| &line[..pos]);
+    let mut offset = 0;
+    code.split(SEPARATOR).map(|segment| {
+        let start = offset;
+        offset += segment.len() + SEPARATOR.len_utf8();
+        let code = segment.find(':').map_or(0, |pos| pos + 1);
+        segment[code..].split_whitespace().map(|token| {
+            let begin = start + token.as_ptr() as usize - segment.as_ptr() as usize;
+            begin..begin + token.len()
+        }).collect()
+    }).collect()
+}
+
+#[test]
+fn test_parse_line() {
+    assert_eq!(Result::Ok(SourceLine::default()), parse_line("   "));
+    assert_eq!(Result::Ok(SourceLine {
+        label: Some("loop".to_string()),
+        tokens: vec!["GOTOZ".to_string(), "0".to_string(), "end".to_string()],
+        comment: Some("; done?".to_string()),
+    }), parse_line(" loop:  GOTOZ 0\tend ; done?  "));
+    assert_eq!(Result::Ok(SourceLine {
+        label: None,
+        tokens: vec![],
+        comment: Some(";; header: with colon".to_string()),
+    }), parse_line(";; header: with colon"));
+    assert!(parse_line("1abc: INC 0").is_err());
+    let segments = parse_segments("a: INC 1 | b: DEC 2|GOTOZ 2 a ; x | y").unwrap();
+    assert_eq!(3, segments.len());
+    assert_eq!((Some("b".to_string()), vec!["DEC".to_string(), "2".to_string()]), (segments[1].label.clone(), segments[1].tokens.clone()));
+    assert_eq!(Some("; x | y".to_string()), segments[2].comment);
+    assert_eq!(Some("a".to_string()), parse_line("a: INC 1 | b: DEC 2").unwrap().label);
+    assert!(parse_line(": INC 0").is_err());
+    assert_eq!(vec![vec![3..6, 7..8], vec![14..17, 18..19], vec![20..25, 26..27, 28..29]],
+               token_spans("a: INC 1 | b: DEC 2|GOTOZ 2 a ; x | y"));
+}
@@ -0,0 +1,177 @@
+//! Source maps from the output of `goto compile` back to the program source,
+//! in the Source Map v3 format browsers and editors understand.
+//!
+//! Binary outputs, WebAssembly and `.gbc`, are one line whose columns are
+//! byte offsets like in the source maps of WebAssembly. Compiled programs
+//! report errors with the program counter, so the map also has the source
+//! line of every instruction in the extension field `x_goto_lines`.
+
+use std::convert::TryFrom;
+
+use crate::json::Value;
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourceMap {
+    /// the generated file
+    pub file: String,
+    /// the program source
+    pub source: String,
+    /// the source line of every instruction, from 1
+    pub lines: Vec<usize>,
+    /// the line and column where the code of every instruction starts in the
+    /// generated file, both from 0
+    pub generated: Vec<(usize, usize)>,
+}
+
+/// The lines and columns of byte offsets in a text, the offsets in order.
+pub fn text_positions(text: &str, offsets: &[usize]) -> Vec<(usize, usize)> {
+    let mut positions = vec![];
+    let (mut line, mut line_start, mut scanned) = (0, 0, 0);
+    for &offset in offsets {
+        for (index, byte) in text.as_bytes()[scanned..offset].iter().enumerate() {
+            if *byte == b'\n' {
+                line += 1;
+                line_start = scanned + index + 1;
+            }
+        }
+        scanned = offset;
+        positions.push((line, offset - line_start));
+    }
+    positions
+}
+
+fn encode_vlq(out: &mut String, value: i64) {
+    let mut rest = if value < 0 { ((-value) << 1) | 1 } else { value << 1 } as u64;
+    loop {
+        let digit = (rest & 31) as usize;
+        rest >>= 5;
+        out.push(BASE64[digit | if rest > 0 { 32 } else { 0 }] as char);
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+fn decode_segment(segment: &str) -> Result<Vec<i64>, String> {
+    let mut values = vec![];
+    let (mut value, mut shift) = (0u64, 0);
+    for c in segment.bytes() {
+        let digit = BASE64.iter().position(|&b| b == c).ok_or_else(|| format!("invalid character {} in mappings", c as char))? as u64;
+        value |= (digit & 31) << shift;
+        shift += 5;
+        if shift > 60 {
+            return Err("a number in the mappings is too large".to_string());
+        }
+        if digit & 32 == 0 {
+            values.push(if value & 1 == 1 { -((value >> 1) as i64) } else { (value >> 1) as i64 });
+            value = 0;
+            shift = 0;
+        }
+    }
+    if shift > 0 {
+        return Err("the mappings end in a number".to_string());
+    }
+    Ok(values)
+}
+
+impl SourceMap {
+    /// The source line of the instruction at `pc`.
+    pub fn line(&self, pc: usize) -> Option<usize> {
+        self.lines.get(pc).cloned()
+    }
+
+    fn mappings(&self) -> String {
+        let mut mappings = String::new();
+        let (mut line, mut column, mut source_line) = (0, 0i64, 0i64);
+        for (index, (&(generated_line, generated_column), &source)) in self.generated.iter().zip(&self.lines).enumerate() {
+            if generated_line > line {
+                mappings.extend(std::iter::repeat_n(';', generated_line - line));
+                line = generated_line;
+                column = 0;
+            } else if index > 0 {
+                mappings.push(',');
+            }
+            encode_vlq(&mut mappings, generated_column as i64 - column);
+            column = generated_column as i64;
+            // the only source, its line and column 0
+            encode_vlq(&mut mappings, 0);
+            encode_vlq(&mut mappings, source.saturating_sub(1) as i64 - source_line);
+            source_line = source.saturating_sub(1) as i64;
+            encode_vlq(&mut mappings, 0);
+        }
+        mappings
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::object(vec![
+            ("version", 3u64.into()),
+            ("file", self.file.as_str().into()),
+            ("sources", vec![self.source.as_str()].into()),
+            ("names", Value::Array(vec![])),
+            ("mappings", self.mappings().into()),
+            ("x_goto_lines", self.lines.iter().map(|&line| Value::from(line)).collect::<Vec<_>>().into()),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Result<SourceMap, String> {
+        if value.get("version").and_then(Value::as_u64) != Some(3) {
+            return Err("only version 3 source maps are supported".to_string());
+        }
+        let text = |key: &str| value.get(key).and_then(Value::as_str).ok_or_else(|| format!("missing or invalid {}", key));
+        let source = value.get("sources").and_then(Value::as_array).and_then(|sources| sources.first()).and_then(Value::as_str)
+            .ok_or("missing or invalid sources")?;
+        let lines = value.get("x_goto_lines").and_then(Value::as_array)
+            .ok_or("missing or invalid x_goto_lines, the map was not written by goto compile")?
+            .iter()
+            .map(|line| line.as_u64().map(|line| line as usize).ok_or("missing or invalid x_goto_lines"))
+            .collect::<Result<_, _>>()?;
+        let mut generated = vec![];
+        let mut column = 0i64;
+        for (line, segments) in text("mappings")?.split(';').enumerate() {
+            column = if line == 0 { column } else { 0 };
+            for segment in segments.split(',').filter(|segment| !segment.is_empty()) {
+                column += *decode_segment(segment)?.first().ok_or("empty segment in mappings")?;
+                generated.push((line, usize::try_from(column).map_err(|_| "a negative column in mappings")?));
+            }
+        }
+        Ok(SourceMap { file: text("file")?.to_string(), source: source.to_string(), lines, generated })
+    }
+
+    pub fn parse(text: &str) -> Result<SourceMap, String> {
+        SourceMap::from_json(&Value::parse(text)?)
+    }
+
+    /// Prefixes the errors a compiled program reports, like
+    /// `error at pc 3 after 7 steps: DEC 0 on a cell that is 0`, with the
+    /// source and line of the instruction as `prog.goto:5: `.
+    pub fn symbolize(&self, text: &str) -> String {
+        let mut symbolized = String::new();
+        for line in text.split_inclusive('\n') {
+            let pc = line.find("error at pc ").and_then(|start| {
+                let digits = &line[start + "error at pc ".len()..];
+                digits[..digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len())].parse::<usize>().ok()
+            });
+            if let Some(line) = pc.and_then(|pc| self.line(pc)) {
+                symbolized += &format!("{}:{}: ", self.source, line);
+            }
+            symbolized += line;
+        }
+        symbolized
+    }
+}
+
+#[test]
+fn test_source_map() {
+    let text = "int main() {\n    /* 0: INC 0 */\n    x++;\nL1: /* DEC 0 */\n";
+    assert_eq!(vec![(1, 0), (3, 0), (3, 4)], text_positions(text, &[13, 41, 45]));
+
+    let map = SourceMap { file: "prog.c".to_string(), source: "prog.goto".to_string(), lines: vec![1, 3, 3, 2], generated: vec![(1, 0), (3, 0), (3, 4), (20, 1000)] };
+    let json = map.to_json().to_string();
+    assert_eq!(r#"{"version":3,"file":"prog.c","sources":["prog.goto"],"names":[],"mappings":";AAAA;;AAEA,IAAA;;;;;;;;;;;;;;;;;w+BADA","x_goto_lines":[1,3,3,2]}"#, json);
+    assert_eq!(Ok(map.clone()), SourceMap::parse(&json));
+    assert_eq!("prog.goto:2: error at pc 3 after 9 steps: DEC 0 on a cell that is 0\nresult: [0]\nerror at pc 7 after 1 steps\n",
+               map.symbolize("error at pc 3 after 9 steps: DEC 0 on a cell that is 0\nresult: [0]\nerror at pc 7 after 1 steps\n"));
+    assert!(SourceMap::parse(r#"{"version":3,"file":"a","sources":["b"],"mappings":"A"}"#).is_err());
+}
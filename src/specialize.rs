@@ -0,0 +1,225 @@
+//! Partial evaluation of a program for inputs where some cells are known.
+//!
+//! Known cells are tracked while specializing and only the instructions that
+//! depend on the other cells end up in the residual program, which expects
+//! the known cells to start at 0 and counts them up to their final values
+//! before it stops. Every instruction is specialized once per combination of
+//! known values it is reached with, after `MAX_VARIANTS` of them the cells
+//! that keep changing are counted up and treated as unknown from then on.
+
+use std::collections::BTreeMap;
+
+use crate::while_lang::Generator;
+use crate::{GotoProgram, Instruction, RegisterIndex};
+
+const MAX_VARIANTS: usize = 8;
+// the largest known value the residual program counts a cell up to
+const MAX_INCREMENTS: u64 = 10_000;
+
+type Known = BTreeMap<RegisterIndex, u64>;
+
+/// Parses known cells like `0=5,1=?`, where `?` marks a cell as unknown.
+pub fn parse_known(text: &str) -> Result<Known, String> {
+    let mut known = Known::new();
+    for part in text.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let invalid = || format!("invalid known cell {}, expected something like 0=5 or 1=?", part);
+        let mut split = part.splitn(2, '=');
+        let cell = split.next().unwrap().trim().parse().map_err(|_| invalid())?;
+        match split.next().map(str::trim).ok_or_else(invalid)? {
+            "?" => {}
+            value => {
+                known.insert(cell, value.parse().map_err(|_| invalid())?);
+            }
+        }
+    }
+    Ok(known)
+}
+
+struct Specializer<'a> {
+    program: &'a GotoProgram,
+    generator: Generator,
+    variants: BTreeMap<usize, Vec<(Known, String)>>,
+    pending: Vec<(String, usize, Known)>,
+    outside: String,
+}
+
+impl<'a> Specializer<'a> {
+    fn count_up(&mut self, cell: RegisterIndex, value: u64) -> Result<(), String> {
+        if value > MAX_INCREMENTS {
+            return Err(format!("cell {} would have to be counted up to {} in the residual program", cell, value));
+        }
+        self.generator.increment(cell, value);
+        Ok(())
+    }
+
+    /// Emits code from `pc` on until it stops or joins code that is already there.
+    fn block(&mut self, mut pc: usize, mut known: Known) -> Result<(), String> {
+        loop {
+            if pc >= self.program.instructions.len() {
+                self.generator.emit(format!("GOTO {}", self.outside));
+                return Ok(());
+            }
+            let variants = self.variants.entry(pc).or_default();
+            if variants.len() >= MAX_VARIANTS {
+                let first = variants[0].0.clone();
+                let changing: Vec<_> = known.iter().filter(|(cell, value)| first.get(cell) != Some(value)).map(|(c, v)| (*c, *v)).collect();
+                for (cell, value) in changing {
+                    known.remove(&cell);
+                    self.count_up(cell, value)?;
+                }
+            }
+            if let Some((_, label)) = self.variants[&pc].iter().find(|(k, _)| *k == known) {
+                self.generator.emit(format!("GOTO {}", label));
+                return Ok(());
+            }
+            let label = self.generator.label();
+            self.generator.place(&label);
+            self.variants.get_mut(&pc).unwrap().push((known.clone(), label));
+            match self.program.instructions[pc] {
+                Instruction::Inc { cell } => match known.get_mut(&cell) {
+                    Some(value) if *value < u64::MAX => *value += 1,
+                    _ => self.generator.emit(format!("INC {}", cell)),
+                },
+                Instruction::Dec { cell } => match known.get_mut(&cell) {
+                    Some(0) => {
+                        // the cell is 0 in the residual program as well, so this fails there too
+                        self.generator.emit(format!("DEC {}", cell));
+                        return Ok(());
+                    }
+                    Some(value) => *value -= 1,
+                    None => self.generator.emit(format!("DEC {}", cell)),
+                },
+                Instruction::Assert { cell, value } => match known.get(&cell) {
+                    Some(&known_value) if known_value == value => {}
+                    Some(&known_value) => {
+                        // count the cell up so that the assertion fails with the same message
+                        self.count_up(cell, known_value)?;
+                        self.generator.emit(format!("ASSERT {} {}", cell, value));
+                        return Ok(());
+                    }
+                    None => self.generator.emit(format!("ASSERT {} {}", cell, value)),
+                },
+                Instruction::Print { cell } | Instruction::OutC { cell } | Instruction::Push { cell } => {
+                    if let Some(value) = known.remove(&cell) {
+                        self.count_up(cell, value)?;
+                    }
+                    self.generator.emit(self.program.instructions[pc].to_string());
+                }
+                Instruction::Read { cell } | Instruction::Rand { cell, .. } | Instruction::Pop { cell } => {
+                    known.remove(&cell);
+                    self.generator.emit(self.program.instructions[pc].to_string());
+                }
+                Instruction::StoreI { cell, value } => match known.get_mut(&cell) {
+                    Some(known_value) => *known_value = value,
+                    None => self.generator.emit(self.program.instructions[pc].to_string()),
+                },
+                Instruction::Goto { cell } => {
+                    pc = cell;
+                    continue;
+                }
+                Instruction::Nop => {}
+                Instruction::GotoZ { condition_cell, goto_cell } => match known.get(&condition_cell) {
+                    Some(0) => {
+                        pc = goto_cell;
+                        continue;
+                    }
+                    Some(_) => {}
+                    None => {
+                        let target = self.generator.label();
+                        self.generator.emit(format!("GOTOZ {} {}", condition_cell, target));
+                        self.pending.push((target, goto_cell, known.clone()));
+                    }
+                },
+                Instruction::GotoNz { condition_cell, goto_cell } => match known.get(&condition_cell) {
+                    Some(0) => {}
+                    Some(_) => {
+                        pc = goto_cell;
+                        continue;
+                    }
+                    None => {
+                        let target = self.generator.label();
+                        self.generator.emit(format!("GOTONZ {} {}", condition_cell, target));
+                        self.pending.push((target, goto_cell, known.clone()));
+                    }
+                },
+                Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => {
+                    let is_eq = matches!(self.program.instructions[pc], Instruction::GotoEq { .. });
+                    match (known.get(&left), known.get(&right)) {
+                        (Some(l), Some(r)) => {
+                            if (is_eq && l == r) || (!is_eq && l > r) {
+                                pc = goto_cell;
+                                continue;
+                            }
+                        }
+                        _ => {
+                            // the residual program compares the actual values
+                            for cell in [left, right] {
+                                if let Some(value) = known.remove(&cell) {
+                                    self.count_up(cell, value)?;
+                                }
+                            }
+                            let target = self.generator.label();
+                            self.generator.emit(format!("{} {} {} {}", if is_eq { "GOTOEQ" } else { "GOTOGT" }, left, right, target));
+                            self.pending.push((target, goto_cell, known.clone()));
+                        }
+                    }
+                }
+                Instruction::Stop => {
+                    for (cell, value) in known {
+                        self.count_up(cell, value)?;
+                    }
+                    self.generator.emit("STOP".to_string());
+                    return Ok(());
+                }
+            }
+            pc += 1;
+        }
+    }
+}
+
+/// The source of a residual program that behaves like `program` on any
+/// memory that has the `known` values, but with the known cells at 0.
+pub fn specialize(program: &GotoProgram, known: &Known) -> Result<String, String> {
+    let mut generator = Generator::new(0);
+    let outside = generator.label();
+    let mut specializer = Specializer { program, generator, variants: BTreeMap::new(), pending: vec![], outside };
+    specializer.block(0, known.clone())?;
+    while let Some((label, pc, known)) = specializer.pending.pop() {
+        specializer.generator.place(&label);
+        specializer.block(pc, known)?;
+    }
+    let outside = specializer.outside.clone();
+    specializer.generator.place(&outside);
+    let description: Vec<_> = known.iter().map(|(cell, value)| format!("{}={}", cell, value)).collect();
+    let source = specializer.generator.finish(&format!("specialized for {}, the known cells have to start at 0", description.join(",")));
+    // most variants are only reached by falling through, leave out their labels
+    let jumped_to: Vec<_> = source.lines().filter_map(|line| line.split_whitespace().last()).collect();
+    let used = |line: &&str| !line.ends_with(':') || jumped_to.contains(&line.trim_end_matches(':'));
+    Ok(source.lines().filter(used).map(|line| format!("{}\n", line)).collect())
+}
+
+#[test]
+fn test_specialize() {
+    assert_eq!(Ok(vec![(0, 5)].into_iter().collect()), parse_known("0=5,1=?"));
+    assert!(parse_known("0").is_err());
+
+    // adds cell 0 to cell 1 and counts the additions in cell 2
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nINC 2\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let residual = specialize(&program, &parse_known("0=3").unwrap()).unwrap();
+    let residual = crate::parse_program(residual).unwrap();
+    assert_eq!([Instruction::Inc { cell: 1 }, Instruction::Inc { cell: 2 }].repeat(3), residual.instructions[..6].to_vec());
+    assert_eq!(Instruction::Stop, residual.instructions[6]);
+
+    // the same program with the counter known, it grows with the unknown loop count
+    for (known, memory) in [("2=4", vec![7, 1, 4]), ("2=4", vec![3, 0, 4]), ("1=2", vec![12, 2, 0]), ("0=0,1=1", vec![0, 1, 5])] {
+        let residual = specialize(&program, &parse_known(known).unwrap()).unwrap();
+        let residual = crate::parse_program(residual).unwrap();
+        let mut original = crate::GotoProgramState::new(&program, memory.clone());
+        let known = parse_known(known).unwrap();
+        let residual_memory = memory.iter().enumerate().map(|(cell, value)| if known.contains_key(&cell) { 0 } else { *value }).collect();
+        let mut specialized = crate::GotoProgramState::new(&residual, residual_memory);
+        assert_eq!(Ok(true), original.run_with_limit(1000));
+        assert_eq!(Ok(true), specialized.run_with_limit(100_000));
+        assert_eq!(original.memory, specialized.memory);
+    }
+}
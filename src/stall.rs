@@ -0,0 +1,98 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::{GotoProgramState, RegisterIndex};
+
+/// Reported when a whole window of steps stayed within a few instructions
+/// and ended with the memory it started with.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Stall {
+    pub pcs: Vec<RegisterIndex>,
+    pub since_step: u64,
+    pub steps: u64,
+}
+
+impl fmt::Display for Stall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "execution looks stuck: only instructions {:?} ran in the {} steps since step {} and the memory did not change",
+               self.pcs, self.steps, self.since_step)
+    }
+}
+
+pub struct StallDetector {
+    window: u64,
+    max_pcs: usize,
+    window_start: u64,
+    window_memory: Vec<u64>,
+    pcs: BTreeSet<RegisterIndex>,
+    reported: bool,
+}
+
+impl StallDetector {
+    pub fn new(window: u64, max_pcs: usize) -> StallDetector {
+        StallDetector {
+            window,
+            max_pcs,
+            window_start: 0,
+            window_memory: vec![],
+            pcs: BTreeSet::new(),
+            reported: false,
+        }
+    }
+
+    /// Call before every step. Returns a stall once when it starts, it is
+    /// reported again only after execution made progress in between.
+    pub fn observe(&mut self, state: &GotoProgramState) -> Option<Stall> {
+        if state.steps == self.window_start {
+            self.window_memory.clone_from(&state.memory);
+        }
+        if self.pcs.len() <= self.max_pcs {
+            self.pcs.insert(state.program_counter);
+        }
+        if state.steps < self.window_start + self.window {
+            return None;
+        }
+        let stalled = self.pcs.len() <= self.max_pcs && self.window_memory == state.memory;
+        let stall = Stall {
+            pcs: self.pcs.iter().cloned().collect(),
+            since_step: self.window_start,
+            steps: self.window,
+        };
+        self.window_start = state.steps;
+        self.window_memory.clone_from(&state.memory);
+        self.pcs.clear();
+        self.pcs.insert(state.program_counter);
+        let report = stalled && !self.reported;
+        self.reported = stalled;
+        if report { Some(stall) } else { None }
+    }
+}
+
+#[test]
+fn test_stall_detector() {
+    let program = crate::parse_program("loop: GOTOZ 0 end
+    DEC 0
+    INC 1
+    GOTO loop
+    end: STOP
+    stuck: GOTOZ 1 end
+    GOTO stuck".to_string()).unwrap();
+    let mut state = crate::GotoProgramState::new(&program, vec![20, 0]);
+    let mut detector = StallDetector::new(10, 2);
+    loop {
+        assert_eq!(None, detector.observe(&state));
+        if state.step().unwrap() {
+            break;
+        }
+    }
+
+    let mut state = crate::GotoProgramState::new(&program, vec![0, 1]);
+    state.program_counter = 5;
+    let mut detector = StallDetector::new(10, 2);
+    let mut stalls = vec![];
+    for _ in 0..50 {
+        stalls.extend(detector.observe(&state));
+        state.step().unwrap();
+    }
+    assert_eq!(vec![Stall { pcs: vec![5, 6], since_step: 0, steps: 10 }], stalls);
+}
@@ -0,0 +1,180 @@
+//! Static numbers about the shape of a program, for comparing solutions or
+//! the size of generated code.
+//!
+//! Loops are the natural loops of the back edges a depth first search from
+//! the first instruction finds, loops with the same head count as one. The
+//! nesting is the largest number of loops an instruction is in.
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+
+use crate::json::Value;
+use crate::lint::successors;
+use crate::{ranges, GotoProgram, RegisterIndex, MNEMONICS};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Stats {
+    pub instructions: usize,
+    /// the number of every mnemonic the program has, in the order of `MNEMONICS`
+    pub opcodes: Vec<(&'static str, usize)>,
+    pub jump_targets: usize,
+    pub basic_blocks: usize,
+    pub loops: usize,
+    pub max_nesting: usize,
+    pub cells: Vec<RegisterIndex>,
+}
+
+fn mnemonic(instruction: &crate::Instruction) -> String {
+    instruction.to_string().split_whitespace().next().unwrap_or_default().to_string()
+}
+
+/// The heads of the back edges with the instructions of their loops.
+fn natural_loops(program: &GotoProgram) -> Vec<(usize, BTreeSet<usize>)> {
+    let count = program.instructions.len();
+    if count == 0 {
+        return vec![];
+    }
+    // iterative depth first search, an edge to an instruction on the stack is a back edge
+    let mut on_stack = vec![false; count];
+    let mut visited = vec![false; count];
+    let mut back_edges = vec![];
+    let mut stack = vec![(0, successors(program, 0), 0)];
+    visited[0] = true;
+    on_stack[0] = true;
+    while let Some((pc, next, index)) = stack.last_mut() {
+        let pc = *pc;
+        match next.get(*index).cloned() {
+            Some(target) => {
+                *index += 1;
+                if on_stack[target] {
+                    back_edges.push((pc, target));
+                } else if !visited[target] {
+                    visited[target] = true;
+                    on_stack[target] = true;
+                    stack.push((target, successors(program, target), 0));
+                }
+            }
+            None => {
+                on_stack[pc] = false;
+                stack.pop();
+            }
+        }
+    }
+    let mut predecessors = vec![vec![]; count];
+    for pc in (0..count).filter(|&pc| visited[pc]) {
+        for next in successors(program, pc) {
+            predecessors[next].push(pc);
+        }
+    }
+    let mut loops: Vec<(usize, BTreeSet<usize>)> = vec![];
+    for (tail, head) in back_edges {
+        // the instructions that reach the tail without going through the head
+        let mut body: BTreeSet<usize> = [head].iter().cloned().collect();
+        let mut todo = vec![tail];
+        while let Some(pc) = todo.pop() {
+            if body.insert(pc) {
+                todo.extend(predecessors[pc].iter().cloned());
+            }
+        }
+        match loops.iter_mut().find(|(other, _)| *other == head) {
+            Some((_, other)) => other.extend(body),
+            None => loops.push((head, body)),
+        }
+    }
+    loops
+}
+
+impl Stats {
+    pub fn of(program: &GotoProgram) -> Stats {
+        let count = program.instructions.len();
+        let mnemonics: Vec<String> = program.instructions.iter().map(mnemonic).collect();
+        let opcodes = MNEMONICS.iter()
+            .map(|&(name, _)| (name, mnemonics.iter().filter(|m| *m == name).count()))
+            .filter(|&(_, n)| n > 0)
+            .collect();
+        let targets: BTreeSet<usize> = program.instructions.iter().filter_map(|i| i.jump_target()).filter(|&t| t < count).collect();
+        // a block starts at the first instruction, a jump target and after a jump or STOP
+        let mut leaders = targets.clone();
+        if count > 0 {
+            leaders.insert(0);
+        }
+        for pc in 0..count {
+            if successors(program, pc) != [pc + 1] && pc + 1 < count {
+                leaders.insert(pc + 1);
+            }
+        }
+        let loops = natural_loops(program);
+        let max_nesting = (0..count).map(|pc| loops.iter().filter(|(_, body)| body.contains(&pc)).count()).max().unwrap_or(0);
+        Stats {
+            instructions: count,
+            opcodes,
+            jump_targets: targets.len(),
+            basic_blocks: leaders.len(),
+            loops: loops.len(),
+            max_nesting,
+            cells: ranges::used_cells(program),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::object(vec![
+            ("instructions", self.instructions.into()),
+            ("opcodes", Value::object(self.opcodes.iter().map(|&(name, n)| (name, n.into())).collect())),
+            ("jump_targets", self.jump_targets.into()),
+            ("basic_blocks", self.basic_blocks.into()),
+            ("loops", self.loops.into()),
+            ("max_nesting", self.max_nesting.into()),
+            ("cells", Value::Array(self.cells.iter().map(|&cell| cell.into()).collect())),
+        ])
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut opcodes = String::new();
+        for (name, n) in &self.opcodes {
+            write!(opcodes, "{}{} {}", if opcodes.is_empty() { "" } else { ", " }, name, n)?;
+        }
+        writeln!(f, "instructions: {} ({})", self.instructions, opcodes)?;
+        writeln!(f, "jump targets: {}", self.jump_targets)?;
+        writeln!(f, "basic blocks: {}", self.basic_blocks)?;
+        writeln!(f, "loops:        {}, nested at most {} deep", self.loops, self.max_nesting)?;
+        let cells: Vec<String> = self.cells.iter().map(ToString::to_string).collect();
+        writeln!(f, "cells:        {} ({})", self.cells.len(), cells.join(", "))
+    }
+}
+
+#[test]
+fn test_stats() {
+    // multiplies cell 0 and 1 into cell 2 with cell 3 to restore cell 1
+    let program = crate::parse_program("outer: GOTOZ 0 end
+        DEC 0
+        inner: GOTOZ 1 restore
+        DEC 1
+        INC 2
+        INC 3
+        GOTO inner
+        restore: GOTOZ 3 outer
+        DEC 3
+        INC 1
+        GOTO restore
+        end: STOP".to_string()).unwrap();
+    let stats = Stats::of(&program);
+    assert_eq!(Stats {
+        instructions: 12,
+        opcodes: vec![("STOP", 1), ("INC", 3), ("DEC", 3), ("GOTO", 2), ("GOTOZ", 3)],
+        jump_targets: 4,
+        basic_blocks: 7,
+        loops: 3,
+        max_nesting: 2,
+        cells: vec![0, 1, 2, 3],
+    }, stats);
+    assert_eq!("instructions: 12 (STOP 1, INC 3, DEC 3, GOTO 2, GOTOZ 3)
+jump targets: 4
+basic blocks: 7
+loops:        3, nested at most 2 deep
+cells:        4 (0, 1, 2, 3)
+", stats.to_string());
+    assert_eq!(r#"{"instructions":0,"opcodes":{},"jump_targets":0,"basic_blocks":0,"loops":0,"max_nesting":0,"cells":[]}"#,
+               Stats::of(&crate::parse_program(String::new()).unwrap()).to_json().to_string());
+}
@@ -0,0 +1,106 @@
+//! Regression tests for program suites: every `name.goto` with a
+//! `name.test` next to it is run against the cases of that test
+//! specification, see [`crate::grading`] for the format.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::grading::{Outcome, TestCase, TestSpec};
+use crate::{parse_program, GotoProgram, GotoProgramState};
+
+/// The programs with a test file under the paths, which are files or
+/// directories searched recursively except for hidden ones like `.git`, in
+/// order of their path.
+pub fn discover(paths: &[PathBuf]) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut programs = vec![];
+    let mut todo = paths.to_vec();
+    while let Some(path) = todo.pop() {
+        if path.is_dir() {
+            let entries = fs::read_dir(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            for entry in entries {
+                let entry = entry.map_err(|e| format!("{}: {}", path.display(), e))?;
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    todo.push(entry.path());
+                }
+            }
+        } else if path.extension().is_some_and(|e| e == "goto") {
+            let spec = path.with_extension("test");
+            if spec.is_file() {
+                programs.push((path, spec));
+            }
+        }
+    }
+    programs.sort();
+    programs.dedup();
+    Ok(programs)
+}
+
+/// The result of a case with every expected cell that differs, as
+/// (cell, expected, actual) where a missing cell has no actual value.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CaseReport {
+    pub name: String,
+    pub outcome: Outcome,
+    pub differences: Vec<(usize, u64, Option<u64>)>,
+}
+
+fn run_case(program: &GotoProgram, case: &TestCase, spec: &TestSpec) -> CaseReport {
+    let mut state = GotoProgramState::new(program, case.input.clone());
+    let (outcome, differences) = match state.run_with_limit(spec.limits.max_steps) {
+        Err(e) => (Outcome::RuntimeError(e.to_string()), vec![]),
+        Ok(false) => (Outcome::StepLimitExceeded, vec![]),
+        Ok(true) => {
+            let differences: Vec<_> = case.expected.iter()
+                .map(|&(cell, expected)| (cell, expected, state.memory.get(cell).cloned()))
+                .filter(|&(_, expected, actual)| actual != Some(expected))
+                .collect();
+            let outcome = match differences.first() {
+                Some(&(cell, expected, actual)) => Outcome::WrongOutput { cell, expected, actual },
+                None => Outcome::Passed,
+            };
+            (outcome, differences)
+        }
+    };
+    CaseReport { name: case.name.clone(), outcome, differences }
+}
+
+/// Runs the cases of the test file against the program.
+pub fn run_file(program: &Path, spec: &Path) -> Result<Vec<CaseReport>, String> {
+    let read = |path: &Path| fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e));
+    let program = parse_program(read(program)?)?;
+    let spec = TestSpec::parse(&read(spec)?)?;
+    Ok(spec.cases.iter().map(|case| run_case(&program, case, &spec)).collect())
+}
+
+/// The lines printed for a failed case.
+pub fn explain(report: &CaseReport) -> String {
+    let mut text = String::new();
+    if report.differences.is_empty() {
+        writeln!(text, "    {}", report.outcome).unwrap();
+    }
+    for &(cell, expected, actual) in &report.differences {
+        match actual {
+            Some(actual) => writeln!(text, "    cell {}: expected {}, found {}", cell, expected, actual).unwrap(),
+            None => writeln!(text, "    cell {}: expected {}, but the memory ends before it", cell, expected).unwrap(),
+        }
+    }
+    text
+}
+
+#[test]
+fn test_suite() {
+    let dir = std::env::temp_dir().join(format!("goto-suite-{}", std::process::id()));
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    fs::write(dir.join("nested/add.goto"), "loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP").unwrap();
+    fs::write(dir.join("nested/add.test"), "case small\ninput 2 3\nexpect 0=0 1=5\ncase wrong\ninput 1 1\nexpect 0=1 1=3 4=0").unwrap();
+    fs::write(dir.join("untested.goto"), "STOP").unwrap();
+    let found = discover(std::slice::from_ref(&dir)).unwrap();
+    assert_eq!(vec![(dir.join("nested/add.goto"), dir.join("nested/add.test"))], found);
+
+    let reports = run_file(&found[0].0, &found[0].1).unwrap();
+    assert_eq!(Outcome::Passed, reports[0].outcome);
+    assert_eq!("    cell 0: expected 1, found 0\n    cell 1: expected 3, found 2\n    cell 4: expected 0, but the memory ends before it\n",
+               explain(&reports[1]));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,133 @@
+//! Searches for the shortest program that passes every case of a test
+//! specification, either by trying all programs of each length in order or
+//! by trying random ones. Candidates run on the bytecode VM.
+//!
+//! The instructions of a candidate of length `n` work on the cells
+//! `0..cells` and jump to `0..n`. Numbering all of them gives every candidate
+//! an index, the exhaustive search splits the indices between threads and
+//! returns the candidate with the lowest index that passes, so the result
+//! does not depend on the number of threads.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+use crate::bytecode::Bytecode;
+use crate::grading::TestSpec;
+use crate::random::Random;
+use crate::{GotoProgram, GotoProgramState, Instruction};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Search {
+    pub cells: usize,
+    pub max_length: usize,
+    /// the step limit of every candidate run
+    pub max_steps: u64,
+}
+
+/// The cells a specification mentions, which the candidates need.
+pub fn spec_cells(spec: &TestSpec) -> usize {
+    spec.cases.iter()
+        .flat_map(|case| case.expected.iter().map(|(cell, _)| cell + 1).chain(Some(case.input.len())))
+        .max().unwrap_or(0)
+}
+
+fn alphabet(cells: usize, length: usize) -> Vec<Instruction> {
+    let mut instructions = vec![Instruction::Stop];
+    for cell in 0..cells {
+        instructions.push(Instruction::Inc { cell });
+        instructions.push(Instruction::Dec { cell });
+    }
+    for target in 0..length {
+        instructions.push(Instruction::Goto { cell: target });
+        for condition_cell in 0..cells {
+            instructions.push(Instruction::GotoZ { condition_cell, goto_cell: target });
+        }
+    }
+    instructions
+}
+
+fn candidate(alphabet: &[Instruction], length: usize, mut index: u64) -> GotoProgram {
+    let base = alphabet.len() as u64;
+    let mut instructions = Vec::with_capacity(length);
+    for _ in 0..length {
+        instructions.push(alphabet[(index % base) as usize]);
+        index /= base;
+    }
+    GotoProgram { source_lines: (1..=length).collect(), instructions, banks: 1, aliases: vec![], data: vec![], header: Default::default() }
+}
+
+fn passes(program: &GotoProgram, spec: &TestSpec, search: &Search) -> bool {
+    if !program.instructions.contains(&Instruction::Stop) {
+        return false;
+    }
+    let bytecode = match Bytecode::compile(program) {
+        Ok(bytecode) => bytecode,
+        Err(_) => return false,
+    };
+    spec.cases.iter().all(|case| {
+        let mut memory = case.input.clone();
+        memory.resize(memory.len().max(search.cells), 0);
+        let mut state = GotoProgramState::new(program, memory);
+        bytecode.run(&mut state, search.max_steps) == Ok(true)
+            && case.expected.iter().all(|&(cell, value)| state.memory.get(cell) == Some(&value))
+    })
+}
+
+/// The first passing program of the shortest length, using `jobs` threads.
+pub fn exhaustive(spec: &TestSpec, search: &Search, jobs: usize) -> Result<Option<GotoProgram>, String> {
+    let jobs = jobs.max(1) as u64;
+    for length in 1..=search.max_length {
+        let alphabet = alphabet(search.cells, length);
+        let count = (alphabet.len() as u64).checked_pow(length as u32)
+            .ok_or_else(|| format!("there are too many programs of length {}", length))?;
+        let best = AtomicU64::new(u64::MAX);
+        thread::scope(|scope| {
+            for job in 0..jobs {
+                let (alphabet, best) = (&alphabet, &best);
+                scope.spawn(move || {
+                    let mut index = job;
+                    while index < count && index < best.load(Ordering::Relaxed) {
+                        if passes(&candidate(alphabet, length, index), spec, search) {
+                            best.fetch_min(index, Ordering::Relaxed);
+                            return;
+                        }
+                        index += jobs;
+                    }
+                });
+            }
+        });
+        let best = best.into_inner();
+        if best != u64::MAX {
+            return Ok(Some(candidate(&alphabet, length, best)));
+        }
+    }
+    Ok(None)
+}
+
+/// Tries `tries` random programs of every length up to the bound, shortest first.
+pub fn stochastic(spec: &TestSpec, search: &Search, tries: u64, seed: u64) -> Option<GotoProgram> {
+    let mut random = Random::new(seed);
+    (1..=search.max_length).find_map(|length| {
+        let alphabet = alphabet(search.cells, length);
+        (0..tries).map(|_| {
+            let instructions = (0..length).map(|_| alphabet[random.below(alphabet.len() as u64) as usize]).collect();
+            GotoProgram { source_lines: (1..=length).collect(), instructions, banks: 1, aliases: vec![], data: vec![], header: Default::default() }
+        }).find(|program| passes(program, spec, search))
+    })
+}
+
+#[test]
+fn test_superopt() {
+    // clears cell 0
+    let spec = TestSpec::parse("case\ninput 0\nexpect 0=0\ncase\ninput 3\nexpect 0=0\ncase\ninput 5 0\nexpect 0=0").unwrap();
+    assert_eq!(2, spec_cells(&spec));
+    let search = Search { cells: 1, max_length: 4, max_steps: 100 };
+    let found = exhaustive(&spec, &search, 1).unwrap().unwrap();
+    assert_eq!(crate::parse_program("GOTOZ 0 3\nDEC 0\nGOTO 0\nSTOP".to_string()).unwrap().instructions, found.instructions);
+    assert_eq!(Some(found), exhaustive(&spec, &search, 3).unwrap());
+    assert_eq!(None, exhaustive(&spec, &Search { max_length: 3, ..search }, 2).unwrap());
+
+    let spec = TestSpec::parse("case\ninput 0\nexpect 0=2").unwrap();
+    let found = stochastic(&spec, &Search { cells: 1, max_length: 3, max_steps: 100 }, 10000, 1).unwrap();
+    assert_eq!(vec![Instruction::Inc { cell: 0 }, Instruction::Inc { cell: 0 }, Instruction::Stop], found.instructions);
+}
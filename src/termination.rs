@@ -0,0 +1,163 @@
+//! Proves that simple programs stop on every input.
+//!
+//! Every loop is a strongly connected part of the control flow. A loop stops
+//! if some cell is decremented in it but never otherwise written and the
+//! loops left after removing those decrements stop as well: the decrements
+//! can only run as often as the cell's value allows, and between two of them
+//! execution is stuck in the smaller loops. A decrement of 0 is an error, which also stops.
+
+use std::fmt::Write;
+
+use crate::lint::{reachable, successors};
+use crate::{GotoProgram, Instruction, RegisterIndex};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Loop {
+    pub instructions: Vec<usize>,
+    /// a cell that bounds how often the loop runs, `None` if none was found
+    pub decreasing_cell: Option<RegisterIndex>,
+}
+
+/// The strongly connected parts of the control flow between `members` that
+/// contain a cycle.
+fn cycles(program: &GotoProgram, members: &[usize]) -> Vec<Vec<usize>> {
+    let count = program.instructions.len();
+    let mut inside = vec![false; count];
+    for &pc in members {
+        inside[pc] = true;
+    }
+    let edges = |pc: usize| successors(program, pc).into_iter().filter(|&n| inside[n]).collect::<Vec<_>>();
+    let mut reverse = vec![vec![]; count];
+    for &pc in members {
+        for next in edges(pc) {
+            reverse[next].push(pc);
+        }
+    }
+    // Kosaraju: order the members by when depth first search finishes them,
+    // then collect what reaches each of them backwards, last finished first
+    let mut visited = vec![false; count];
+    let mut finished = vec![];
+    for &root in members {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![(root, edges(root), 0)];
+        while let Some((pc, next, index)) = stack.last_mut() {
+            if let Some(&n) = next.get(*index) {
+                *index += 1;
+                if !visited[n] {
+                    visited[n] = true;
+                    stack.push((n, edges(n), 0));
+                }
+            } else {
+                finished.push(*pc);
+                stack.pop();
+            }
+        }
+    }
+    let mut assigned = vec![false; count];
+    let mut components = vec![];
+    for &root in finished.iter().rev() {
+        if assigned[root] {
+            continue;
+        }
+        assigned[root] = true;
+        let mut component = vec![];
+        let mut todo = vec![root];
+        while let Some(pc) = todo.pop() {
+            component.push(pc);
+            for &previous in &reverse[pc] {
+                if !assigned[previous] {
+                    assigned[previous] = true;
+                    todo.push(previous);
+                }
+            }
+        }
+        component.sort_unstable();
+        if component.len() > 1 || edges(root).contains(&root) {
+            components.push(component);
+        }
+    }
+    components.sort();
+    components
+}
+
+fn decreasing_cell(program: &GotoProgram, component: &[usize]) -> Option<RegisterIndex> {
+    let cells_of = |wanted: fn(&Instruction) -> Option<RegisterIndex>| -> Vec<RegisterIndex> {
+        component.iter().filter_map(|&pc| wanted(&program.instructions[pc])).collect()
+    };
+    // every write but a decrement may raise the cell again
+    let incremented = cells_of(|i| match *i {
+        Instruction::Inc { cell } | Instruction::Read { cell } | Instruction::Rand { cell, .. } | Instruction::Pop { cell }
+        | Instruction::StoreI { cell, .. } => Some(cell),
+        _ => None,
+    });
+    let mut decremented = cells_of(|i| match *i { Instruction::Dec { cell } => Some(cell), _ => None });
+    decremented.sort_unstable();
+    decremented.dedup();
+    decremented.into_iter().filter(|cell| !incremented.contains(cell)).find(|&cell| {
+        let rest: Vec<_> = component.iter().cloned()
+            .filter(|&pc| program.instructions[pc] != Instruction::Dec { cell })
+            .collect();
+        cycles(program, &rest).iter().all(|inner| decreasing_cell(program, inner).is_some())
+    })
+}
+
+/// The loops among the instructions that can run, with a cell that shows
+/// that each of them stops if there is one.
+pub fn loops(program: &GotoProgram) -> Vec<Loop> {
+    let members: Vec<_> = reachable(program).into_iter().enumerate().filter(|(_, r)| *r).map(|(pc, _)| pc).collect();
+    cycles(program, &members).into_iter()
+        .map(|instructions| Loop { decreasing_cell: decreasing_cell(program, &instructions), instructions })
+        .collect()
+}
+
+pub fn report(program: &GotoProgram, loops: &[Loop]) -> String {
+    if loops.is_empty() {
+        return "termination: proven, the program has no loops\n".to_string();
+    }
+    let proven = loops.iter().all(|l| l.decreasing_cell.is_some());
+    let mut report = format!("termination: {}\n", if proven { "proven" } else { "unknown" });
+    for l in loops {
+        let lines: Vec<_> = l.instructions.iter().map(|&pc| program.source_lines[pc]).collect();
+        let span = format!("lines {}-{}", lines.iter().min().unwrap(), lines.iter().max().unwrap());
+        match l.decreasing_cell {
+            Some(cell) => writeln!(report, "  {}: stops, cell {} decreases and is never increased", span, cell).unwrap(),
+            None => writeln!(report, "  {}: unknown, no cell decreases on every round", span).unwrap(),
+        }
+    }
+    report
+}
+
+#[test]
+fn test_termination() {
+    let no_loops = crate::parse_program("GOTOZ 0 end\nINC 1\nend: STOP".to_string()).unwrap();
+    assert!(loops(&no_loops).is_empty());
+
+    // multiplies cell 0 and 1 into cell 2, restoring cell 1 through cell 3 in every round
+    let multiply = crate::parse_program("outer: GOTOZ 0 end
+        DEC 0
+        inner: GOTOZ 1 restore
+        DEC 1
+        INC 2
+        INC 3
+        GOTO inner
+        restore: GOTOZ 3 outer
+        DEC 3
+        INC 1
+        GOTO restore
+        end: STOP".to_string()).unwrap();
+    assert_eq!(vec![Loop { instructions: (0..11).collect(), decreasing_cell: Some(0) }], loops(&multiply));
+
+    let forever = crate::parse_program("DEC 0\nloop: GOTOZ 0 end\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let found = loops(&forever);
+    assert_eq!(vec![Loop { instructions: vec![1, 2, 3], decreasing_cell: None }], found);
+    assert_eq!("termination: unknown\n  lines 2-4: unknown, no cell decreases on every round\n", report(&forever, &found));
+
+    // the other writes may raise the counter again
+    for write in ["STOREI 0 5", "POP 0", "READ 0", "RAND 0 9"] {
+        let reset = crate::parse_program(format!(".dialect extended\nloop: DEC 0\n{}\nGOTO loop", write)).unwrap();
+        assert_eq!(vec![Loop { instructions: vec![0, 1, 2], decreasing_cell: None }], loops(&reset), "{}", write);
+    }
+}
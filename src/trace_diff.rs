@@ -0,0 +1,135 @@
+//! The first place where two structured traces from `--record-trace` part
+//! ways, for comparing a program with its optimized version or two versions
+//! of a program on the same input.
+//!
+//! Traces are aligned step by step, or by the memory writes alone when the
+//! programs take different jumps to the same writes.
+
+use std::fmt::Write;
+
+use crate::golden::TraceEvent;
+use crate::MemoryWrite;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Align {
+    /// the nth step of one trace with the nth step of the other
+    Steps,
+    /// the nth write of one trace with the nth write of the other, steps
+    /// without a write are skipped
+    Writes,
+}
+
+impl Align {
+    pub const NAMES: [&'static str; 2] = ["steps", "writes"];
+
+    pub fn from_name(name: &str) -> Option<Align> {
+        match name {
+            "steps" => Some(Align::Steps),
+            "writes" => Some(Align::Writes),
+            _ => None,
+        }
+    }
+
+    fn events<'a>(&self, trace: &'a [TraceEvent]) -> Vec<&'a TraceEvent> {
+        match self {
+            Align::Steps => trace.iter().collect(),
+            Align::Writes => trace.iter().filter(|event| event.write.is_some()).collect(),
+        }
+    }
+}
+
+/// The first aligned events that differ and why, `None` in place of the
+/// event of a trace that ended.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Divergence<'a> {
+    pub index: usize,
+    pub first: Option<&'a TraceEvent>,
+    pub second: Option<&'a TraceEvent>,
+    pub reason: String,
+}
+
+fn show_write(write: Option<MemoryWrite>) -> String {
+    write.map_or("no write".to_string(), |write| write.to_string())
+}
+
+/// Why two aligned events differ, pcs only count when aligning steps.
+fn difference(align: Align, first: &TraceEvent, second: &TraceEvent) -> Option<String> {
+    if align == Align::Steps && first.program_counter != second.program_counter {
+        return Some(format!("pc {} vs {}", first.program_counter, second.program_counter));
+    }
+    if first.write != second.write {
+        return Some(format!("{} vs {}", show_write(first.write), show_write(second.write)));
+    }
+    None
+}
+
+pub fn diverge<'a>(first: &'a [TraceEvent], second: &'a [TraceEvent], align: Align) -> Option<Divergence<'a>> {
+    let (first, second) = (align.events(first), align.events(second));
+    for index in 0..first.len().max(second.len()) {
+        let (a, b) = (first.get(index).cloned(), second.get(index).cloned());
+        let reason = match (a, b) {
+            (Some(a), Some(b)) => difference(align, a, b),
+            (None, _) => Some(format!("the first trace ends after {} events", first.len())),
+            (_, None) => Some(format!("the second trace ends after {} events", second.len())),
+        };
+        if let Some(reason) = reason {
+            return Some(Divergence { index, first: a, second: b, reason });
+        }
+    }
+    None
+}
+
+fn show_event(event: Option<&&TraceEvent>) -> String {
+    match event {
+        Some(event) => match event.write {
+            Some(write) => format!("step {} pc {}: {}, {}", event.step, event.program_counter, event.instruction, write),
+            None => format!("step {} pc {}: {}", event.step, event.program_counter, event.instruction),
+        },
+        None => "-".to_string(),
+    }
+}
+
+/// The first divergence with `context` aligned events around it side by side.
+pub fn report(names: (&str, &str), first: &[TraceEvent], second: &[TraceEvent], align: Align, context: usize) -> String {
+    let divergence = match diverge(first, second, align) {
+        Some(divergence) => divergence,
+        None => return format!("the traces agree on all {} events\n", align.events(first).len()),
+    };
+    let (first, second) = (align.events(first), align.events(second));
+    let index = divergence.index;
+    let mut report = format!("traces diverge at event {}: {}\n", index + 1, divergence.reason);
+    let rows: Vec<(usize, String, String)> = (index.saturating_sub(context)..=index + context)
+        .filter(|&i| i < first.len().max(second.len()))
+        .map(|i| (i, show_event(first.get(i)), show_event(second.get(i))))
+        .collect();
+    let width = rows.iter().map(|(_, a, _)| a.chars().count()).chain(Some(names.0.chars().count())).max().unwrap();
+    let _ = writeln!(report, "  {:width$}  {}", names.0, names.1, width = width);
+    for (i, a, b) in rows {
+        let _ = writeln!(report, "{} {:width$}  {}", if i == index { ">" } else { " " }, a, b, width = width);
+    }
+    report
+}
+
+#[test]
+fn test_trace_diff() {
+    let event = |step, pc, cell: Option<(crate::RegisterIndex, u64)>| TraceEvent {
+        step,
+        program_counter: pc,
+        instruction: if cell.is_some() { "INC 0".to_string() } else { "GOTO 0".to_string() },
+        write: cell.map(|(cell, new)| MemoryWrite { cell, old: new - 1, new }),
+        next_program_counter: pc + 1,
+    };
+    let first = vec![event(1, 0, Some((0, 1))), event(2, 1, None), event(3, 2, Some((1, 1))), event(4, 3, Some((0, 2)))];
+    let second = vec![event(1, 0, Some((0, 1))), event(2, 2, Some((1, 1))), event(3, 3, Some((0, 3)))];
+    let divergence = diverge(&first, &second, Align::Steps).unwrap();
+    assert_eq!((1, "pc 1 vs 2".to_string()), (divergence.index, divergence.reason));
+    assert_eq!("traces diverge at event 3: mem[0]: 1 \u{2192} 2 vs mem[0]: 2 \u{2192} 3
+  a.jsonl                            b.jsonl
+  step 3 pc 2: INC 0, mem[1]: 0 \u{2192} 1  step 2 pc 2: INC 0, mem[1]: 0 \u{2192} 1
+> step 4 pc 3: INC 0, mem[0]: 1 \u{2192} 2  step 3 pc 3: INC 0, mem[0]: 2 \u{2192} 3
+", report(("a.jsonl", "b.jsonl"), &first, &second, Align::Writes, 1));
+    assert_eq!("the second trace ends after 1 events", diverge(&first, &second[..1], Align::Writes).unwrap().reason);
+    assert_eq!("the traces agree on all 3 events\n", report(("a", "b"), &first, &first, Align::Writes, 3));
+    assert_eq!("traces diverge at event 2: pc 2 vs 1\n  a                                  b\n> step 2 pc 2: INC 0, mem[1]: 0 \u{2192} 1  step 2 pc 1: GOTO 0\n",
+               report(("a", "b"), &second, &first, Align::Steps, 0));
+}
@@ -0,0 +1,1060 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::{ranges, GotoProgram, Instruction};
+
+const PRELUDE: &str = r#"#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static uint64_t *mem;
+static size_t size;
+static uint64_t steps;
+
+static void fail(size_t pc, const char *message, size_t operand) {
+    fprintf(stderr, "error at pc %zu after %" PRIu64 " steps: ", pc, steps);
+    fprintf(stderr, message, operand, size);
+    fputc('\n', stderr);
+    exit(1);
+}
+
+#define CHECK(pc, cell) if ((cell) >= size) fail(pc, "cell %zu is outside of the memory of size %zu", cell)
+"#;
+
+/// What generated code does when INC overflows or DEC hits 0.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Arithmetic {
+    /// stop with an error like the interpreter does
+    Checked,
+    Wrapping,
+    Saturating,
+}
+
+impl Arithmetic {
+    pub const NAMES: [&'static str; 3] = ["checked", "wrapping", "saturating"];
+
+    pub fn from_name(name: &str) -> Option<Arithmetic> {
+        match name {
+            "checked" => Some(Arithmetic::Checked),
+            "wrapping" => Some(Arithmetic::Wrapping),
+            "saturating" => Some(Arithmetic::Saturating),
+            _ => None,
+        }
+    }
+}
+
+/// Translates a program into a standalone C file that prints the memory when the
+/// program stops. Without `memory` the initial memory is read from the command line.
+/// Here and in the other translations `OUTC` writes UTF-8 and `RAND` uses the
+/// generator of `random` with seed 0.
+pub fn to_c(program: &GotoProgram, memory: Option<&[u64]>, arithmetic: Arithmetic) -> String {
+    to_c_mapped(program, memory, arithmetic).0
+}
+
+/// `to_c` with the offset in the C file where the code of every instruction
+/// starts, for a `sourcemap::SourceMap`. The other `_mapped` translations
+/// are alike.
+pub fn to_c_mapped(program: &GotoProgram, memory: Option<&[u64]>, arithmetic: Arithmetic) -> (String, Vec<usize>) {
+    let mut c = PRELUDE.to_string();
+    let mut offsets = vec![];
+    if program.instructions.iter().any(|i| matches!(i, Instruction::Rand { max, .. } if *max > 0)) {
+        c += r#"
+static uint64_t random_state;
+
+static uint64_t random_below(uint64_t bound) {
+    uint64_t limit = UINT64_MAX - UINT64_MAX % bound;
+    for (;;) {
+        uint64_t z = random_state += UINT64_C(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)) * UINT64_C(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)) * UINT64_C(0x94D049BB133111EB);
+        z ^= z >> 31;
+        if (z < limit) return z % bound;
+    }
+}
+"#;
+    }
+    if program.instructions.iter().any(|i| matches!(i, Instruction::Push { .. } | Instruction::Pop { .. })) {
+        c += "\nstatic uint64_t *stack;\nstatic size_t stack_size;\n";
+    }
+    if program.instructions.iter().any(|i| matches!(i, Instruction::Push { .. })) {
+        c += r#"static size_t stack_capacity;
+
+static void push(uint64_t value) {
+    if (stack_size == stack_capacity) {
+        stack_capacity = 2 * stack_capacity + 16;
+        stack = realloc(stack, stack_capacity * sizeof(uint64_t));
+        if (!stack) {
+            fprintf(stderr, "out of memory for the stack\n");
+            exit(1);
+        }
+    }
+    stack[stack_size++] = value;
+}
+"#;
+    }
+    if program.instructions.iter().any(|i| matches!(i, Instruction::OutC { .. })) {
+        c += r#"
+static void outc(size_t pc, size_t cell, uint64_t c) {
+    if (c > 0x10FFFF || (c >= 0xD800 && c < 0xE000)) fail(pc, "OUTC %zu: the value is not a character in utf-8", cell);
+    if (c < 0x80) {
+        putchar((int) c);
+    } else if (c < 0x800) {
+        putchar((int) (0xC0 | c >> 6));
+    } else if (c < 0x10000) {
+        putchar((int) (0xE0 | c >> 12));
+        putchar((int) (0x80 | (c >> 6 & 0x3F)));
+    } else {
+        putchar((int) (0xF0 | c >> 18));
+        putchar((int) (0x80 | (c >> 12 & 0x3F)));
+        putchar((int) (0x80 | (c >> 6 & 0x3F)));
+    }
+    if (c >= 0x80) putchar((int) (0x80 | (c & 0x3F)));
+}
+"#;
+    }
+    c += "\nint main(int argc, char **argv) {\n";
+    match memory {
+        Some(memory) => {
+            let values: Vec<_> = memory.iter().map(|v| format!("UINT64_C({})", v)).collect();
+            writeln!(c, "    static uint64_t initial[] = {{{}}};", if values.is_empty() { "0".to_string() } else { values.join(", ") }).unwrap();
+            writeln!(c, "    (void) argc;\n    (void) argv;\n    mem = initial;\n    size = {};", memory.len()).unwrap();
+        }
+        None => {
+            c += "    size = (size_t) argc - 1;\n";
+            c += "    mem = calloc(size ? size : 1, sizeof(uint64_t));\n";
+            c += "    for (size_t i = 0; i < size; i++) mem[i] = strtoull(argv[i + 1], NULL, 10);\n";
+        }
+    }
+    let count = program.instructions.len();
+    let jump = |target: usize| if target < count {
+        format!("goto L{};", target)
+    } else {
+        format!("fail({}, \"program counter %zu is outside of the program\", {});", target, target)
+    };
+    // only jump targets get labels to keep compilers from warning about unused ones
+    let targets: BTreeSet<usize> = program.instructions.iter()
+        .filter_map(Instruction::jump_target)
+        .collect();
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        offsets.push(c.len());
+        if targets.contains(&pc) {
+            writeln!(c, "L{}: /* {} */", pc, instruction).unwrap();
+        } else {
+            writeln!(c, "    /* {}: {} */", pc, instruction).unwrap();
+        }
+        let code = match *instruction {
+            Instruction::Stop => "steps++; goto done;".to_string(),
+            Instruction::Inc { cell } => {
+                let update = match arithmetic {
+                    Arithmetic::Checked => format!("if (mem[{cell}] == UINT64_MAX) fail({pc}, \"INC %zu overflows\", {cell}); mem[{cell}]++;", pc = pc, cell = cell),
+                    Arithmetic::Wrapping => format!("mem[{}]++;", cell),
+                    Arithmetic::Saturating => format!("if (mem[{cell}] != UINT64_MAX) mem[{cell}]++;", cell = cell),
+                };
+                format!("CHECK({}, {}); {} steps++;", pc, cell, update)
+            }
+            Instruction::Dec { cell } => {
+                let update = match arithmetic {
+                    Arithmetic::Checked => format!("if (mem[{cell}] == 0) fail({pc}, \"DEC %zu on a cell that is 0\", {cell}); mem[{cell}]--;", pc = pc, cell = cell),
+                    Arithmetic::Wrapping => format!("mem[{}]--;", cell),
+                    Arithmetic::Saturating => format!("if (mem[{cell}] != 0) mem[{cell}]--;", cell = cell),
+                };
+                format!("CHECK({}, {}); {} steps++;", pc, cell, update)
+            }
+            Instruction::Goto { cell } => format!("steps++; {}", jump(cell)),
+            Instruction::Nop => "steps++;".to_string(),
+            Instruction::GotoZ { condition_cell, goto_cell } => format!(
+                "CHECK({pc}, {cell}); steps++; if (mem[{cell}] == 0) {jump}",
+                pc = pc, cell = condition_cell, jump = jump(goto_cell)),
+            Instruction::GotoNz { condition_cell, goto_cell } => format!(
+                "CHECK({pc}, {cell}); steps++; if (mem[{cell}] != 0) {jump}",
+                pc = pc, cell = condition_cell, jump = jump(goto_cell)),
+            Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => format!(
+                "CHECK({pc}, {left}); CHECK({pc}, {right}); steps++; if (mem[{left}] {op} mem[{right}]) {jump}",
+                pc = pc, left = left, right = right, jump = jump(goto_cell),
+                op = if let Instruction::GotoEq { .. } = instruction { "==" } else { ">" }),
+            Instruction::Assert { cell, value } => format!(
+                "CHECK({pc}, {cell}); if (mem[{cell}] != UINT64_C({value})) fail({pc}, \"{instruction} failed, cell {cell} is %zu\", (size_t) mem[{cell}]); steps++;",
+                pc = pc, cell = cell, value = value, instruction = instruction),
+            Instruction::Print { cell } => format!(
+                "CHECK({pc}, {cell}); printf(\"%\" PRIu64 \"\\n\", mem[{cell}]); steps++;", pc = pc, cell = cell),
+            Instruction::Read { cell } => format!(
+                "CHECK({pc}, {cell}); if (scanf(\"%\" SCNu64, &mem[{cell}]) != 1) fail({pc}, \"READ %zu: there is no more input\", {cell}); steps++;",
+                pc = pc, cell = cell),
+            Instruction::OutC { cell } => format!("CHECK({pc}, {cell}); outc({pc}, {cell}, mem[{cell}]); steps++;", pc = pc, cell = cell),
+            Instruction::Push { cell } => format!("CHECK({pc}, {cell}); push(mem[{cell}]); steps++;", pc = pc, cell = cell),
+            Instruction::StoreI { cell, value } => format!("CHECK({pc}, {cell}); mem[{cell}] = UINT64_C({value}); steps++;", pc = pc, cell = cell, value = value),
+            Instruction::Pop { cell } => format!(
+                "CHECK({pc}, {cell}); if (!stack_size) fail({pc}, \"POP %zu on an empty stack\", {cell}); mem[{cell}] = stack[--stack_size]; steps++;",
+                pc = pc, cell = cell),
+            Instruction::Rand { cell, max: 0 } => format!("CHECK({pc}, {cell}); fail({pc}, \"RAND %zu 0 has no number below 0\", {cell});", pc = pc, cell = cell),
+            Instruction::Rand { cell, max } => format!(
+                "CHECK({pc}, {cell}); mem[{cell}] = random_below(UINT64_C({max})); steps++;", pc = pc, cell = cell, max = max),
+        };
+        writeln!(c, "    {}", code).unwrap();
+    }
+    writeln!(c, "    {}", jump(count)).unwrap();
+    if program.instructions.contains(&Instruction::Stop) {
+        c += "done:\n";
+    }
+    c += "    printf(\"result: [\");\n";
+    c += "    for (size_t i = 0; i < size; i++) printf(i ? \", %\" PRIu64 : \"%\" PRIu64, mem[i]);\n";
+    c += "    printf(\"]\\n\");\n";
+    c += "    return 0;\n}\n";
+    (c, offsets)
+}
+
+/// Translates a program into a self-contained Rust `main.rs` that prints the memory
+/// when the program stops. Without `memory` the initial memory is read from the
+/// command line.
+pub fn to_rust(program: &GotoProgram, memory: Option<&[u64]>, arithmetic: Arithmetic) -> String {
+    to_rust_mapped(program, memory, arithmetic).0
+}
+
+pub fn to_rust_mapped(program: &GotoProgram, memory: Option<&[u64]>, arithmetic: Arithmetic) -> (String, Vec<usize>) {
+    let mut rust = String::new();
+    let mut offsets = vec![];
+    writeln!(rust, "// generated by goto compile --target rust").unwrap();
+    rust += "#![allow(unused_assignments, unreachable_code)]\n\n";
+    rust += "use std::process::exit;\n\n";
+    rust += "fn fail(pc: usize, steps: u64, message: String) -> ! {\n";
+    rust += "    eprintln!(\"error at pc {} after {} steps: {}\", pc, steps, message);\n";
+    rust += "    exit(1)\n}\n\n";
+    rust += "fn cell(mem: &mut [u64], cell: usize, pc: usize, steps: u64) -> &mut u64 {\n";
+    rust += "    let size = mem.len();\n";
+    rust += "    mem.get_mut(cell).unwrap_or_else(|| fail(pc, steps, format!(\"cell {} is outside of the memory of size {}\", cell, size)))\n";
+    rust += "}\n\n";
+    if program.instructions.iter().any(|i| matches!(i, Instruction::Read { .. })) {
+        rust += "fn read(pc: usize, steps: u64, cell: usize) -> u64 {\n";
+        rust += "    use std::io::Read;\n";
+        rust += "    let token: String = std::io::stdin().lock().bytes().map_while(Result::ok).map(char::from)\n";
+        rust += "        .skip_while(|c| c.is_whitespace()).take_while(|c| !c.is_whitespace()).collect();\n";
+        rust += "    if token.is_empty() {\n";
+        rust += "        fail(pc, steps, format!(\"READ {}: there is no more input\", cell));\n";
+        rust += "    }\n";
+        rust += "    token.parse().unwrap_or_else(|_| fail(pc, steps, format!(\"READ {}: {} is not a number\", cell, token)))\n";
+        rust += "}\n\n";
+    }
+    let random = program.instructions.iter().any(|i| matches!(i, Instruction::Rand { max, .. } if *max > 0));
+    if random {
+        rust += "fn random_below(state: &mut u64, bound: u64) -> u64 {\n";
+        rust += "    let limit = u64::MAX - u64::MAX % bound;\n";
+        rust += "    loop {\n";
+        rust += "        *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);\n";
+        rust += "        let mut z = *state;\n";
+        rust += "        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);\n";
+        rust += "        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);\n";
+        rust += "        z ^= z >> 31;\n";
+        rust += "        if z < limit {\n            return z % bound;\n        }\n";
+        rust += "    }\n}\n\n";
+    }
+    rust += "fn main() {\n";
+    if random {
+        rust += "    let mut random: u64 = 0;\n";
+    }
+    if program.instructions.iter().any(|i| matches!(i, Instruction::Push { .. } | Instruction::Pop { .. })) {
+        rust += "    let mut stack: Vec<u64> = Vec::new();\n";
+    }
+    match memory {
+        Some(memory) => writeln!(rust, "    let mut mem: Vec<u64> = vec!{:?};", memory).unwrap(),
+        None => {
+            rust += "    let mut mem: Vec<u64> = std::env::args().skip(1)\n";
+            rust += "        .map(|arg| arg.parse().unwrap_or_else(|_| fail(0, 0, format!(\"{} is not a cell value\", arg))))\n";
+            rust += "        .collect();\n";
+        }
+    }
+    rust += "    let mut pc: usize = 0;\n    let mut steps: u64 = 0;\n    loop {\n        match pc {\n";
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        offsets.push(rust.len());
+        let code = match *instruction {
+            Instruction::Stop => "steps += 1;\n                break;".to_string(),
+            Instruction::Inc { cell } | Instruction::Dec { cell } => {
+                let (method, message) = match instruction {
+                    Instruction::Inc { .. } => ("add", format!("INC {} overflows", cell)),
+                    _ => ("sub", format!("DEC {} on a cell that is 0", cell)),
+                };
+                let update = match arithmetic {
+                    Arithmetic::Checked => format!(
+                        "*value = value.checked_{}(1).unwrap_or_else(|| fail({}, steps, \"{}\".to_string()));", method, pc, message),
+                    Arithmetic::Wrapping => format!("*value = value.wrapping_{}(1);", method),
+                    Arithmetic::Saturating => format!("*value = value.saturating_{}(1);", method),
+                };
+                format!("let value = cell(&mut mem, {}, {}, steps);\n                {}\n                steps += 1;\n                pc = {};",
+                        cell, pc, update, pc + 1)
+            }
+            Instruction::Goto { cell } => format!("steps += 1;\n                pc = {};", cell),
+            Instruction::Nop => format!("steps += 1;\n                pc = {};", pc + 1),
+            Instruction::GotoZ { condition_cell, goto_cell } => format!(
+                "let zero = *cell(&mut mem, {}, {}, steps) == 0;\n                steps += 1;\n                pc = if zero {{ {} }} else {{ {} }};",
+                condition_cell, pc, goto_cell, pc + 1),
+            Instruction::GotoNz { condition_cell, goto_cell } => format!(
+                "let zero = *cell(&mut mem, {}, {}, steps) == 0;\n                steps += 1;\n                pc = if zero {{ {} }} else {{ {} }};",
+                condition_cell, pc, pc + 1, goto_cell),
+            Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => format!(
+                "let left = *cell(&mut mem, {left}, {pc}, steps);\n                let right = *cell(&mut mem, {right}, {pc}, steps);\n                steps += 1;\n                pc = if left {op} right {{ {target} }} else {{ {next} }};",
+                left = left, right = right, pc = pc, target = goto_cell, next = pc + 1,
+                op = if let Instruction::GotoEq { .. } = instruction { "==" } else { ">" }),
+            Instruction::Assert { cell: asserted, value } => format!(
+                "let value = *cell(&mut mem, {cell}, {pc}, steps);\n                if value != {value} {{\n                    fail({pc}, steps, format!(\"{instruction} failed, cell {cell} is {{}}\", value));\n                }}\n                steps += 1;\n                pc = {next};",
+                cell = asserted, pc = pc, value = value, instruction = instruction, next = pc + 1),
+            Instruction::Print { cell } => format!(
+                "println!(\"{{}}\", *cell(&mut mem, {}, {}, steps));\n                steps += 1;\n                pc = {};", cell, pc, pc + 1),
+            Instruction::Read { cell: read } => format!(
+                "let value = read({pc}, steps, {cell});\n                *cell(&mut mem, {cell}, {pc}, steps) = value;\n                steps += 1;\n                pc = {next};",
+                cell = read, pc = pc, next = pc + 1),
+            Instruction::OutC { cell: written } => format!(
+                "let value = *cell(&mut mem, {cell}, {pc}, steps);\n                let c = if value <= 0x10FFFF {{ std::char::from_u32(value as u32) }} else {{ None }};\n                print!(\"{{}}\", c.unwrap_or_else(|| fail({pc}, steps, format!(\"OUTC {cell}: {{}} is not a character in utf-8\", value))));\n                steps += 1;\n                pc = {next};",
+                cell = written, pc = pc, next = pc + 1),
+            Instruction::StoreI { cell, value } => format!(
+                "*cell(&mut mem, {}, {}, steps) = {};\n                steps += 1;\n                pc = {};",
+                cell, pc, value, pc + 1),
+            Instruction::Push { cell } => format!(
+                "let value = *cell(&mut mem, {}, {}, steps);\n                stack.push(value);\n                steps += 1;\n                pc = {};",
+                cell, pc, pc + 1),
+            Instruction::Pop { cell: popped } => format!(
+                "let value = cell(&mut mem, {cell}, {pc}, steps);\n                *value = stack.pop().unwrap_or_else(|| fail({pc}, steps, \"POP {cell} on an empty stack\".to_string()));\n                steps += 1;\n                pc = {next};",
+                cell = popped, pc = pc, next = pc + 1),
+            Instruction::Rand { cell, max: 0 } => format!(
+                "cell(&mut mem, {cell}, {pc}, steps);\n                fail({pc}, steps, \"RAND {cell} 0 has no number below 0\".to_string());",
+                cell = cell, pc = pc),
+            Instruction::Rand { cell, max } => format!(
+                "let value = random_below(&mut random, {max});\n                *cell(&mut mem, {cell}, {pc}, steps) = value;\n                steps += 1;\n                pc = {next};",
+                cell = cell, pc = pc, max = max, next = pc + 1),
+        };
+        writeln!(rust, "            // {}\n            {} => {{\n                {}\n            }}", instruction, pc, code).unwrap();
+    }
+    rust += "            _ => fail(pc, steps, format!(\"program counter {} is outside of the program\", pc)),\n";
+    rust += "        }\n    }\n    println!(\"result: {:?}\", mem);\n}\n";
+    (rust, offsets)
+}
+
+fn llvm_string(name: &str, text: &str) -> String {
+    let mut escaped = String::new();
+    for byte in text.bytes().chain(Some(0)) {
+        match byte {
+            b' '..=b'~' if byte != b'"' && byte != b'\\' => escaped.push(byte as char),
+            _ => escaped += &format!("\\{:02X}", byte),
+        }
+    }
+    format!("@{} = private unnamed_addr constant [{} x i8] c\"{}\"\n", name, text.len() + 1, escaped)
+}
+
+/// Translates a program into textual LLVM IR with a `main` that prints the memory
+/// when the program stops. Jump targets start basic blocks and the memory is a
+/// global array, so the IR needs no phi nodes. Without `memory` the cells are read
+/// from the command line into an array large enough for every cell the program uses.
+pub fn to_llvm_ir(program: &GotoProgram, memory: Option<&[u64]>) -> String {
+    to_llvm_ir_mapped(program, memory).0
+}
+
+pub fn to_llvm_ir_mapped(program: &GotoProgram, memory: Option<&[u64]>) -> (String, Vec<usize>) {
+    let mut offsets = vec![];
+    let count = program.instructions.len();
+    let used_cells = ranges::used_cells(program).last().map(|cell| cell + 1).unwrap_or(0);
+    let cells = memory.map(|m| m.len()).unwrap_or(used_cells).max(1);
+    let mut ir = String::from("; generated by goto compile --target llvm-ir\n\n");
+    match memory {
+        Some(memory) if !memory.is_empty() => {
+            let values: Vec<_> = memory.iter().map(|v| format!("i64 {}", v)).collect();
+            writeln!(ir, "@mem = internal global [{} x i64] [{}]", cells, values.join(", ")).unwrap();
+        }
+        _ => writeln!(ir, "@mem = internal global [{} x i64] zeroinitializer", cells).unwrap(),
+    }
+    writeln!(ir, "@size = internal global i64 {}", memory.map(|m| m.len()).unwrap_or(0)).unwrap();
+    ir += "@steps = internal global i64 0\n";
+    ir += "@random = internal global i64 0\n";
+    ir += "@stack = internal global ptr null\n@stack.size = internal global i64 0\n@stack.capacity = internal global i64 0\n";
+    let prefix = "error at pc %zu after %llu steps: ";
+    ir += &llvm_string("fmt.cell", &format!("{}cell %zu is outside of the memory of size %zu\n", prefix));
+    ir += &llvm_string("fmt.inc", &format!("{}INC %zu overflows\n", prefix));
+    ir += &llvm_string("fmt.dec", &format!("{}DEC %zu on a cell that is 0\n", prefix));
+    ir += &llvm_string("fmt.pc", &format!("{}program counter %zu is outside of the program\n", prefix));
+    ir += &llvm_string("fmt.read", &format!("{}READ %zu: there is no more input\n", prefix));
+    ir += &llvm_string("fmt.outc", &format!("{}OUTC %zu: the value is not a character in utf-8\n", prefix));
+    ir += &llvm_string("fmt.rand", &format!("{}RAND %zu 0 has no number below 0\n", prefix));
+    ir += &llvm_string("fmt.pop", &format!("{}POP %zu on an empty stack\n", prefix));
+    ir += &llvm_string("fmt.args", "the program uses at most %zu cells\n");
+    ir += &llvm_string("fmt.open", "result: [");
+    ir += &llvm_string("fmt.value", "%llu");
+    ir += &llvm_string("fmt.print", "%llu\n");
+    ir += &llvm_string("fmt.separator", ", ");
+    ir += &llvm_string("fmt.close", "]\n");
+    ir += r#"
+declare i32 @printf(ptr, ...)
+declare i32 @scanf(ptr, ...)
+declare i32 @putchar(i32)
+declare ptr @realloc(ptr, i64)
+declare i32 @dprintf(i32, ptr, ...)
+declare i64 @strtoull(ptr, ptr, i32)
+declare void @exit(i32) noreturn
+
+define internal void @fail(ptr %format, i64 %pc, i64 %operand) noreturn {
+  %steps = load i64, ptr @steps
+  %size = load i64, ptr @size
+  call i32 (i32, ptr, ...) @dprintf(i32 2, ptr %format, i64 %pc, i64 %steps, i64 %operand, i64 %size)
+  call void @exit(i32 1)
+  unreachable
+}
+
+; the lead byte of a UTF-8 sequence and its continuation bytes
+define internal void @put_bits(i64 %c, i64 %shift, i64 %mask, i64 %marker) {
+  %shifted = lshr i64 %c, %shift
+  %bits = and i64 %shifted, %mask
+  %byte = or i64 %bits, %marker
+  %int = trunc i64 %byte to i32
+  call i32 @putchar(i32 %int)
+  ret void
+}
+
+define internal void @outc(i64 %pc, i64 %cell, i64 %c) {
+  %large = icmp ugt i64 %c, 1114111
+  %above_low = icmp uge i64 %c, 55296
+  %below_high = icmp ult i64 %c, 57344
+  %surrogate = and i1 %above_low, %below_high
+  %invalid = or i1 %large, %surrogate
+  br i1 %invalid, label %fail, label %one
+fail:
+  call void @fail(ptr @fmt.outc, i64 %pc, i64 %cell)
+  unreachable
+one:
+  %is_one = icmp ult i64 %c, 128
+  br i1 %is_one, label %put_one, label %two
+put_one:
+  call void @put_bits(i64 %c, i64 0, i64 127, i64 0)
+  ret void
+two:
+  %is_two = icmp ult i64 %c, 2048
+  br i1 %is_two, label %put_two, label %three
+put_two:
+  call void @put_bits(i64 %c, i64 6, i64 31, i64 192)
+  br label %last
+three:
+  %is_three = icmp ult i64 %c, 65536
+  br i1 %is_three, label %put_three, label %put_four
+put_three:
+  call void @put_bits(i64 %c, i64 12, i64 15, i64 224)
+  br label %second_to_last
+put_four:
+  call void @put_bits(i64 %c, i64 18, i64 7, i64 240)
+  call void @put_bits(i64 %c, i64 12, i64 63, i64 128)
+  br label %second_to_last
+second_to_last:
+  call void @put_bits(i64 %c, i64 6, i64 63, i64 128)
+  br label %last
+last:
+  call void @put_bits(i64 %c, i64 0, i64 63, i64 128)
+  ret void
+}
+
+; SplitMix64 like `random::Random::below`
+define internal i64 @random_below(i64 %bound) {
+entry:
+  %rest = urem i64 -1, %bound
+  %limit = sub i64 -1, %rest
+  br label %next
+next:
+  %state = load i64, ptr @random
+  %z0 = add i64 %state, -7046029254386353131
+  store i64 %z0, ptr @random
+  %s1 = lshr i64 %z0, 30
+  %x1 = xor i64 %z0, %s1
+  %z1 = mul i64 %x1, -4658895280553007687
+  %s2 = lshr i64 %z1, 27
+  %x2 = xor i64 %z1, %s2
+  %z2 = mul i64 %x2, -7723592293110705685
+  %s3 = lshr i64 %z2, 31
+  %z3 = xor i64 %z2, %s3
+  %accepted = icmp ult i64 %z3, %limit
+  br i1 %accepted, label %done, label %next
+done:
+  %result = urem i64 %z3, %bound
+  ret i64 %result
+}
+
+define internal void @push(i64 %value) {
+entry:
+  %size = load i64, ptr @stack.size
+  %capacity = load i64, ptr @stack.capacity
+  %full = icmp eq i64 %size, %capacity
+  br i1 %full, label %grow, label %store
+grow:
+  %doubled = mul i64 %capacity, 2
+  %grown = add i64 %doubled, 16
+  %bytes = mul i64 %grown, 8
+  %old = load ptr, ptr @stack
+  %new = call ptr @realloc(ptr %old, i64 %bytes)
+  store ptr %new, ptr @stack
+  store i64 %grown, ptr @stack.capacity
+  br label %store
+store:
+  %base = load ptr, ptr @stack
+  %slot = getelementptr i64, ptr %base, i64 %size
+  store i64 %value, ptr %slot
+  %next = add i64 %size, 1
+  store i64 %next, ptr @stack.size
+  ret void
+}
+
+define internal i64 @pop(i64 %pc, i64 %cell) {
+  %size = load i64, ptr @stack.size
+  %empty = icmp eq i64 %size, 0
+  br i1 %empty, label %fail, label %ok
+fail:
+  call void @fail(ptr @fmt.pop, i64 %pc, i64 %cell)
+  unreachable
+ok:
+  %top = sub i64 %size, 1
+  store i64 %top, ptr @stack.size
+  %base = load ptr, ptr @stack
+  %slot = getelementptr i64, ptr %base, i64 %top
+  %value = load i64, ptr %slot
+  ret i64 %value
+}
+
+define internal void @count_step() {
+  %steps = load i64, ptr @steps
+  %next = add i64 %steps, 1
+  store i64 %next, ptr @steps
+  ret void
+}
+
+"#;
+    writeln!(ir, "define internal ptr @cell(i64 %pc, i64 %cell) {{
+  %size = load i64, ptr @size
+  %inside = icmp ult i64 %cell, %size
+  br i1 %inside, label %ok, label %outside
+outside:
+  call void @fail(ptr @fmt.cell, i64 %pc, i64 %cell)
+  unreachable
+ok:
+  %pointer = getelementptr [{} x i64], ptr @mem, i64 0, i64 %cell
+  ret ptr %pointer
+}}
+", cells).unwrap();
+
+    ir += "define i32 @main(i32 %argc, ptr %argv) {\nentry:\n  %index = alloca i64\n";
+    if memory.is_none() {
+        writeln!(ir, "  %argc64 = sext i32 %argc to i64
+  %count = sub i64 %argc64, 1
+  %too_many = icmp ugt i64 %count, {cells}
+  br i1 %too_many, label %args.fail, label %args.start
+args.fail:
+  call i32 (i32, ptr, ...) @dprintf(i32 2, ptr @fmt.args, i64 {cells})
+  call void @exit(i32 1)
+  unreachable
+args.start:
+  store i64 %count, ptr @size
+  store i64 0, ptr %index
+  br label %args.loop
+args.loop:
+  %i = load i64, ptr %index
+  %more = icmp ult i64 %i, %count
+  br i1 %more, label %args.body, label %args.done
+args.body:
+  %arg.index = add i64 %i, 1
+  %arg.pointer = getelementptr ptr, ptr %argv, i64 %arg.index
+  %arg = load ptr, ptr %arg.pointer
+  %value = call i64 @strtoull(ptr %arg, ptr null, i32 10)
+  %cell.pointer = getelementptr [{cells} x i64], ptr @mem, i64 0, i64 %i
+  store i64 %value, ptr %cell.pointer
+  %i.next = add i64 %i, 1
+  store i64 %i.next, ptr %index
+  br label %args.loop
+args.done:", cells = cells).unwrap();
+    }
+
+    let targets: BTreeSet<usize> = program.instructions.iter()
+        .filter_map(Instruction::jump_target)
+        .filter(|target| *target < count)
+        .collect();
+    let block = |target: usize, pc: usize| if target < count { format!("L{}", target) } else { format!("i{}.outside", pc) };
+    let outside = |ir: &mut String, target: usize, pc: usize| if target >= count {
+        writeln!(ir, "i{}.outside:\n  call void @fail(ptr @fmt.pc, i64 {}, i64 {})\n  unreachable", pc, target, target).unwrap();
+    };
+    let mut open = true;
+    let mut assert_messages = String::new();
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        offsets.push(ir.len());
+        if targets.contains(&pc) || !open {
+            if open {
+                writeln!(ir, "  br label %L{}", pc).unwrap();
+            }
+            writeln!(ir, "L{}:", pc).unwrap();
+        }
+        open = true;
+        writeln!(ir, "  ; {}", instruction).unwrap();
+        match *instruction {
+            Instruction::Stop => {
+                ir += "  call void @count_step()\n  br label %done\n";
+                open = false;
+            }
+            Instruction::Nop => ir += "  call void @count_step()\n",
+            Instruction::Inc { cell } | Instruction::Dec { cell } => {
+                let (limit, name, operation) = match instruction {
+                    Instruction::Inc { .. } => (-1, "inc", "add"),
+                    _ => (0, "dec", "sub"),
+                };
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %v{pc} = load i64, ptr %p{pc}
+  %limit{pc} = icmp eq i64 %v{pc}, {limit}
+  br i1 %limit{pc}, label %i{pc}.fail, label %i{pc}.ok
+i{pc}.fail:
+  call void @fail(ptr @fmt.{name}, i64 {pc}, i64 {cell})
+  unreachable
+i{pc}.ok:
+  %n{pc} = {operation} i64 %v{pc}, 1
+  store i64 %n{pc}, ptr %p{pc}
+  call void @count_step()", pc = pc, cell = cell, limit = limit, name = name, operation = operation).unwrap();
+            }
+            Instruction::Goto { cell } => {
+                writeln!(ir, "  call void @count_step()\n  br label %{}", block(cell, pc)).unwrap();
+                outside(&mut ir, cell, pc);
+                open = false;
+            }
+            Instruction::GotoZ { condition_cell, goto_cell } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %v{pc} = load i64, ptr %p{pc}
+  call void @count_step()
+  %zero{pc} = icmp eq i64 %v{pc}, 0
+  br i1 %zero{pc}, label %{target}, label %i{pc}.next", pc = pc, cell = condition_cell, target = block(goto_cell, pc)).unwrap();
+                outside(&mut ir, goto_cell, pc);
+                writeln!(ir, "i{}.next:", pc).unwrap();
+            }
+            Instruction::GotoNz { condition_cell, goto_cell } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %v{pc} = load i64, ptr %p{pc}
+  call void @count_step()
+  %zero{pc} = icmp eq i64 %v{pc}, 0
+  br i1 %zero{pc}, label %i{pc}.next, label %{target}", pc = pc, cell = condition_cell, target = block(goto_cell, pc)).unwrap();
+                outside(&mut ir, goto_cell, pc);
+                writeln!(ir, "i{}.next:", pc).unwrap();
+            }
+            Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => {
+                writeln!(ir, "  %pl{pc} = call ptr @cell(i64 {pc}, i64 {left})
+  %vl{pc} = load i64, ptr %pl{pc}
+  %pr{pc} = call ptr @cell(i64 {pc}, i64 {right})
+  %vr{pc} = load i64, ptr %pr{pc}
+  call void @count_step()
+  %jumps{pc} = icmp {op} i64 %vl{pc}, %vr{pc}
+  br i1 %jumps{pc}, label %{target}, label %i{pc}.next", pc = pc, left = left, right = right, target = block(goto_cell, pc),
+                         op = if let Instruction::GotoEq { .. } = instruction { "eq" } else { "ugt" }).unwrap();
+                outside(&mut ir, goto_cell, pc);
+                writeln!(ir, "i{}.next:", pc).unwrap();
+            }
+            Instruction::Assert { cell, value } => {
+                assert_messages += &llvm_string(&format!("fmt.assert{}", pc),
+                                                &format!("{}{} failed, cell {} is %llu\n", prefix, instruction, cell));
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %v{pc} = load i64, ptr %p{pc}
+  %holds{pc} = icmp eq i64 %v{pc}, {value}
+  br i1 %holds{pc}, label %i{pc}.ok, label %i{pc}.fail
+i{pc}.fail:
+  call void @fail(ptr @fmt.assert{pc}, i64 {pc}, i64 %v{pc})
+  unreachable
+i{pc}.ok:
+  call void @count_step()", pc = pc, cell = cell, value = value as i64).unwrap();
+            }
+            Instruction::Print { cell } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %v{pc} = load i64, ptr %p{pc}
+  call i32 (ptr, ...) @printf(ptr @fmt.print, i64 %v{pc})
+  call void @count_step()", pc = pc, cell = cell).unwrap();
+            }
+            Instruction::OutC { cell } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %v{pc} = load i64, ptr %p{pc}
+  call void @outc(i64 {pc}, i64 {cell}, i64 %v{pc})
+  call void @count_step()", pc = pc, cell = cell).unwrap();
+            }
+            Instruction::StoreI { cell, value } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  store i64 {value}, ptr %p{pc}
+  call void @count_step()", pc = pc, cell = cell, value = value as i64).unwrap();
+            }
+            Instruction::Push { cell } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %v{pc} = load i64, ptr %p{pc}
+  call void @push(i64 %v{pc})
+  call void @count_step()", pc = pc, cell = cell).unwrap();
+            }
+            Instruction::Pop { cell } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %v{pc} = call i64 @pop(i64 {pc}, i64 {cell})
+  store i64 %v{pc}, ptr %p{pc}
+  call void @count_step()", pc = pc, cell = cell).unwrap();
+            }
+            Instruction::Rand { cell, max: 0 } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  call void @fail(ptr @fmt.rand, i64 {pc}, i64 {cell})
+  unreachable", pc = pc, cell = cell).unwrap();
+                open = false;
+            }
+            Instruction::Rand { cell, max } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %v{pc} = call i64 @random_below(i64 {max})
+  store i64 %v{pc}, ptr %p{pc}
+  call void @count_step()", pc = pc, cell = cell, max = max as i64).unwrap();
+            }
+            Instruction::Read { cell } => {
+                writeln!(ir, "  %p{pc} = call ptr @cell(i64 {pc}, i64 {cell})
+  %r{pc} = call i32 (ptr, ...) @scanf(ptr @fmt.value, ptr %p{pc})
+  %read{pc} = icmp eq i32 %r{pc}, 1
+  br i1 %read{pc}, label %i{pc}.ok, label %i{pc}.fail
+i{pc}.fail:
+  call void @fail(ptr @fmt.read, i64 {pc}, i64 {cell})
+  unreachable
+i{pc}.ok:
+  call void @count_step()", pc = pc, cell = cell).unwrap();
+            }
+        }
+    }
+    if open {
+        writeln!(ir, "  call void @fail(ptr @fmt.pc, i64 {}, i64 {})\n  unreachable", count, count).unwrap();
+    }
+    writeln!(ir, "done:
+  call i32 (ptr, ...) @printf(ptr @fmt.open)
+  store i64 0, ptr %index
+  br label %print.loop
+print.loop:
+  %j = load i64, ptr %index
+  %size = load i64, ptr @size
+  %print.more = icmp ult i64 %j, %size
+  br i1 %print.more, label %print.body, label %print.done
+print.body:
+  %first = icmp eq i64 %j, 0
+  br i1 %first, label %print.value, label %print.separator
+print.separator:
+  call i32 (ptr, ...) @printf(ptr @fmt.separator)
+  br label %print.value
+print.value:
+  %value.pointer = getelementptr [{} x i64], ptr @mem, i64 0, i64 %j
+  %printed = load i64, ptr %value.pointer
+  call i32 (ptr, ...) @printf(ptr @fmt.value, i64 %printed)
+  %j.next = add i64 %j, 1
+  store i64 %j.next, ptr %index
+  br label %print.loop
+print.done:
+  call i32 (ptr, ...) @printf(ptr @fmt.close)
+  ret i32 0
+}}", cells).unwrap();
+    if !assert_messages.is_empty() {
+        ir += "\n";
+        ir += &assert_messages;
+    }
+    (ir, offsets)
+}
+
+#[test]
+fn test_to_llvm_ir() {
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let ir = to_llvm_ir(&program, Some(&[3, 4]));
+    assert!(ir.contains("@mem = internal global [2 x i64] [i64 3, i64 4]\n"));
+    assert!(ir.contains("L0:\n  ; GOTOZ 0 4\n"));
+    assert!(ir.contains("  br i1 %zero0, label %L4, label %i0.next\n"));
+    assert!(ir.contains("  ; GOTO 0\n  call void @count_step()\n  br label %L0\nL4:\n"));
+    assert!(ir.contains(r#"@fmt.close = private unnamed_addr constant [3 x i8] c"]\0A\00""#));
+    assert!(to_llvm_ir(&program, None).contains("@mem = internal global [2 x i64] zeroinitializer\n"));
+}
+
+fn unsigned_leb128(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn signed_leb128(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn wasm_name(bytes: &mut Vec<u8>, name: &str) {
+    unsigned_leb128(bytes, name.len() as u64);
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+fn wasm_section(module: &mut Vec<u8>, id: u8, content: &[u8]) {
+    module.push(id);
+    unsigned_leb128(module, content.len() as u64);
+    module.extend_from_slice(content);
+}
+
+/// The exit statuses of the `run` function exported by `to_wasm`.
+pub const WASM_STOPPED: i32 = 0;
+pub const WASM_DEC_ZERO: i32 = 1;
+pub const WASM_OVERFLOW: i32 = 2;
+pub const WASM_CELL_OUTSIDE: i32 = 3;
+pub const WASM_PC_OUTSIDE: i32 = 4;
+pub const WASM_ASSERT_FAILED: i32 = 5;
+
+// locals of the run function
+const PTR: u64 = 0;
+const LEN: u64 = 1;
+const PC: u64 = 2;
+const STEPS: u64 = 3;
+
+/// Emits the code of the `run` function, see `to_wasm`.
+struct WasmCode {
+    bytes: Vec<u8>,
+}
+
+impl WasmCode {
+    fn op(&mut self, opcode: u8, immediate: u64) -> &mut WasmCode {
+        self.bytes.push(opcode);
+        unsigned_leb128(&mut self.bytes, immediate);
+        self
+    }
+
+    fn i32_const(&mut self, value: usize) -> &mut WasmCode {
+        self.bytes.push(0x41);
+        signed_leb128(&mut self.bytes, value as u32 as i32 as i64);
+        self
+    }
+
+    fn i64_const(&mut self, value: i64) -> &mut WasmCode {
+        self.bytes.push(0x42);
+        signed_leb128(&mut self.bytes, value);
+        self
+    }
+
+    fn raw(&mut self, bytes: &[u8]) -> &mut WasmCode {
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+
+    fn load_cell(&mut self, cell: usize) -> &mut WasmCode {
+        // local.get ptr; i64.load align=8 offset=8*cell
+        self.op(0x20, PTR).op(0x29, 3);
+        unsigned_leb128(&mut self.bytes, 8 * cell as u64);
+        self
+    }
+
+    fn store_cell(&mut self, cell: usize) -> &mut WasmCode {
+        self.op(0x37, 3);
+        unsigned_leb128(&mut self.bytes, 8 * cell as u64);
+        self
+    }
+
+    fn count_step(&mut self) -> &mut WasmCode {
+        self.op(0x20, STEPS).i64_const(1).raw(&[0x7C]).op(0x21, STEPS)
+    }
+
+    fn set_pc(&mut self, pc: usize) -> &mut WasmCode {
+        self.i32_const(pc).op(0x21, PC)
+    }
+
+    /// Publishes pc and steps in the exported globals and returns `status`.
+    fn exit(&mut self, status: i32) -> &mut WasmCode {
+        self.op(0x20, PC).op(0x24, 0).op(0x20, STEPS).op(0x24, 1).i32_const(status as usize).raw(&[0x0F])
+    }
+
+    /// `if` the value on the stack is true, stop at `pc` with `status`.
+    fn fail_if(&mut self, pc: usize, status: i32) -> &mut WasmCode {
+        self.raw(&[0x04, 0x40]).set_pc(pc).exit(status).raw(&[0x0B])
+    }
+
+    fn check_cell(&mut self, pc: usize, cell: usize) -> &mut WasmCode {
+        // len <= cell
+        self.op(0x20, LEN).i32_const(cell).raw(&[0x4D]).fail_if(pc, WASM_CELL_OUTSIDE)
+    }
+}
+
+/// Translates a program into a WebAssembly module. It exports its `memory`,
+/// `run(ptr, len)` that runs the program on the `len` 64 bit cells at `ptr`
+/// and returns one of the `WASM_*` statuses, and the globals `pc` and `steps`
+/// describing where the run ended.
+pub fn to_wasm(program: &GotoProgram) -> Result<Vec<u8>, String> {
+    to_wasm_mapped(program).map(|(module, _)| module)
+}
+
+pub fn to_wasm_mapped(program: &GotoProgram) -> Result<(Vec<u8>, Vec<usize>), String> {
+    let count = program.instructions.len();
+    let too_large = |value: usize| value > (u32::MAX / 8) as usize;
+    for instruction in &program.instructions {
+        let operands = match *instruction {
+            Instruction::Stop | Instruction::Nop => [0, 0, 0],
+            Instruction::Inc { cell } | Instruction::Dec { cell } | Instruction::Goto { cell } | Instruction::Assert { cell, .. }
+            | Instruction::StoreI { cell, .. } => [cell, 0, 0],
+            Instruction::GotoZ { condition_cell, goto_cell } | Instruction::GotoNz { condition_cell, goto_cell } => [condition_cell, goto_cell, 0],
+            Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => [left, right, goto_cell],
+            Instruction::Print { .. } | Instruction::Read { .. } | Instruction::OutC { .. } => {
+                return Err(format!("{} has no translation to WebAssembly, the module has no console", instruction));
+            }
+            Instruction::Rand { .. } | Instruction::Push { .. } | Instruction::Pop { .. } => {
+                return Err(format!("{} has no translation to WebAssembly", instruction));
+            }
+        };
+        if operands.iter().any(|&operand| too_large(operand)) {
+            return Err(format!("{} has operands too large for WebAssembly", instruction));
+        }
+    }
+
+    // a loop around blocks B_n .. B_0, br_table jumps to the end of B_pc where
+    // the code of instruction pc starts, the end of B_n handles pc outside
+    let mut code = WasmCode { bytes: vec![] };
+    code.raw(&[0x03, 0x40]);
+    for _ in 0..=count {
+        code.raw(&[0x02, 0x40]);
+    }
+    code.op(0x20, PC).op(0x0E, count as u64);
+    for target in 0..=count {
+        unsigned_leb128(&mut code.bytes, target as u64);
+    }
+    code.raw(&[0x0B]);
+    let jump = |code: &mut WasmCode, pc: usize, target: usize, depth: usize| {
+        code.set_pc(target).op(0x0C, (count - pc + depth) as u64);
+    };
+    let mut offsets = vec![];
+    for (pc, instruction) in program.instructions.iter().enumerate() {
+        offsets.push(code.bytes.len());
+        match *instruction {
+            Instruction::Stop => {
+                code.count_step().set_pc(pc).exit(WASM_STOPPED);
+            }
+            Instruction::Inc { cell } => {
+                code.check_cell(pc, cell)
+                    .load_cell(cell).i64_const(-1).raw(&[0x51]).fail_if(pc, WASM_OVERFLOW)
+                    .op(0x20, PTR).load_cell(cell).i64_const(1).raw(&[0x7C]).store_cell(cell)
+                    .count_step().set_pc(pc + 1);
+            }
+            Instruction::Dec { cell } => {
+                code.check_cell(pc, cell)
+                    .load_cell(cell).raw(&[0x50]).fail_if(pc, WASM_DEC_ZERO)
+                    .op(0x20, PTR).load_cell(cell).i64_const(1).raw(&[0x7D]).store_cell(cell)
+                    .count_step().set_pc(pc + 1);
+            }
+            Instruction::Goto { cell } => {
+                code.count_step();
+                jump(&mut code, pc, cell, 0);
+            }
+            Instruction::Nop => {
+                code.count_step().set_pc(pc + 1);
+            }
+            Instruction::GotoZ { condition_cell, goto_cell } => {
+                code.check_cell(pc, condition_cell).count_step()
+                    .load_cell(condition_cell).raw(&[0x50, 0x04, 0x40]);
+                jump(&mut code, pc, goto_cell, 1);
+                code.raw(&[0x0B]).set_pc(pc + 1);
+            }
+            Instruction::GotoNz { condition_cell, goto_cell } => {
+                code.check_cell(pc, condition_cell).count_step()
+                    .load_cell(condition_cell).i64_const(0).raw(&[0x52, 0x04, 0x40]);
+                jump(&mut code, pc, goto_cell, 1);
+                code.raw(&[0x0B]).set_pc(pc + 1);
+            }
+            Instruction::GotoEq { left, right, goto_cell } | Instruction::GotoGt { left, right, goto_cell } => {
+                // i64.eq or i64.gt_u
+                let compare = if let Instruction::GotoEq { .. } = instruction { 0x51 } else { 0x56 };
+                code.check_cell(pc, left).check_cell(pc, right).count_step()
+                    .load_cell(left).load_cell(right).raw(&[compare, 0x04, 0x40]);
+                jump(&mut code, pc, goto_cell, 1);
+                code.raw(&[0x0B]).set_pc(pc + 1);
+            }
+            Instruction::Assert { cell, value } => {
+                code.check_cell(pc, cell)
+                    .load_cell(cell).i64_const(value as i64).raw(&[0x52]).fail_if(pc, WASM_ASSERT_FAILED)
+                    .count_step().set_pc(pc + 1);
+            }
+            Instruction::StoreI { cell, value } => {
+                code.check_cell(pc, cell)
+                    .op(0x20, PTR).i64_const(value as i64).store_cell(cell)
+                    .count_step().set_pc(pc + 1);
+            }
+            Instruction::Print { .. } | Instruction::Read { .. } | Instruction::OutC { .. } | Instruction::Rand { .. }
+            | Instruction::Push { .. } | Instruction::Pop { .. } => {
+                unreachable!("rejected above")
+            }
+        }
+        code.raw(&[0x0B]);
+    }
+    code.exit(WASM_PC_OUTSIDE).raw(&[0x0B, 0x00, 0x0B]);
+
+    let mut module = b"\0asm\x01\0\0\0".to_vec();
+    wasm_section(&mut module, 1, &[0x01, 0x60, 0x02, 0x7F, 0x7F, 0x01, 0x7F]);
+    wasm_section(&mut module, 3, &[0x01, 0x00]);
+    wasm_section(&mut module, 5, &[0x01, 0x00, 0x01]);
+    wasm_section(&mut module, 6, &[0x02, 0x7F, 0x01, 0x41, 0x00, 0x0B, 0x7E, 0x01, 0x42, 0x00, 0x0B]);
+    let mut exports = vec![0x04];
+    for (name, kind, index) in [("run", 0x00, 0x00), ("memory", 0x02, 0x00), ("pc", 0x03, 0x00), ("steps", 0x03, 0x01)] {
+        wasm_name(&mut exports, name);
+        exports.extend_from_slice(&[kind, index]);
+    }
+    wasm_section(&mut module, 7, &exports);
+    let mut body = vec![0x02, 0x01, 0x7F, 0x01, 0x7E];
+    body.extend_from_slice(&code.bytes);
+    let mut function = vec![0x01];
+    unsigned_leb128(&mut function, body.len() as u64);
+    function.extend_from_slice(&body);
+    wasm_section(&mut module, 10, &function);
+    // the code of the function ends the module
+    let start = module.len() - code.bytes.len();
+    Ok((module, offsets.iter().map(|offset| start + offset).collect()))
+}
+
+#[test]
+fn test_leb128() {
+    let mut bytes = vec![];
+    unsigned_leb128(&mut bytes, 624485);
+    signed_leb128(&mut bytes, -123456);
+    signed_leb128(&mut bytes, 64);
+    assert_eq!(vec![0xE5, 0x8E, 0x26, 0xC0, 0xBB, 0x78, 0xC0, 0x00], bytes);
+}
+
+#[test]
+fn test_to_wasm() {
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let module = to_wasm(&program).unwrap();
+    assert_eq!(b"\0asm\x01\0\0\0", &module[..8]);
+    assert!(module.windows(3).any(|w| w == b"run"));
+    let (_, offsets) = to_wasm_mapped(&program).unwrap();
+    assert_eq!(5, offsets.len());
+    // STOP starts with counting the step, local.get steps
+    assert_eq!(&[0x20, STEPS as u8], &module[offsets[4]..offsets[4] + 2]);
+    assert!(to_wasm(&crate::parse_program("INC 4294967295".to_string()).unwrap()).is_err());
+}
+
+#[test]
+fn test_to_rust() {
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let rust = to_rust(&program, Some(&[3, 4]), Arithmetic::Saturating);
+    assert!(rust.contains("    let mut mem: Vec<u64> = vec![3, 4];\n"));
+    assert!(rust.contains("            // DEC 0\n            1 => {\n                let value = cell(&mut mem, 0, 1, steps);\n                *value = value.saturating_sub(1);\n"));
+    assert!(rust.contains("pc = if zero { 4 } else { 1 };"));
+    assert!(to_rust(&program, None, Arithmetic::Checked).contains("value.checked_add(1).unwrap_or_else(|| fail(2, steps, \"INC 1 overflows\".to_string()));"));
+}
+
+#[test]
+fn test_to_c() {
+    let program = crate::parse_program("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP".to_string()).unwrap();
+    let c = to_c(&program, Some(&[3, 4]), Arithmetic::Checked);
+    assert!(c.contains("static uint64_t initial[] = {UINT64_C(3), UINT64_C(4)};"));
+    assert!(c.contains("L0: /* GOTOZ 0 4 */\n    CHECK(0, 0); steps++; if (mem[0] == 0) goto L4;\n"));
+    assert!(c.contains("    /* 3: GOTO 0 */\n    steps++; goto L0;\n"));
+    assert!(c.contains("    fail(5, \"program counter %zu is outside of the program\", 5);\ndone:"));
+    assert!(to_c(&program, None, Arithmetic::Wrapping).contains("mem[i] = strtoull(argv[i + 1], NULL, 10);"));
+    let (mapped, offsets) = to_c_mapped(&program, Some(&[3, 4]), Arithmetic::Checked);
+    assert_eq!(c, mapped);
+    assert!(c[offsets[0]..].starts_with("L0: /* GOTOZ 0 4 */"));
+    assert!(c[offsets[3]..].starts_with("    /* 3: GOTO 0 */"));
+    let asserting = crate::parse_program("ASSERT 1 7".to_string()).unwrap();
+    assert!(to_c(&asserting, None, Arithmetic::Checked)
+        .contains("CHECK(0, 1); if (mem[1] != UINT64_C(7)) fail(0, \"ASSERT 1 7 failed, cell 1 is %zu\", (size_t) mem[1]); steps++;"));
+    let echoing = to_c(&crate::parse_program("READ 0\nPRINT 0".to_string()).unwrap(), None, Arithmetic::Checked);
+    assert!(echoing.contains("CHECK(0, 0); if (scanf(\"%\" SCNu64, &mem[0]) != 1) fail(0, \"READ %zu: there is no more input\", 0); steps++;"));
+    assert!(echoing.contains("CHECK(1, 0); printf(\"%\" PRIu64 \"\\n\", mem[0]); steps++;"));
+    let rolling = to_c(&crate::parse_program("RAND 2 6".to_string()).unwrap(), None, Arithmetic::Checked);
+    assert!(rolling.contains("static uint64_t random_below(uint64_t bound) {"));
+    assert!(rolling.contains("CHECK(0, 2); mem[2] = random_below(UINT64_C(6)); steps++;"));
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use goto::{GotoProgram, GotoProgramState};
+
+use crate::line_editor::{read_key, Key, RawMode};
+
+const MEMORY_WIDTH: usize = 24;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+const KEYS: &str = "s/space step  c continue  p pause  b breakpoint  r restart  q quit";
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Mode {
+    Paused,
+    Running,
+    Halted(String),
+}
+
+struct Tui<'a> {
+    name: String,
+    source: Vec<String>,
+    input: Vec<u64>,
+    state: GotoProgramState<'a>,
+    breakpoint_lines: BTreeSet<usize>,
+    mode: Mode,
+}
+
+fn fit(text: &str, width: usize) -> String {
+    let mut text: String = text.chars().take(width).collect();
+    let length = text.chars().count();
+    text.extend(std::iter::repeat_n(' ', width - length));
+    text
+}
+
+impl<'a> Tui<'a> {
+    fn current_line(&self) -> Option<usize> {
+        self.state.program.source_lines.get(self.state.program_counter).cloned()
+    }
+
+    fn restart(&mut self) {
+        self.state = GotoProgramState::new(self.state.program, self.input.clone());
+        self.mode = Mode::Paused;
+    }
+
+    fn step(&mut self) {
+        if let Mode::Halted(_) = self.mode {
+            return;
+        }
+        match self.state.step() {
+            Ok(false) => {}
+            Ok(true) => self.mode = Mode::Halted("stopped".to_string()),
+            Err(e) => self.mode = Mode::Halted(format!("error: {}", e)),
+        }
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        if let Some(line) = self.current_line() {
+            if !self.breakpoint_lines.remove(&line) {
+                self.breakpoint_lines.insert(line);
+            }
+        }
+    }
+
+    /// Runs up to `steps` instructions, stopping early at breakpoints.
+    fn run_slice(&mut self, steps: u64) {
+        for _ in 0..steps {
+            self.step();
+            if self.mode != Mode::Running {
+                return;
+            }
+            if self.current_line().map(|l| self.breakpoint_lines.contains(&l)).unwrap_or(false) {
+                self.mode = Mode::Paused;
+                return;
+            }
+        }
+    }
+
+    fn render(&self, width: usize, height: usize) -> Vec<String> {
+        let status = match &self.mode {
+            Mode::Paused => "paused",
+            Mode::Running => "running",
+            Mode::Halted(reason) => reason,
+        };
+        let title = format!(" {}  pc: {}  steps: {}  {}", self.name, self.state.program_counter, self.state.steps, status);
+        let mut lines = vec![format!("\x1b[7m{}\x1b[0m", fit(&title, width))];
+
+        let body_height = height.saturating_sub(2);
+        let listing_width = width.saturating_sub(MEMORY_WIDTH + 1);
+        let current = self.current_line();
+        let first = current.unwrap_or(1).saturating_sub(body_height / 2 + 1)
+            .min(self.source.len().saturating_sub(body_height));
+        for row in 0..body_height {
+            let line_nr = first + row + 1;
+            let listing = match self.source.get(first + row) {
+                Some(text) => {
+                    let marker = if self.breakpoint_lines.contains(&line_nr) { '*' } else { ' ' };
+                    fit(&format!("{}{:>4} {}", marker, line_nr, text), listing_width)
+                }
+                None => fit("", listing_width),
+            };
+            let listing = if Some(line_nr) == current {
+                format!("\x1b[7m{}\x1b[0m", listing)
+            } else {
+                listing
+            };
+            let memory = match (row, self.state.memory.get(row.wrapping_sub(1))) {
+                (0, _) => fit(" memory", MEMORY_WIDTH),
+                (_, Some(value)) => fit(&format!(" [{}] {}", row - 1, value), MEMORY_WIDTH),
+                _ => fit("", MEMORY_WIDTH),
+            };
+            lines.push(format!("{}\u{2502}{}", listing, memory));
+        }
+        lines.push(fit(KEYS, width));
+        lines
+    }
+}
+
+fn terminal_size() -> (usize, usize) {
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            (size.ws_col as usize, size.ws_row as usize)
+        } else {
+            (80, 24)
+        }
+    }
+}
+
+fn key_pressed() -> bool {
+    let mut fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+    unsafe { libc::poll(&mut fd, 1, 0) > 0 }
+}
+
+fn draw(out: &mut impl Write, tui: &Tui) -> io::Result<()> {
+    let (width, height) = terminal_size();
+    let frame = tui.render(width, height).join("\x1b[K\r\n");
+    write!(out, "\x1b[H{}\x1b[K", frame)?;
+    out.flush()
+}
+
+pub fn run(name: &str, source: &str, program: &GotoProgram, input: Vec<u64>) -> io::Result<()> {
+    let mut tui = Tui {
+        name: name.to_string(),
+        source: source.lines().map(|l| l.to_string()).collect(),
+        input: input.clone(),
+        state: GotoProgramState::new(program, input),
+        breakpoint_lines: BTreeSet::new(),
+        mode: Mode::Paused,
+    };
+    let _raw = RawMode::without_signals()?;
+    let mut out = io::stdout();
+    // alternate screen, hidden cursor
+    write!(out, "\x1b[?1049h\x1b[?25l\x1b[2J")?;
+    let mut input = io::stdin();
+    let result = (|| -> io::Result<()> {
+        loop {
+            draw(&mut out, &tui)?;
+            if tui.mode == Mode::Running {
+                let started = Instant::now();
+                while tui.mode == Mode::Running && started.elapsed() < REDRAW_INTERVAL && !key_pressed() {
+                    tui.run_slice(1000);
+                }
+                if !key_pressed() {
+                    continue;
+                }
+            }
+            match read_key(&mut input)? {
+                Key::Char('q') | Key::Eof => return Ok(()),
+                Key::Char('s') | Key::Char(' ') | Key::Enter => {
+                    tui.mode = match tui.mode { Mode::Running => Mode::Paused, ref mode => mode.clone() };
+                    tui.step();
+                }
+                Key::Char('c') if tui.mode == Mode::Paused => {
+                    tui.mode = Mode::Running;
+                    // leave the current breakpoint before checking for the next one
+                    tui.run_slice(1);
+                }
+                Key::Char('p') if tui.mode == Mode::Running => tui.mode = Mode::Paused,
+                Key::Char('b') => tui.toggle_breakpoint(),
+                Key::Char('r') => tui.restart(),
+                _ => {}
+            }
+        }
+    })();
+    write!(out, "\x1b[?25h\x1b[?1049l")?;
+    out.flush()?;
+    result
+}
+
+#[test]
+fn test_render() {
+    let source = "loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP";
+    let program = goto::parse_program(source.to_string()).unwrap();
+    let mut tui = Tui {
+        name: "add.goto".to_string(),
+        source: source.lines().map(|l| l.to_string()).collect(),
+        input: vec![1, 2],
+        state: GotoProgramState::new(&program, vec![1, 2]),
+        breakpoint_lines: BTreeSet::new(),
+        mode: Mode::Paused,
+    };
+    tui.step();
+    tui.toggle_breakpoint();
+    let frame = tui.render(40, 6);
+    assert_eq!(vec![
+        "\x1b[7m add.goto  pc: 1  steps: 1  paused      \x1b[0m",
+        "    1 loop: GOT\u{2502} memory                 ",
+        "\x1b[7m*   2 DEC 0    \x1b[0m\u{2502} [0] 1                  ",
+        "    3 INC 1    \u{2502} [1] 2                  ",
+        "    4 GOTO loop\u{2502}                        ",
+        "s/space step  c continue  p pause  b bre",
+    ], frame);
+
+    tui.mode = Mode::Running;
+    tui.run_slice(100);
+    assert_eq!(Mode::Halted("stopped".to_string()), tui.mode);
+    assert_eq!(vec![0, 3], tui.state.memory);
+    tui.restart();
+    tui.mode = Mode::Running;
+    tui.run_slice(100);
+    assert_eq!((Mode::Paused, 1), (tui.mode.clone(), tui.state.program_counter));
+}
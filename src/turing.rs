@@ -0,0 +1,215 @@
+//! A compiler from Turing machines to goto programs.
+//!
+//! Machines are JSON objects like
+//!
+//! ```text
+//! {"states": ["q0", "done"], "start": "q0", "halt": ["done"],
+//!  "alphabet": ["_", "1"], "blank": "_",
+//!  "transitions": [{"state": "q0", "read": "1", "write": "1", "move": "R", "next": "q0"}]}
+//! ```
+//!
+//! with moves `L`, `R` and `N`. The machine halts in a state listed in `halt`
+//! and when there is no transition for the state and the symbol under the head.
+//!
+//! The tape is encoded as the pair of the part left of the head and the part
+//! from the head on, each a number in base `k`, the size of the alphabet, with
+//! the blank as digit 0 and the digit nearest to the head least significant.
+//! Both parts have finitely many symbols that are not blank, so both numbers
+//! are finite. The state is the position in the goto program.
+
+use crate::json::Value;
+use crate::while_lang::Generator;
+
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+// the right part without the symbol under the head
+const REST: usize = 2;
+const TEMPORARY: usize = 3;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Move {
+    Left,
+    Right,
+    None,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Transition {
+    pub state: usize,
+    pub read: usize,
+    pub write: usize,
+    pub movement: Move,
+    pub next: usize,
+}
+
+/// States and symbols are indices into `states` and `alphabet`, the blank is symbol 0.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TuringMachine {
+    pub states: Vec<String>,
+    pub start: usize,
+    pub halting: Vec<bool>,
+    pub alphabet: Vec<String>,
+    pub transitions: Vec<Transition>,
+}
+
+fn strings(value: Option<&Value>, key: &str) -> Result<Vec<String>, String> {
+    value.and_then(|v| v.as_array())
+        .ok_or_else(|| format!("missing or invalid {}", key))?
+        .iter()
+        .map(|v| v.as_str().map(|s| s.to_string()).ok_or_else(|| format!("missing or invalid {}", key)))
+        .collect()
+}
+
+fn index_of(names: &[String], name: Option<&str>, kind: &str) -> Result<usize, String> {
+    let name = name.ok_or_else(|| format!("missing or invalid {}", kind))?;
+    names.iter().position(|n| n == name).ok_or_else(|| format!("unknown {} {}", kind, name))
+}
+
+impl TuringMachine {
+    pub fn parse(text: &str) -> Result<TuringMachine, String> {
+        TuringMachine::from_json(&Value::parse(text)?)
+    }
+
+    pub fn from_json(value: &Value) -> Result<TuringMachine, String> {
+        let states = strings(value.get("states"), "states")?;
+        let start = index_of(&states, value.get("start").and_then(|v| v.as_str()), "state")?;
+        let mut halting = vec![false; states.len()];
+        if value.get("halt").is_some() {
+            for name in strings(value.get("halt"), "halt")? {
+                halting[index_of(&states, Some(&name), "state")?] = true;
+            }
+        }
+        let mut alphabet = strings(value.get("alphabet"), "alphabet")?;
+        let blank = index_of(&alphabet, value.get("blank").and_then(|v| v.as_str()), "symbol")?;
+        let blank = alphabet.remove(blank);
+        alphabet.insert(0, blank);
+        let transitions = value.get("transitions").and_then(|v| v.as_array())
+            .ok_or("missing or invalid transitions")?
+            .iter()
+            .map(|transition| {
+                let field = |key: &str| transition.get(key).and_then(|v| v.as_str());
+                let movement = match field("move") {
+                    Some("L") => Move::Left,
+                    Some("R") => Move::Right,
+                    Some("N") => Move::None,
+                    other => return Err(format!("invalid move {:?}, expected L, R or N", other)),
+                };
+                Ok(Transition {
+                    state: index_of(&states, field("state"), "state")?,
+                    read: index_of(&alphabet, field("read"), "symbol")?,
+                    write: index_of(&alphabet, field("write"), "symbol")?,
+                    movement,
+                    next: index_of(&states, field("next"), "state")?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        for (i, transition) in transitions.iter().enumerate() {
+            if transitions[..i].iter().any(|t| (t.state, t.read) == (transition.state, transition.read)) {
+                return Err(format!("more than one transition for state {} reading {}",
+                                   states[transition.state], alphabet[transition.read]));
+            }
+        }
+        Ok(TuringMachine { states, start, halting, alphabet, transitions })
+    }
+
+    /// The goto source, with comments on how the tape is encoded.
+    pub fn to_goto(&self) -> String {
+        let k = self.alphabet.len();
+        let target = |state: usize| if self.halting[state] { "halt".to_string() } else { format!("s{}", state) };
+        let mut g = Generator::new(TEMPORARY);
+        let running: Vec<_> = (0..self.states.len()).filter(|s| !self.halting[*s]).collect();
+        if running.first() != Some(&self.start) {
+            g.emit(format!("GOTO {}", target(self.start)));
+        }
+        for &state in &running {
+            g.comment(&format!("state {}", self.states[state]));
+            // divide the right part by k, the remainder is the symbol under the head
+            divide(&mut g, RIGHT, REST, &format!("s{}", state), k);
+            for read in 0..k {
+                g.place(&format!("s{}_{}", state, read));
+                let transition = self.transitions.iter().find(|t| (t.state, t.read) == (state, read));
+                let transition = match transition {
+                    Some(transition) => transition,
+                    None => {
+                        g.move_into(REST, &vec![RIGHT; k]);
+                        g.increment(RIGHT, read as u64);
+                        g.emit("GOTO halt".to_string());
+                        continue;
+                    }
+                };
+                match transition.movement {
+                    Move::None => {
+                        g.move_into(REST, &vec![RIGHT; k]);
+                        g.increment(RIGHT, transition.write as u64);
+                    }
+                    Move::Right => {
+                        g.move_into(LEFT, &vec![TEMPORARY; k]);
+                        g.increment(TEMPORARY, transition.write as u64);
+                        g.move_into(TEMPORARY, &[LEFT]);
+                        g.move_into(REST, &[RIGHT]);
+                    }
+                    Move::Left => {
+                        let prefix = format!("s{}_{}_left", state, read);
+                        divide(&mut g, LEFT, TEMPORARY, &prefix, k);
+                        for symbol in 0..k {
+                            g.place(&format!("{}_{}", prefix, symbol));
+                            g.move_into(REST, &vec![RIGHT; k * k]);
+                            g.increment(RIGHT, (transition.write * k + symbol) as u64);
+                            g.move_into(TEMPORARY, &[LEFT]);
+                            g.emit(format!("GOTO {}", target(transition.next)));
+                        }
+                        continue;
+                    }
+                }
+                g.emit(format!("GOTO {}", target(transition.next)));
+            }
+        }
+        g.place("halt");
+        g.emit("STOP".to_string());
+        let symbols: Vec<_> = self.alphabet.iter().enumerate().map(|(digit, s)| format!("{} = {}", s, digit)).collect();
+        g.finish(&format!("translated from a Turing machine, needs a memory of 4 cells: cell 0 is the tape left of \
+                           the head, cell 1 the tape from the head on, cells 2 and 3 are scratch space\n\
+                           ; both are base {} numbers with the digit nearest to the head least significant, \
+                           symbols as digits: {}", k, symbols.join(", ")))
+    }
+}
+
+/// Divides `dividend` by `k` into `quotient`, then continues at `{prefix}_{remainder}`.
+fn divide(g: &mut Generator, dividend: usize, quotient: usize, prefix: &str, k: usize) {
+    g.place(prefix);
+    for digit in 0..k {
+        g.emit(format!("GOTOZ {} {}_{}", dividend, prefix, digit));
+        g.emit(format!("DEC {}", dividend));
+    }
+    g.emit(format!("INC {}", quotient));
+    g.emit(format!("GOTO {}", prefix));
+}
+
+pub fn to_goto(text: &str) -> Result<String, String> {
+    Ok(TuringMachine::parse(text)?.to_goto())
+}
+
+#[test]
+fn test_turing_machine() {
+    // replaces the 1s by xs going right, then turns them back into 1s going left
+    let machine = r#"{"states": ["mark", "unmark", "done"], "start": "mark", "halt": ["done"],
+        "alphabet": ["1", "x", "_"], "blank": "_",
+        "transitions": [
+            {"state": "mark", "read": "1", "write": "x", "move": "R", "next": "mark"},
+            {"state": "mark", "read": "_", "write": "_", "move": "L", "next": "unmark"},
+            {"state": "unmark", "read": "x", "write": "1", "move": "L", "next": "unmark"},
+            {"state": "unmark", "read": "_", "write": "_", "move": "R", "next": "done"}
+        ]}"#;
+    let program = crate::parse_program(to_goto(machine).unwrap()).unwrap();
+    // 111 with _ = 0 and 1 = 1 in base 3
+    let mut state = crate::GotoProgramState::new(&program, vec![0, 1 + 3 + 9, 0, 0]);
+    assert_eq!(Ok(true), state.run_with_limit(100_000));
+    assert_eq!(vec![0, 13, 0, 0], state.memory);
+
+    // without a transition the machine halts with the head where it is
+    let mut state = crate::GotoProgramState::new(&program, vec![0, 2 + 3, 0, 0]);
+    assert_eq!(Ok(true), state.run_with_limit(100_000));
+    assert_eq!(vec![0, 5, 0, 0], state.memory);
+
+    assert!(to_goto(r#"{"states": ["a"], "start": "b", "alphabet": ["_"], "blank": "_", "transitions": []}"#).is_err());
+}
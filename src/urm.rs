@@ -0,0 +1,254 @@
+//! Translation between goto programs and Unlimited Register Machine programs
+//! as in Cutland's Computability.
+//!
+//! URM programs have the instructions `Z(n)` (set `Rn` to 0), `S(n)`
+//! (increment `Rn`), `T(m,n)` (copy `Rm` to `Rn`) and `J(m,n,q)` (jump to
+//! instruction `q` if `Rm` equals `Rn`), numbered from 1, and stop when the
+//! next instruction does not exist. Register `Rn` is cell `n - 1`.
+//!
+//! Both directions need scratch registers after the ones the program uses.
+//! URMs cannot fail, so where a goto program fails the URM translation stops
+//! instead: on DEC on a cell that is 0 and on jumps outside of the program.
+
+use std::fmt::{self, Write};
+
+use crate::while_lang::Generator;
+use crate::{GotoProgram, Instruction};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UrmInstruction {
+    Zero(usize),
+    Successor(usize),
+    Transfer(usize, usize),
+    Jump(usize, usize, usize),
+}
+
+impl fmt::Display for UrmInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            UrmInstruction::Zero(n) => write!(f, "Z({})", n),
+            UrmInstruction::Successor(n) => write!(f, "S({})", n),
+            UrmInstruction::Transfer(m, n) => write!(f, "T({},{})", m, n),
+            UrmInstruction::Jump(m, n, q) => write!(f, "J({},{},{})", m, n, q),
+        }
+    }
+}
+
+fn parse_instruction(text: &str) -> Result<UrmInstruction, String> {
+    let open = text.find('(').ok_or("expected an instruction like Z(1)")?;
+    let arguments = text[open + 1..].strip_suffix(')').ok_or("missing )")?;
+    let arguments = arguments.split(',')
+        .map(|a| a.trim().parse::<usize>().map_err(|e| format!("invalid register {}: {}", a.trim(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if arguments.contains(&0) {
+        return Err("registers and instructions are numbered from 1".to_string());
+    }
+    match (text[..open].trim(), arguments.as_slice()) {
+        ("Z", &[n]) => Ok(UrmInstruction::Zero(n)),
+        ("S", &[n]) => Ok(UrmInstruction::Successor(n)),
+        ("T", &[m, n]) => Ok(UrmInstruction::Transfer(m, n)),
+        ("J", &[m, n, q]) => Ok(UrmInstruction::Jump(m, n, q)),
+        (name, _) => Err(format!("unknown instruction {} with {} arguments", name, arguments.len())),
+    }
+}
+
+/// Parses one instruction per line. Lines may start with their number, like
+/// `1 Z(3)` or `I1: Z(3)`, and `;` starts a comment.
+pub fn parse(text: &str) -> Result<Vec<UrmInstruction>, String> {
+    let mut instructions = vec![];
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let instruction = match line.split_once(char::is_whitespace) {
+            Some((number, rest)) if !number.contains('(') => rest.trim(),
+            _ => line,
+        };
+        instructions.push(parse_instruction(instruction)
+            .map_err(|e| format!("error in line {}: {}", line_number + 1, e))?);
+    }
+    Ok(instructions)
+}
+
+fn highest_register(instructions: &[UrmInstruction]) -> usize {
+    instructions.iter().map(|instruction| match *instruction {
+        UrmInstruction::Zero(n) | UrmInstruction::Successor(n) => n,
+        UrmInstruction::Transfer(m, n) | UrmInstruction::Jump(m, n, _) => m.max(n),
+    }).max().unwrap_or(1)
+}
+
+/// Translates a URM program into goto source, with a comment on the memory it needs.
+pub fn to_goto(text: &str) -> Result<String, String> {
+    let instructions = parse(text)?;
+    let registers = highest_register(&instructions);
+    // cells for comparing two registers, the generator copies through the one after them
+    let (left, right) = (registers, registers + 1);
+    let target = |q: usize| if q <= instructions.len() { format!("i{}", q) } else { "halt".to_string() };
+    let mut g = Generator::new(registers + 2);
+    for (index, instruction) in instructions.iter().enumerate() {
+        g.place(&format!("i{}", index + 1));
+        match *instruction {
+            UrmInstruction::Zero(n) => g.clear(n - 1),
+            UrmInstruction::Successor(n) => g.emit(format!("INC {}", n - 1)),
+            UrmInstruction::Transfer(m, n) if m == n => {}
+            UrmInstruction::Transfer(m, n) => {
+                g.clear(n - 1);
+                g.copy(m - 1, n - 1);
+            }
+            UrmInstruction::Jump(m, n, q) if m == n => g.emit(format!("GOTO {}", target(q))),
+            UrmInstruction::Jump(m, n, q) => {
+                g.copy(m - 1, left);
+                g.copy(n - 1, right);
+                let (compare, left_zero, different) = (g.label(), g.label(), g.label());
+                // count both copies down together, they are equal if they reach 0 at the same time
+                g.place(&compare);
+                g.emit(format!("GOTOZ {} {}", left, left_zero));
+                g.emit(format!("GOTOZ {} {}", right, different));
+                g.emit(format!("DEC {}", left));
+                g.emit(format!("DEC {}", right));
+                g.emit(format!("GOTO {}", compare));
+                g.place(&left_zero);
+                g.emit(format!("GOTOZ {} {}", right, target(q)));
+                g.place(&different);
+                g.clear(left);
+                g.clear(right);
+            }
+        }
+    }
+    g.place("halt");
+    g.emit("STOP".to_string());
+    Ok(g.finish(&format!("translated from a URM program, needs a memory of {} cells, cells from {} on are scratch space",
+                         registers + 3, registers)))
+}
+
+/// The number of URM instructions a goto instruction becomes.
+fn expansion_length(instruction: &Instruction) -> usize {
+    match *instruction {
+        Instruction::Dec { .. } => 9,
+        Instruction::Assert { value, .. } => value as usize + 3,
+        Instruction::StoreI { value, .. } => value as usize + 1,
+        Instruction::GotoNz { .. } => 2,
+        Instruction::GotoGt { .. } => 7,
+        _ => 1,
+    }
+}
+
+/// Translates a goto program into URM instructions. URMs have no
+/// decrement, so a DEC counts up to the predecessor in two scratch registers.
+/// A failing DEC or ASSERT halts, an ASSERT counts up to its value first,
+/// a STOREI counts up from 0 and a GOTOGT counts up until it meets one of
+/// the two cells.
+/// URMs have no input, output, randomness or stack, so the instructions for
+/// them are errors.
+pub fn from_goto(program: &GotoProgram) -> Result<Vec<UrmInstruction>, String> {
+    let highest = program.instructions.iter().map(|instruction| match *instruction {
+        Instruction::Inc { cell } | Instruction::Dec { cell } | Instruction::Assert { cell, .. }
+        | Instruction::StoreI { cell, .. } => Ok(cell),
+        Instruction::GotoZ { condition_cell, .. } | Instruction::GotoNz { condition_cell, .. } => Ok(condition_cell),
+        Instruction::GotoEq { left, right, .. } | Instruction::GotoGt { left, right, .. } => Ok(left.max(right)),
+        Instruction::Goto { .. } | Instruction::Stop | Instruction::Nop => Ok(0),
+        Instruction::Print { .. } | Instruction::Read { .. } | Instruction::OutC { .. } | Instruction::Rand { .. }
+        | Instruction::Push { .. } | Instruction::Pop { .. } => {
+            Err(format!("{} has no translation to a URM", instruction))
+        }
+    }).collect::<Result<Vec<_>, _>>()?.into_iter().max().unwrap_or(0);
+    // register numbers of the scratch registers, `zero` is kept at 0
+    let (zero, counter, predecessor) = (highest + 2, highest + 3, highest + 4);
+    // URM instruction numbers of the goto instructions, after the one that clears `zero`
+    let mut starts = vec![2];
+    for instruction in &program.instructions {
+        starts.push(starts.last().unwrap() + expansion_length(instruction));
+    }
+    let halt = *starts.last().unwrap();
+    let start = |pc: usize| starts.get(pc).cloned().filter(|_| pc < program.instructions.len()).unwrap_or(halt);
+    let mut urm = vec![UrmInstruction::Zero(zero)];
+    for instruction in &program.instructions {
+        let here = urm.len() + 1;
+        match *instruction {
+            Instruction::Inc { cell } => urm.push(UrmInstruction::Successor(cell + 1)),
+            Instruction::Dec { cell } => urm.extend_from_slice(&[
+                UrmInstruction::Jump(cell + 1, zero, halt),
+                UrmInstruction::Zero(counter),
+                UrmInstruction::Successor(counter),
+                UrmInstruction::Zero(predecessor),
+                // counter is always predecessor + 1
+                UrmInstruction::Jump(counter, cell + 1, here + 8),
+                UrmInstruction::Successor(counter),
+                UrmInstruction::Successor(predecessor),
+                UrmInstruction::Jump(zero, zero, here + 4),
+                UrmInstruction::Transfer(predecessor, cell + 1),
+            ]),
+            Instruction::Goto { cell } => urm.push(UrmInstruction::Jump(zero, zero, start(cell))),
+            Instruction::GotoZ { condition_cell, goto_cell } => {
+                urm.push(UrmInstruction::Jump(condition_cell + 1, zero, start(goto_cell)));
+            }
+            Instruction::GotoNz { condition_cell, goto_cell } => urm.extend_from_slice(&[
+                UrmInstruction::Jump(condition_cell + 1, zero, here + 2),
+                UrmInstruction::Jump(zero, zero, start(goto_cell)),
+            ]),
+            Instruction::Nop => urm.push(UrmInstruction::Jump(zero, zero, here + 1)),
+            Instruction::GotoEq { left, right, goto_cell } => urm.push(UrmInstruction::Jump(left + 1, right + 1, start(goto_cell))),
+            Instruction::GotoGt { left, right, goto_cell } => urm.extend_from_slice(&[
+                UrmInstruction::Zero(counter),
+                // counter reaches right first exactly when left is larger or equal
+                UrmInstruction::Jump(counter, right + 1, here + 5),
+                UrmInstruction::Jump(counter, left + 1, here + 7),
+                UrmInstruction::Successor(counter),
+                UrmInstruction::Jump(zero, zero, here + 1),
+                UrmInstruction::Jump(left + 1, counter, here + 7),
+                UrmInstruction::Jump(zero, zero, start(goto_cell)),
+            ]),
+            Instruction::Stop => urm.push(UrmInstruction::Jump(zero, zero, halt)),
+            Instruction::Assert { cell, value } => {
+                urm.push(UrmInstruction::Zero(counter));
+                urm.extend(std::iter::repeat_n(UrmInstruction::Successor(counter), value as usize));
+                urm.push(UrmInstruction::Jump(cell + 1, counter, here + value as usize + 3));
+                urm.push(UrmInstruction::Jump(zero, zero, halt));
+            }
+            Instruction::StoreI { cell, value } => {
+                urm.push(UrmInstruction::Zero(cell + 1));
+                urm.extend(std::iter::repeat_n(UrmInstruction::Successor(cell + 1), value as usize));
+            }
+            Instruction::Print { .. } | Instruction::Read { .. } | Instruction::OutC { .. } | Instruction::Rand { .. }
+            | Instruction::Push { .. } | Instruction::Pop { .. } => unreachable!("rejected above"),
+        }
+    }
+    Ok(urm)
+}
+
+/// One instruction per line, preceded by its number.
+pub fn to_text(instructions: &[UrmInstruction]) -> String {
+    let mut text = String::new();
+    for (index, instruction) in instructions.iter().enumerate() {
+        writeln!(text, "{} {}", index + 1, instruction).unwrap();
+    }
+    text
+}
+
+#[test]
+fn test_round_trip() {
+    // Cutland's addition program, R1 := R1 + R2
+    let program = crate::parse_program(to_goto("1 J(2,3,5)\n2 S(1)\n3 S(3)\n4 J(1,1,1)").unwrap()).unwrap();
+    let mut state = crate::GotoProgramState::new(&program, vec![4, 3, 0, 0, 0, 0]);
+    assert_eq!(Ok(true), state.run_with_limit(10_000));
+    assert_eq!(vec![7, 3, 3, 0, 0, 0], state.memory);
+
+    // goto to URM and back, the scratch registers are at the end of the memory
+    let program = crate::parse_program("loop: GOTOZ 0 end
+        DEC 0
+        INC 1
+        INC 1
+        GOTO loop
+        end: STOP".to_string()).unwrap();
+    let urm = from_goto(&program).unwrap();
+    assert_eq!(urm, parse(&to_text(&urm)).unwrap());
+    let back = crate::parse_program(to_goto(&to_text(&urm)).unwrap()).unwrap();
+    let mut state = crate::GotoProgramState::new(&back, vec![5, 1, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(Ok(true), state.run_with_limit(100_000));
+    assert_eq!(&[0, 11], &state.memory[..2]);
+
+    assert!(parse("Z(0)").is_err());
+    assert!(parse("J(1,2)").is_err());
+    assert!(from_goto(&crate::parse_program("PRINT 0".to_string()).unwrap()).is_err());
+}
@@ -0,0 +1,115 @@
+//! Bounded model checking: runs a program on every input of an input space
+//! for a bounded number of steps and looks for an input that breaks a
+//! property about which instructions it reaches.
+
+use std::fmt;
+
+use crate::inputs::InputSpace;
+use crate::{GotoProgram, GotoProgramState, Instruction};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Target {
+    /// the instruction on a source line
+    Line(usize),
+    /// any STOP, to check that the program halts
+    Stop,
+}
+
+impl Target {
+    pub fn parse(text: &str) -> Result<Target, String> {
+        match text {
+            "stop" | "STOP" => Ok(Target::Stop),
+            line => line.parse().map(Target::Line)
+                .map_err(|_| format!("invalid target {}, expected a line number or stop", text)),
+        }
+    }
+
+    /// Which instructions count as the target.
+    fn instructions(self, program: &GotoProgram) -> Result<Vec<bool>, String> {
+        let hits: Vec<_> = program.instructions.iter().zip(&program.source_lines).map(|(instruction, line)| match self {
+            Target::Line(target) => *line == target,
+            Target::Stop => *instruction == Instruction::Stop,
+        }).collect();
+        if !hits.contains(&true) {
+            return Err(format!("there is no {} in the program", self));
+        }
+        Ok(hits)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Line(line) => write!(f, "instruction on line {}", line),
+            Target::Stop => write!(f, "STOP"),
+        }
+    }
+}
+
+/// Whether one run reached the target, and how it ended if it did not.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Run {
+    Reached { step: u64 },
+    Missed { ending: String },
+}
+
+fn run(program: &GotoProgram, hits: &[bool], memory: Vec<u64>, within: u64) -> Run {
+    let mut state = GotoProgramState::new(program, memory);
+    loop {
+        if hits.get(state.program_counter) == Some(&true) {
+            return Run::Reached { step: state.steps };
+        }
+        if state.steps >= within {
+            return Run::Missed { ending: format!("is still running after {} steps", within) };
+        }
+        match state.step() {
+            Ok(true) => return Run::Missed { ending: format!("stops after {} steps", state.steps) },
+            Ok(false) => {}
+            Err(e) => return Run::Missed { ending: format!("fails after {} steps: {}", state.steps, e) },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Counterexample {
+    pub input: Vec<u64>,
+    pub run: Run,
+}
+
+/// Checks that every input reaches `target` within `within` steps, or if
+/// `reaches` is false that none does. Returns how many inputs were checked
+/// or the first input for which it does not hold.
+pub fn check(program: &GotoProgram, target: Target, reaches: bool, inputs: &InputSpace, within: u64) -> Result<Result<u64, Counterexample>, String> {
+    let hits = target.instructions(program)?;
+    let mut checked = 0;
+    for input in inputs.iter() {
+        let run = run(program, &hits, input.clone(), within);
+        if matches!(run, Run::Reached { .. }) != reaches {
+            return Ok(Err(Counterexample { input, run }));
+        }
+        checked += 1;
+    }
+    Ok(Ok(checked))
+}
+
+#[test]
+fn test_check() {
+    // halts when cell 0 is at most 3, loops forever otherwise
+    let program = crate::parse_program("GOTOZ 0 end
+        DEC 0
+        GOTOZ 0 end
+        DEC 0
+        GOTOZ 0 end
+        DEC 0
+        GOTOZ 0 end
+        loop: GOTO loop
+        end: STOP".to_string()).unwrap();
+    let inputs = InputSpace { cells: 0..1, values: 0..4, size: 1 };
+    assert_eq!(Ok(Ok(4)), check(&program, Target::Stop, true, &inputs, 100));
+    let inputs = InputSpace { values: 0..6, ..inputs };
+    let counterexample = check(&program, Target::Stop, true, &inputs, 100).unwrap().unwrap_err();
+    assert_eq!(Counterexample { input: vec![4], run: Run::Missed { ending: "is still running after 100 steps".to_string() } }, counterexample);
+    let reached = check(&program, Target::Line(8), false, &inputs, 100).unwrap().unwrap_err();
+    assert_eq!(Counterexample { input: vec![4], run: Run::Reached { step: 7 } }, reached);
+    assert!(check(&program, Target::Line(20), true, &inputs, 100).is_err());
+}
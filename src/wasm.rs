@@ -0,0 +1,93 @@
+//! wasm-bindgen bindings for running the interpreter in a browser, so that
+//! a web playground can run and visualize programs client-side.
+//!
+//! Build them with `wasm-pack build --target web -- --features wasm`, or with
+//! `cargo build --lib --release --target wasm32-unknown-unknown --features wasm`
+//! and `wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/goto.wasm`.
+//!
+//! ```js
+//! const playground = parse("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP");
+//! playground.reset(new BigUint64Array([2n, 5n]));
+//! while (!playground.step()) {}
+//! playground.memory(), playground.pc(), playground.line(), playground.steps()
+//! ```
+//!
+//! `parse` and `step` throw an `Error` with the message when they fail.
+
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{parse_program, GotoProgram, GotoProgramState};
+
+/// A parsed program with a run of it.
+#[wasm_bindgen]
+pub struct Playground {
+    // declared before the program, so dropped before it
+    state: GotoProgramState<'static>,
+    program: Rc<GotoProgram>,
+}
+
+impl Playground {
+    fn new(source: &str) -> Result<Playground, String> {
+        let program = Rc::new(parse_program(source.to_string())?);
+        // the state borrows the program from the `Rc` next to it, which keeps
+        // it alive and in place until the state is dropped first
+        let borrowed: &'static GotoProgram = unsafe { &*Rc::as_ptr(&program) };
+        Ok(Playground { state: GotoProgramState::new(borrowed, vec![]), program })
+    }
+
+    fn try_step(&mut self) -> Result<bool, String> {
+        self.state.step().map_err(String::from)
+    }
+}
+
+/// Parses the source, the run starts with an empty memory.
+#[wasm_bindgen]
+pub fn parse(source: &str) -> Result<Playground, JsError> {
+    Playground::new(source).map_err(|e| JsError::new(&e))
+}
+
+#[wasm_bindgen]
+impl Playground {
+    /// Restarts the run on the memory.
+    pub fn reset(&mut self, memory: Vec<u64>) {
+        self.state = GotoProgramState::new(self.state.program, memory);
+    }
+
+    /// Executes one instruction, returns whether it was a STOP.
+    pub fn step(&mut self) -> Result<bool, JsError> {
+        self.try_step().map_err(|e| JsError::new(&e))
+    }
+
+    pub fn memory(&self) -> Vec<u64> {
+        self.state.memory.clone()
+    }
+
+    pub fn pc(&self) -> usize {
+        self.state.program_counter
+    }
+
+    /// The source line of the instruction at the program counter, 0 past the end.
+    pub fn line(&self) -> usize {
+        self.program.source_lines.get(self.state.program_counter).cloned().unwrap_or(0)
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.state.steps
+    }
+}
+
+#[test]
+fn test_playground() {
+    assert_eq!(Some("error in line 1: Unknown label: nowhere".to_string()), Playground::new("GOTO nowhere").err());
+
+    let mut playground = Playground::new("loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP").unwrap();
+    playground.reset(vec![2, 5]);
+    while !playground.try_step().unwrap() {}
+    assert_eq!(vec![0, 7], playground.memory());
+    assert_eq!((4, 5, 10), (playground.pc(), playground.line(), playground.steps()));
+
+    playground.reset(vec![]);
+    assert_eq!(Err("cell 0 is outside of the memory of size 0".to_string()), playground.try_step());
+}
@@ -0,0 +1,58 @@
+use std::fs::{metadata, read_to_string};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+use goto::{parse_program, read_input, GotoProgramState};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+fn modified(files: &[&str]) -> Vec<Option<SystemTime>> {
+    files.iter().map(|file| metadata(file).and_then(|m| m.modified()).ok()).collect()
+}
+
+/// Runs a program once and describes the outcome in a single line.
+fn run_once(source: &str, input: &str, max_steps: u64) -> String {
+    let program = match parse_program(source.to_string()) {
+        Ok(program) => program,
+        Err(e) => return format!("parse error: {}", e),
+    };
+    let memory = match read_input(input.to_string()) {
+        Ok(memory) => memory,
+        Err(e) => return format!("input error: {}", e),
+    };
+    let mut state = GotoProgramState::new(&program, program.initial_memory(&memory));
+    match state.run_with_limit(max_steps) {
+        Ok(true) => format!("result: {:?} after {} steps", state.memory, state.steps),
+        Ok(false) => format!("no STOP within {} steps, pc {}, mem: {:?}", max_steps, state.program_counter, state.memory),
+        Err(e) => format!("error at pc {} after {} steps: {}", state.program_counter, state.steps, e),
+    }
+}
+
+/// Re-runs the program whenever the source or the input file changes, until killed.
+pub fn run(source_file: &str, input_file: &str, max_steps: u64) {
+    let files = [source_file, input_file];
+    let mut last_modified = None;
+    loop {
+        let current = modified(&files);
+        if last_modified.as_ref() != Some(&current) {
+            last_modified = Some(current);
+            let source = read_to_string(source_file);
+            let input = read_to_string(input_file);
+            match (source, input) {
+                (Ok(source), Ok(input)) => println!("{}", run_once(&source, &input, max_steps)),
+                (Err(e), _) => println!("cannot read {}: {}", source_file, e),
+                (_, Err(e)) => println!("cannot read {}: {}", input_file, e),
+            }
+        }
+        sleep(POLL_INTERVAL);
+    }
+}
+
+#[test]
+fn test_run_once() {
+    let source = "loop: GOTOZ 0 end\nDEC 0\nINC 1\nGOTO loop\nend: STOP";
+    assert_eq!("result: [0, 3] after 10 steps", run_once(source, "2 1", 100));
+    assert_eq!("no STOP within 5 steps, pc 1, mem: [1, 2]", run_once(source, "2 1", 5));
+    assert_eq!("error at pc 0 after 0 steps: cell 0 is outside of the memory of size 0", run_once(source, "", 5));
+    assert_eq!("parse error: error in line 1: Unknown label: nowhere", run_once("GOTO nowhere", "", 5));
+}
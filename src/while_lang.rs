@@ -0,0 +1,311 @@
+//! A compiler from the WHILE language of computability courses to goto.
+//!
+//! ```text
+//! program := statement (";" statement)*
+//! statement := xi ":=" xj ("+" | "-") c
+//!            | "WHILE" xi "!=" "0" "DO" program "END"
+//! ```
+//!
+//! Variable `xi` lives in cell `i`. Subtraction is modified subtraction that
+//! stops at 0. Copying a variable needs a scratch cell, which is the cell
+//! after the highest variable, so the generated program needs one cell more
+//! than the variables it uses. The LOOP language in `loop_lang` shares the
+//! statements and the code generation.
+
+use std::fmt::Write;
+
+use crate::RegisterIndex;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Statement {
+    Assign { target: RegisterIndex, source: RegisterIndex, add: bool, constant: u64 },
+    While { variable: RegisterIndex, body: Vec<Statement> },
+    /// Runs the body as often as the variable says when the loop is entered.
+    Loop { variable: RegisterIndex, body: Vec<Statement> },
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let length = if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        } else if rest.starts_with(":=") || rest.starts_with("!=") {
+            2
+        } else if c.is_ascii_alphanumeric() {
+            rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len())
+        } else {
+            c.len_utf8()
+        };
+        tokens.push(rest[..length].to_string());
+        rest = &rest[length..];
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+    /// LOOP instead of WHILE loops
+    bounded: bool,
+}
+
+impl Parser {
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("unexpected end of program")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            ref token if token == expected => Ok(()),
+            token => Err(format!("expected {}, found {}", expected, token)),
+        }
+    }
+
+    fn variable(&mut self) -> Result<RegisterIndex, String> {
+        let token = self.next()?;
+        token.strip_prefix('x').and_then(|n| n.parse().ok())
+            .ok_or_else(|| format!("expected a variable like x1, found {}", token))
+    }
+
+    fn program(&mut self) -> Result<Vec<Statement>, String> {
+        let mut statements = vec![self.statement()?];
+        while self.tokens.get(self.pos).map(|t| t.as_str()) == Some(";") {
+            self.pos += 1;
+            if matches!(self.tokens.get(self.pos).map(|t| t.as_str()), None | Some("END")) {
+                break;
+            }
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, String> {
+        match self.tokens.get(self.pos).map(|t| t.as_str()) {
+            Some("WHILE") if !self.bounded => {
+                self.pos += 1;
+                let variable = self.variable()?;
+                self.expect("!=")?;
+                self.expect("0")?;
+                self.expect("DO")?;
+                let body = self.program()?;
+                self.expect("END")?;
+                Ok(Statement::While { variable, body })
+            }
+            Some("LOOP") if self.bounded => {
+                self.pos += 1;
+                let variable = self.variable()?;
+                self.expect("DO")?;
+                let body = self.program()?;
+                self.expect("END")?;
+                Ok(Statement::Loop { variable, body })
+            }
+            _ => {
+                let target = self.variable()?;
+                self.expect(":=")?;
+                let source = self.variable()?;
+                let add = match self.next()?.as_str() {
+                    "+" => true,
+                    "-" => false,
+                    token => return Err(format!("expected + or -, found {}", token)),
+                };
+                let constant = self.next()?;
+                let constant = constant.parse().map_err(|_| format!("expected a constant, found {}", constant))?;
+                Ok(Statement::Assign { target, source, add, constant })
+            }
+        }
+    }
+}
+
+pub(crate) fn parse_statements(text: &str, bounded: bool) -> Result<Vec<Statement>, String> {
+    let mut parser = Parser { tokens: tokenize(text), pos: 0, bounded };
+    let program = parser.program()?;
+    match parser.tokens.get(parser.pos) {
+        Some(token) => Err(format!("unexpected {}", token)),
+        None => Ok(program),
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Statement>, String> {
+    parse_statements(text, false)
+}
+
+pub(crate) fn highest_variable(statements: &[Statement]) -> RegisterIndex {
+    statements.iter().map(|statement| match statement {
+        Statement::Assign { target, source, .. } => *target.max(source),
+        Statement::While { variable, body } | Statement::Loop { variable, body } => {
+            (*variable).max(highest_variable(body))
+        }
+    }).max().unwrap_or(0)
+}
+
+pub(crate) fn loop_depth(statements: &[Statement]) -> usize {
+    statements.iter().map(|statement| match statement {
+        Statement::Loop { body, .. } => 1 + loop_depth(body),
+        Statement::While { body, .. } => loop_depth(body),
+        Statement::Assign { .. } => 0,
+    }).max().unwrap_or(0)
+}
+
+/// Writes goto source line by line, `scratch` is a cell that is 0 between statements.
+pub(crate) struct Generator {
+    lines: Vec<String>,
+    labels: usize,
+    scratch: RegisterIndex,
+}
+
+impl Generator {
+    pub(crate) fn new(scratch: RegisterIndex) -> Generator {
+        Generator { lines: vec![], labels: 0, scratch }
+    }
+
+    pub(crate) fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("l{}", self.labels)
+    }
+
+    pub(crate) fn emit(&mut self, line: String) {
+        self.lines.push(format!("    {}", line));
+    }
+
+    pub(crate) fn place(&mut self, label: &str) {
+        self.lines.push(format!("{}:", label));
+    }
+
+    pub(crate) fn comment(&mut self, text: &str) {
+        self.lines.push(format!("; {}", text));
+    }
+
+    pub(crate) fn increment(&mut self, cell: RegisterIndex, times: u64) {
+        for _ in 0..times {
+            self.emit(format!("INC {}", cell));
+        }
+    }
+
+    /// Repeats `body` while `cell` is not zero, `body` has to decrement it.
+    fn while_nonzero(&mut self, cell: RegisterIndex, body: impl FnOnce(&mut Generator)) {
+        let (start, end) = (self.label(), self.label());
+        self.place(&start);
+        self.emit(format!("GOTOZ {} {}", cell, end));
+        body(self);
+        self.emit(format!("GOTO {}", start));
+        self.place(&end);
+    }
+
+    /// Adds the value of `from` to all of `to`, leaving `from` at zero.
+    pub(crate) fn move_into(&mut self, from: RegisterIndex, to: &[RegisterIndex]) {
+        self.while_nonzero(from, |g| {
+            g.emit(format!("DEC {}", from));
+            for cell in to {
+                g.emit(format!("INC {}", cell));
+            }
+        });
+    }
+
+    pub(crate) fn clear(&mut self, cell: RegisterIndex) {
+        self.move_into(cell, &[]);
+    }
+
+    /// Adds the value of `from` to `to` through the scratch cell.
+    pub(crate) fn copy(&mut self, from: RegisterIndex, to: RegisterIndex) {
+        let scratch = self.scratch;
+        self.move_into(from, &[to, scratch]);
+        self.move_into(scratch, &[from]);
+    }
+
+    /// The source with `header` as a comment in the first line.
+    pub(crate) fn finish(self, header: &str) -> String {
+        let mut goto = format!("; {}\n", header);
+        for line in self.lines {
+            writeln!(goto, "{}", line).unwrap();
+        }
+        goto
+    }
+
+    fn statements(&mut self, statements: &[Statement], depth: usize) {
+        for statement in statements {
+            match *statement {
+                Statement::Assign { target, source, add, constant } => {
+                    if target != source {
+                        self.clear(target);
+                        self.copy(source, target);
+                    }
+                    if add {
+                        self.increment(target, constant);
+                    } else {
+                        for _ in 0..constant {
+                            let skip = self.label();
+                            self.emit(format!("GOTOZ {} {}", target, skip));
+                            self.emit(format!("DEC {}", target));
+                            self.place(&skip);
+                        }
+                    }
+                }
+                Statement::While { variable, ref body } => {
+                    let (start, end) = (self.label(), self.label());
+                    self.place(&start);
+                    self.emit(format!("GOTOZ {} {}", variable, end));
+                    self.statements(body, depth);
+                    self.emit(format!("GOTO {}", start));
+                    self.place(&end);
+                }
+                Statement::Loop { variable, ref body } => {
+                    // count down a copy, the body may change the variable
+                    let counter = self.scratch + 1 + depth;
+                    self.clear(counter);
+                    self.copy(variable, counter);
+                    self.while_nonzero(counter, |g| {
+                        g.emit(format!("DEC {}", counter));
+                        g.statements(body, depth + 1);
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The number of cells the compiled program needs.
+pub fn memory_size(statements: &[Statement]) -> usize {
+    highest_variable(statements) + 2 + loop_depth(statements)
+}
+
+/// Compiles statements into goto source, with a comment on the memory it needs.
+/// Cells after the variables are scratch space, LOOP counters come after the scratch cell.
+pub fn compile(statements: &[Statement], language: &str) -> String {
+    let scratch = highest_variable(statements) + 1;
+    let mut generator = Generator::new(scratch);
+    generator.statements(statements, 0);
+    generator.emit("STOP".to_string());
+    generator.finish(&format!("compiled from {}, needs a memory of {} cells, cells from {} on are scratch space",
+                              language, memory_size(statements), scratch))
+}
+
+pub fn to_goto(text: &str) -> Result<String, String> {
+    Ok(compile(&parse(text)?, "WHILE"))
+}
+
+#[test]
+fn test_while_to_goto() {
+    // x0 := x0 + x1 * x2
+    let source = "WHILE x1 != 0 DO
+            x3 := x2 + 0;
+            WHILE x3 != 0 DO x0 := x0 + 1; x3 := x3 - 1 END;
+            x1 := x1 - 1;
+        END";
+    let program = crate::parse_program(to_goto(source).unwrap()).unwrap();
+    let mut state = crate::GotoProgramState::new(&program, vec![9, 3, 4, 7, 0]);
+    assert_eq!(Ok(true), state.run_with_limit(10_000));
+    assert_eq!(vec![21, 0, 4, 0, 0], state.memory);
+
+    let program = crate::parse_program(to_goto("x1 := x0 - 5").unwrap()).unwrap();
+    let mut state = crate::GotoProgramState::new(&program, vec![3, 8, 0]);
+    assert_eq!(Ok(true), state.run_with_limit(10_000));
+    assert_eq!(vec![3, 0, 0], state.memory);
+
+    assert!(parse("x1 := 3").is_err());
+    assert!(parse("WHILE x1 != 0 DO x1 := x1 - 1").is_err());
+    assert!(parse("LOOP x1 DO x1 := x1 - 1 END").is_err());
+}